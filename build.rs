@@ -0,0 +1,13 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/esteria.proto");
+        let fds = protox::compile(["esteria.proto"], ["proto"]).expect("proto file is valid");
+        tonic_prost_build::configure()
+            .build_client(false)
+            .build_transport(false)
+            .compile_fds(fds)
+            .expect("failed to generate gRPC code");
+    }
+}
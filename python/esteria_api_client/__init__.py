@@ -1,5 +1,21 @@
-from ._esteria_api_client import PyEncoding as Encoding
-from ._esteria_api_client import PySmsClient as SmsClient
-from ._esteria_api_client import PySmsFlags as SmsFlags
+from ._esteria_api_client import (
+    Encoding,
+    HttpError,
+    InvalidResponse,
+    SendFailed,
+    SmsClient,
+    SmsError,
+    SmsFlags,
+    SmsResult,
+)
 
-__all__ = ["SmsClient", "SmsFlags", "Encoding"]
+__all__ = [
+    "SmsClient",
+    "SmsFlags",
+    "Encoding",
+    "SmsResult",
+    "SmsError",
+    "SendFailed",
+    "HttpError",
+    "InvalidResponse",
+]
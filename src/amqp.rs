@@ -0,0 +1,233 @@
+use crate::worker::{Delivery, JobQueue, JobResult, WorkerError};
+use futures_util::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicPublishOptions};
+use lapin::types::FieldTable;
+use lapin::{BasicProperties, Channel, Consumer};
+use tokio::sync::Mutex;
+
+/// [`JobQueue`] consuming a RabbitMQ (AMQP 0-9-1) queue through `lapin`
+///
+/// A job's ID is its `message_id` property, or else its `correlation_id`;
+/// publish jobs with one of them so that redeliveries keep the idempotency
+/// key (the delivery tag used as a last resort changes on redelivery).
+/// Results are published through the default exchange to the job's
+/// `reply_to` queue, with its correlation ID. Nacked jobs are requeued.
+///
+/// The connection and channel are the caller's, so any `lapin` runtime and
+/// TLS setup can be used; set a prefetch limit with `basic_qos` first, as
+/// every unacknowledged job is otherwise pushed to this consumer.
+pub struct AmqpQueue {
+    channel: Channel,
+    consumer: Mutex<Consumer>,
+}
+
+impl AmqpQueue {
+    /// Consume `queue` on `channel`, under `consumer_tag`
+    ///
+    /// # Errors
+    ///
+    /// Returns `WorkerError::Queue` if the consumer can't be started, e.g. the
+    /// queue doesn't exist
+    pub async fn new(
+        channel: Channel,
+        queue: &str,
+        consumer_tag: &str,
+    ) -> Result<Self, WorkerError> {
+        let consumer = channel
+            .basic_consume(
+                queue,
+                consumer_tag,
+                BasicConsumeOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(queue_error)?;
+        Ok(Self {
+            channel,
+            consumer: Mutex::new(consumer),
+        })
+    }
+
+    /// Channel the queue is consumed on
+    #[must_use]
+    pub fn channel(&self) -> &Channel {
+        &self.channel
+    }
+}
+
+impl std::fmt::Debug for AmqpQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AmqpQueue")
+            .field("channel", &self.channel.id())
+            .finish_non_exhaustive()
+    }
+}
+
+fn queue_error(err: lapin::Error) -> WorkerError {
+    WorkerError::Queue(err.to_string())
+}
+
+/// Delivery tag of a delivery received from an [`AmqpQueue`]
+fn delivery_tag(delivery: &Delivery) -> Result<u64, WorkerError> {
+    delivery
+        .receipt
+        .as_deref()
+        .and_then(|receipt| receipt.parse().ok())
+        .ok_or_else(|| WorkerError::Queue(format!("job {} has no delivery tag", delivery.id)))
+}
+
+impl JobQueue for AmqpQueue {
+    async fn receive(&self) -> Result<Option<Delivery>, WorkerError> {
+        let Some(message) = self.consumer.lock().await.next().await else {
+            return Ok(None);
+        };
+        let message = message.map_err(queue_error)?;
+        let properties = &message.properties;
+        let correlation_id = properties
+            .correlation_id()
+            .as_ref()
+            .map(|id| id.to_string());
+        let id = properties
+            .message_id()
+            .as_ref()
+            .map(|id| id.to_string())
+            .or_else(|| correlation_id.clone())
+            .unwrap_or_else(|| message.delivery_tag.to_string());
+
+        let mut delivery =
+            Delivery::new(id, message.data).with_receipt(message.delivery_tag.to_string());
+        delivery.reply_to = properties
+            .reply_to()
+            .as_ref()
+            .map(|queue| queue.to_string());
+        delivery.correlation_id = correlation_id;
+        Ok(Some(delivery))
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+        self.channel
+            .basic_ack(delivery_tag(delivery)?, BasicAckOptions::default())
+            .await
+            .map_err(queue_error)
+    }
+
+    async fn nack(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+        let options = BasicNackOptions {
+            requeue: true,
+            ..BasicNackOptions::default()
+        };
+        self.channel
+            .basic_nack(delivery_tag(delivery)?, options)
+            .await
+            .map_err(queue_error)
+    }
+
+    /// Publish `result` to the job's `reply_to` queue; without one it is dropped
+    async fn reply(&self, delivery: &Delivery, result: &JobResult) -> Result<(), WorkerError> {
+        let Some(reply_to) = &delivery.reply_to else {
+            return Ok(());
+        };
+        let mut properties =
+            BasicProperties::default().with_content_type("application/json".into());
+        if let Some(correlation_id) = &delivery.correlation_id {
+            properties = properties.with_correlation_id(correlation_id.as_str().into());
+        }
+        self.channel
+            .basic_publish(
+                "",
+                reply_to,
+                BasicPublishOptions::default(),
+                &result.to_json(),
+                properties,
+            )
+            .await
+            .map_err(queue_error)?
+            .await
+            .map_err(queue_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::contract;
+    use lapin::options::{BasicGetOptions, QueueDeclareOptions};
+    use lapin::{Connection, ConnectionProperties};
+
+    #[tokio::test]
+    #[ignore = "needs a RabbitMQ broker at ESTERIA_TEST_AMQP_URL"]
+    async fn follows_the_job_queue_contract() {
+        let url = std::env::var("ESTERIA_TEST_AMQP_URL")
+            .unwrap_or_else(|_| "amqp://127.0.0.1:5672/%2f".to_string());
+        let properties = ConnectionProperties::default()
+            .with_executor(tokio_executor_trait::Tokio::current())
+            .with_reactor(tokio_reactor_trait::Tokio::current());
+        let connection = Connection::connect(&url, properties).await.unwrap();
+        let channel = connection.create_channel().await.unwrap();
+        let exclusive = QueueDeclareOptions {
+            exclusive: true,
+            ..QueueDeclareOptions::default()
+        };
+        let jobs = channel
+            .queue_declare("", exclusive, FieldTable::default())
+            .await
+            .unwrap();
+        let replies = channel
+            .queue_declare("", exclusive, FieldTable::default())
+            .await
+            .unwrap();
+
+        let payload = br#"{"sender": "Shop", "number": "+37060000000", "text": "Hi"}"#;
+        let job = BasicProperties::default()
+            .with_message_id("job-1".into())
+            .with_correlation_id("request-7".into())
+            .with_reply_to(replies.name().clone());
+        channel
+            .basic_publish(
+                "",
+                jobs.name().as_str(),
+                BasicPublishOptions::default(),
+                payload,
+                job,
+            )
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+
+        let queue = AmqpQueue::new(channel.clone(), jobs.name().as_str(), "worker")
+            .await
+            .unwrap();
+        let delivery = contract::check(&queue, payload).await;
+        assert_eq!(delivery.id, "job-1");
+        assert_eq!(delivery.correlation_id.as_deref(), Some("request-7"));
+
+        let result = JobResult {
+            job_id: delivery.id.clone(),
+            number: None,
+            message_id: Some("1001".to_string()),
+            error: None,
+        };
+        queue.reply(&delivery, &result).await.unwrap();
+        let reply = loop {
+            let reply = channel
+                .basic_get(replies.name().as_str(), BasicGetOptions { no_ack: true })
+                .await
+                .unwrap();
+            if let Some(reply) = reply {
+                break reply;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        };
+        assert_eq!(reply.data, result.to_json());
+        assert_eq!(
+            reply
+                .properties
+                .correlation_id()
+                .as_ref()
+                .map(|id| id.as_str()),
+            Some("request-7")
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::esteria::{SmsError, SmsFlags, SmsRequest};
+use crate::redact::Redaction;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::fmt;
+use std::sync::Arc;
+
+/// Structured record of one send attempt
+///
+/// Attempts refused client-side (validation, filters, opt-outs) are recorded
+/// too, without a latency. Duplicates answered from the idempotency store are
+/// not attempts and are not recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Recipient number with the middle digits masked
+    pub number: String,
+    pub sender: String,
+    pub flags: SmsFlags,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub user_key: Option<String>,
+    /// Message ID if the gateway accepted the message
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub message_id: Option<String>,
+    /// Gateway error code if the gateway rejected the message
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub code: Option<i64>,
+    /// Error message if the attempt failed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub error: Option<String>,
+    /// Gateway round trip time, if the gateway was contacted
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub latency_ms: Option<i64>,
+}
+
+/// Destination for audit records
+///
+/// Registered with [`crate::SmsClientBuilder::with_audit_sink`] and called
+/// once per send attempt, after it completes.
+pub trait AuditSink: Send + Sync + fmt::Debug {
+    fn record(&self, record: &AuditRecord);
+}
+
+/// Audit sink calling a function with every record
+#[derive(Clone)]
+pub struct CallbackAuditSink {
+    callback: Arc<dyn Fn(&AuditRecord) + Send + Sync>,
+}
+
+impl CallbackAuditSink {
+    pub fn new(callback: impl Fn(&AuditRecord) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Arc::new(callback),
+        }
+    }
+}
+
+impl fmt::Debug for CallbackAuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallbackAuditSink").finish_non_exhaustive()
+    }
+}
+
+impl AuditSink for CallbackAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        (self.callback)(record);
+    }
+}
+
+/// Audit sink appending one JSON object per line to a file
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct JsonlAuditSink {
+    path: std::path::PathBuf,
+    file: std::sync::Mutex<std::fs::File>,
+}
+
+#[cfg(feature = "serde")]
+impl JsonlAuditSink {
+    /// Append to `path`, creating the file if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::Error` if the file can't be opened
+    pub fn open(path: impl Into<std::path::PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            file: std::sync::Mutex::new(file),
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(feature = "serde")]
+impl AuditSink for JsonlAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        use std::io::Write;
+
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(err) => {
+                log::error!("Failed to encode audit record: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Err(err) = file.write_all(&line) {
+            log::error!(
+                "Failed to write audit record to {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+/// What a send attempt learned from the gateway
+#[derive(Debug, Default)]
+pub(crate) struct GatewayCall {
+    pub(crate) latency: Option<TimeDelta>,
+    pub(crate) code: Option<i128>,
+}
+
+impl AuditRecord {
+    pub(crate) fn new(
+        request: &SmsRequest<'_>,
+        result: &Result<String, SmsError>,
+        call: &GatewayCall,
+    ) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            number: Redaction::Mask.apply(&request.number),
+            sender: request.sender.to_string(),
+            flags: request.flags,
+            user_key: request.user_key.as_deref().map(str::to_string),
+            message_id: result.as_ref().ok().cloned(),
+            code: call.code.and_then(|code| i64::try_from(code).ok()),
+            error: result.as_ref().err().map(ToString::to_string),
+            latency_ms: call.latency.map(|latency| latency.num_milliseconds()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esteria::SmsClient;
+    use httpmock::prelude::*;
+    use std::sync::Mutex;
+
+    #[tokio::test]
+    async fn records_every_attempt() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200).body("1001");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234568");
+            then.status(200).body("2");
+        });
+
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = CallbackAuditSink::new({
+            let records = records.clone();
+            move |record: &AuditRecord| records.lock().unwrap().push(record.clone())
+        });
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_audit_sink(Arc::new(sink))
+            .build()
+            .unwrap();
+
+        for number in ["+37061234567", "+37061234568", "12"] {
+            let _ = client
+                .send_sms(SmsRequest::new("k", "Alice", number, "Hi"))
+                .await;
+        }
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].number, "+3706*****67");
+        assert_eq!(records[0].message_id.as_deref(), Some("1001"));
+        assert!(records[0].latency_ms.is_some());
+        assert_eq!(records[1].code, Some(2));
+        assert!(records[1].error.is_some());
+        assert_eq!(records[2].latency_ms, None);
+    }
+}
@@ -0,0 +1,59 @@
+use reqwest::RequestBuilder;
+
+/// How gateway requests authenticate, besides the `api-key` parameter
+///
+/// The `api-key` query parameter is sent whenever a request has a non-empty
+/// API key, so deployments requiring both a key and a header work. Leave the
+/// request's API key empty to authenticate by header only.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub enum Auth {
+    /// Only the `api-key` query parameter (default)
+    #[default]
+    ApiKey,
+    /// HTTP basic auth
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// `Authorization: Bearer` token
+    Bearer(String),
+}
+
+impl Auth {
+    /// HTTP basic auth with a username and password
+    #[must_use]
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self::Basic {
+            username: username.into(),
+            password: Some(password.into()),
+        }
+    }
+
+    /// Bearer token auth
+    #[must_use]
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Self::Bearer(token.into())
+    }
+
+    pub(crate) fn apply(&self, builder: RequestBuilder) -> RequestBuilder {
+        match self {
+            Self::ApiKey => builder,
+            Self::Basic { username, password } => builder.basic_auth(username, password.as_ref()),
+            Self::Bearer(token) => builder.bearer_auth(token),
+        }
+    }
+}
+
+/// `Debug` output omits passwords and tokens
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ApiKey => f.write_str("ApiKey"),
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Self::Bearer(_) => f.write_str("Bearer(..)"),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::conversation::keyword;
+use crate::esteria::{SmsClient, SmsError, SmsRequest};
+use crate::optout::is_opt_out_keyword;
+use crate::template::SmsTemplate;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Custom reply to an inbound message from a number, or `None` not to reply
+pub type KeywordHandler = dyn Fn(&str, &str) -> Option<String> + Send + Sync;
+
+#[derive(Clone)]
+enum Response {
+    Template(SmsTemplate),
+    Handler(Arc<KeywordHandler>),
+}
+
+/// Automatic replies to inbound keywords like `INFO` or `HELP`
+///
+/// The first word of an inbound message selects the response, compared
+/// case-insensitively. Templates may use the `{number}`, `{keyword}` and
+/// `{text}` placeholders. Opt-out keywords are never answered; record them
+/// with [`crate::optout::process_inbound`].
+#[derive(Clone)]
+pub struct AutoResponder {
+    api_key: String,
+    sender: String,
+    responses: HashMap<String, Response>,
+    fallback: Option<Arc<KeywordHandler>>,
+}
+
+impl fmt::Debug for AutoResponder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keywords: Vec<_> = self.responses.keys().collect();
+        keywords.sort();
+        f.debug_struct("AutoResponder")
+            .field("sender", &self.sender)
+            .field("keywords", &keywords)
+            .field("fallback", &self.fallback.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl AutoResponder {
+    /// Reply from `sender`; an empty `api_key` uses the client's credentials provider
+    pub fn new(api_key: impl Into<String>, sender: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            sender: sender.into(),
+            responses: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Reply to `keyword` with a rendered template
+    #[must_use]
+    pub fn with_response(mut self, keyword: &str, template: SmsTemplate) -> Self {
+        self.responses
+            .insert(keyword.to_uppercase(), Response::Template(template));
+        self
+    }
+
+    /// Reply to `keyword` with the text returned by `handler(number, text)`
+    #[must_use]
+    pub fn with_handler(
+        mut self,
+        keyword: &str,
+        handler: impl Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.responses
+            .insert(keyword.to_uppercase(), Response::Handler(Arc::new(handler)));
+        self
+    }
+
+    /// Reply to messages matching no keyword with `handler(number, text)`
+    #[must_use]
+    pub fn with_fallback(
+        mut self,
+        handler: impl Fn(&str, &str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.fallback = Some(Arc::new(handler));
+        self
+    }
+
+    /// Reply text for an inbound message, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError::Template` if a template uses an unknown placeholder
+    pub fn reply(&self, number: &str, text: &str) -> Result<Option<String>, SmsError> {
+        if is_opt_out_keyword(text) {
+            return Ok(None);
+        }
+        let keyword = keyword(text);
+        match self.responses.get(&keyword) {
+            Some(Response::Template(template)) => {
+                let context =
+                    HashMap::from([("number", number), ("keyword", &keyword), ("text", text)]);
+                Ok(Some(template.render(&context)?))
+            }
+            Some(Response::Handler(handler)) => Ok(handler(number, text)),
+            None => Ok(self
+                .fallback
+                .as_ref()
+                .and_then(|handler| handler(number, text))),
+        }
+    }
+
+    /// Answer an inbound message from `number`, returning the reply's message ID
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError` if the reply can't be rendered or sent
+    pub async fn handle(
+        &self,
+        client: &SmsClient,
+        number: &str,
+        text: &str,
+    ) -> Result<Option<String>, SmsError> {
+        let Some(reply) = self.reply(number, text)? else {
+            return Ok(None);
+        };
+        let request = SmsRequest::new(&*self.api_key, &*self.sender, number, reply);
+        client.send_sms(request).await.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn replies_to_keywords() {
+        let server = MockServer::start();
+        let info = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Open 9-17. Reply HELP for help.");
+            then.status(200).body("1001");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+
+        let responder = AutoResponder::new("k", "Shop")
+            .with_response(
+                "info",
+                SmsTemplate::new("Open 9-17. Reply HELP for help.").unwrap(),
+            )
+            .with_response("help", SmsTemplate::new("{keyword}: call us").unwrap())
+            .with_handler("order", |_, text| {
+                Some(format!("Order {} is on its way", &text[6..]))
+            })
+            .with_fallback(|number, _| (number == "+37060000001").then(|| "Hi!".to_string()));
+
+        let sent = responder
+            .handle(&client, "+37061234567", "Info?")
+            .await
+            .unwrap();
+        assert_eq!(sent.as_deref(), Some("1001"));
+        info.assert();
+
+        let reply = |text| responder.reply("+37061234567", text).unwrap();
+        assert_eq!(reply("help me").as_deref(), Some("HELP: call us"));
+        assert_eq!(reply("ORDER 42").as_deref(), Some("Order 42 is on its way"));
+        assert_eq!(reply("STOP"), None);
+        assert_eq!(reply("hello"), None);
+        assert_eq!(
+            responder.reply("+37060000001", "hello").unwrap().as_deref(),
+            Some("Hi!")
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Blocking client for non-async code
+//!
+//! [`SmsClient`] wraps the async [`crate::SmsClient`] together with its own
+//! single-threaded tokio runtime, so callers don't need one. Like
+//! `reqwest::blocking`, it must not be used from within an async runtime;
+//! calls made there panic.
+
+use crate::bulk::{BulkReport, Recipient};
+use crate::esteria::{MultipartResult, SmsError, SmsRequest};
+use crate::template::SmsTemplate;
+use crate::wap::WapPush;
+use std::io;
+use tokio::runtime::{Builder, Runtime};
+
+/// Blocking SMS client
+pub struct SmsClient {
+    inner: crate::SmsClient,
+    runtime: Runtime,
+}
+
+impl SmsClient {
+    /// Wrap a configured async client
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::Error` if the runtime can't be created
+    pub fn new(client: crate::SmsClient) -> io::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self {
+            inner: client,
+            runtime,
+        })
+    }
+
+    /// Create a blocking client with a custom API base URL
+    ///
+    /// # Errors
+    ///
+    /// Returns `io::Error` if the runtime can't be created
+    pub fn with_api_base_url(api_base_url: impl Into<String>) -> io::Result<Self> {
+        Self::new(crate::SmsClient::with_api_base_url(api_base_url))
+    }
+
+    /// The wrapped async client, e.g. for [`crate::SmsClient::estimate_cost`]
+    #[must_use]
+    pub fn inner(&self) -> &crate::SmsClient {
+        &self.inner
+    }
+
+    /// Send an SMS message, blocking until the gateway responds
+    ///
+    /// # Errors
+    ///
+    /// See [`crate::SmsClient::send_sms`]
+    pub fn send_sms(&self, request: SmsRequest<'_>) -> Result<String, SmsError> {
+        self.runtime.block_on(self.inner.send_sms(request))
+    }
+
+    /// Send a message, splitting it into concatenated parts if needed
+    ///
+    /// # Errors
+    ///
+    /// See [`crate::SmsClient::send_long_sms`]
+    pub fn send_long_sms(&self, request: SmsRequest<'_>) -> Result<MultipartResult, SmsError> {
+        self.runtime.block_on(self.inner.send_long_sms(request))
+    }
+
+    /// Send a WAP Push Service Indication
+    ///
+    /// # Errors
+    ///
+    /// See [`crate::SmsClient::send_wap_push`]
+    pub fn send_wap_push(
+        &self,
+        api_key: &str,
+        sender: &str,
+        number: &str,
+        push: &WapPush,
+    ) -> Result<MultipartResult, SmsError> {
+        self.runtime
+            .block_on(self.inner.send_wap_push(api_key, sender, number, push))
+    }
+
+    /// Send the same message to many numbers, see [`crate::SmsClient::send_bulk`]
+    pub fn send_bulk<I>(&self, base: SmsRequest<'_>, numbers: I) -> BulkReport
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.runtime.block_on(self.inner.send_bulk(base, numbers))
+    }
+
+    /// Send a personalized template, see [`crate::SmsClient::send_personalized`]
+    pub fn send_personalized<I>(
+        &self,
+        base: SmsRequest<'_>,
+        template: &SmsTemplate,
+        recipients: I,
+    ) -> BulkReport
+    where
+        I: IntoIterator<Item = Recipient>,
+    {
+        self.runtime
+            .block_on(self.inner.send_personalized(base, template, recipients))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[test]
+    fn send_sms_blocks_until_response() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url()).unwrap();
+        let id = client
+            .send_sms(SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+            .unwrap();
+        assert_eq!(id, "1001");
+        m.assert();
+    }
+}
@@ -0,0 +1,508 @@
+use crate::esteria::{SmsClient, SmsError, SmsRequest};
+use crate::redact;
+use crate::template::SmsTemplate;
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, StreamExt};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Recipient of a personalized message
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recipient {
+    pub number: String,
+    /// Values for the template placeholders
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub variables: HashMap<String, String>,
+}
+
+impl std::fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recipient")
+            .field("number", &redact::number(&self.number))
+            .field("variables", &self.variables)
+            .finish()
+    }
+}
+
+impl Recipient {
+    /// Create a recipient without variables
+    #[must_use]
+    pub fn new(number: impl Into<String>) -> Self {
+        Self {
+            number: number.into(),
+            variables: HashMap::new(),
+        }
+    }
+
+    /// Bind a template placeholder for this recipient
+    #[must_use]
+    pub fn with_variable(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Outcome of a bulk send for one recipient
+pub struct RecipientResult {
+    pub number: String,
+    /// Message ID on success
+    pub result: Result<String, SmsError>,
+}
+
+impl std::fmt::Debug for RecipientResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecipientResult")
+            .field("number", &redact::number(&self.number))
+            .field("result", &self.result)
+            .finish()
+    }
+}
+
+/// Per-recipient outcomes of a bulk send, in input order
+#[derive(Debug, Default)]
+pub struct BulkReport {
+    pub results: Vec<RecipientResult>,
+    /// Whether the batch was cancelled before every recipient was sent to
+    pub cancelled: bool,
+    /// Recipients taken from the input but not sent because the batch was
+    /// cancelled; recipients after them are left in the input
+    pub skipped: usize,
+}
+
+impl BulkReport {
+    /// Recipients the message was sent to, with their message IDs
+    pub fn succeeded(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.results.iter().filter_map(|r| match &r.result {
+            Ok(message_id) => Some((r.number.as_str(), message_id.as_str())),
+            Err(_) => None,
+        })
+    }
+
+    /// Recipients the message could not be sent to, with the reason
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &SmsError)> {
+        self.results.iter().filter_map(|r| match &r.result {
+            Ok(_) => None,
+            Err(err) => Some((r.number.as_str(), err)),
+        })
+    }
+
+    /// Whether the message was sent to every recipient
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        !self.cancelled && self.results.iter().all(|r| r.result.is_ok())
+    }
+}
+
+/// Outcome counts of a bulk send whose results were passed to a callback
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BulkSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Whether the batch was cancelled before every recipient was sent to
+    pub cancelled: bool,
+    /// Recipients taken from the input but not sent because the batch was
+    /// cancelled
+    pub skipped: usize,
+}
+
+impl BulkSummary {
+    /// Whether the message was sent to every recipient
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        !self.cancelled && self.failed == 0
+    }
+}
+
+impl BulkReport {
+    fn new(results: Vec<RecipientResult>, summary: BulkSummary) -> Self {
+        Self {
+            results,
+            cancelled: summary.cancelled,
+            skipped: summary.skipped,
+        }
+    }
+}
+
+impl SmsClient {
+    /// Send the same message to many recipients
+    ///
+    /// `base` supplies the credentials, sender, text and options; its number is
+    /// replaced by each recipient's. Up to the configured bulk concurrency (see
+    /// [`crate::SmsClientBuilder::with_bulk_concurrency`]) requests are in flight
+    /// at once, taking recipients from `numbers` as they go. Failures are
+    /// reported per recipient and don't stop the batch. An idempotency key on
+    /// `base` is combined with each recipient's number.
+    ///
+    /// The report keeps the outcome of every recipient; for large inputs, see
+    /// [`SmsClient::send_bulk_with_progress`], which passes them on instead.
+    pub async fn send_bulk<I>(&self, base: SmsRequest<'_>, numbers: I) -> BulkReport
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.send_bulk_with_cancel(base, numbers, future::pending())
+            .await
+    }
+
+    /// Send the same message to many recipients until `cancel` completes
+    ///
+    /// Once `cancel` completes, requests in flight are finished and no more
+    /// recipients are taken from `numbers`, so the report is
+    /// [cancelled](BulkReport::cancelled) and a batch can be resumed by
+    /// passing `numbers.by_ref()`.
+    pub async fn send_bulk_with_cancel<I>(
+        &self,
+        base: SmsRequest<'_>,
+        numbers: I,
+        cancel: impl Future<Output = ()>,
+    ) -> BulkReport
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let mut results = Vec::new();
+        let summary = self
+            .send_bulk_with_progress(base, numbers, cancel, |result| results.push(result))
+            .await;
+        BulkReport::new(results, summary)
+    }
+
+    /// Send the same message to many recipients, passing each outcome to
+    /// `on_result` instead of keeping it
+    ///
+    /// Works like [`SmsClient::send_bulk_with_cancel`]; `on_result` is called
+    /// in input order as soon as each recipient's outcome is known, so
+    /// results can be persisted while the rest of the batch is still
+    /// sending, and memory doesn't grow with the number of recipients.
+    pub async fn send_bulk_with_progress<I>(
+        &self,
+        base: SmsRequest<'_>,
+        numbers: I,
+        cancel: impl Future<Output = ()>,
+        on_result: impl FnMut(RecipientResult),
+    ) -> BulkSummary
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let jobs = numbers.into_iter().map(|number| {
+            let number = number.into();
+            let text = Ok(base.text.clone());
+            (number, text)
+        });
+        self.dispatch(&base, jobs, cancel, on_result).await
+    }
+
+    /// Send a template rendered individually for each recipient
+    ///
+    /// Works like [`SmsClient::send_bulk`], except that the text of `base` is
+    /// replaced by `template` rendered with the recipient's variables.
+    /// Recipients with unbound placeholders fail with `SmsError::Template`
+    /// without sending.
+    pub async fn send_personalized<I>(
+        &self,
+        base: SmsRequest<'_>,
+        template: &SmsTemplate,
+        recipients: I,
+    ) -> BulkReport
+    where
+        I: IntoIterator<Item = Recipient>,
+    {
+        self.send_personalized_with_cancel(base, template, recipients, future::pending())
+            .await
+    }
+
+    /// Send a personalized template until `cancel` completes
+    ///
+    /// Cancellation works as for [`SmsClient::send_bulk_with_cancel`].
+    pub async fn send_personalized_with_cancel<I>(
+        &self,
+        base: SmsRequest<'_>,
+        template: &SmsTemplate,
+        recipients: I,
+        cancel: impl Future<Output = ()>,
+    ) -> BulkReport
+    where
+        I: IntoIterator<Item = Recipient>,
+    {
+        let mut results = Vec::new();
+        let summary = self
+            .send_personalized_with_progress(base, template, recipients, cancel, |result| {
+                results.push(result);
+            })
+            .await;
+        BulkReport::new(results, summary)
+    }
+
+    /// Send a personalized template, passing each outcome to `on_result`
+    /// instead of keeping it
+    ///
+    /// Works like [`SmsClient::send_personalized_with_cancel`], with
+    /// outcomes passed on as for [`SmsClient::send_bulk_with_progress`].
+    pub async fn send_personalized_with_progress<I>(
+        &self,
+        base: SmsRequest<'_>,
+        template: &SmsTemplate,
+        recipients: I,
+        cancel: impl Future<Output = ()>,
+        on_result: impl FnMut(RecipientResult),
+    ) -> BulkSummary
+    where
+        I: IntoIterator<Item = Recipient>,
+    {
+        let jobs = recipients.into_iter().map(|recipient| {
+            let text = template
+                .render(&recipient.variables)
+                .map(Cow::Owned)
+                .map_err(SmsError::from);
+            (recipient.number, text)
+        });
+        self.dispatch(&base, jobs, cancel, on_result).await
+    }
+
+    /// Send to one recipient of a batch, unless it was cancelled meanwhile
+    async fn send_job(
+        &self,
+        base: &SmsRequest<'_>,
+        cancelled: &AtomicBool,
+        number: String,
+        text: Result<Cow<'_, str>, SmsError>,
+    ) -> Option<RecipientResult> {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let result = match text {
+            Ok(text) => {
+                let request = SmsRequest {
+                    number: Cow::Borrowed(number.as_str()),
+                    text,
+                    idempotency_key: base
+                        .idempotency_key
+                        .as_ref()
+                        .map(|key| Cow::Owned(format!("{key}:{number}"))),
+                    ..base.as_borrowed()
+                };
+                self.send_sms(request).await
+            }
+            Err(err) => Err(err),
+        };
+        Some(RecipientResult { number, result })
+    }
+
+    async fn dispatch<'t, I>(
+        &self,
+        base: &SmsRequest<'_>,
+        jobs: I,
+        cancel: impl Future<Output = ()>,
+        mut on_result: impl FnMut(RecipientResult),
+    ) -> BulkSummary
+    where
+        I: Iterator<Item = (String, Result<Cow<'t, str>, SmsError>)>,
+    {
+        let cancelled = AtomicBool::new(false);
+        let cancelled = &cancelled;
+        // Check before taking a job, so none is lost once cancelled. Jobs
+        // are started by a method rather than a closure taking the job, which
+        // the compiler couldn't prove `Send` for, e.g. under `tokio::spawn`
+        let mut jobs = jobs;
+        let sends = std::iter::from_fn(|| {
+            if cancelled.load(Ordering::Relaxed) {
+                return None;
+            }
+            let (number, text) = jobs.next()?;
+            Some(self.send_job(base, cancelled, number, text))
+        });
+
+        let mut sends = pin!(stream::iter(sends).buffered(self.bulk_concurrency));
+        let mut cancel = pin!(cancel);
+        let mut cancel_pending = true;
+        let mut summary = BulkSummary::default();
+        loop {
+            let next = if cancel_pending {
+                match future::select(sends.next(), cancel.as_mut()).await {
+                    Either::Left((next, _)) => next,
+                    Either::Right(((), _)) => {
+                        cancelled.store(true, Ordering::Relaxed);
+                        summary.cancelled = true;
+                        cancel_pending = false;
+                        continue;
+                    }
+                }
+            } else {
+                sends.next().await
+            };
+            match next {
+                Some(Some(result)) => {
+                    if result.result.is_ok() {
+                        summary.succeeded += 1;
+                    } else {
+                        summary.failed += 1;
+                    }
+                    on_result(result);
+                }
+                Some(None) => summary.skipped += 1,
+                None => break,
+            }
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn cancelled_batch_finishes_in_flight_sends() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200)
+                .body("1001")
+                .delay(std::time::Duration::from_millis(200));
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let base = SmsRequest::new("k", "Alice", "", "Hi");
+        let mut numbers = ["+37061234567", "+37061234568", "+37061234569"].into_iter();
+        let cancel = tokio::time::sleep(std::time::Duration::from_millis(50));
+        let report = client
+            .send_bulk_with_cancel(base, numbers.by_ref(), cancel)
+            .await;
+
+        assert_eq!(report.succeeded().count(), 1);
+        assert!(report.cancelled);
+        assert!(!report.is_success());
+        assert_eq!(
+            numbers.collect::<Vec<_>>(),
+            ["+37061234568", "+37061234569"]
+        );
+        m.assert_calls(1);
+
+        // An endless input is not drained once cancelled
+        let endless = std::iter::repeat("+37061234567");
+        let cancel = tokio::time::sleep(std::time::Duration::from_millis(50));
+        let base = SmsRequest::new("k", "Alice", "", "Hi");
+        let report = client.send_bulk_with_cancel(base, endless, cancel).await;
+        assert_eq!(report.results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn send_personalized_reports_per_recipient() {
+        let server = MockServer::start();
+        let ona = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567")
+                .query_param("text", "Hi Ona, code 1111");
+            then.status(200).body("1001");
+        });
+        let jonas = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234568");
+            then.status(200).body("7");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_bulk_concurrency(2)
+            .build()
+            .unwrap();
+        let template = SmsTemplate::new("Hi {name}, code {code}").unwrap();
+        let recipients = vec![
+            Recipient::new("+37061234567")
+                .with_variable("name", "Ona")
+                .with_variable("code", "1111"),
+            Recipient::new("+37061234568")
+                .with_variable("name", "Jonas")
+                .with_variable("code", "2222"),
+            Recipient::new("+37061234569").with_variable("name", "Petras"),
+        ];
+
+        let base = SmsRequest::new("k", "Alice", "", "");
+        let report = client.send_personalized(base, &template, recipients).await;
+
+        assert!(!report.is_success());
+        assert_eq!(
+            report.succeeded().collect::<Vec<_>>(),
+            vec![("+37061234567", "1001")]
+        );
+        let failed: Vec<_> = report.failed().collect();
+        assert_eq!(failed.len(), 2);
+        assert!(matches!(failed[0].1, SmsError::SendFailed { .. }));
+        assert!(matches!(failed[1].1, SmsError::Template(_)));
+        ona.assert();
+        jonas.assert();
+    }
+
+    #[tokio::test]
+    async fn send_personalized_reports_progress_in_order() {
+        let server = MockServer::start();
+        let slow = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200)
+                .body("1001")
+                .delay(std::time::Duration::from_millis(100));
+        });
+        let fast = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234568");
+            then.status(200).body("1002");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_bulk_concurrency(2)
+            .build()
+            .unwrap();
+        let template = SmsTemplate::new("Hi").unwrap();
+        let recipients = [
+            Recipient::new("+37061234567"),
+            Recipient::new("+37061234568"),
+        ];
+        let mut seen = Vec::new();
+        let summary = client
+            .send_personalized_with_progress(
+                SmsRequest::new("k", "Alice", "", ""),
+                &template,
+                recipients,
+                future::pending(),
+                |recipient| seen.push(recipient.result.unwrap()),
+            )
+            .await;
+
+        assert!(summary.is_success());
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(seen, ["1001", "1002"]);
+        slow.assert();
+        fast.assert();
+    }
+
+    #[tokio::test]
+    async fn send_bulk_uses_base_text() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send").query_param("text", "Sale!");
+            then.status(200).body("1234");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let base = SmsRequest::new("k", "Alice", "", "Sale!");
+        let report = client
+            .send_bulk(base, ["+37061234567", "+37061234568"])
+            .await;
+
+        assert!(report.is_success());
+        assert_eq!(report.results.len(), 2);
+        m.assert_calls(2);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::bulk::{BulkReport, Recipient, RecipientResult};
+use crate::esteria::{SmsClient, SmsError, SmsRequest, SmsRequestOwned};
+use crate::optout::registry_key;
+use crate::rate_limit::{Rate, RateLimit, RateLimiter};
+use crate::signing::sha256;
+use crate::template::SmsTemplate;
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// Variant name of the template given to [`Campaign::new`] in an A/B test
+pub const CONTROL_VARIANT: &str = "control";
+
+/// Lifecycle state of a [`Campaign`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CampaignState {
+    /// Not started yet
+    Pending,
+    /// Started, waiting for the scheduled start time
+    Scheduled,
+    /// Sending
+    Running,
+    /// Paused before the next recipient
+    Paused,
+    /// Every recipient was processed
+    Finished,
+}
+
+/// Snapshot of a campaign's progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CampaignProgress {
+    pub state: CampaignState,
+    pub total: usize,
+    pub sent: usize,
+    pub failed: usize,
+}
+
+impl CampaignProgress {
+    /// Recipients not processed yet
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.total - self.sent - self.failed
+    }
+}
+
+/// Personalized send to a list of recipients, with a schedule and a rate limit
+///
+/// Recipients are sent to one at a time, in order, after the optional start
+/// time. The campaign's rate applies on top of the client's own limits.
+/// [`Campaign::pause`] and [`Campaign::resume`] take `&self`, so a campaign
+/// shared between tasks can be controlled while [`Campaign::start`] runs.
+pub struct Campaign {
+    base: SmsRequestOwned,
+    template: SmsTemplate,
+    variants: Vec<(String, SmsTemplate, u8)>,
+    recipients: Vec<Recipient>,
+    start_at: Option<DateTime<Utc>>,
+    limiter: Option<RateLimiter>,
+    drip: Option<Duration>,
+    phase: Mutex<CampaignState>,
+    paused: watch::Sender<bool>,
+    sent: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl std::fmt::Debug for Campaign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Campaign")
+            .field("template", &self.template)
+            .field("variants", &self.variants)
+            .field("recipients", &self.recipients.len())
+            .field("start_at", &self.start_at)
+            .field("progress", &self.progress())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Campaign {
+    /// Send `template` to `recipients`, with credentials, sender and options from `base`
+    ///
+    /// The text of `base` is replaced by the rendered template and its number
+    /// by each recipient's, as for [`SmsClient::send_personalized`].
+    pub fn new(
+        base: SmsRequest<'_>,
+        template: SmsTemplate,
+        recipients: impl IntoIterator<Item = Recipient>,
+    ) -> Self {
+        Self {
+            base: base.into_owned(),
+            template,
+            variants: Vec::new(),
+            recipients: recipients.into_iter().collect(),
+            start_at: None,
+            limiter: None,
+            drip: None,
+            phase: Mutex::new(CampaignState::Pending),
+            paused: watch::Sender::new(false),
+            sent: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Don't send before `at`
+    #[must_use]
+    pub fn with_schedule(mut self, at: DateTime<Utc>) -> Self {
+        self.start_at = Some(at);
+        self
+    }
+
+    /// Send at most at `rate`
+    #[must_use]
+    pub fn with_rate(mut self, rate: Rate) -> Self {
+        self.limiter = Some(RateLimiter::new(
+            RateLimit::default().with_default_sender(rate),
+        ));
+        self
+    }
+
+    /// Send `template` instead to `percent` percent of the recipients
+    ///
+    /// Each recipient is assigned a variant by a hash of their number, so
+    /// reruns and resends pick the same one; recipients not assigned to any
+    /// variant get the [`CONTROL_VARIANT`]. The variant name is appended to
+    /// the request's user key as `{user_key}:{name}`, or is the user key if
+    /// there is none, so delivery reports can be compared per variant.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the variants add up to more than 100 percent
+    #[must_use]
+    pub fn with_variant(
+        mut self,
+        name: impl Into<String>,
+        template: SmsTemplate,
+        percent: u8,
+    ) -> Self {
+        let total: u32 = self
+            .variants
+            .iter()
+            .map(|(_, _, percent)| u32::from(*percent))
+            .sum();
+        assert!(
+            total + u32::from(percent) <= 100,
+            "campaign variants exceed 100 percent"
+        );
+        self.variants.push((name.into(), template, percent));
+        self
+    }
+
+    /// Spread the sends evenly over `window` instead of sending as fast as allowed
+    ///
+    /// Recipient `i` of `n` is sent `window * i / n` after the start, plus any
+    /// time spent paused, so replies arrive at a steady pace. The rate limit
+    /// still applies.
+    #[must_use]
+    pub fn with_drip(mut self, window: Duration) -> Self {
+        self.drip = Some(window);
+        self
+    }
+
+    /// Stop before the next recipient until [`Campaign::resume`]
+    ///
+    /// Pausing a campaign that hasn't started makes it wait once started.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    /// Continue a paused campaign
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    /// Current state and counts
+    #[must_use]
+    pub fn progress(&self) -> CampaignProgress {
+        let phase = *self.phase.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = match phase {
+            CampaignState::Finished => phase,
+            _ if *self.paused.borrow() => CampaignState::Paused,
+            _ => phase,
+        };
+        CampaignProgress {
+            state,
+            total: self.recipients.len(),
+            sent: self.sent.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Run the campaign to the end and report the outcome per recipient
+    ///
+    /// # Panics
+    ///
+    /// Panics if the campaign was already started
+    pub async fn start(&self, client: &SmsClient) -> BulkReport {
+        {
+            let mut phase = self.phase.lock().unwrap_or_else(PoisonError::into_inner);
+            assert_eq!(
+                *phase,
+                CampaignState::Pending,
+                "campaign was already started"
+            );
+            *phase = CampaignState::Scheduled;
+        }
+        if let Some(at) = self.start_at
+            && let Ok(delay) = (at - Utc::now()).to_std()
+        {
+            tokio::time::sleep(delay).await;
+        }
+        self.set_phase(CampaignState::Running);
+
+        let mut paused = self.paused.subscribe();
+        let mut origin = Instant::now();
+        let mut results = Vec::with_capacity(self.recipients.len());
+        for (index, recipient) in self.recipients.iter().enumerate() {
+            if let Some(window) = self.drip {
+                let offset = window.mul_f64(index as f64 / self.recipients.len() as f64);
+                tokio::time::sleep_until(origin + offset).await;
+            }
+            if *paused.borrow_and_update() {
+                let since = Instant::now();
+                // The sender is dropped with the campaign only, so this can't fail
+                let _ = paused.wait_for(|paused| !paused).await;
+                origin += since.elapsed();
+            }
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire(&recipient.number, &self.base.sender).await;
+            }
+            let result = self.send(client, recipient).await;
+            let counter = if result.is_ok() {
+                &self.sent
+            } else {
+                &self.failed
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            results.push(RecipientResult {
+                number: recipient.number.clone(),
+                result,
+            });
+        }
+
+        self.set_phase(CampaignState::Finished);
+        BulkReport {
+            results,
+            ..BulkReport::default()
+        }
+    }
+
+    /// Name of the variant sent to `number`
+    #[must_use]
+    pub fn variant(&self, number: &str) -> &str {
+        self.assign(number).0
+    }
+
+    fn assign(&self, number: &str) -> (&str, &SmsTemplate) {
+        let digest = sha256(registry_key(number).as_bytes());
+        let mut bucket = u64::from_be_bytes(digest[..8].try_into().expect("8 bytes")) % 100;
+        for (name, template, percent) in &self.variants {
+            match bucket.checked_sub(u64::from(*percent)) {
+                Some(rest) => bucket = rest,
+                None => return (name, template),
+            }
+        }
+        (CONTROL_VARIANT, &self.template)
+    }
+
+    async fn send(&self, client: &SmsClient, recipient: &Recipient) -> Result<String, SmsError> {
+        let (variant, template) = self.assign(&recipient.number);
+        let text = template.render(&recipient.variables)?;
+        let user_key = if self.variants.is_empty() {
+            self.base.user_key.as_deref().map(Cow::Borrowed)
+        } else {
+            Some(Cow::Owned(match &self.base.user_key {
+                Some(key) => format!("{key}:{variant}"),
+                None => variant.to_string(),
+            }))
+        };
+        let request = SmsRequest {
+            number: Cow::Borrowed(recipient.number.as_str()),
+            text: Cow::Owned(text),
+            user_key,
+            idempotency_key: self
+                .base
+                .idempotency_key
+                .as_ref()
+                .map(|key| Cow::Owned(format!("{key}:{}", recipient.number))),
+            ..self.base.as_borrowed()
+        };
+        client.send_sms(request).await
+    }
+
+    fn set_phase(&self, state: CampaignState) {
+        *self.phase.lock().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn pauses_and_resumes() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+
+        let base = SmsRequest::new("k", "Shop", "", "");
+        let template = SmsTemplate::new("Hi {name}").unwrap();
+        let recipients = [
+            Recipient::new("+37061234567").with_variable("name", "Ann"),
+            Recipient::new("+37061234568"),
+        ];
+        let campaign = Campaign::new(base, template, recipients).with_rate(Rate::per_second(50.0));
+        campaign.pause();
+        assert_eq!(campaign.progress().state, CampaignState::Paused);
+
+        let (report, ()) = tokio::join!(campaign.start(&client), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(campaign.progress().sent, 0);
+            campaign.resume();
+        });
+
+        m.assert_calls(1);
+        let progress = campaign.progress();
+        assert_eq!(progress.state, CampaignState::Finished);
+        assert_eq!((progress.sent, progress.failed), (1, 1));
+        assert_eq!(progress.remaining(), 0);
+        assert!(matches!(
+            report.failed().next(),
+            Some(("+37061234568", SmsError::Template(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn drips_over_window() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+
+        let recipients = (0..3).map(|i| Recipient::new(format!("+3706123456{i}")));
+        let campaign = Campaign::new(
+            SmsRequest::new("k", "Shop", "", ""),
+            SmsTemplate::new("Hi").unwrap(),
+            recipients,
+        )
+        .with_drip(Duration::from_millis(300));
+
+        let started = std::time::Instant::now();
+        let report = campaign.start(&client).await;
+        assert!(report.is_success());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn splits_variants_by_number() {
+        let server = MockServer::start();
+        let b = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "B")
+                .query_param("user-key", "spring:b");
+            then.status(200).body("1001");
+        });
+        let control = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "A")
+                .query_param("user-key", "spring:control");
+            then.status(200).body("1002");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+
+        let recipients: Vec<_> = (0..40)
+            .map(|i| Recipient::new(format!("+370612345{i:02}")))
+            .collect();
+        let campaign = Campaign::new(
+            SmsRequest::new("k", "Shop", "", "").with_user_key("spring"),
+            SmsTemplate::new("A").unwrap(),
+            recipients.clone(),
+        )
+        .with_variant("b", SmsTemplate::new("B").unwrap(), 50);
+
+        let report = campaign.start(&client).await;
+        assert!(report.is_success());
+        let in_b = recipients
+            .iter()
+            .filter(|r| campaign.variant(&r.number) == "b")
+            .count();
+        assert!((10..=30).contains(&in_b));
+        b.assert_calls(in_b);
+        control.assert_calls(40 - in_b);
+        assert_eq!(
+            campaign.variant("370 612 34500"),
+            campaign.variant("+37061234500")
+        );
+    }
+}
@@ -1,32 +1,141 @@
-use crate::esteria::{Encoding, SmsClient, SmsFlags, SmsRequest};
+use crate::bulk::RecipientResult;
+use crate::config::{self, ConfigError, ConfigLoader, SmsClientConfig};
+use crate::csv::{self, CsvError, RecipientReader};
+use crate::esteria::{Encoding, SmsClient, SmsError, SmsFlags, SmsRequest};
+use crate::rate_limit::Rate;
+use crate::template::SmsTemplate;
 use chrono::{DateTime, Utc};
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Exit code for configuration errors, e.g. a missing API key
+pub const EXIT_CONFIG: u8 = 3;
+/// Exit code for requests rejected before sending, e.g. an invalid number
+pub const EXIT_INVALID: u8 = 4;
+/// Exit code for recipients that opted out or are blocked
+pub const EXIT_BLOCKED: u8 = 5;
+/// Exit code for HTTP failures and timeouts
+pub const EXIT_TRANSPORT: u8 = 6;
+/// Exit code for gateway responses that aren't a message ID or known code
+pub const EXIT_UNKNOWN_RESPONSE: u8 = 7;
+/// Exit code for bulk sends that failed for some recipients
+pub const EXIT_PARTIAL: u8 = 8;
+/// Gateway error codes are reported as this plus the code, e.g. 103 for code 3
+pub const EXIT_GATEWAY_BASE: u8 = 100;
 
 #[derive(Parser, Debug)]
-#[command(name = "esteria-api-client")]
+#[command(name = "esteria")]
 #[command(author, version, about = "Send SMS via Esteria API", long_about = None)]
-#[allow(clippy::struct_excessive_bools)]
 struct Cli {
+    /// Configuration file [default: esteria.toml, if present]
+    #[arg(short = 'c', long, global = true, env = "ESTERIA_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Configuration profile, e.g. sandbox
+    #[arg(short = 'p', long, global = true, env = "ESTERIA_PROFILE")]
+    profile: Option<String>,
+
     /// API base URL (e.g., <https://api.esteria.eu>)
-    #[arg(short = 'u', long, env = "ESTERIA_API_BASE_URL")]
-    api_url: String,
+    #[arg(short = 'u', long, global = true)]
+    api_url: Option<String>,
 
     /// API key for authentication
-    #[arg(short = 'k', long, env = "ESTERIA_API_KEY")]
-    api_key: String,
+    #[arg(short = 'k', long, global = true)]
+    api_key: Option<String>,
+
+    /// Output format of results
+    #[arg(short = 'o', long, global = true, value_enum, default_value_t)]
+    output: OutputFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// Message IDs, and a results CSV for bulk sends
+    #[default]
+    Human,
+    /// One JSON object per message (NDJSON for bulk sends)
+    Json,
+}
 
-    /// Sender name or number
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send one SMS and print its message ID
+    Send(SendArgs),
+    /// Send a template to every recipient of a CSV file and write a results CSV
+    Bulk(BulkArgs),
+    /// Serve a REST API sending through the configured gateway account
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+}
+
+#[cfg(feature = "server")]
+#[derive(Args, Debug)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(short = 'l', long, default_value = "127.0.0.1:8080")]
+    listen: std::net::SocketAddr,
+}
+
+#[derive(Args, Debug)]
+struct BulkArgs {
+    /// Recipients CSV with a header row; columns other than the number are template variables
+    #[arg(short = 'f', long)]
+    file: PathBuf,
+
+    /// File with the message template, e.g. "Hi {name}"
+    #[arg(long)]
+    template: PathBuf,
+
+    /// CSV column holding the phone numbers
+    #[arg(long, default_value = "number")]
+    number_column: String,
+
+    /// Sender name or number [default: the configured default sender]
     #[arg(short = 's', long)]
-    sender: String,
+    sender: Option<String>,
 
+    /// Messages per second for the batch [default: the configured rate limit]
+    #[arg(long)]
+    rate: Option<f64>,
+
+    /// Requests in flight at once
+    #[arg(long, default_value_t = 1)]
+    concurrency: usize,
+
+    /// Results file with a message ID or error per recipient [default: stdout]
+    #[arg(long)]
+    results: Option<PathBuf>,
+
+    /// Test mode (don't actually send)
+    #[arg(long)]
+    test: bool,
+}
+
+#[derive(Args, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+struct SendArgs {
     /// Recipient phone number (with or without +)
-    #[arg(short = 'n', long)]
-    number: String,
+    #[arg(short = 'n', long, visible_alias = "number")]
+    to: String,
 
     /// Message text to send
     #[arg(short = 't', long)]
     text: String,
 
+    /// Sender name or number [default: the configured default sender]
+    #[arg(short = 's', long)]
+    sender: Option<String>,
+
     /// Schedule time (RFC3339 format, e.g., 2024-12-31T23:59:59Z)
     #[arg(long)]
     time: Option<String>,
@@ -67,93 +176,371 @@ struct Cli {
     #[arg(long)]
     convert: bool,
 
-    /// Encoding: default, 8bit, or udh
+    /// Encoding: default, 8bit, udh, auto, or ucs2
     #[arg(long, default_value = "default")]
     encoding: String,
 }
 
-/// Run the CLI application
-///
-/// # Errors
-///
-/// Returns an error if:
-/// - Invalid timestamp format is provided
-/// - SMS sending fails (API error or network issue)
-/// - Any other IO or parsing error occurs
-pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
-    env_logger::init();
+impl SendArgs {
+    fn flags(&self) -> SmsFlags {
+        let mut flags = SmsFlags::empty();
+        for (set, flag) in [
+            (self.debug, SmsFlags::DEBUG),
+            (self.nolog, SmsFlags::NOLOG),
+            (self.flash, SmsFlags::FLASH),
+            (self.test, SmsFlags::TEST),
+            (self.nobl, SmsFlags::NOBL),
+            (self.convert, SmsFlags::CONVERT),
+        ] {
+            flags.set(flag, set);
+        }
+        flags
+    }
 
-    let cli = Cli::parse();
+    fn encoding(&self) -> Result<Encoding, String> {
+        match self.encoding.to_lowercase().as_str() {
+            "default" => Ok(Encoding::Default),
+            "8bit" => Ok(Encoding::EightBit),
+            "udh" => Ok(Encoding::Udh),
+            "auto" => Ok(Encoding::Auto),
+            "ucs2" => Ok(Encoding::Ucs2),
+            _ => Err(format!("invalid encoding '{}'", self.encoding)),
+        }
+    }
+}
+
+/// Exit code reported for a failed send
+#[must_use]
+pub fn exit_code(err: &SmsError) -> u8 {
+    match err {
+        SmsError::SendFailed {
+            code: Some(code), ..
+        } => u8::try_from(*code)
+            .ok()
+            .and_then(|code| EXIT_GATEWAY_BASE.checked_add(code))
+            .unwrap_or(EXIT_UNKNOWN_RESPONSE),
+        SmsError::SendFailed { code: None, .. } | SmsError::UnknownResponse { .. } => {
+            EXIT_UNKNOWN_RESPONSE
+        }
+        SmsError::RequestFailed(_)
+        | SmsError::HttpStatus { .. }
+        | SmsError::Timeout { .. }
+        | SmsError::Cancelled { .. }
+        | SmsError::InProgress { .. }
+        | SmsError::Uncertain { .. } => EXIT_TRANSPORT,
+        SmsError::InvalidNumber { .. } | SmsError::InvalidSender { .. } | SmsError::Template(_) => {
+            EXIT_INVALID
+        }
+        SmsError::OptedOut { .. } | SmsError::Blocked { .. } => EXIT_BLOCKED,
+        SmsError::Tls { .. } | SmsError::Credentials(_) => EXIT_CONFIG,
+    }
+}
 
-    let client = SmsClient::with_api_base_url(cli.api_url);
+/// Configuration from the file, profile, environment and command line, in
+/// increasing precedence
+fn load_config(cli: &Cli) -> Result<SmsClientConfig, ConfigError> {
+    let path = cli
+        .config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(config::DEFAULT_CONFIG_PATH));
+    let file_required = cli.config.is_some() || cli.profile.is_some();
 
-    let mut flags = SmsFlags::empty();
-    if cli.debug {
-        flags |= SmsFlags::DEBUG;
+    let mut loader = ConfigLoader::new();
+    if file_required || Path::new(&path).exists() {
+        loader = loader.with_file(&path, cli.profile.as_deref())?;
     }
-    if cli.nolog {
-        flags |= SmsFlags::NOLOG;
+    let args = SmsClientConfig {
+        base_url: cli.api_url.clone(),
+        api_key: cli.api_key.clone(),
+        ..SmsClientConfig::default()
+    };
+    Ok(loader.with_env()?.finish()?.merge(args))
+}
+
+fn fail(message: impl Display, code: u8) -> ExitCode {
+    eprintln!("✗ {message}");
+    ExitCode::from(code)
+}
+
+/// Sender to use, after checking that an API key is configured
+fn sender<'a>(config: &'a SmsClientConfig, arg: Option<&'a str>) -> Result<&'a str, ExitCode> {
+    if config.api_key.is_none() {
+        return Err(fail(
+            "no API key; pass --api-key, set ESTERIA_API_KEY or add api_key to the configuration",
+            EXIT_CONFIG,
+        ));
     }
-    if cli.flash {
-        flags |= SmsFlags::FLASH;
+    arg.or(config.default_sender.as_deref()).ok_or_else(|| {
+        fail(
+            "no sender; pass --sender or configure default_sender",
+            EXIT_CONFIG,
+        )
+    })
+}
+
+/// JSON result of one message: number, message ID, gateway code, error and timing
+fn result_json(
+    number: &str,
+    result: &Result<String, SmsError>,
+    elapsed: Option<Duration>,
+) -> serde_json::Value {
+    let mut value = match result {
+        Ok(message_id) => json!({
+            "number": number,
+            "message_id": message_id.trim(),
+            "code": null,
+            "error": null,
+        }),
+        Err(err) => json!({
+            "number": number,
+            "message_id": null,
+            "code": match err {
+                SmsError::SendFailed { code, .. } => *code,
+                _ => None,
+            },
+            "error": err,
+        }),
+    };
+    if let Some(elapsed) = elapsed {
+        value["elapsed_ms"] = json!(elapsed.as_millis());
+    }
+    value
+}
+
+async fn send(
+    config: &SmsClientConfig,
+    args: &SendArgs,
+) -> Result<(Result<String, SmsError>, Duration), ExitCode> {
+    let sender = sender(config, args.sender.as_deref())?;
+    let encoding = args
+        .encoding()
+        .map_err(|message| fail(message, EXIT_INVALID))?;
+    let time = args
+        .time
+        .as_deref()
+        .map(|time| {
+            DateTime::parse_from_rfc3339(time)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|err| fail(format!("invalid --time: {err}"), EXIT_INVALID))
+        })
+        .transpose()?;
+
+    let client = config
+        .builder()
+        .build()
+        .map_err(|err| fail(&err, exit_code(&err)))?;
+
+    let mut request = SmsRequest::new("", sender, &args.to, &args.text)
+        .with_flags(args.flags())
+        .with_encoding(encoding);
+    if let Some(time) = time {
+        request = request.with_time(time);
     }
-    if cli.test {
-        flags |= SmsFlags::TEST;
+    if let Some(url) = args.dlr_url.as_deref() {
+        request = request.with_dlr_url(url);
     }
-    if cli.nobl {
-        flags |= SmsFlags::NOBL;
+    if let Some(expired) = args.expired {
+        request = request.with_expired(expired);
     }
-    if cli.convert {
-        flags |= SmsFlags::CONVERT;
+    if let Some(key) = args.user_key.as_deref() {
+        request = request.with_user_key(key);
     }
 
-    let encoding = match cli.encoding.to_lowercase().as_str() {
-        "default" => Encoding::Default,
-        "8bit" => Encoding::EightBit,
-        "udh" => Encoding::Udh,
-        _ => {
-            eprintln!("Invalid encoding '{}'. Using 8bit.", cli.encoding);
-            Encoding::EightBit
-        }
-    };
+    let started = Instant::now();
+    let result = client.send_sms(request).await;
+    Ok((result, started.elapsed()))
+}
 
-    let time = if let Some(time_str) = cli.time {
-        Some(DateTime::parse_from_rfc3339(&time_str)?.with_timezone(&Utc))
+/// Write one bulk outcome and flush it, so results survive an interrupted batch
+fn write_result(
+    recipient: &RecipientResult,
+    format: OutputFormat,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    if format == OutputFormat::Json {
+        writeln!(
+            out,
+            "{}",
+            result_json(&recipient.number, &recipient.result, None)
+        )?;
     } else {
-        None
+        let (message_id, error) = match &recipient.result {
+            Ok(message_id) => (message_id.trim().to_string(), String::new()),
+            Err(err) => (String::new(), err.to_string()),
+        };
+        csv::write_record(out, &[&recipient.number, &message_id, &error])?;
+    }
+    out.flush()
+}
+
+async fn bulk(
+    config: &SmsClientConfig,
+    args: &BulkArgs,
+    format: OutputFormat,
+) -> Result<(), ExitCode> {
+    let sender = sender(config, args.sender.as_deref())?;
+    let template = std::fs::read_to_string(&args.template)
+        .map_err(|err| fail(format!("failed to read template: {err}"), EXIT_CONFIG))?;
+    let template = SmsTemplate::new(template.trim_end_matches(['\r', '\n']))
+        .map_err(|err| fail(format!("invalid template: {err}"), EXIT_INVALID))?;
+    let file = File::open(&args.file).map_err(|err| {
+        fail(
+            format!("failed to open {}: {err}", args.file.display()),
+            EXIT_CONFIG,
+        )
+    })?;
+    let recipients = RecipientReader::new(BufReader::new(file), &args.number_column)
+        .map_err(|err| fail(err, EXIT_INVALID))?;
+    let mut results: Box<dyn Write> = match &args.results {
+        Some(path) => Box::new(File::create(path).map_err(|err| {
+            fail(
+                format!("failed to create {}: {err}", path.display()),
+                EXIT_CONFIG,
+            )
+        })?),
+        None => Box::new(io::stdout().lock()),
     };
 
-    let mut request = SmsRequest::new(&cli.api_key, &cli.sender, &cli.number, &cli.text)
-        .with_flags(flags)
-        .with_encoding(encoding);
+    let mut builder = config.builder().with_bulk_concurrency(args.concurrency);
+    if let Some(rate) = args.rate {
+        if !(rate > 0.0 && rate.is_finite()) {
+            return Err(fail("--rate must be positive", EXIT_INVALID));
+        }
+        let limits = config.rate_limit.clone().unwrap_or_default();
+        builder = builder.with_rate_limit(limits.with_sender(sender, Rate::per_second(rate)));
+    }
+    let client: SmsClient = builder.build().map_err(|err| fail(&err, exit_code(&err)))?;
 
-    if let Some(t) = time {
-        request = request.with_time(t);
+    // A malformed row ends the batch; rows before it are still sent and reported
+    let malformed: RefCell<Option<CsvError>> = RefCell::new(None);
+    let recipients = recipients.map_while(|recipient| {
+        recipient
+            .map_err(|err| *malformed.borrow_mut() = Some(err))
+            .ok()
+    });
+    let mut base = SmsRequest::new("", sender, "", "");
+    if args.test {
+        base = base.with_flags(SmsFlags::TEST);
     }
+    // Ctrl-C finishes the sends in flight and stops reading rows
+    let cancel = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    let write_failed =
+        |err: io::Error| fail(format!("failed to write results: {err}"), EXIT_TRANSPORT);
+    if format != OutputFormat::Json {
+        csv::write_record(&mut results, &["number", "message_id", "error"])
+            .map_err(write_failed)?;
+    }
+    let mut write_error = None;
+    let summary = client
+        .send_personalized_with_progress(base, &template, recipients, cancel, |recipient| {
+            if write_error.is_none() {
+                write_error = write_result(&recipient, format, &mut results).err();
+            }
+        })
+        .await;
 
-    if let Some(url) = cli.dlr_url.as_deref() {
-        request = request.with_dlr_url(url);
+    if let Some(err) = write_error {
+        return Err(write_failed(err));
+    }
+    eprintln!(
+        "Sent {} of {} messages",
+        summary.succeeded,
+        summary.succeeded + summary.failed
+    );
+    if summary.cancelled {
+        eprintln!("Cancelled; the remaining rows were not sent");
     }
 
-    if let Some(exp) = cli.expired {
-        request = request.with_expired(exp);
+    if let Some(err) = malformed.into_inner() {
+        return Err(fail(err, EXIT_INVALID));
+    }
+    if summary.is_success() {
+        Ok(())
+    } else {
+        Err(ExitCode::from(EXIT_PARTIAL))
     }
+}
 
-    if let Some(key) = cli.user_key.as_deref() {
-        request = request.with_user_key(key);
+#[cfg(feature = "server")]
+async fn serve(config: &SmsClientConfig, args: &ServeArgs) -> Result<(), ExitCode> {
+    use crate::delivery::MemoryDeliveryStore;
+    use crate::server::Server;
+    use std::sync::Arc;
+
+    if config.api_key.is_none() {
+        return Err(fail(
+            "no API key; pass --api-key, set ESTERIA_API_KEY or add api_key to the configuration",
+            EXIT_CONFIG,
+        ));
     }
+    let client: SmsClient = config
+        .builder()
+        .build()
+        .map_err(|err| fail(&err, exit_code(&err)))?;
+    let listener = tokio::net::TcpListener::bind(args.listen)
+        .await
+        .map_err(|err| {
+            fail(
+                format!("failed to listen on {}: {err}", args.listen),
+                EXIT_CONFIG,
+            )
+        })?;
+    eprintln!("Listening on http://{}", args.listen);
 
-    println!("Sending SMS to {}...", cli.number);
+    let server = Server::new(Arc::new(client), Arc::new(MemoryDeliveryStore::new()));
+    let shutdown = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    server.serve(listener, shutdown).await;
+    Ok(())
+}
 
-    match client.send_sms(request).await {
-        Ok(code) => {
-            println!("✓ SMS sent successfully!");
-            println!("Message ID: {code}");
-            Ok(())
-        }
-        Err(e) => {
-            eprintln!("✗ Failed to send SMS: {e}");
-            Err(Box::new(e))
+/// Run the CLI application
+///
+/// Results go to stdout and diagnostics to stderr. The exit code is 0 on
+/// success, 2 for usage errors and otherwise one of the `EXIT_*` codes, with
+/// gateway error codes offset by [`EXIT_GATEWAY_BASE`].
+pub async fn run() -> ExitCode {
+    env_logger::init();
+
+    let cli = Cli::parse();
+    let config = match load_config(&cli) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("✗ {err}");
+            return ExitCode::from(EXIT_CONFIG);
         }
+    };
+
+    match &cli.command {
+        Command::Send(args) => match send(&config, args).await {
+            Ok((result, elapsed)) => {
+                if cli.output == OutputFormat::Json {
+                    println!("{}", result_json(&args.to, &result, Some(elapsed)));
+                }
+                match result {
+                    Ok(message_id) => {
+                        if cli.output == OutputFormat::Human {
+                            println!("{}", message_id.trim());
+                        }
+                        ExitCode::SUCCESS
+                    }
+                    Err(err) if cli.output == OutputFormat::Json => ExitCode::from(exit_code(&err)),
+                    Err(err) => fail(format!("Failed to send SMS: {err}"), exit_code(&err)),
+                }
+            }
+            Err(code) => code,
+        },
+        Command::Bulk(args) => match bulk(&config, args, cli.output).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => code,
+        },
+        #[cfg(feature = "server")]
+        Command::Serve(args) => match serve(&config, args).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(code) => code,
+        },
     }
 }
@@ -0,0 +1,653 @@
+use crate::credentials::{Credentials, RotatingCredentials};
+#[cfg(feature = "serde")]
+use crate::esteria::SmsClient;
+use crate::esteria::{SmsClientBuilder, SmsError, SmsRequest};
+use crate::rate_limit::{Rate, RateLimit};
+use crate::retry::RetryPolicy;
+use std::collections::BTreeMap;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Error types for configuration loading
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read configuration: {0}")]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "serde")]
+    #[error("invalid configuration file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid value for {key}: {reason}")]
+    InvalidValue { key: String, reason: String },
+    #[error("unknown configuration profile: {0}")]
+    UnknownProfile(String),
+    #[error("failed to build client: {0}")]
+    Client(#[from] SmsError),
+}
+
+/// Environment variable naming the configuration file read by [`SmsClient::from_profile`]
+#[cfg(feature = "serde")]
+pub const CONFIG_PATH_VAR: &str = "ESTERIA_CONFIG";
+/// Configuration file read by [`SmsClient::from_profile`] if `ESTERIA_CONFIG` is unset
+#[cfg(feature = "serde")]
+pub const DEFAULT_CONFIG_PATH: &str = "esteria.toml";
+
+/// Client settings shared by services and the CLI
+///
+/// Loaded from environment variables or, with the `serde` feature, a TOML file:
+///
+/// ```toml
+/// base_url = "https://api.esteria.eu"
+/// api_key = "..."
+/// default_sender = "Shop"
+/// timeout_secs = 10
+///
+/// [retry]
+/// max_attempts = 3
+/// initial_backoff_ms = 200
+/// max_backoff_ms = 10000
+///
+/// [rate_limit]
+/// default = 50          # messages per second
+/// default_sender = 10
+///
+/// [rate_limit.countries]
+/// "+370" = 30
+///
+/// [rate_limit.senders]
+/// Bank = 5
+///
+/// [profiles.sandbox]
+/// base_url = "https://sandbox.example"
+///
+/// [profiles.brand-b]
+/// default_sender = "BrandB"
+/// ```
+///
+/// Settings under `profiles.<name>` override the top-level ones when that
+/// profile is loaded, key by key.
+#[derive(Clone, Default, PartialEq)]
+pub struct SmsClientConfig {
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    pub default_sender: Option<String>,
+    pub timeout: Option<Duration>,
+    pub retry: Option<RetryPolicy>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// `Debug` output omits the API key
+impl std::fmt::Debug for SmsClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmsClientConfig")
+            .field("base_url", &self.base_url)
+            .field("api_key", &self.api_key.as_ref().map(|_| "***"))
+            .field("default_sender", &self.default_sender)
+            .field("timeout", &self.timeout)
+            .field("retry", &self.retry)
+            .field("rate_limit", &self.rate_limit)
+            .finish()
+    }
+}
+
+impl SmsClientConfig {
+    /// Read the configuration from `ESTERIA_*` environment variables
+    ///
+    /// `ESTERIA_API_BASE_URL`, `ESTERIA_API_KEY`, `ESTERIA_SENDER`,
+    /// `ESTERIA_TIMEOUT_SECS`, `ESTERIA_RETRY_MAX_ATTEMPTS`,
+    /// `ESTERIA_RETRY_INITIAL_BACKOFF_MS`, `ESTERIA_RETRY_MAX_BACKOFF_MS`,
+    /// `ESTERIA_RATE_LIMIT` (default messages per second) and
+    /// `ESTERIA_SENDER_RATE_LIMIT` (per sender). Unset variables are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError::InvalidValue` if a variable can't be parsed
+    pub fn from_env() -> Result<Self, ConfigError> {
+        ConfigLoader::new().with_env()?.finish()
+    }
+
+    /// Read the configuration from a TOML file, e.g. `esteria.toml`
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` if the file can't be read, is malformed or has
+    /// unknown keys or invalid values
+    #[cfg(feature = "serde")]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Read a named profile from a TOML file
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` as [`SmsClientConfig::from_file`] does, or
+    /// `ConfigError::UnknownProfile` if the file has no such profile
+    #[cfg(feature = "serde")]
+    pub fn from_file_profile(path: impl AsRef<Path>, profile: &str) -> Result<Self, ConfigError> {
+        Self::from_toml_profile(&std::fs::read_to_string(path)?, profile)
+    }
+
+    /// Parse the configuration from TOML text
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` if the text is malformed or has unknown keys or
+    /// invalid values
+    #[cfg(feature = "serde")]
+    pub fn from_toml(input: &str) -> Result<Self, ConfigError> {
+        ConfigLoader::new().with_toml(input, None)?.finish()
+    }
+
+    /// Parse a named profile from TOML text
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` as [`SmsClientConfig::from_toml`] does, or
+    /// `ConfigError::UnknownProfile` if the text has no such profile
+    #[cfg(feature = "serde")]
+    pub fn from_toml_profile(input: &str, profile: &str) -> Result<Self, ConfigError> {
+        ConfigLoader::new()
+            .with_toml(input, Some(profile))?
+            .finish()
+    }
+
+    /// Override settings with those set in `other`
+    ///
+    /// Sections are replaced whole: a `retry` or `rate_limit` set in `other`
+    /// drops every setting of that section in `self`. Use [`ConfigLoader`]
+    /// to layer sources key by key.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            base_url: other.base_url.or(self.base_url),
+            api_key: other.api_key.or(self.api_key),
+            default_sender: other.default_sender.or(self.default_sender),
+            timeout: other.timeout.or(self.timeout),
+            retry: other.retry.or(self.retry),
+            rate_limit: other.rate_limit.or(self.rate_limit),
+        }
+    }
+
+    /// Client builder with these settings applied
+    ///
+    /// The API key is supplied through a credentials provider, so requests
+    /// with an empty API key, such as those from [`SmsClientConfig::request`],
+    /// use it.
+    #[must_use]
+    pub fn builder(&self) -> SmsClientBuilder {
+        let mut builder = SmsClientBuilder::default();
+        if let Some(base_url) = &self.base_url {
+            builder = builder.with_api_base_url(base_url);
+        }
+        if let Some(api_key) = &self.api_key {
+            builder = builder.with_credentials_provider(Arc::new(RotatingCredentials::new(
+                Credentials::new(api_key),
+            )));
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.with_timeout(timeout);
+        }
+        if let Some(retry) = self.retry {
+            builder = builder.with_retry(retry);
+        }
+        if let Some(rate_limit) = &self.rate_limit {
+            builder = builder.with_rate_limit(rate_limit.clone());
+        }
+        builder
+    }
+
+    /// Request from the default sender, authenticated by the configured API key
+    #[must_use]
+    pub fn request<'a>(&'a self, number: &'a str, text: &'a str) -> SmsRequest<'a> {
+        SmsRequest::new(
+            "",
+            self.default_sender.as_deref().unwrap_or_default(),
+            number,
+            text,
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SmsClient {
+    /// Create a client from a named profile of the configuration file
+    ///
+    /// The file is `ESTERIA_CONFIG`, or `esteria.toml` in the working
+    /// directory. `ESTERIA_*` environment variables override the profile.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` if the configuration can't be loaded or the
+    /// client can't be built from it
+    pub fn from_profile(profile: &str) -> Result<Self, ConfigError> {
+        let path = std::env::var(CONFIG_PATH_VAR).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.into());
+        let config = ConfigLoader::new()
+            .with_file(path, Some(profile))?
+            .with_env()?
+            .finish()?;
+        Ok(config.builder().build()?)
+    }
+}
+
+/// Settings of a configuration file, a profile or the environment
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+struct Settings {
+    base_url: Option<String>,
+    api_key: Option<String>,
+    default_sender: Option<String>,
+    timeout_secs: Option<u64>,
+    retry: RetrySettings,
+    rate_limit: RateLimitSettings,
+    #[cfg(feature = "serde")]
+    profiles: BTreeMap<String, Settings>,
+}
+
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+struct RetrySettings {
+    max_attempts: Option<u32>,
+    initial_backoff_ms: Option<u64>,
+    max_backoff_ms: Option<u64>,
+}
+
+/// Rates in messages per second
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+struct RateLimitSettings {
+    default: Option<f64>,
+    default_sender: Option<f64>,
+    countries: BTreeMap<String, f64>,
+    senders: BTreeMap<String, f64>,
+}
+
+fn invalid(key: &str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: key.to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// Positive rate in messages per second
+fn rate(key: &str, per_second: f64) -> Result<Rate, ConfigError> {
+    if !(per_second > 0.0 && per_second.is_finite()) {
+        return Err(invalid(key, "rate must be positive"));
+    }
+    Ok(Rate::per_second(per_second))
+}
+
+/// Configuration layered from several sources, key by key
+///
+/// Each source overrides only the keys it sets, so `ESTERIA_RETRY_MAX_ATTEMPTS`
+/// keeps the backoffs of the file and `ESTERIA_RATE_LIMIT` keeps its country
+/// and sender limits. Sections are combined and checked by
+/// [`ConfigLoader::finish`], after the last source.
+#[derive(Debug, Default)]
+pub struct ConfigLoader {
+    config: SmsClientConfig,
+    max_attempts: Option<u32>,
+    initial_backoff: Option<Duration>,
+    max_backoff: Option<Duration>,
+}
+
+impl ConfigLoader {
+    /// Start with no settings
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the settings of TOML text, then those of `profile` over them
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` as [`SmsClientConfig::from_toml_profile`] does
+    #[cfg(feature = "serde")]
+    pub fn with_toml(mut self, input: &str, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let mut settings: Settings = toml::from_str(input)?;
+        let mut profiles = std::mem::take(&mut settings.profiles);
+        self.apply(settings)?;
+
+        if let Some(profile) = profile {
+            let settings = profiles
+                .remove(profile)
+                .ok_or_else(|| ConfigError::UnknownProfile(profile.to_string()))?;
+            if !settings.profiles.is_empty() {
+                return Err(invalid(
+                    &format!("profiles.{profile}.profiles"),
+                    "profiles can't be nested",
+                ));
+            }
+            self.apply(settings)?;
+        }
+        Ok(self)
+    }
+
+    /// Add the settings of a TOML file, then those of `profile` over them
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError` as [`SmsClientConfig::from_file_profile`] does
+    #[cfg(feature = "serde")]
+    pub fn with_file(
+        self,
+        path: impl AsRef<Path>,
+        profile: Option<&str>,
+    ) -> Result<Self, ConfigError> {
+        self.with_toml(&std::fs::read_to_string(path)?, profile)
+    }
+
+    /// Add the settings of `ESTERIA_*` environment variables, see
+    /// [`SmsClientConfig::from_env`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError::InvalidValue` if a variable can't be parsed
+    pub fn with_env(self) -> Result<Self, ConfigError> {
+        self.with_vars(|name| std::env::var(name).ok())
+    }
+
+    fn with_vars(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        fn parse<T: std::str::FromStr>(
+            value: Option<String>,
+            key: &str,
+            expected: &str,
+        ) -> Result<Option<T>, ConfigError> {
+            value
+                .map(|value| value.trim().parse().map_err(|_| invalid(key, expected)))
+                .transpose()
+        }
+        const INTEGER: &str = "expected a non-negative integer";
+        const RATE: &str = "expected messages per second";
+
+        let settings = Settings {
+            base_url: var("ESTERIA_API_BASE_URL"),
+            api_key: var("ESTERIA_API_KEY"),
+            default_sender: var("ESTERIA_SENDER"),
+            timeout_secs: parse(var("ESTERIA_TIMEOUT_SECS"), "timeout_secs", INTEGER)?,
+            retry: RetrySettings {
+                max_attempts: parse(
+                    var("ESTERIA_RETRY_MAX_ATTEMPTS"),
+                    "retry.max_attempts",
+                    INTEGER,
+                )?,
+                initial_backoff_ms: parse(
+                    var("ESTERIA_RETRY_INITIAL_BACKOFF_MS"),
+                    "retry.initial_backoff_ms",
+                    INTEGER,
+                )?,
+                max_backoff_ms: parse(
+                    var("ESTERIA_RETRY_MAX_BACKOFF_MS"),
+                    "retry.max_backoff_ms",
+                    INTEGER,
+                )?,
+            },
+            rate_limit: RateLimitSettings {
+                default: parse(var("ESTERIA_RATE_LIMIT"), "rate_limit.default", RATE)?,
+                default_sender: parse(
+                    var("ESTERIA_SENDER_RATE_LIMIT"),
+                    "rate_limit.default_sender",
+                    RATE,
+                )?,
+                ..RateLimitSettings::default()
+            },
+            #[cfg(feature = "serde")]
+            profiles: BTreeMap::new(),
+        };
+        self.apply(settings)?;
+        Ok(self)
+    }
+
+    fn rate_limit(&mut self) -> RateLimit {
+        self.config.rate_limit.take().unwrap_or_default()
+    }
+
+    /// Override the keys `settings` sets
+    fn apply(&mut self, settings: Settings) -> Result<(), ConfigError> {
+        let Settings {
+            base_url,
+            api_key,
+            default_sender,
+            timeout_secs,
+            retry,
+            rate_limit,
+            ..
+        } = settings;
+        self.config.base_url = base_url.or(self.config.base_url.take());
+        self.config.api_key = api_key.or(self.config.api_key.take());
+        self.config.default_sender = default_sender.or(self.config.default_sender.take());
+        if let Some(secs) = timeout_secs {
+            self.config.timeout = Some(Duration::from_secs(secs));
+        }
+
+        self.max_attempts = retry.max_attempts.or(self.max_attempts);
+        if let Some(ms) = retry.initial_backoff_ms {
+            self.initial_backoff = Some(Duration::from_millis(ms));
+        }
+        if let Some(ms) = retry.max_backoff_ms {
+            self.max_backoff = Some(Duration::from_millis(ms));
+        }
+
+        if let Some(per_second) = rate_limit.default {
+            let rate = rate("rate_limit.default", per_second)?;
+            self.config.rate_limit = Some(self.rate_limit().with_default(rate));
+        }
+        if let Some(per_second) = rate_limit.default_sender {
+            let rate = rate("rate_limit.default_sender", per_second)?;
+            self.config.rate_limit = Some(self.rate_limit().with_default_sender(rate));
+        }
+        for (prefix, per_second) in rate_limit.countries {
+            let rate = rate(&format!("rate_limit.countries.{prefix}"), per_second)?;
+            self.config.rate_limit = Some(self.rate_limit().with_country(&prefix, rate));
+        }
+        for (sender, per_second) in rate_limit.senders {
+            let rate = rate(&format!("rate_limit.senders.{sender}"), per_second)?;
+            self.config.rate_limit = Some(self.rate_limit().with_sender(sender, rate));
+        }
+        Ok(())
+    }
+
+    /// Combine the layered settings
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError::InvalidValue` if a retry backoff is set without
+    /// `retry.max_attempts`
+    pub fn finish(mut self) -> Result<SmsClientConfig, ConfigError> {
+        if self.max_attempts.is_some()
+            || self.initial_backoff.is_some()
+            || self.max_backoff.is_some()
+        {
+            let Some(max_attempts) = self.max_attempts else {
+                return Err(invalid(
+                    "retry.max_attempts",
+                    "required when retry backoff is set",
+                ));
+            };
+            let initial = self
+                .initial_backoff
+                .unwrap_or(crate::retry::DEFAULT_INITIAL_BACKOFF);
+            let max = self
+                .max_backoff
+                .unwrap_or(crate::retry::DEFAULT_MAX_BACKOFF);
+            self.config.retry = Some(RetryPolicy::new(max_attempts).with_backoff(initial, max));
+        }
+        Ok(self.config)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+# Gateway settings
+base_url = "https://api.esteria.eu"
+api_key = "k#1"  # quoted hashes aren't comments
+default_sender = "Shop"
+timeout_secs = 10
+
+[retry]
+max_attempts = 3
+initial_backoff_ms = 100
+
+[rate_limit]
+default = 50
+
+[rate_limit.countries]
+"+370" = 2.5
+"#;
+
+    #[test]
+    fn parses_toml_file() {
+        let config = SmsClientConfig::from_toml(FILE).unwrap();
+        assert_eq!(config.base_url.as_deref(), Some("https://api.esteria.eu"));
+        assert_eq!(config.api_key.as_deref(), Some("k#1"));
+        assert_eq!(config.default_sender.as_deref(), Some("Shop"));
+        assert_eq!(config.timeout, Some(Duration::from_secs(10)));
+        assert_eq!(
+            config.retry,
+            Some(RetryPolicy::new(3).with_backoff(
+                Duration::from_millis(100),
+                crate::retry::DEFAULT_MAX_BACKOFF
+            ))
+        );
+        assert_eq!(
+            config.rate_limit,
+            Some(
+                RateLimit::new()
+                    .with_default(Rate::per_second(50.0))
+                    .with_country("+370", Rate::per_second(2.5))
+            )
+        );
+        assert!(!format!("{config:?}").contains("k#1"));
+    }
+
+    #[test]
+    fn profiles_override_top_level_settings() {
+        let file = format!(
+            "{FILE}\n[profiles.sandbox]\nbase_url = \"https://sandbox.example\"\n\
+             [profiles.sandbox.retry]\ninitial_backoff_ms = 1\n"
+        );
+
+        let sandbox = SmsClientConfig::from_toml_profile(&file, "sandbox").unwrap();
+        assert_eq!(sandbox.base_url.as_deref(), Some("https://sandbox.example"));
+        assert_eq!(sandbox.default_sender.as_deref(), Some("Shop"));
+        assert_eq!(
+            sandbox.retry,
+            Some(
+                RetryPolicy::new(3)
+                    .with_backoff(Duration::from_millis(1), crate::retry::DEFAULT_MAX_BACKOFF)
+            )
+        );
+
+        let base = SmsClientConfig::from_toml(&file).unwrap();
+        assert_eq!(base.base_url.as_deref(), Some("https://api.esteria.eu"));
+        assert!(matches!(
+            SmsClientConfig::from_toml_profile(&file, "prod"),
+            Err(ConfigError::UnknownProfile(name)) if name == "prod"
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(matches!(
+            SmsClientConfig::from_toml("base_url = https://example.com"),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            SmsClientConfig::from_toml("[retry]\nattempts = 3"),
+            Err(ConfigError::Toml(err)) if err.message().contains("attempts")
+        ));
+        assert!(matches!(
+            SmsClientConfig::from_toml("timeout_secs = -1"),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            SmsClientConfig::from_toml("[rate_limit.countries]\n\"+370\" = 0"),
+            Err(ConfigError::InvalidValue { key, .. }) if key == "rate_limit.countries.+370"
+        ));
+        assert!(matches!(
+            SmsClientConfig::from_toml("[retry]\nmax_backoff_ms = 100"),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn reads_environment_and_merges() {
+        let env = |name: &str| match name {
+            "ESTERIA_API_KEY" => Some("env-key".to_string()),
+            "ESTERIA_TIMEOUT_SECS" => Some("5".to_string()),
+            "ESTERIA_RETRY_MAX_ATTEMPTS" => Some("2".to_string()),
+            _ => None,
+        };
+        let config = ConfigLoader::new()
+            .with_vars(env)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(config.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.retry.map(|retry| retry.max_attempts()), Some(2));
+
+        let merged = SmsClientConfig::from_toml(FILE).unwrap().merge(config);
+        assert_eq!(merged.api_key.as_deref(), Some("env-key"));
+        assert_eq!(merged.default_sender.as_deref(), Some("Shop"));
+
+        assert!(matches!(
+            ConfigLoader::new()
+                .with_vars(|name| (name == "ESTERIA_RATE_LIMIT").then(|| "fast".to_string())),
+            Err(ConfigError::InvalidValue { .. })
+        ));
+    }
+
+    fn layered(vars: &[(&str, &str)]) -> SmsClientConfig {
+        ConfigLoader::new()
+            .with_toml(FILE, None)
+            .unwrap()
+            .with_vars(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| (*value).to_string())
+            })
+            .unwrap()
+            .finish()
+            .unwrap()
+    }
+
+    #[test]
+    fn environment_overrides_single_keys() {
+        let config = layered(&[("ESTERIA_RATE_LIMIT", "10")]);
+        assert_eq!(
+            config.rate_limit,
+            Some(
+                RateLimit::new()
+                    .with_default(Rate::per_second(10.0))
+                    .with_country("+370", Rate::per_second(2.5))
+            )
+        );
+
+        let config = layered(&[("ESTERIA_RETRY_MAX_ATTEMPTS", "7")]);
+        assert_eq!(
+            config.retry,
+            Some(RetryPolicy::new(7).with_backoff(
+                Duration::from_millis(100),
+                crate::retry::DEFAULT_MAX_BACKOFF
+            ))
+        );
+
+        let config = layered(&[("ESTERIA_RETRY_INITIAL_BACKOFF_MS", "5")]);
+        assert_eq!(
+            config.retry,
+            Some(
+                RetryPolicy::new(3)
+                    .with_backoff(Duration::from_millis(5), crate::retry::DEFAULT_MAX_BACKOFF)
+            )
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::esteria::SmsError;
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use crate::tls::TlsOptions;
+use reqwest::dns::Resolve;
+use reqwest::{ClientBuilder, NoProxy, Proxy};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Idle connections kept per host, enough for high bulk concurrency
+pub const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = 32;
+/// How long an idle connection to the gateway is kept for reuse
+pub const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// TCP keepalive interval, so dead connections behind NAT or firewalls are noticed
+pub const DEFAULT_TCP_KEEPALIVE: Duration = Duration::from_secs(30);
+
+/// HTTP protocol versions used to talk to the gateway
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/2 if the gateway offers it during the TLS handshake, HTTP/1.1 otherwise
+    #[default]
+    Negotiate,
+    /// HTTP/1.1 only
+    Http1Only,
+    /// HTTP/2 without negotiation, e.g. for a plain-text gateway known to speak it
+    Http2PriorKnowledge,
+}
+
+/// Egress proxy for gateway requests
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    url: String,
+    basic_auth: Option<(String, String)>,
+    no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Send gateway requests through the proxy at `url`
+    ///
+    /// E.g. `http://proxy.example:3128`, or `socks5://proxy.example:1080` with
+    /// the `socks` feature.
+    #[must_use]
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            basic_auth: None,
+            no_proxy: Vec::new(),
+        }
+    }
+
+    /// Authenticate to an HTTP proxy with a `Proxy-Authorization` basic auth header
+    #[must_use]
+    pub fn with_basic_auth(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.basic_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Bypass the proxy for these hosts, domains (`.example.com`) or IP ranges
+    #[must_use]
+    pub fn with_no_proxy<I>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.no_proxy.extend(hosts.into_iter().map(Into::into));
+        self
+    }
+
+    fn to_proxy(&self) -> reqwest::Result<Proxy> {
+        let mut proxy = Proxy::all(&self.url)?;
+        if let Some((username, password)) = &self.basic_auth {
+            proxy = proxy.basic_auth(username, password);
+        }
+        if !self.no_proxy.is_empty() {
+            proxy = proxy.no_proxy(NoProxy::from_string(&self.no_proxy.join(",")));
+        }
+        Ok(proxy)
+    }
+}
+
+/// `Debug` output hides the proxy password
+impl std::fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field(
+                "basic_auth",
+                &self
+                    .basic_auth
+                    .as_ref()
+                    .map(|(username, _)| (username, "***")),
+            )
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+/// Connection settings applied to the underlying HTTP client
+#[derive(Clone)]
+pub(crate) struct ConnectionOptions {
+    pub(crate) pool_max_idle_per_host: usize,
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    pub(crate) tcp_keepalive: Option<Duration>,
+    pub(crate) http_version: HttpVersion,
+    /// Hostnames pinned to fixed addresses, bypassing the resolver
+    pub(crate) dns_overrides: Vec<(String, Vec<SocketAddr>)>,
+    pub(crate) dns_resolver: Option<Arc<dyn Resolve>>,
+    pub(crate) proxy: Option<ProxyConfig>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub(crate) tls: TlsOptions,
+}
+
+impl std::fmt::Debug for ConnectionOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("ConnectionOptions");
+        debug
+            .field("pool_max_idle_per_host", &self.pool_max_idle_per_host)
+            .field("pool_idle_timeout", &self.pool_idle_timeout)
+            .field("tcp_keepalive", &self.tcp_keepalive)
+            .field("http_version", &self.http_version)
+            .field("dns_overrides", &self.dns_overrides)
+            .field(
+                "dns_resolver",
+                &self.dns_resolver.as_ref().map(|_| "custom"),
+            )
+            .field("proxy", &self.proxy);
+        #[cfg(any(feature = "rustls", feature = "native-tls"))]
+        debug.field("tls", &self.tls);
+        debug.finish()
+    }
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            pool_idle_timeout: Some(DEFAULT_POOL_IDLE_TIMEOUT),
+            tcp_keepalive: Some(DEFAULT_TCP_KEEPALIVE),
+            http_version: HttpVersion::Negotiate,
+            dns_overrides: Vec::new(),
+            dns_resolver: None,
+            proxy: None,
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            tls: TlsOptions::default(),
+        }
+    }
+}
+
+impl ConnectionOptions {
+    pub(crate) fn apply(&self, builder: ClientBuilder) -> Result<ClientBuilder, SmsError> {
+        let mut builder = builder
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(resolver) = &self.dns_resolver {
+            builder = builder.dns_resolver(resolver.clone());
+        }
+        for (host, addrs) in &self.dns_overrides {
+            builder = builder.resolve_to_addrs(host, addrs);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.to_proxy()?);
+        }
+        #[cfg(feature = "rustls")]
+        if let Some(config) =
+            self.tls
+                .client_config(self.http_version)
+                .map_err(|err| SmsError::Tls {
+                    reason: err.to_string(),
+                })?
+        {
+            builder = builder.tls_backend_preconfigured(config);
+        }
+        #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+        {
+            builder = self
+                .tls
+                .apply_native(builder)
+                .map_err(|err| SmsError::Tls {
+                    reason: err.to_string(),
+                })?;
+        }
+        Ok(match self.http_version {
+            HttpVersion::Negotiate => builder,
+            HttpVersion::Http1Only => builder.http1_only(),
+            HttpVersion::Http2PriorKnowledge => builder.http2_prior_knowledge(),
+        })
+    }
+}
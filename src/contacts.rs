@@ -0,0 +1,293 @@
+use crate::bulk::Recipient;
+use crate::csv::{CsvError, RecipientReader, write_record};
+use crate::optout::registry_key;
+use crate::redact;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::io::{self, BufRead, Write};
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
+
+/// CSV column listing the groups of a contact, separated by `;`
+pub const GROUPS_COLUMN: &str = "groups";
+
+/// Error types for contact lists
+#[derive(Error, Debug)]
+pub enum ContactError {
+    #[error("contact store failed: {0}")]
+    Store(String),
+    #[error(transparent)]
+    Csv(#[from] CsvError),
+}
+
+impl From<io::Error> for ContactError {
+    fn from(err: io::Error) -> Self {
+        Self::Csv(CsvError::Io(err))
+    }
+}
+
+/// Contact with free-form attributes and group memberships
+///
+/// Attributes become template variables when the contact is used as a
+/// [`Recipient`].
+#[derive(Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Contact {
+    pub number: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attributes: BTreeMap<String, String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub groups: BTreeSet<String>,
+}
+
+impl std::fmt::Debug for Contact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Contact")
+            .field("number", &redact::number(&self.number))
+            .field("attributes", &self.attributes)
+            .field("groups", &self.groups)
+            .finish()
+    }
+}
+
+impl Contact {
+    /// Create a contact without attributes or groups
+    #[must_use]
+    pub fn new(number: impl Into<String>) -> Self {
+        Self {
+            number: number.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set an attribute, e.g. `name`
+    #[must_use]
+    pub fn with_attribute(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(name.into(), value.into());
+        self
+    }
+
+    /// Add the contact to `group`
+    #[must_use]
+    pub fn with_group(mut self, group: impl Into<String>) -> Self {
+        self.groups.insert(group.into());
+        self
+    }
+
+    /// Whether the contact is a member of `group`
+    #[must_use]
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.contains(group)
+    }
+}
+
+impl From<Contact> for Recipient {
+    fn from(contact: Contact) -> Self {
+        Self {
+            number: contact.number,
+            variables: contact.attributes.into_iter().collect(),
+        }
+    }
+}
+
+/// Storage for contacts, keyed by number
+///
+/// Numbers are compared as digits (see [`registry_key`]), so `+370 612 34567`
+/// and `37061234567` are the same contact.
+pub trait ContactStore: Send + Sync {
+    /// The contact with `number`, if any
+    fn get(
+        &self,
+        number: &str,
+    ) -> impl Future<Output = Result<Option<Contact>, ContactError>> + Send;
+
+    /// Insert or replace the contact with the same number
+    fn save(&self, contact: &Contact) -> impl Future<Output = Result<(), ContactError>> + Send;
+
+    /// Remove the contact with `number`, returning whether it existed
+    fn remove(&self, number: &str) -> impl Future<Output = Result<bool, ContactError>> + Send;
+
+    /// All contacts, or the members of `group`, ordered by number
+    fn list(
+        &self,
+        group: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<Contact>, ContactError>> + Send;
+}
+
+/// In-memory contact store, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryContactStore {
+    contacts: Mutex<BTreeMap<String, Contact>>,
+}
+
+impl MemoryContactStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ContactStore for MemoryContactStore {
+    async fn get(&self, number: &str) -> Result<Option<Contact>, ContactError> {
+        let contacts = self.contacts.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(contacts.get(&registry_key(number)).cloned())
+    }
+
+    async fn save(&self, contact: &Contact) -> Result<(), ContactError> {
+        let mut contacts = self.contacts.lock().unwrap_or_else(PoisonError::into_inner);
+        contacts.insert(registry_key(&contact.number), contact.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, number: &str) -> Result<bool, ContactError> {
+        let mut contacts = self.contacts.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(contacts.remove(&registry_key(number)).is_some())
+    }
+
+    async fn list(&self, group: Option<&str>) -> Result<Vec<Contact>, ContactError> {
+        let contacts = self.contacts.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(contacts
+            .values()
+            .filter(|contact| group.is_none_or(|group| contact.in_group(group)))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Recipients for a bulk send or campaign: all contacts, or the members of `group`
+///
+/// # Errors
+///
+/// Returns `ContactError::Store` if the contacts can't be listed
+pub async fn recipients(
+    store: &impl ContactStore,
+    group: Option<&str>,
+) -> Result<Vec<Recipient>, ContactError> {
+    let contacts = store.list(group).await?;
+    Ok(contacts.into_iter().map(Recipient::from).collect())
+}
+
+/// Import contacts from CSV with a header row, returning how many were saved
+///
+/// Numbers are read from `number_column` and groups from the optional
+/// [`GROUPS_COLUMN`]; every other column becomes an attribute. Imported
+/// contacts replace existing ones with the same number.
+///
+/// # Errors
+///
+/// Returns `ContactError::Csv` on malformed input, stopping at that record,
+/// or `ContactError::Store` if a contact can't be saved
+pub async fn import_csv(
+    store: &impl ContactStore,
+    reader: impl BufRead,
+    number_column: &str,
+) -> Result<usize, ContactError> {
+    let mut imported = 0;
+    for recipient in RecipientReader::new(reader, number_column)? {
+        let mut recipient = recipient?;
+        let mut contact = Contact::new(recipient.number);
+        if let Some(groups) = recipient.variables.remove(GROUPS_COLUMN) {
+            contact.groups = groups
+                .split(';')
+                .map(str::trim)
+                .filter(|group| !group.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        contact.attributes = recipient.variables.into_iter().collect();
+        store.save(&contact).await?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Export all contacts, or the members of `group`, as CSV, returning how many were written
+///
+/// The header is `number`, [`GROUPS_COLUMN`] and every attribute name in
+/// alphabetical order, so the output can be read back with [`import_csv`].
+///
+/// # Errors
+///
+/// Returns `ContactError` if the contacts can't be listed or written
+pub async fn export_csv<W: Write + ?Sized>(
+    store: &impl ContactStore,
+    writer: &mut W,
+    group: Option<&str>,
+) -> Result<usize, ContactError> {
+    let contacts = store.list(group).await?;
+    let attributes: BTreeSet<&str> = contacts
+        .iter()
+        .flat_map(|contact| contact.attributes.keys().map(String::as_str))
+        .collect();
+
+    let mut header = vec!["number", GROUPS_COLUMN];
+    header.extend(&attributes);
+    write_record(writer, &header)?;
+    for contact in &contacts {
+        let groups = contact
+            .groups
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(";");
+        let mut record = vec![contact.number.as_str(), groups.as_str()];
+        record.extend(
+            attributes
+                .iter()
+                .map(|name| contact.attributes.get(*name).map_or("", String::as_str)),
+        );
+        write_record(writer, &record)?;
+    }
+    Ok(contacts.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_contacts_by_group() {
+        let store = MemoryContactStore::new();
+        store
+            .save(
+                &Contact::new("+37061234567")
+                    .with_attribute("name", "Ana")
+                    .with_group("vip"),
+            )
+            .await
+            .unwrap();
+        store.save(&Contact::new("+37061234568")).await.unwrap();
+
+        let vip = recipients(&store, Some("vip")).await.unwrap();
+        assert_eq!(
+            vip,
+            [Recipient::new("+37061234567").with_variable("name", "Ana")]
+        );
+        assert_eq!(store.list(None).await.unwrap().len(), 2);
+        assert!(store.get("370 612 34567").await.unwrap().is_some());
+        assert!(store.remove("+37061234568").await.unwrap());
+        assert!(!store.remove("+37061234568").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn round_trips_csv() {
+        let input = "number,name,groups\n+37061234567,Ana,vip; staff\n+37061234568,\"Ben, Jr\",\n";
+        let store = MemoryContactStore::new();
+        assert_eq!(
+            import_csv(&store, input.as_bytes(), "number")
+                .await
+                .unwrap(),
+            2
+        );
+        let ana = store.get("+37061234567").await.unwrap().unwrap();
+        assert!(ana.in_group("staff") && ana.in_group("vip"));
+
+        let mut out = Vec::new();
+        export_csv(&store, &mut out, None).await.unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "number,groups,name\r\n+37061234567,staff;vip,Ana\r\n+37061234568,,\"Ben, Jr\"\r\n"
+        );
+    }
+}
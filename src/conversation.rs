@@ -0,0 +1,267 @@
+use crate::esteria::{SmsClient, SmsError, SmsRequest};
+use crate::optout::registry_key;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
+
+/// Error types for conversations
+#[derive(Error, Debug)]
+pub enum ConversationError {
+    #[error("conversation store failed: {0}")]
+    Store(String),
+    #[error(transparent)]
+    Send(#[from] SmsError),
+}
+
+/// Direction of a message in a thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Direction {
+    /// Sent by us
+    Outbound,
+    /// Received from the contact
+    Inbound,
+}
+
+/// Message of a conversation thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreadMessage {
+    pub direction: Direction,
+    pub text: String,
+    /// Gateway message ID of outbound messages
+    pub message_id: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// Messages exchanged with one contact, optionally about one keyword
+///
+/// `state` is free for the application, e.g. the step of a workflow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Thread {
+    /// Contact number, as digits (see [`registry_key`])
+    pub number: String,
+    /// Uppercase keyword, e.g. `YES` for a thread expecting "YES" replies
+    pub keyword: Option<String>,
+    pub messages: Vec<ThreadMessage>,
+    pub state: Option<String>,
+}
+
+impl Thread {
+    fn new(number: String, keyword: Option<String>) -> Self {
+        Self {
+            number,
+            keyword,
+            messages: Vec::new(),
+            state: None,
+        }
+    }
+
+    /// Last message we sent, e.g. the question an inbound message answers
+    #[must_use]
+    pub fn last_outbound(&self) -> Option<&ThreadMessage> {
+        self.messages
+            .iter()
+            .rev()
+            .find(|message| message.direction == Direction::Outbound)
+    }
+}
+
+/// First word of an inbound message, uppercase and without punctuation
+pub(crate) fn keyword(text: &str) -> String {
+    text.split_whitespace()
+        .next()
+        .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation()))
+        .unwrap_or_default()
+        .to_uppercase()
+}
+
+/// Storage for conversation threads
+pub trait ConversationStore: Send + Sync {
+    /// All threads with the contact `number` (as digits)
+    fn threads(
+        &self,
+        number: &str,
+    ) -> impl Future<Output = Result<Vec<Thread>, ConversationError>> + Send;
+
+    /// Insert or replace the thread with the same number and keyword
+    fn save(&self, thread: &Thread) -> impl Future<Output = Result<(), ConversationError>> + Send;
+}
+
+/// In-memory conversation store, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryConversationStore {
+    threads: Mutex<HashMap<String, Vec<Thread>>>,
+}
+
+impl MemoryConversationStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConversationStore for MemoryConversationStore {
+    async fn threads(&self, number: &str) -> Result<Vec<Thread>, ConversationError> {
+        let threads = self.threads.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(threads.get(number).cloned().unwrap_or_default())
+    }
+
+    async fn save(&self, thread: &Thread) -> Result<(), ConversationError> {
+        let mut threads = self.threads.lock().unwrap_or_else(PoisonError::into_inner);
+        let threads = threads.entry(thread.number.clone()).or_default();
+        match threads.iter_mut().find(|t| t.keyword == thread.keyword) {
+            Some(existing) => *existing = thread.clone(),
+            None => threads.push(thread.clone()),
+        }
+        Ok(())
+    }
+}
+
+/// Two-way conversations, threading outbound messages and inbound replies
+///
+/// Threads are keyed by contact number and an optional keyword. An inbound
+/// message whose first word is the keyword of one of the contact's threads
+/// joins that thread; any other goes to the contact's thread without a
+/// keyword.
+#[derive(Debug)]
+pub struct Conversations<S> {
+    store: S,
+}
+
+impl<S: ConversationStore> Conversations<S> {
+    /// Keep threads in `store`
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// The underlying store, e.g. to save an updated thread state
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Send `request` and add it to the contact's thread for `keyword`
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConversationError::Send` if the message could not be sent,
+    /// or `ConversationError::Store` if the thread can't be saved
+    pub async fn send(
+        &self,
+        client: &SmsClient,
+        request: SmsRequest<'_>,
+        keyword: Option<&str>,
+    ) -> Result<Thread, ConversationError> {
+        let number = registry_key(&request.number);
+        let text = request.text.to_string();
+        let message_id = client.send_sms(request).await?;
+
+        let keyword = keyword.map(str::to_uppercase);
+        let mut thread = self.thread(&number, keyword).await?;
+        thread.messages.push(ThreadMessage {
+            direction: Direction::Outbound,
+            text,
+            message_id: Some(message_id.trim().to_string()),
+            at: Utc::now(),
+        });
+        self.store.save(&thread).await?;
+        Ok(thread)
+    }
+
+    /// Add an inbound message from `number` to its thread and return it
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConversationError::Store` if the thread can't be saved
+    pub async fn receive(&self, number: &str, text: &str) -> Result<Thread, ConversationError> {
+        let number = registry_key(number);
+        let word = keyword(text);
+
+        let mut threads = self.store.threads(&number).await?;
+        let index = threads
+            .iter()
+            .position(|thread| thread.keyword.as_deref() == Some(word.as_str()))
+            .or_else(|| threads.iter().position(|thread| thread.keyword.is_none()));
+        let mut thread = match index {
+            Some(index) => threads.swap_remove(index),
+            None => Thread::new(number, None),
+        };
+        thread.messages.push(ThreadMessage {
+            direction: Direction::Inbound,
+            text: text.to_string(),
+            message_id: None,
+            at: Utc::now(),
+        });
+        self.store.save(&thread).await?;
+        Ok(thread)
+    }
+
+    async fn thread(
+        &self,
+        number: &str,
+        keyword: Option<String>,
+    ) -> Result<Thread, ConversationError> {
+        let existing = self
+            .store
+            .threads(number)
+            .await?
+            .into_iter()
+            .find(|thread| thread.keyword == keyword);
+        Ok(existing.unwrap_or_else(|| Thread::new(number.to_string(), keyword)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn threads_replies_by_number_and_keyword() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let conversations = Conversations::new(MemoryConversationStore::new());
+
+        let request = SmsRequest::new("k", "Shop", "+37061234567", "Reply YES to confirm");
+        conversations
+            .send(&client, request, Some("yes"))
+            .await
+            .unwrap();
+
+        let thread = conversations
+            .receive("+370 612 34567", "yes, please")
+            .await
+            .unwrap();
+        assert_eq!(thread.keyword.as_deref(), Some("YES"));
+        assert_eq!(thread.messages.len(), 2);
+        assert_eq!(
+            thread.last_outbound().unwrap().message_id.as_deref(),
+            Some("1001")
+        );
+
+        let other = conversations
+            .receive("37061234567", "Who is this?")
+            .await
+            .unwrap();
+        assert_eq!(other.keyword, None);
+        assert_eq!(other.messages.len(), 1);
+        assert_eq!(
+            conversations
+                .store()
+                .threads("37061234567")
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::{PoisonError, RwLock};
+use thiserror::Error;
+
+/// Error types for credential providers
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CredentialsError {
+    /// The credentials could not be fetched, e.g. the secret store is unreachable
+    #[error("credentials unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// Gateway API key and optional request signing secret
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub api_key: String,
+    /// Replaces the secret of the client's [`crate::signing::RequestSigner`], if any
+    pub secret: Option<String>,
+}
+
+impl Credentials {
+    /// Credentials with an API key only
+    #[must_use]
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            secret: None,
+        }
+    }
+
+    /// Add a request signing secret
+    #[must_use]
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+}
+
+/// `Debug` output omits the key and secret
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials").finish_non_exhaustive()
+    }
+}
+
+/// Source of the gateway credentials, asked on every send
+///
+/// Lets keys be rotated at runtime or kept in a secret store such as Vault or
+/// AWS Secrets Manager. `credentials` is called for every request, so
+/// implementations should cache and return quickly; fetching can happen in a
+/// background task that updates a [`RotatingCredentials`].
+pub trait CredentialsProvider: Send + Sync + std::fmt::Debug {
+    /// Credentials to use for the next request
+    ///
+    /// # Errors
+    ///
+    /// Returns `CredentialsError` if no credentials are available
+    fn credentials(&self) -> Result<Credentials, CredentialsError>;
+
+    /// Called when the gateway rejects the current credentials, so the next
+    /// request can use fresh ones
+    ///
+    /// # Errors
+    ///
+    /// Returns `CredentialsError` if the credentials can't be refreshed
+    fn refresh(&self) -> Result<(), CredentialsError> {
+        Ok(())
+    }
+}
+
+/// Credentials that can be replaced while the client is in use
+#[derive(Debug)]
+pub struct RotatingCredentials {
+    current: RwLock<Credentials>,
+}
+
+impl RotatingCredentials {
+    /// Start with `credentials`
+    #[must_use]
+    pub fn new(credentials: Credentials) -> Self {
+        Self {
+            current: RwLock::new(credentials),
+        }
+    }
+
+    /// Replace the credentials used by subsequent requests
+    pub fn rotate(&self, credentials: Credentials) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = credentials;
+    }
+}
+
+impl CredentialsProvider for RotatingCredentials {
+    fn credentials(&self) -> Result<Credentials, CredentialsError> {
+        Ok(self
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_replaces_credentials() {
+        let provider = RotatingCredentials::new(Credentials::new("old"));
+        assert_eq!(provider.credentials().unwrap().api_key, "old");
+
+        provider.rotate(Credentials::new("new").with_secret("s3cret"));
+        let credentials = provider.credentials().unwrap();
+        assert_eq!(credentials.api_key, "new");
+        assert_eq!(credentials.secret.as_deref(), Some("s3cret"));
+        assert!(!format!("{credentials:?}").contains("s3cret"));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::bulk::Recipient;
+use std::io::{self, BufRead, Write};
+use thiserror::Error;
+
+/// Error types for CSV reading
+#[derive(Error, Debug)]
+pub enum CsvError {
+    #[error("failed to read CSV: {0}")]
+    Io(#[from] io::Error),
+    #[error("CSV line {line}: {reason}")]
+    Malformed { line: usize, reason: String },
+    #[error("CSV has no {0} column")]
+    MissingColumn(String),
+}
+
+/// Reader of RFC 4180 CSV records, one at a time
+///
+/// Fields may be quoted, with `""` for a quote and line breaks inside
+/// quotes. Blank lines are skipped.
+#[derive(Debug)]
+pub struct CsvReader<R> {
+    reader: R,
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    /// Read records from `reader`
+    pub fn new(reader: R) -> Self {
+        Self { reader, line: 0 }
+    }
+
+    /// Line number of the end of the last record read, starting at 1
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> io::Result<bool> {
+        let read = self.reader.read_line(buf)?;
+        if read > 0 {
+            self.line += 1;
+        }
+        Ok(read > 0)
+    }
+
+    fn read_record(&mut self) -> Result<Option<Vec<String>>, CsvError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if !self.read_line(&mut line)? {
+                return Ok(None);
+            }
+            if !line.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+        }
+
+        let start = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.chars().collect::<Vec<_>>().into_iter().peekable();
+        loop {
+            let Some(ch) = chars.next() else {
+                if !quoted {
+                    break;
+                }
+                // A line break inside quotes continues the field on the next line
+                line.clear();
+                if !self.read_line(&mut line)? {
+                    return Err(CsvError::Malformed {
+                        line: start,
+                        reason: "unterminated quoted field".to_string(),
+                    });
+                }
+                chars = line.chars().collect::<Vec<_>>().into_iter().peekable();
+                continue;
+            };
+            match ch {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' if quoted => quoted = false,
+                '"' if field.is_empty() => quoted = true,
+                ',' if !quoted => fields.push(std::mem::take(&mut field)),
+                '\r' | '\n' if !quoted => {}
+                ch => field.push(ch),
+            }
+        }
+        fields.push(field);
+        Ok(Some(fields))
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<Vec<String>, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Recipients read from a CSV file with a header row
+///
+/// One column holds the number; every other column becomes a template
+/// variable named after its header.
+#[derive(Debug)]
+pub struct RecipientReader<R> {
+    records: CsvReader<R>,
+    header: Vec<String>,
+    number: usize,
+}
+
+impl<R: BufRead> RecipientReader<R> {
+    /// Read the header row of `reader`, numbers being in `number_column`
+    ///
+    /// # Errors
+    ///
+    /// Returns `CsvError` if the header can't be read or lacks `number_column`
+    pub fn new(reader: R, number_column: &str) -> Result<Self, CsvError> {
+        let mut records = CsvReader::new(reader);
+        let header: Vec<String> = records
+            .next()
+            .transpose()?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|name| name.trim().to_string())
+            .collect();
+        let number = header
+            .iter()
+            .position(|name| name == number_column)
+            .ok_or_else(|| CsvError::MissingColumn(number_column.to_string()))?;
+        Ok(Self {
+            records,
+            header,
+            number,
+        })
+    }
+
+    /// Line number of the end of the last recipient read, starting at 1
+    pub fn line(&self) -> usize {
+        self.records.line()
+    }
+}
+
+impl<R: BufRead> Iterator for RecipientReader<R> {
+    type Item = Result<Recipient, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(err) => return Some(Err(err)),
+        };
+        if record.len() != self.header.len() {
+            return Some(Err(CsvError::Malformed {
+                line: self.records.line(),
+                reason: format!(
+                    "expected {} fields, found {}",
+                    self.header.len(),
+                    record.len()
+                ),
+            }));
+        }
+
+        let mut recipient = Recipient::new(record[self.number].trim());
+        for (index, (name, value)) in self.header.iter().zip(record).enumerate() {
+            if index != self.number {
+                recipient = recipient.with_variable(name, value);
+            }
+        }
+        Some(Ok(recipient))
+    }
+}
+
+/// Write one CSV record, quoting fields that need it
+///
+/// # Errors
+///
+/// Returns the error of the underlying writer
+pub fn write_record<W: Write + ?Sized>(writer: &mut W, fields: &[&str]) -> io::Result<()> {
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            writer.write_all(b",")?;
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            writer.write_all(field.as_bytes())?;
+        }
+    }
+    writer.write_all(b"\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_quoted_fields() {
+        let input = "a,\"b, \"\"c\"\"\",d\r\n\n\"multi\nline\",,\n";
+        let records: Vec<_> = CsvReader::new(input.as_bytes())
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            [vec!["a", "b, \"c\"", "d"], vec!["multi\nline", "", ""]]
+        );
+
+        let err = CsvReader::new("\"open\n".as_bytes()).next().unwrap();
+        assert!(matches!(err, Err(CsvError::Malformed { line: 1, .. })));
+    }
+
+    #[test]
+    fn reads_recipients_with_variables() {
+        let input = "name,number\nAna,+37060000001\nBen,+37060000002,extra\n";
+        let mut recipients = RecipientReader::new(input.as_bytes(), "number").unwrap();
+
+        let ana = recipients.next().unwrap().unwrap();
+        assert_eq!(
+            ana,
+            Recipient::new("+37060000001").with_variable("name", "Ana")
+        );
+        assert!(matches!(
+            recipients.next(),
+            Some(Err(CsvError::Malformed { line: 3, .. }))
+        ));
+        assert!(recipients.next().is_none());
+
+        assert!(matches!(
+            RecipientReader::new(input.as_bytes(), "phone"),
+            Err(CsvError::MissingColumn(_))
+        ));
+    }
+
+    #[test]
+    fn writes_quoted_fields() {
+        let mut out = Vec::new();
+        write_record(&mut out, &["1", "a,b", "say \"hi\""]).unwrap();
+        assert_eq!(out, b"1,\"a,b\",\"say \"\"hi\"\"\"\r\n");
+    }
+}
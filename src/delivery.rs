@@ -0,0 +1,912 @@
+use crate::esteria::{MultipartResult, SmsClient, SmsError, SmsRequest};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::signing::DlrUrlSigner;
+use crate::sms;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
+
+/// Error types for delivery stores
+#[derive(Error, Debug)]
+pub enum DeliveryStoreError {
+    #[error("delivery store backend failed: {0}")]
+    Backend(String),
+    #[cfg(feature = "redis")]
+    #[error("Redis request failed: {0}")]
+    Redis(#[from] redis::RedisError),
+}
+
+/// Delivery state of a message, parsed from gateway DLR statuses
+///
+/// Understands status words (`delivered`, `expired`, ...), SMPP receipt
+/// states (`DELIVRD`, `UNDELIV`, `REJECTD`, ...) and Kannel DLR types (`1`
+/// delivered, `2` undelivered, `4` queued, `8` sent, `16` rejected), in any
+/// case. Anything else is kept as [`DeliveryStatus::Other`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum DeliveryStatus {
+    /// Waiting at the gateway or SMSC
+    Queued,
+    /// Handed to the operator network
+    Sent,
+    Delivered,
+    /// Not delivered to the phone, e.g. unreachable or deleted
+    Undelivered {
+        reason: Option<String>,
+    },
+    /// Validity period ran out before delivery
+    Expired,
+    /// Refused by the gateway or operator
+    Rejected,
+    /// Status the parser doesn't know, as reported
+    Other(String),
+}
+
+impl DeliveryStatus {
+    /// Parse a status reported by a delivery report
+    ///
+    /// See [`DeliveryStatus::from_report`] to keep the reason of undelivered
+    /// messages.
+    #[must_use]
+    pub fn parse(status: &str) -> Self {
+        match status.trim().to_ascii_lowercase().as_str() {
+            "queued" | "pending" | "buffered" | "4" => Self::Queued,
+            "sent" | "enroute" | "accepted" | "acceptd" | "8" => Self::Sent,
+            "delivered" | "delivrd" | "1" => Self::Delivered,
+            "undelivered" | "undeliv" | "failed" | "deleted" | "2" => {
+                Self::Undelivered { reason: None }
+            }
+            "expired" => Self::Expired,
+            "rejected" | "rejectd" | "16" => Self::Rejected,
+            _ => Self::Other(status.trim().to_string()),
+        }
+    }
+
+    /// Parse the status of a delivery report with its error or reason
+    ///
+    /// The reason, e.g. an SMPP error code or the SMSC's answer, is kept for
+    /// undelivered messages and ignored otherwise, as is a blank one.
+    #[must_use]
+    pub fn from_report(status: &str, reason: Option<&str>) -> Self {
+        match Self::parse(status) {
+            Self::Undelivered { .. } => Self::Undelivered {
+                reason: reason
+                    .map(str::trim)
+                    .filter(|reason| !reason.is_empty())
+                    .map(str::to_string),
+            },
+            status => status,
+        }
+    }
+
+    /// Status word of the report, without the reason, as understood by
+    /// [`DeliveryStatus::parse`]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Queued => "queued",
+            Self::Sent => "sent",
+            Self::Delivered => "delivered",
+            Self::Undelivered { .. } => "undelivered",
+            Self::Expired => "expired",
+            Self::Rejected => "rejected",
+            Self::Other(status) => status,
+        }
+    }
+
+    /// Reason given for an undelivered message
+    #[must_use]
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Undelivered { reason } => reason.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// No further reports follow
+    #[must_use]
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            Self::Delivered | Self::Undelivered { .. } | Self::Expired | Self::Rejected
+        )
+    }
+
+    /// The message reached the phone
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Delivered)
+    }
+}
+
+impl std::fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason() {
+            Some(reason) => write!(f, "{}: {reason}", self.as_str()),
+            None => f.write_str(self.as_str()),
+        }
+    }
+}
+
+/// Tracked message, linking the gateway message ID to a business record
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DeliveryRecord {
+    pub message_id: String,
+    /// User key the message was sent with
+    pub user_key: Option<String>,
+    /// Status of the last delivery report, if any
+    pub status: Option<DeliveryStatus>,
+    pub updated_at: DateTime<Utc>,
+    /// Message IDs of all parts of a multipart message, this one included;
+    /// empty for single messages
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub parts: Vec<String>,
+}
+
+/// Delivery of a whole message, aggregated over its parts
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MessageDelivery {
+    /// Message IDs of the parts, in order (one for single messages)
+    pub message_ids: Vec<String>,
+    pub user_key: Option<String>,
+    /// The first failure of a part that will not be delivered, otherwise
+    /// Delivered if every part is, otherwise the status of the first part
+    /// that isn't
+    pub status: DeliveryStatus,
+}
+
+impl MessageDelivery {
+    /// Aggregate the records of all parts, or `None` while some part has
+    /// no status yet and none has failed
+    fn aggregate(records: &[DeliveryRecord]) -> Option<Self> {
+        let statuses: Vec<Option<&DeliveryStatus>> = records
+            .iter()
+            .map(|record| record.status.as_ref())
+            .collect();
+        let failure = statuses
+            .iter()
+            .flatten()
+            .find(|status| status.is_terminal() && !status.is_success());
+        let status = match failure {
+            Some(failure) => (*failure).clone(),
+            None => statuses
+                .into_iter()
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .find(|status| !status.is_success())
+                .map_or(DeliveryStatus::Delivered, DeliveryStatus::clone),
+        };
+        Some(Self {
+            message_ids: records.iter().map(|r| r.message_id.clone()).collect(),
+            user_key: records.iter().find_map(|r| r.user_key.clone()),
+            status,
+        })
+    }
+}
+
+/// Storage for sent messages, updated from delivery reports
+pub trait DeliveryStore: Send + Sync {
+    /// Record a sent message
+    fn save(
+        &self,
+        message_id: &str,
+        user_key: Option<&str>,
+    ) -> impl Future<Output = Result<(), DeliveryStoreError>> + Send;
+
+    /// Record the parts of a multipart message, each linked to all of them
+    fn save_parts(
+        &self,
+        message_ids: &[String],
+        user_key: Option<&str>,
+    ) -> impl Future<Output = Result<(), DeliveryStoreError>> + Send;
+
+    /// Set the status of a tracked message and return the updated record
+    ///
+    /// Returns `None` for messages that were not saved.
+    fn update(
+        &self,
+        message_id: &str,
+        status: &DeliveryStatus,
+    ) -> impl Future<Output = Result<Option<DeliveryRecord>, DeliveryStoreError>> + Send;
+
+    /// Look up a tracked message
+    fn get(
+        &self,
+        message_id: &str,
+    ) -> impl Future<Output = Result<Option<DeliveryRecord>, DeliveryStoreError>> + Send;
+}
+
+/// In-memory delivery store, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryDeliveryStore {
+    records: Mutex<HashMap<String, DeliveryRecord>>,
+}
+
+impl MemoryDeliveryStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn records(&self) -> std::sync::MutexGuard<'_, HashMap<String, DeliveryRecord>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl DeliveryStore for MemoryDeliveryStore {
+    async fn save(
+        &self,
+        message_id: &str,
+        user_key: Option<&str>,
+    ) -> Result<(), DeliveryStoreError> {
+        let record = DeliveryRecord {
+            message_id: message_id.to_string(),
+            user_key: user_key.map(str::to_string),
+            status: None,
+            updated_at: Utc::now(),
+            parts: Vec::new(),
+        };
+        self.records().insert(message_id.to_string(), record);
+        Ok(())
+    }
+
+    async fn save_parts(
+        &self,
+        message_ids: &[String],
+        user_key: Option<&str>,
+    ) -> Result<(), DeliveryStoreError> {
+        let mut records = self.records();
+        for message_id in message_ids {
+            let record = DeliveryRecord {
+                message_id: message_id.clone(),
+                user_key: user_key.map(str::to_string),
+                status: None,
+                updated_at: Utc::now(),
+                parts: message_ids.to_vec(),
+            };
+            records.insert(message_id.clone(), record);
+        }
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        message_id: &str,
+        status: &DeliveryStatus,
+    ) -> Result<Option<DeliveryRecord>, DeliveryStoreError> {
+        let mut records = self.records();
+        let Some(record) = records.get_mut(message_id) else {
+            return Ok(None);
+        };
+        record.status = Some(status.clone());
+        record.updated_at = Utc::now();
+        Ok(Some(record.clone()))
+    }
+
+    async fn get(&self, message_id: &str) -> Result<Option<DeliveryRecord>, DeliveryStoreError> {
+        Ok(self.records().get(message_id).cloned())
+    }
+}
+
+/// Delivery store keeping records as Redis hashes
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct RedisDeliveryStore {
+    conn: redis::aio::ConnectionManager,
+    prefix: String,
+    ttl: Option<std::time::Duration>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisDeliveryStore {
+    /// Connect to Redis at `url`, e.g. `redis://127.0.0.1/`
+    ///
+    /// # Errors
+    ///
+    /// Returns `DeliveryStoreError::Redis` if the URL is invalid or the server unreachable
+    pub async fn connect(url: &str) -> Result<Self, DeliveryStoreError> {
+        let client = redis::Client::open(url)?;
+        Ok(Self::new(client.get_connection_manager().await?))
+    }
+
+    /// Use an existing connection
+    #[must_use]
+    pub fn new(conn: redis::aio::ConnectionManager) -> Self {
+        Self {
+            conn,
+            prefix: "esteria:dlr:".to_string(),
+            ttl: None,
+        }
+    }
+
+    /// Set the key prefix (default `esteria:dlr:`)
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Expire records after `ttl`, refreshed on every update
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn key(&self, message_id: &str) -> String {
+        format!("{}{message_id}", self.prefix)
+    }
+
+    fn refresh_ttl(&self, pipe: &mut redis::Pipeline, key: &str) {
+        if let Some(ttl) = self.ttl {
+            let seconds = i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX).max(1);
+            pipe.expire(key, seconds).ignore();
+        }
+    }
+
+    fn parse(message_id: &str, mut fields: HashMap<String, String>) -> Option<DeliveryRecord> {
+        let updated_at = fields.remove("updated_at")?.parse().ok()?;
+        let reason = fields.remove("reason");
+        Some(DeliveryRecord {
+            message_id: message_id.to_string(),
+            user_key: fields.remove("user_key"),
+            status: fields
+                .remove("status")
+                .map(|status| DeliveryStatus::from_report(&status, reason.as_deref())),
+            updated_at: DateTime::from_timestamp(updated_at, 0)?,
+            parts: fields
+                .remove("parts")
+                .map(|parts| parts.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl DeliveryStore for RedisDeliveryStore {
+    async fn save(
+        &self,
+        message_id: &str,
+        user_key: Option<&str>,
+    ) -> Result<(), DeliveryStoreError> {
+        let key = self.key(message_id);
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(&key).ignore();
+        pipe.hset(&key, "updated_at", Utc::now().timestamp())
+            .ignore();
+        if let Some(user_key) = user_key {
+            pipe.hset(&key, "user_key", user_key).ignore();
+        }
+        self.refresh_ttl(&mut pipe, &key);
+        let () = pipe.query_async(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+
+    async fn save_parts(
+        &self,
+        message_ids: &[String],
+        user_key: Option<&str>,
+    ) -> Result<(), DeliveryStoreError> {
+        let parts = message_ids.join(",");
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for message_id in message_ids {
+            let key = self.key(message_id);
+            pipe.del(&key).ignore();
+            pipe.hset(&key, "updated_at", Utc::now().timestamp())
+                .ignore();
+            pipe.hset(&key, "parts", &parts).ignore();
+            if let Some(user_key) = user_key {
+                pipe.hset(&key, "user_key", user_key).ignore();
+            }
+            self.refresh_ttl(&mut pipe, &key);
+        }
+        let () = pipe.query_async(&mut self.conn.clone()).await?;
+        Ok(())
+    }
+
+    async fn update(
+        &self,
+        message_id: &str,
+        status: &DeliveryStatus,
+    ) -> Result<Option<DeliveryRecord>, DeliveryStoreError> {
+        let key = self.key(message_id);
+        let mut conn = self.conn.clone();
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(&key)
+            .query_async(&mut conn)
+            .await?;
+        if !exists {
+            return Ok(None);
+        }
+
+        let mut pipe = redis::pipe();
+        pipe.atomic().hset(&key, "status", status.as_str()).ignore();
+        match status.reason() {
+            Some(reason) => pipe.hset(&key, "reason", reason).ignore(),
+            None => pipe.hdel(&key, "reason").ignore(),
+        };
+        pipe.hset(&key, "updated_at", Utc::now().timestamp())
+            .ignore();
+        self.refresh_ttl(&mut pipe, &key);
+        pipe.hgetall(&key);
+        let (fields,): (HashMap<String, String>,) = pipe.query_async(&mut conn).await?;
+        Ok(Self::parse(message_id, fields))
+    }
+
+    async fn get(&self, message_id: &str) -> Result<Option<DeliveryRecord>, DeliveryStoreError> {
+        let fields: HashMap<String, String> = redis::cmd("HGETALL")
+            .arg(self.key(message_id))
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(Self::parse(message_id, fields))
+    }
+}
+
+/// Query parameter carrying the user key in URLs built by [`DlrUrl`]
+pub const DLR_USER_KEY_PARAM: &str = "user_key";
+
+/// Percent-encode everything except RFC 3986 unreserved characters
+pub(crate) fn percent_encode(value: &str) -> String {
+    value.bytes().fold(String::new(), |mut out, b| {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(char::from(b));
+        } else {
+            out.push('%');
+            out.push_str(&sms::to_hex(&[b]));
+        }
+        out
+    })
+}
+
+/// Builder of delivery report callback URLs
+///
+/// Parameters are URL-encoded, while gateway placeholder tokens (e.g.
+/// `%d` for the status on Kannel-style gateways) are appended verbatim so
+/// the gateway can substitute them:
+///
+/// ```
+/// # use esteria_api_client::delivery::DlrUrl;
+/// let url = DlrUrl::new("https://example.com/dlr".parse().unwrap())
+///     .with_user_key("order 7")
+///     .with_placeholder("status", "%d")
+///     .build();
+/// assert_eq!(url, "https://example.com/dlr?user_key=order+7&status=%d");
+/// ```
+#[derive(Debug, Clone)]
+pub struct DlrUrl {
+    url: Url,
+    placeholders: Vec<(String, String)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    signer: Option<DlrUrlSigner>,
+}
+
+impl DlrUrl {
+    /// Callback to `base`, keeping any query it already has
+    ///
+    /// A fragment is dropped, as it is never sent to the server.
+    #[must_use]
+    pub fn new(mut base: Url) -> Self {
+        base.set_fragment(None);
+        Self {
+            url: base,
+            placeholders: Vec::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            signer: None,
+        }
+    }
+
+    /// Add a parameter, URL-encoded
+    #[must_use]
+    pub fn with_param(mut self, name: &str, value: &str) -> Self {
+        self.url.query_pairs_mut().append_pair(name, value);
+        self
+    }
+
+    /// Add our key for the message, e.g. an order ID
+    #[must_use]
+    pub fn with_user_key(self, user_key: &str) -> Self {
+        self.with_param(DLR_USER_KEY_PARAM, user_key)
+    }
+
+    /// Add a parameter whose value is a gateway placeholder, appended as is
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` contains `&`, `#` or whitespace, which would break
+    /// the query
+    #[must_use]
+    pub fn with_placeholder(mut self, name: &str, token: &str) -> Self {
+        assert!(
+            !token.contains(['&', '#']) && !token.contains(char::is_whitespace),
+            "invalid placeholder token {token:?}"
+        );
+        self.placeholders
+            .push((name.to_string(), token.to_string()));
+        self
+    }
+
+    /// Add a token over the parameters, checked by [`DlrUrlSigner::verify`]
+    ///
+    /// Placeholders are not covered; the verifying signer must list them as
+    /// [unsigned](DlrUrlSigner::with_unsigned_params).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_signer(mut self, signer: DlrUrlSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// The callback URL, to pass to [`SmsRequest::with_dlr_url`]
+    #[must_use]
+    pub fn build(&self) -> String {
+        #[cfg(not(target_arch = "wasm32"))]
+        let url = match &self.signer {
+            Some(signer) => signer.sign(&self.url),
+            None => self.url.clone(),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let url = self.url.clone();
+        let mut built = url.to_string();
+        let mut separator = if url.query().is_some() { '&' } else { '?' };
+        for (name, token) in &self.placeholders {
+            built.push(separator);
+            built.push_str(&percent_encode(name));
+            built.push('=');
+            built.push_str(token);
+            separator = '&';
+        }
+        built
+    }
+}
+
+/// Poll `store` every `interval` for status changes of a tracked message
+///
+/// Yields the parsed status whenever it changes, and ends after a
+/// [terminal](DeliveryStatus::is_terminal) status, once the message is no
+/// longer tracked, or after yielding a store error. The gateway has no status
+/// endpoint, so the store must be fed by a DLR receiver, e.g. the REST server
+/// (feature `server`) sharing a Redis store.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn watch_status<'s, S: DeliveryStore>(
+    store: &'s S,
+    message_id: &'s str,
+    interval: std::time::Duration,
+) -> impl futures_util::Stream<Item = Result<DeliveryStatus, DeliveryStoreError>> + Send + 's {
+    let state = (Some(None::<DeliveryStatus>), true);
+    futures_util::stream::unfold(state, move |(last, first)| async move {
+        let last = last?;
+        if !first {
+            tokio::time::sleep(interval).await;
+        }
+        loop {
+            let status = match store.get(message_id).await {
+                Ok(Some(record)) => record.status,
+                Ok(None) => return None,
+                Err(err) => return Some((Err(err), (None, false))),
+            };
+            if let Some(status) = status
+                && last.as_ref() != Some(&status)
+            {
+                let next = (!status.is_terminal()).then(|| Some(status.clone()));
+                return Some((Ok(status), (next, false)));
+            }
+            tokio::time::sleep(interval).await;
+        }
+    })
+}
+
+impl SmsClient {
+    /// Send an SMS and record its message ID and user key in `store`
+    ///
+    /// A store failure is logged rather than returned, since the message has
+    /// already been accepted by the gateway and must not be sent again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError` if the message could not be sent
+    pub async fn send_tracked<S: DeliveryStore>(
+        &self,
+        request: SmsRequest<'_>,
+        store: &S,
+    ) -> Result<String, SmsError> {
+        let user_key = request.user_key.clone();
+        let message_id = self.send_sms(request).await?;
+        if let Err(err) = store.save(&message_id, user_key.as_deref()).await {
+            log::error!("Failed to track message {message_id}: {err}");
+        }
+        Ok(message_id)
+    }
+
+    /// Send a long SMS as concatenated parts and track all of them in `store`
+    ///
+    /// Delivery reports of the parts are then aggregated by
+    /// [`SmsClient::record_delivery`]. Store failures are logged, as for
+    /// [`SmsClient::send_tracked`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first part that fails (see [`SmsClient::send_long_sms`])
+    pub async fn send_long_tracked<S: DeliveryStore>(
+        &self,
+        request: SmsRequest<'_>,
+        store: &S,
+    ) -> Result<MultipartResult, SmsError> {
+        let user_key = request.user_key.clone();
+        let result = self.send_long_sms(request).await?;
+        let saved = match result.message_ids.as_slice() {
+            [message_id] => store.save(message_id, user_key.as_deref()).await,
+            parts => store.save_parts(parts, user_key.as_deref()).await,
+        };
+        if let Err(err) = saved {
+            log::error!("Failed to track message {:?}: {err}", result.message_ids);
+        }
+        Ok(result)
+    }
+
+    /// Apply a delivery report to `store` and notify event listeners
+    ///
+    /// Returns the updated record, or `None` if the message is not tracked
+    /// (listeners are then not notified). Besides the per-part
+    /// `on_delivered`, listeners get `on_message_delivered` once, when the
+    /// aggregated status of the message first becomes
+    /// [terminal](DeliveryStatus::is_terminal): every part delivered, or
+    /// some part failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DeliveryStoreError` if the store can't be updated
+    pub async fn record_delivery<S: DeliveryStore>(
+        &self,
+        store: &S,
+        message_id: &str,
+        status: &DeliveryStatus,
+    ) -> Result<Option<DeliveryRecord>, DeliveryStoreError> {
+        let previous = store
+            .get(message_id)
+            .await?
+            .and_then(|record| record.status);
+        let Some(record) = store.update(message_id, status).await? else {
+            return Ok(None);
+        };
+        for listener in &self.event_listeners {
+            listener.on_delivered(&record);
+        }
+
+        let mut parts = if record.parts.is_empty() {
+            vec![record.clone()]
+        } else {
+            let mut parts = Vec::with_capacity(record.parts.len());
+            for part in &record.parts {
+                match store.get(part).await? {
+                    Some(part) => parts.push(part),
+                    None => return Ok(Some(record)),
+                }
+            }
+            parts
+        };
+        let is_terminal = |delivery: &Option<MessageDelivery>| {
+            delivery
+                .as_ref()
+                .is_some_and(|delivery| delivery.status.is_terminal())
+        };
+        let delivery = MessageDelivery::aggregate(&parts);
+        if !is_terminal(&delivery) {
+            return Ok(Some(record));
+        }
+        // Compare with the message before this report, so the event fires once
+        if let Some(part) = parts.iter_mut().find(|part| part.message_id == message_id) {
+            part.status = previous;
+        }
+        if !is_terminal(&MessageDelivery::aggregate(&parts))
+            && let Some(delivery) = delivery
+        {
+            for listener in &self.event_listeners {
+                listener.on_message_delivered(&delivery);
+            }
+        }
+        Ok(Some(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn memory_store_tracks_updates() {
+        let store = MemoryDeliveryStore::new();
+        store.save("1001", Some("order-7")).await.unwrap();
+
+        let delivered = DeliveryStatus::Delivered;
+        let record = store.update("1001", &delivered).await.unwrap().unwrap();
+        assert_eq!(record.user_key.as_deref(), Some("order-7"));
+        assert_eq!(record.status, Some(delivered.clone()));
+        assert_eq!(store.get("1001").await.unwrap(), Some(record));
+        assert_eq!(store.update("1002", &delivered).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn send_tracked_saves_user_key() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("user-key", "order-7");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let store = MemoryDeliveryStore::new();
+        let request =
+            SmsRequest::new("k", "Alice", "+37061234567", "Shipped").with_user_key("order-7");
+        let message_id = client.send_tracked(request, &store).await.unwrap();
+
+        assert_eq!(message_id, "1001");
+        let record = store.get("1001").await.unwrap().unwrap();
+        assert_eq!(record.user_key.as_deref(), Some("order-7"));
+        assert_eq!(record.status, None);
+        m.assert();
+    }
+
+    #[derive(Debug, Default)]
+    struct Deliveries(Mutex<Vec<MessageDelivery>>);
+
+    impl crate::events::EventListener for Deliveries {
+        fn on_message_delivered(&self, delivery: &MessageDelivery) {
+            self.0.lock().unwrap().push(delivery.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn aggregates_multipart_deliveries() {
+        let deliveries = std::sync::Arc::new(Deliveries::default());
+        let client = SmsClient::builder()
+            .with_event_listener(deliveries.clone())
+            .build()
+            .unwrap();
+        let store = MemoryDeliveryStore::new();
+        let parts = ["1001".to_string(), "1002".to_string()];
+        store.save_parts(&parts, Some("order-7")).await.unwrap();
+
+        let delivered = DeliveryStatus::Delivered;
+        client
+            .record_delivery(&store, "1002", &delivered)
+            .await
+            .unwrap();
+        assert!(deliveries.0.lock().unwrap().is_empty());
+
+        let record = client
+            .record_delivery(&store, "1001", &delivered)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.parts, parts);
+        client
+            .record_delivery(&store, "1002", &DeliveryStatus::Expired)
+            .await
+            .unwrap();
+
+        let deliveries = deliveries.0.lock().unwrap();
+        let statuses: Vec<_> = deliveries.iter().map(|d| d.status.clone()).collect();
+        assert_eq!(statuses, [DeliveryStatus::Delivered]);
+        assert_eq!(deliveries[0].message_ids, parts);
+        assert_eq!(deliveries[0].user_key.as_deref(), Some("order-7"));
+    }
+
+    #[tokio::test]
+    async fn reports_failed_parts_once() {
+        let deliveries = std::sync::Arc::new(Deliveries::default());
+        let client = SmsClient::builder()
+            .with_event_listener(deliveries.clone())
+            .build()
+            .unwrap();
+        let store = MemoryDeliveryStore::new();
+        let parts = ["1001".to_string(), "1002".to_string()];
+        store.save_parts(&parts, None).await.unwrap();
+
+        for (part, status) in [
+            ("1001", "queued"),
+            ("1001", "sent"),
+            ("1002", "undelivered"),
+            ("1001", "delivered"),
+        ] {
+            let status = DeliveryStatus::parse(status);
+            client.record_delivery(&store, part, &status).await.unwrap();
+        }
+
+        let deliveries = deliveries.0.lock().unwrap();
+        let statuses: Vec<_> = deliveries.iter().map(|d| d.status.clone()).collect();
+        assert_eq!(statuses, [DeliveryStatus::Undelivered { reason: None }]);
+    }
+
+    #[test]
+    fn parses_gateway_statuses() {
+        assert_eq!(DeliveryStatus::parse("DELIVRD"), DeliveryStatus::Delivered);
+        assert_eq!(DeliveryStatus::parse(" 8 "), DeliveryStatus::Sent);
+        assert_eq!(
+            DeliveryStatus::parse("failed"),
+            DeliveryStatus::Undelivered { reason: None }
+        );
+        assert_eq!(DeliveryStatus::parse("16"), DeliveryStatus::Rejected);
+        let other = DeliveryStatus::parse("Limbo");
+        assert_eq!(other, DeliveryStatus::Other("Limbo".to_string()));
+        assert!(!other.is_terminal());
+        assert!(DeliveryStatus::Expired.is_terminal());
+        assert!(!DeliveryStatus::Expired.is_success());
+
+        let undelivered = DeliveryStatus::from_report("UNDELIV", Some(" 013 "));
+        assert_eq!(undelivered.reason(), Some("013"));
+        assert_eq!(undelivered.to_string(), "undelivered: 013");
+        assert_eq!(
+            DeliveryStatus::from_report("failed", Some("")),
+            DeliveryStatus::Undelivered { reason: None }
+        );
+        assert_eq!(
+            DeliveryStatus::from_report("DELIVRD", Some("000")),
+            DeliveryStatus::Delivered
+        );
+    }
+
+    #[tokio::test]
+    async fn watch_status_polls_until_final() {
+        use futures_util::StreamExt;
+        use std::time::Duration;
+
+        let store = std::sync::Arc::new(MemoryDeliveryStore::new());
+        store.save("1001", None).await.unwrap();
+        let reports = store.clone();
+        tokio::spawn(async move {
+            for status in ["sent", "ENROUTE", "delivered", "expired"] {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let status = DeliveryStatus::parse(status);
+                reports.update("1001", &status).await.unwrap();
+            }
+        });
+
+        let statuses: Vec<_> = watch_status(&*store, "1001", Duration::from_millis(5))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(statuses, [DeliveryStatus::Sent, DeliveryStatus::Delivered]);
+
+        let untracked: Vec<_> = watch_status(&*store, "9", Duration::from_millis(5))
+            .collect()
+            .await;
+        assert!(untracked.is_empty());
+    }
+
+    #[test]
+    fn builds_signed_dlr_urls_with_placeholders() {
+        let signer = DlrUrlSigner::new("secret").with_unsigned_params(["status"]);
+        let base = Url::parse("https://example.com/dlr?tenant=a#ignored").unwrap();
+        let url = DlrUrl::new(base)
+            .with_user_key("order&7")
+            .with_placeholder("status", "%d")
+            .with_signer(signer.clone())
+            .build();
+        assert!(url.starts_with("https://example.com/dlr?tenant=a&user_key=order%267&token="));
+        assert!(url.ends_with("&status=%d"));
+
+        let callback = Url::parse(&url.replace("%d", "1")).unwrap();
+        assert!(signer.verify(&callback));
+        let user_key = callback
+            .query_pairs()
+            .find(|(name, _)| name == DLR_USER_KEY_PARAM)
+            .map(|(_, value)| value.into_owned());
+        assert_eq!(user_key.as_deref(), Some("order&7"));
+    }
+}
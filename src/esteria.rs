@@ -1,20 +1,264 @@
-use chrono::{DateTime, Utc};
-use reqwest::Client;
+use crate::audit::{AuditRecord, AuditSink, GatewayCall};
+use crate::auth::Auth;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::connection::{ConnectionOptions, HttpVersion, ProxyConfig};
+use crate::credentials::{CredentialsError, CredentialsProvider};
+use crate::events::EventListener;
+use crate::filter::{BlockReason, NumberFilter};
+use crate::idempotency::{self, IdempotencyStore, Reservation};
+#[cfg(feature = "metrics")]
+use crate::metrics::SmsMetrics;
+use crate::middleware::Middleware;
+use crate::optout::OptOutRegistry;
+use crate::phone::{self, PhoneError};
+use crate::pricing::PriceTable;
+use crate::quiet_hours::{self, QuietHours};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::rate_limit::{RateLimit, RateLimiter};
+use crate::redact;
+use crate::response;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::retry::RetryPolicy;
+use crate::sender::{self, SenderError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::signing::RequestSigner;
+use crate::sms;
+use crate::template::TemplateError;
+#[cfg(all(
+    any(feature = "rustls", feature = "native-tls"),
+    not(target_arch = "wasm32")
+))]
+use crate::tls::Certificate;
+#[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+use crate::tls::Identity;
+use crate::transliterate::Transliterator;
+use crate::udh::Udh;
+use crate::wap::WapPush;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures_util::future::{self, Either};
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::dns::Resolve;
+use reqwest::{Client, Request, StatusCode};
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::future::Future;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::SocketAddr;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::Duration;
 use thiserror::Error;
 
 /// Error types for SMS operations
-#[derive(Error, Debug)]
+///
+/// Numbers in messages and `Debug` output follow the [`redact`] policy.
+#[derive(Error)]
 pub enum SmsError {
-    #[error("SMS sending failed to: {number}, {message}")]
-    SendFailed { number: String, message: String },
+    /// The gateway rejected the message; `code` is the gateway error code, if any
+    #[error("SMS sending failed to: {}, {message}", redact::number(number))]
+    SendFailed {
+        number: String,
+        code: Option<i32>,
+        message: String,
+    },
     #[error("HTTP request failed: {0}")]
     RequestFailed(#[from] reqwest::Error),
+    /// The gateway answered with HTTP 429 or a 5xx status, e.g. while overloaded
+    #[error("SMS sending to {} failed with HTTP {status}", redact::number(number))]
+    HttpStatus { number: String, status: u16 },
+    #[error("invalid number: {}, {reason}", redact::number(number))]
+    InvalidNumber { number: String, reason: PhoneError },
+    #[error("template rendering failed: {0}")]
+    Template(#[from] TemplateError),
+    #[error("recipient opted out: {}", redact::number(number))]
+    OptedOut { number: String },
+    #[error("number blocked: {}, {reason}", redact::number(number))]
+    Blocked { number: String, reason: BlockReason },
+    #[error("invalid sender: {sender}, {reason}")]
+    InvalidSender { sender: String, reason: SenderError },
+    #[error("SMS sending to {} timed out", redact::number(number))]
+    Timeout { number: String },
+    #[error("SMS sending to {} was cancelled", redact::number(number))]
+    Cancelled { number: String },
+    /// A send with the same idempotency key has not finished yet
+    #[error("SMS to {} is already being sent", redact::number(number))]
+    InProgress { number: String },
+    /// An earlier send with the same idempotency key may have been accepted
+    #[error("SMS to {} may already have been sent", redact::number(number))]
+    Uncertain { number: String },
+    /// The gateway answered with an unregistered code or a non-numeric body
+    #[error("unknown gateway response: {raw}")]
+    UnknownResponse { raw: String },
+    /// The TLS configuration of the client is unusable, e.g. a bad root certificate
+    #[error("TLS setup failed: {reason}")]
+    Tls { reason: String },
+    /// The client's credentials provider had no credentials
+    #[error("{0}")]
+    Credentials(#[from] CredentialsError),
+}
+
+impl std::fmt::Debug for SmsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SendFailed {
+                number,
+                code,
+                message,
+            } => f
+                .debug_struct("SendFailed")
+                .field("number", &redact::number(number))
+                .field("code", code)
+                .field("message", message)
+                .finish(),
+            Self::RequestFailed(err) => f.debug_tuple("RequestFailed").field(err).finish(),
+            Self::HttpStatus { number, status } => f
+                .debug_struct("HttpStatus")
+                .field("number", &redact::number(number))
+                .field("status", status)
+                .finish(),
+            Self::InvalidNumber { number, reason } => f
+                .debug_struct("InvalidNumber")
+                .field("number", &redact::number(number))
+                .field("reason", reason)
+                .finish(),
+            Self::Template(err) => f.debug_tuple("Template").field(err).finish(),
+            Self::OptedOut { number } => f
+                .debug_struct("OptedOut")
+                .field("number", &redact::number(number))
+                .finish(),
+            Self::Blocked { number, reason } => f
+                .debug_struct("Blocked")
+                .field("number", &redact::number(number))
+                .field("reason", reason)
+                .finish(),
+            Self::InvalidSender { sender, reason } => f
+                .debug_struct("InvalidSender")
+                .field("sender", sender)
+                .field("reason", reason)
+                .finish(),
+            Self::Timeout { number } => f
+                .debug_struct("Timeout")
+                .field("number", &redact::number(number))
+                .finish(),
+            Self::Cancelled { number } => f
+                .debug_struct("Cancelled")
+                .field("number", &redact::number(number))
+                .finish(),
+            Self::InProgress { number } => f
+                .debug_struct("InProgress")
+                .field("number", &redact::number(number))
+                .finish(),
+            Self::Uncertain { number } => f
+                .debug_struct("Uncertain")
+                .field("number", &redact::number(number))
+                .finish(),
+            Self::UnknownResponse { raw } => {
+                f.debug_struct("UnknownResponse").field("raw", raw).finish()
+            }
+            Self::Tls { reason } => f.debug_struct("Tls").field("reason", reason).finish(),
+            Self::Credentials(err) => f.debug_tuple("Credentials").field(err).finish(),
+        }
+    }
+}
+
+impl SmsError {
+    /// Whether sending the same request again may succeed
+    ///
+    /// Gateway code 1 (internal error), codes registered as retryable (see
+    /// [`response::register`]), connection failures, timeouts, HTTP 429 and
+    /// 5xx statuses and duplicates of a send in progress are transient.
+    /// Other gateway codes (invalid parameters, authentication, disallowed
+    /// senders), unknown responses and client-side rejections are permanent.
+    /// A timed out request may still have been accepted, so retries should
+    /// use an idempotency key.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::SendFailed { code, .. } => code
+                .and_then(response::lookup)
+                .is_some_and(|code| code.retryable),
+            Self::RequestFailed(err) => err.is_timeout() || err.is_connect(),
+            Self::Timeout { .. }
+            | Self::HttpStatus { .. }
+            | Self::InProgress { .. }
+            | Self::Credentials(_) => true,
+            Self::InvalidNumber { .. }
+            | Self::Template(_)
+            | Self::OptedOut { .. }
+            | Self::Blocked { .. }
+            | Self::InvalidSender { .. }
+            | Self::Cancelled { .. }
+            | Self::Uncertain { .. }
+            | Self::UnknownResponse { .. }
+            | Self::Tls { .. } => false,
+        }
+    }
+
+    /// Whether the gateway may have accepted the message despite the error
+    ///
+    /// Timeouts, failures after connecting, 5xx statuses and unknown responses
+    /// leave the outcome open; client-side rejections, connection failures,
+    /// HTTP 429 and gateway error codes mean the message was not accepted.
+    pub(crate) fn may_have_been_sent(&self) -> bool {
+        match self {
+            Self::Timeout { .. } | Self::UnknownResponse { .. } => true,
+            Self::RequestFailed(err) => !(err.is_connect() || err.is_builder()),
+            Self::HttpStatus { status, .. } => *status != StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            _ => false,
+        }
+    }
+}
+
+/// Serialized as `{"kind": ..., "number": ..., "message": ...}` for structured logging
+///
+/// The number follows the [`redact`] policy.
+#[cfg(feature = "serde")]
+impl serde::Serialize for SmsError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let (kind, number, message) = match self {
+            Self::SendFailed {
+                number, message, ..
+            } => ("send_failed", Some(number), message.clone()),
+            Self::RequestFailed(err) => ("request_failed", None, err.to_string()),
+            Self::HttpStatus { number, .. } => ("http_status", Some(number), self.to_string()),
+            Self::InvalidNumber { number, reason } => {
+                ("invalid_number", Some(number), reason.to_string())
+            }
+            Self::Template(err) => ("template", None, err.to_string()),
+            Self::OptedOut { number } => ("opted_out", Some(number), self.to_string()),
+            Self::Blocked { number, reason } => ("blocked", Some(number), reason.to_string()),
+            Self::InvalidSender { .. } => ("invalid_sender", None, self.to_string()),
+            Self::Timeout { number } => ("timeout", Some(number), self.to_string()),
+            Self::Cancelled { number } => ("cancelled", Some(number), self.to_string()),
+            Self::InProgress { number } => ("in_progress", Some(number), self.to_string()),
+            Self::Uncertain { number } => ("uncertain", Some(number), self.to_string()),
+            Self::UnknownResponse { raw } => ("unknown_response", None, raw.clone()),
+            Self::Tls { reason } => ("tls", None, reason.clone()),
+            Self::Credentials(err) => ("credentials", None, err.to_string()),
+        };
+
+        let mut state = serializer.serialize_struct("SmsError", 3)?;
+        state.serialize_field("kind", kind)?;
+        match number {
+            Some(number) => {
+                state.serialize_field("number", &redact::number(number).to_string())?;
+            }
+            None => state.skip_field("number")?,
+        }
+        state.serialize_field("message", &message)?;
+        state.end()
+    }
 }
 
 bitflags::bitflags! {
     /// Flags for SMS sending options
     #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(feature = "serde", serde(transparent))]
     pub struct SmsFlags: u32 {
         /// Enable debug mode
         const DEBUG   = 0b0000_0001;
@@ -32,65 +276,406 @@ bitflags::bitflags! {
 }
 
 /// SMS encoding options
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum Encoding {
     /// Default encoding
+    #[default]
     Default,
     /// 8-bit encoding
     EightBit,
     /// User Data Header encoding
     Udh,
+    /// GSM-7 if the text fits the GSM alphabet, UCS-2 otherwise
+    Auto,
+    /// UCS-2 (Unicode) encoding, for Cyrillic, Arabic, emoji and other scripts
+    Ucs2,
+}
+
+/// SMS message class (3GPP TS 23.038), telling the phone where to store the message
+///
+/// The Esteria gateway only documents `flag-flash`, so only class 0 is sent
+/// to it; classes 1-3 are passed on by providers that support them, such as
+/// Kannel, and otherwise left to the gateway default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum MessageClass {
+    /// Class 0: displayed immediately and not stored (flash SMS), sent as `flag-flash`
+    Class0,
+    /// Class 1: stored in phone memory
+    Class1,
+    /// Class 2: stored on the SIM card
+    Class2,
+    /// Class 3: forwarded to terminal equipment
+    Class3,
+}
+
+/// Dispatch priority of a message in client-side queues
+///
+/// Higher-priority messages are taken first, e.g. by [`crate::outbox::Outbox`],
+/// so one-time passwords overtake a backlog of marketing traffic. It is not
+/// sent to the gateway.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Priority {
+    /// Sent after everything else, e.g. marketing campaigns
+    Low,
+    #[default]
+    Normal,
+    /// Sent first, e.g. one-time passwords
+    High,
+}
+
+/// Kind of message content, deciding whether the opt-out footer is added
+///
+/// See [`SmsClientBuilder::with_opt_out_footer`]. It is not sent to the gateway.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum MessageCategory {
+    /// Messages the recipient needs regardless of consent, e.g. one-time passwords
+    #[default]
+    Transactional,
+    /// Promotional content, which must offer a way to opt out
+    Marketing,
 }
 
 /// SMS API client for Esteria
 pub struct SmsClient {
     api_base_url: String,
     client: Client,
+    normalize_numbers: bool,
+    default_country: Option<String>,
+    validate_numbers: bool,
+    validate_senders: bool,
+    transliterator: Option<Transliterator>,
+    concat_reference: AtomicU8,
+    pub(crate) bulk_concurrency: usize,
+    quiet_hours: Option<QuietHours>,
+    gateway_timezone: Tz,
+    opt_out_registry: Option<Arc<dyn OptOutRegistry>>,
+    opt_out_footer: Option<String>,
+    number_filter: Option<NumberFilter>,
+    idempotency: Option<(Arc<dyn IdempotencyStore>, Duration)>,
+    pub(crate) price_table: Option<PriceTable>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<SmsMetrics>>,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    pub(crate) event_listeners: Vec<Arc<dyn EventListener>>,
+    timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    rate_limiter: Option<RateLimiter>,
+    #[cfg(not(target_arch = "wasm32"))]
+    retry: Option<RetryPolicy>,
+    auth: Auth,
+    #[cfg(not(target_arch = "wasm32"))]
+    signer: Option<RequestSigner>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+}
+
+/// Builder for [`SmsClient`] with non-default options
+#[derive(Debug)]
+pub struct SmsClientBuilder {
+    api_base_url: Option<String>,
+    normalize_numbers: bool,
+    default_country: Option<String>,
+    validate_numbers: bool,
+    validate_senders: bool,
+    transliterator: Option<Transliterator>,
+    bulk_concurrency: usize,
+    quiet_hours: Option<QuietHours>,
+    gateway_timezone: Tz,
+    opt_out_registry: Option<Arc<dyn OptOutRegistry>>,
+    opt_out_footer: Option<String>,
+    number_filter: Option<NumberFilter>,
+    idempotency: Option<(Arc<dyn IdempotencyStore>, Duration)>,
+    price_table: Option<PriceTable>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<SmsMetrics>>,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    event_listeners: Vec<Arc<dyn EventListener>>,
+    timeout: Option<Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    rate_limit: Option<RateLimit>,
+    #[cfg(not(target_arch = "wasm32"))]
+    connection: ConnectionOptions,
+    #[cfg(not(target_arch = "wasm32"))]
+    retry: Option<RetryPolicy>,
+    auth: Auth,
+    #[cfg(not(target_arch = "wasm32"))]
+    signer: Option<RequestSigner>,
+    credentials: Option<Arc<dyn CredentialsProvider>>,
+}
+
+impl Default for SmsClientBuilder {
+    fn default() -> Self {
+        Self {
+            api_base_url: None,
+            normalize_numbers: false,
+            default_country: None,
+            validate_numbers: true,
+            validate_senders: true,
+            transliterator: None,
+            bulk_concurrency: 1,
+            quiet_hours: None,
+            gateway_timezone: Tz::UTC,
+            opt_out_registry: None,
+            opt_out_footer: None,
+            number_filter: None,
+            idempotency: None,
+            price_table: None,
+            middleware: Vec::new(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            audit_sink: None,
+            event_listeners: Vec::new(),
+            timeout: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            rate_limit: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            connection: ConnectionOptions::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            retry: None,
+            auth: Auth::ApiKey,
+            #[cfg(not(target_arch = "wasm32"))]
+            signer: None,
+            credentials: None,
+        }
+    }
 }
 
 /// Request structure for sending SMS
+///
+/// String fields are copy-on-write, so a request can either borrow its data
+/// or own it. Use [`SmsRequest::into_owned`] to detach a request from the
+/// data it was built from, e.g. to move it into a spawned task.
+///
+/// Serialized requests (the `serde` feature) include the API key, so outbox
+/// rows, queue jobs and hook payloads hold it in clear. Leave the key empty
+/// and configure it on the client (see
+/// [`SmsClientBuilder::with_credentials_provider`]) to keep it out of them.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SmsRequest<'a> {
-    pub api_key: &'a str,
-    pub sender: &'a str,
-    pub number: &'a str,
-    pub text: &'a str,
+    /// Gateway API key; empty to use the client's credentials provider
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub api_key: Cow<'a, str>,
+    pub sender: Cow<'a, str>,
+    pub number: Cow<'a, str>,
+    pub text: Cow<'a, str>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub time: Option<DateTime<Utc>>,
-    pub dlr_url: Option<&'a str>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub dlr_url: Option<Cow<'a, str>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
     pub expired: Option<i32>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    #[cfg_attr(
+        feature = "openapi",
+        schema(value_type = String, example = "FLASH | TEST")
+    )]
     pub flags: SmsFlags,
-    pub user_key: Option<&'a str>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub user_key: Option<Cow<'a, str>>,
+    #[cfg_attr(feature = "serde", serde(default))]
     pub encoding: Encoding,
+    /// Raw User Data Header, sent hex-encoded
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<Vec<u8>>))]
+    pub udh: Option<Cow<'a, [u8]>>,
+    /// Whether `text` is a hex-encoded binary payload, see [`SmsRequest::with_binary`]
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub binary: bool,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub message_class: Option<MessageClass>,
+    /// Key for duplicate send protection, not sent to the gateway
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub idempotency_key: Option<Cow<'a, str>>,
+    /// Gateway request timeout, overriding the client default
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    #[cfg_attr(feature = "openapi", schema(value_type = Option<crate::openapi::DurationSchema>))]
+    pub timeout: Option<Duration>,
+    /// Order in client-side queues, not sent to the gateway
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub priority: Priority,
+    /// Content category, not sent to the gateway
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub category: MessageCategory,
+}
+
+/// `Debug` output omits the API key and shows the number according to the [`redact`] policy
+impl std::fmt::Debug for SmsRequest<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let api_key = if self.api_key.is_empty() { "" } else { "***" };
+        f.debug_struct("SmsRequest")
+            .field("api_key", &api_key)
+            .field("sender", &self.sender)
+            .field("number", &redact::number(&self.number))
+            .field("text", &self.text)
+            .field("time", &self.time)
+            .field("dlr_url", &self.dlr_url)
+            .field("expired", &self.expired)
+            .field("flags", &self.flags)
+            .field("user_key", &self.user_key)
+            .field("encoding", &self.encoding)
+            .field("udh", &self.udh)
+            .field("binary", &self.binary)
+            .field("message_class", &self.message_class)
+            .field("idempotency_key", &self.idempotency_key)
+            .field("timeout", &self.timeout)
+            .field("priority", &self.priority)
+            .field("category", &self.category)
+            .finish()
+    }
 }
 
+/// Result of a successful send through an [`SmsSender`]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SendResult {
+    /// Gateway message ID
+    pub message_id: String,
+    /// Recipient number as given in the request
+    pub number: String,
+}
+
+impl std::fmt::Debug for SendResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SendResult")
+            .field("message_id", &self.message_id)
+            .field("number", &redact::number(&self.number))
+            .finish()
+    }
+}
+
+/// Result of sending a message that may span several concatenated parts
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultipartResult {
+    /// Concatenation reference shared by all parts (`None` for a single part)
+    pub reference: Option<u8>,
+    /// Message IDs of the parts, in order
+    pub message_ids: Vec<String>,
+}
+
+/// SMS request that owns all of its data
+///
+/// Can be sent across task boundaries; convert back with [`SmsRequest::as_borrowed`].
+pub type SmsRequestOwned = SmsRequest<'static>;
+
 impl<'a> SmsRequest<'a> {
     /// Create a new SMS request with required parameters
     #[must_use]
-    pub fn new(api_key: &'a str, sender: &'a str, number: &'a str, text: &'a str) -> Self {
+    pub fn new(
+        api_key: impl Into<Cow<'a, str>>,
+        sender: impl Into<Cow<'a, str>>,
+        number: impl Into<Cow<'a, str>>,
+        text: impl Into<Cow<'a, str>>,
+    ) -> Self {
         Self {
-            api_key,
-            sender,
-            number,
-            text,
+            api_key: api_key.into(),
+            sender: sender.into(),
+            number: number.into(),
+            text: text.into(),
             time: None,
             dlr_url: None,
             expired: None,
             flags: SmsFlags::empty(),
             user_key: None,
             encoding: Encoding::Default,
+            udh: None,
+            binary: false,
+            message_class: None,
+            idempotency_key: None,
+            timeout: None,
+            priority: Priority::Normal,
+            category: MessageCategory::Transactional,
         }
     }
 
+    /// Create a flash SMS request, displayed immediately instead of being stored
+    #[must_use]
+    pub fn flash(
+        api_key: impl Into<Cow<'a, str>>,
+        sender: impl Into<Cow<'a, str>>,
+        number: impl Into<Cow<'a, str>>,
+        text: impl Into<Cow<'a, str>>,
+    ) -> Self {
+        Self::new(api_key, sender, number, text).with_message_class(MessageClass::Class0)
+    }
+
+    /// Create a test-mode request, validated by the gateway but not delivered
+    #[must_use]
+    pub fn test(
+        api_key: impl Into<Cow<'a, str>>,
+        sender: impl Into<Cow<'a, str>>,
+        number: impl Into<Cow<'a, str>>,
+        text: impl Into<Cow<'a, str>>,
+    ) -> Self {
+        Self::new(api_key, sender, number, text).with_flags(SmsFlags::TEST)
+    }
+
     /// Set scheduled delivery time
+    ///
+    /// The time may be given in any timezone. It is stored as UTC and converted
+    /// to the gateway timezone when sent, see [`SmsClientBuilder::with_gateway_timezone`].
     #[must_use]
-    pub fn with_time(mut self, time: DateTime<Utc>) -> Self {
-        self.time = Some(time);
+    pub fn with_time<Z: TimeZone>(mut self, time: DateTime<Z>) -> Self {
+        self.time = Some(time.with_timezone(&Utc));
         self
     }
 
+    /// Set scheduled delivery time as wall-clock time in `timezone`
+    ///
+    /// Ambiguous times (DST fall-back) resolve to the earlier instant; times
+    /// skipped by a DST change are moved forward by an hour.
+    #[must_use]
+    pub fn with_local_time(self, time: NaiveDateTime, timezone: Tz) -> Self {
+        self.with_time(quiet_hours::resolve_local(timezone, time))
+    }
+
     /// Set delivery report URL
     #[must_use]
-    pub fn with_dlr_url(mut self, dlr_url: &'a str) -> Self {
-        self.dlr_url = Some(dlr_url);
+    pub fn with_dlr_url(mut self, dlr_url: impl Into<Cow<'a, str>>) -> Self {
+        self.dlr_url = Some(dlr_url.into());
         self
     }
 
@@ -110,8 +695,8 @@ impl<'a> SmsRequest<'a> {
 
     /// Set user key for tracking
     #[must_use]
-    pub fn with_user_key(mut self, user_key: &'a str) -> Self {
-        self.user_key = Some(user_key);
+    pub fn with_user_key(mut self, user_key: impl Into<Cow<'a, str>>) -> Self {
+        self.user_key = Some(user_key.into());
         self
     }
 
@@ -121,190 +706,1335 @@ impl<'a> SmsRequest<'a> {
         self.encoding = encoding;
         self
     }
-}
 
-impl Default for SmsClient {
-    fn default() -> Self {
-        Self::with_api_base_url("https://api.esteria.eu")
+    /// Replace the text with a binary payload
+    ///
+    /// The payload is sent hex-encoded with 8-bit coding, and segments are
+    /// counted in payload bytes. Combine with [`SmsRequest::with_udh`] for
+    /// port-addressed payloads (e.g. OTA settings); the payload and header
+    /// must then fit a single message, see [`Udh::remaining_capacity`].
+    #[must_use]
+    pub fn with_binary(mut self, payload: &[u8]) -> Self {
+        self.text = Cow::Owned(sms::to_hex(payload));
+        self.encoding = Encoding::EightBit;
+        self.binary = true;
+        self
     }
-}
 
-impl SmsClient {
-    /// Create a new SMS client using the default API base URL
-    #[allow(dead_code)]
+    /// Set message class
     #[must_use]
-    pub fn new() -> Self {
-        Self::default()
+    pub fn with_message_class(mut self, message_class: MessageClass) -> Self {
+        self.message_class = Some(message_class);
+        self
     }
 
-    /// Create a new SMS client with a custom API base URL
+    /// Set a User Data Header, either raw bytes or a [`Udh`]
     #[must_use]
-    pub fn with_api_base_url(api_base_url: impl Into<String>) -> Self {
-        Self {
-            api_base_url: api_base_url.into(),
-            client: Client::new(),
-        }
+    pub fn with_udh(mut self, udh: impl Into<Cow<'a, [u8]>>) -> Self {
+        self.udh = Some(udh.into());
+        self
     }
 
-    /// Send an SMS message
-    ///
-    /// Returns the message ID on success (> 100)
+    /// Set the idempotency key, see [`SmsClientBuilder::with_idempotency`]
     ///
-    /// # Errors
+    /// Without an explicit key, one is derived from the sender, number and text.
+    #[must_use]
+    pub fn with_idempotency_key(mut self, key: impl Into<Cow<'a, str>>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Fail with `SmsError::Timeout` if the gateway doesn't answer within `timeout`
     ///
-    /// Returns `SmsError::SendFailed` if the API returns an error code (< 100)
-    /// or `SmsError::RequestFailed` if the HTTP request fails
-    pub async fn send_sms(&self, request: SmsRequest<'_>) -> Result<String, SmsError> {
-        let mut params: HashMap<&str, String> = HashMap::new();
+    /// Overrides [`SmsClientBuilder::with_timeout`], e.g. a tight deadline for
+    /// one-time passwords on a client tuned for bulk sends.
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-        params.insert("api-key", request.api_key.to_string());
-        params.insert("sender", request.sender.to_string());
-        params.insert("number", request.number.trim_start_matches('+').to_string());
-        params.insert("text", request.text.to_string());
+    /// Set the dispatch priority in client-side queues (default normal)
+    #[must_use]
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
 
-        if let Some(time) = request.time {
-            params.insert("time", time.format("%Y-%m-%dT%H:%M:%S").to_string());
-        }
+    /// Set the content category (default transactional)
+    #[must_use]
+    pub fn with_category(mut self, category: MessageCategory) -> Self {
+        self.category = category;
+        self
+    }
 
-        if let Some(dlr_url) = request.dlr_url {
-            params.insert("dlr-url", dlr_url.to_string());
+    /// Convert into a request that owns all of its data
+    #[must_use]
+    pub fn into_owned(self) -> SmsRequestOwned {
+        SmsRequest {
+            api_key: Cow::Owned(self.api_key.into_owned()),
+            sender: Cow::Owned(self.sender.into_owned()),
+            number: Cow::Owned(self.number.into_owned()),
+            text: Cow::Owned(self.text.into_owned()),
+            time: self.time,
+            dlr_url: self.dlr_url.map(|v| Cow::Owned(v.into_owned())),
+            expired: self.expired,
+            flags: self.flags,
+            user_key: self.user_key.map(|v| Cow::Owned(v.into_owned())),
+            encoding: self.encoding,
+            udh: self.udh.map(|v| Cow::Owned(v.into_owned())),
+            binary: self.binary,
+            message_class: self.message_class,
+            idempotency_key: self.idempotency_key.map(|v| Cow::Owned(v.into_owned())),
+            timeout: self.timeout,
+            priority: self.priority,
+            category: self.category,
         }
+    }
 
-        if let Some(expired) = request.expired {
-            params.insert("expired", expired.to_string());
+    /// Borrow this request without copying any of its data
+    #[must_use]
+    pub fn as_borrowed(&self) -> SmsRequest<'_> {
+        SmsRequest {
+            api_key: Cow::Borrowed(&self.api_key),
+            sender: Cow::Borrowed(&self.sender),
+            number: Cow::Borrowed(&self.number),
+            text: Cow::Borrowed(&self.text),
+            time: self.time,
+            dlr_url: self.dlr_url.as_deref().map(Cow::Borrowed),
+            expired: self.expired,
+            flags: self.flags,
+            user_key: self.user_key.as_deref().map(Cow::Borrowed),
+            encoding: self.encoding,
+            udh: self.udh.as_deref().map(Cow::Borrowed),
+            binary: self.binary,
+            message_class: self.message_class,
+            idempotency_key: self.idempotency_key.as_deref().map(Cow::Borrowed),
+            timeout: self.timeout,
+            priority: self.priority,
+            category: self.category,
         }
+    }
+}
 
-        if request.flags.contains(SmsFlags::DEBUG) {
-            params.insert("flag-debug", "1".to_string());
-        }
+impl SmsClientBuilder {
+    /// Create a builder using the default API base URL
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        if request.flags.contains(SmsFlags::NOLOG) {
-            params.insert("flag-nolog", "3".to_string());
-        }
+    /// Set the API base URL
+    #[must_use]
+    pub fn with_api_base_url(mut self, api_base_url: impl Into<String>) -> Self {
+        self.api_base_url = Some(api_base_url.into());
+        self
+    }
 
-        if request.flags.contains(SmsFlags::FLASH) {
-            params.insert("flag-flash", "1".to_string());
-        }
+    /// Normalize recipient numbers to E.164 before sending
+    ///
+    /// National numbers are resolved against `default_country` (ISO 3166-1 alpha-2),
+    /// see [`crate::phone::normalize`].
+    #[must_use]
+    pub fn with_phone_normalization(mut self, default_country: Option<&str>) -> Self {
+        self.normalize_numbers = true;
+        self.default_country = default_country.map(str::to_string);
+        self
+    }
 
-        if request.flags.contains(SmsFlags::TEST) {
-            params.insert("flag-test", "1".to_string());
-        }
+    /// Enable or disable pre-send number validation (enabled by default)
+    ///
+    /// When enabled, numbers rejected by [`crate::phone::validate`] fail with
+    /// `SmsError::InvalidNumber` without contacting the gateway.
+    #[must_use]
+    pub fn with_number_validation(mut self, enabled: bool) -> Self {
+        self.validate_numbers = enabled;
+        self
+    }
 
-        if request.flags.contains(SmsFlags::NOBL) {
-            params.insert("flag-nobl", "1".to_string());
-        }
+    /// Enable or disable pre-send sender ID validation (enabled by default)
+    ///
+    /// When enabled, senders rejected by [`crate::sender::validate`] fail with
+    /// `SmsError::InvalidSender` without contacting the gateway.
+    #[must_use]
+    pub fn with_sender_validation(mut self, enabled: bool) -> Self {
+        self.validate_senders = enabled;
+        self
+    }
 
-        if request.flags.contains(SmsFlags::CONVERT) {
-            params.insert("flag-convert", "1".to_string());
-        }
+    /// Transliterate message texts locally before sending
+    ///
+    /// Binary payloads (see [`SmsRequest::with_binary`]) are hex-encoded and
+    /// therefore never changed.
+    #[must_use]
+    pub fn with_transliterator(mut self, transliterator: Transliterator) -> Self {
+        self.transliterator = Some(transliterator);
+        self
+    }
 
-        if let Some(user_key) = request.user_key {
-            params.insert("user-key", user_key.to_string());
-        }
+    /// Set how many requests bulk sends keep in flight at once (default 1)
+    #[must_use]
+    pub fn with_bulk_concurrency(mut self, concurrency: usize) -> Self {
+        self.bulk_concurrency = concurrency.max(1);
+        self
+    }
 
-        match request.encoding {
-            Encoding::Udh => {
-                params.insert("udh", "1".to_string());
-                params.insert("coding", "1".to_string());
-            }
-            Encoding::EightBit => {
-                params.insert("coding", "1".to_string());
-            }
-            Encoding::Default => {}
-        }
+    /// Defer messages that would arrive during quiet hours
+    ///
+    /// Such messages are submitted with the gateway `time` parameter set to
+    /// the end of the window instead of being delivered immediately.
+    #[must_use]
+    pub fn with_quiet_hours(mut self, quiet_hours: QuietHours) -> Self {
+        self.quiet_hours = Some(quiet_hours);
+        self
+    }
 
-        let url = format!("{}/send", self.api_base_url);
-        let response = self.client.get(&url).query(&params).send().await?;
+    /// Set the timezone the gateway interprets the `time` parameter in (default UTC)
+    ///
+    /// The gateway expects a local time without offset, so scheduled times are
+    /// converted to this timezone before being formatted.
+    #[must_use]
+    pub fn with_gateway_timezone(mut self, timezone: Tz) -> Self {
+        self.gateway_timezone = timezone;
+        self
+    }
 
-        let resp_text = response.text().await?;
+    /// Refuse to send to numbers in `registry`
+    ///
+    /// Such sends fail with `SmsError::OptedOut` without contacting the gateway.
+    #[must_use]
+    pub fn with_opt_out_registry(mut self, registry: Arc<dyn OptOutRegistry>) -> Self {
+        self.opt_out_registry = Some(registry);
+        self
+    }
 
-        let result = resp_text.trim().parse::<i128>().ok();
+    /// Append `footer`, e.g. "Reply STOP to unsubscribe", to marketing messages
+    ///
+    /// The footer goes on a line of its own after the text of requests in
+    /// [`MessageCategory::Marketing`], unless the text already ends with it.
+    /// Transactional messages and messages with a User Data Header are sent
+    /// unchanged. Segment counts and cost estimates include the footer.
+    #[must_use]
+    pub fn with_opt_out_footer(mut self, footer: impl Into<String>) -> Self {
+        self.opt_out_footer = Some(footer.into());
+        self
+    }
 
-        if let Some(code) = result {
-            if code > 100 {
-                return Ok(resp_text);
-            }
+    /// Check recipient numbers against a local block list and allow list
+    ///
+    /// Rejected sends fail with `SmsError::Blocked` without contacting the
+    /// gateway. This complements the gateway blacklist (see `SmsFlags::NOBL`).
+    #[must_use]
+    pub fn with_number_filter(mut self, filter: NumberFilter) -> Self {
+        self.number_filter = Some(filter);
+        self
+    }
 
-            let error_msg = get_response_code_message(code);
-            log::error!("SMS sending failed to: {}, {}", request.number, error_msg);
+    /// Protect against duplicate sends within `window`
+    ///
+    /// A send whose idempotency key (see [`SmsRequest::with_idempotency_key`])
+    /// is found in `store` returns the original message ID without contacting
+    /// the gateway. The key is reserved before sending, so a concurrent
+    /// duplicate fails with the retryable `SmsError::InProgress`. A send the
+    /// gateway did not accept releases the key; one that may have been
+    /// accepted, because it timed out, failed with a 5xx status or was
+    /// dropped while in flight, keeps it, and duplicates fail with
+    /// `SmsError::Uncertain` until the window ends. The window should be
+    /// shorter than the interval of any intentionally repeated message, such
+    /// as recurring schedules.
+    #[must_use]
+    pub fn with_idempotency(mut self, store: Arc<dyn IdempotencyStore>, window: Duration) -> Self {
+        self.idempotency = Some((store, window));
+        self
+    }
 
-            return Err(SmsError::SendFailed {
-                number: request.number.to_string(),
-                message: error_msg.to_string(),
-            });
-        }
+    /// Set the price table used by [`SmsClient::estimate_cost`]
+    #[must_use]
+    pub fn with_price_table(mut self, prices: PriceTable) -> Self {
+        self.price_table = Some(prices);
+        self
+    }
 
-        log::error!("SMS sending failed to: {}, unknown error", request.number);
-        Err(SmsError::SendFailed {
-            number: request.number.to_string(),
-            message: "unknown error".to_string(),
-        })
+    /// Add hooks run around every gateway request, after those added before
+    #[must_use]
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
     }
-}
 
-fn get_response_code_message(code: i128) -> &'static str {
-    match code {
-        1 => "system internal error",
-        2 => "missing PARAM_NAME parameter",
-        3 => "unable to authenticate",
-        4 => "IP ADDRESS is not allowed",
-        5 => "invalid SENDER parameter",
-        6 => "SENDER is not allowed",
-        7 => "invalid NUMBER parameter",
-        8 => "invalid CODING parameter",
-        9 => "unable to convert TEXT",
-        10 => "length of UDH and TEXT too long",
-        11 => "empty TEXT parameter",
-        12 => "invalid TIME parameter",
-        13 => "invalid EXPIRED parameter",
-        14 => "invalid DLR-URL parameter",
-        15 => "Invalid FLAG-FLASH parameter",
-        16 => "invalid FLAG-NOLOG parameter",
-        17 => "invalid FLAG-TEST parameter",
-        18 => "invalid FLAG-NOBL parameter",
-        19 => "invalid FLAG-CONVERT parameter",
-        _ => "unknown error",
+    /// Fail sends with `SmsError::Timeout` if the gateway doesn't answer within `timeout`
+    ///
+    /// Individual requests can override it with [`SmsRequest::with_timeout`].
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use chrono::TimeZone;
-    use httpmock::prelude::*;
+    /// Record send metrics in `metrics`
+    #[cfg(feature = "metrics")]
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<SmsMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
 
-    fn base_request<'a>() -> SmsRequest<'a> {
-        SmsRequest::new("k", "Alice", "+1234567890", "Hello")
+    /// Record every send attempt in `sink`
+    #[must_use]
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = Some(sink);
+        self
     }
 
-    #[tokio::test]
-    async fn send_sms_success_and_params() {
-        let server = MockServer::start();
+    /// Notify `listener` of sent, failed and delivered messages
+    #[must_use]
+    pub fn with_event_listener(mut self, listener: Arc<dyn EventListener>) -> Self {
+        self.event_listeners.push(listener);
+        self
+    }
 
-        // Build flags and encoding to verify query mapping
-        let mut flags = SmsFlags::empty();
-        flags |= SmsFlags::DEBUG
-            | SmsFlags::NOLOG
-            | SmsFlags::FLASH
-            | SmsFlags::TEST
-            | SmsFlags::NOBL
-            | SmsFlags::CONVERT;
+    /// Authenticate gateway requests with HTTP basic auth or a bearer token
+    ///
+    /// See [`Auth`] for how this combines with the request's API key.
+    #[must_use]
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = auth;
+        self
+    }
 
-        let time = Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+    /// Sign every gateway request with an HMAC over its query parameters
+    ///
+    /// The signature is computed after all middleware has run, so it covers
+    /// parameters they add or rewrite.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_request_signer(mut self, signer: RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
 
-        let req = base_request()
-            .with_time(time)
-            .with_dlr_url("https://example.com/dlr")
-            .with_expired(60)
-            .with_flags(flags)
-            .with_user_key("ukey")
-            .with_encoding(Encoding::Udh);
+    /// Take the API key, and the signing secret if any, from `provider` on every send
+    ///
+    /// The provider's key is used for requests with an empty API key. When
+    /// the gateway rejects it (code 3), the provider is asked to refresh so
+    /// the next send picks up new credentials.
+    #[must_use]
+    pub fn with_credentials_provider(mut self, provider: Arc<dyn CredentialsProvider>) -> Self {
+        self.credentials = Some(provider);
+        self
+    }
 
-        // Expect all query params and return success code (>100)
-        let m = server.mock(|when, then| {
-            when.method(GET)
-                .path("/send")
-                .query_param("api-key", "k")
+    /// Throttle sends client-side per destination country and sender ID
+    ///
+    /// Sends over the limit wait until their bucket has room again.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_rate_limit(mut self, limits: RateLimit) -> Self {
+        self.rate_limit = Some(limits);
+        self
+    }
+
+    /// Retry sends failing with a retryable error, see [`RetryPolicy`]
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Keep at most `max` idle connections per host for reuse
+    ///
+    /// Defaults to [`DEFAULT_POOL_MAX_IDLE_PER_HOST`](crate::connection::DEFAULT_POOL_MAX_IDLE_PER_HOST), which covers a high bulk
+    /// concurrency without holding on to sockets indefinitely.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.connection.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// Close pooled connections after being idle for `timeout` (`None` keeps them)
+    ///
+    /// Defaults to [`DEFAULT_POOL_IDLE_TIMEOUT`](crate::connection::DEFAULT_POOL_IDLE_TIMEOUT), so steady traffic reuses
+    /// connections instead of paying for a TLS handshake per message.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connection.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Send TCP keepalive probes at `interval` (`None` disables them)
+    ///
+    /// Defaults to [`DEFAULT_TCP_KEEPALIVE`](crate::connection::DEFAULT_TCP_KEEPALIVE).
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_tcp_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.connection.tcp_keepalive = interval;
+        self
+    }
+
+    /// Choose the HTTP protocol version (default [`HttpVersion::Negotiate`])
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_http_version(mut self, version: HttpVersion) -> Self {
+        self.connection.http_version = version;
+        self
+    }
+
+    /// Connect to `host` at `addrs` instead of resolving it through DNS
+    ///
+    /// For gateways reached over a VPN or split DNS where public resolution
+    /// gives the wrong address. A port of `0` uses the one from the base URL.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_resolved_host(mut self, host: &str, addrs: &[SocketAddr]) -> Self {
+        self.connection
+            .dns_overrides
+            .push((host.to_string(), addrs.to_vec()));
+        self
+    }
+
+    /// Resolve hostnames with a custom resolver instead of the system one
+    ///
+    /// Hosts pinned with [`SmsClientBuilder::with_resolved_host`] still take
+    /// precedence.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_dns_resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.connection.dns_resolver = Some(resolver);
+        self
+    }
+
+    /// Send gateway requests through an HTTP or SOCKS proxy
+    ///
+    /// Without this, proxies from the `HTTP_PROXY`/`HTTPS_PROXY` environment
+    /// variables are used.
+    #[cfg(not(target_arch = "wasm32"))]
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.connection.proxy = Some(proxy);
+        self
+    }
+
+    /// Trust `certificate` as a root CA in addition to the system roots
+    ///
+    /// For on-premises gateways with certificates from a private CA. Works
+    /// with either TLS backend; pinning and client certificates need `rustls`.
+    #[cfg(all(
+        any(feature = "rustls", feature = "native-tls"),
+        not(target_arch = "wasm32")
+    ))]
+    #[must_use]
+    pub fn with_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.connection.tls.roots.push(certificate);
+        self
+    }
+
+    /// Only accept gateway certificates with this SHA-256 fingerprint
+    ///
+    /// The certificate must still be trusted; the pin must match the gateway's
+    /// own (end-entity) certificate, not an intermediate, see
+    /// [`Certificate::sha256_fingerprint`]. Call again to accept several
+    /// fingerprints, e.g. the current and the next certificate during rotation.
+    #[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+    #[must_use]
+    pub fn with_pinned_certificate(mut self, sha256: [u8; 32]) -> Self {
+        self.connection.tls.pins.push(sha256);
+        self
+    }
+
+    /// Present a client certificate to gateways that require mutual TLS
+    ///
+    /// The API key is still sent as usual.
+    #[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+    #[must_use]
+    pub fn with_client_identity(mut self, identity: Identity) -> Self {
+        self.connection.tls.identity = Some(identity);
+        self
+    }
+
+    fn http_client(&self) -> Result<Client, SmsError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = self.connection.apply(Client::builder())?;
+        #[cfg(target_arch = "wasm32")]
+        let builder = Client::builder();
+        Ok(builder.build()?)
+    }
+
+    /// Build the client
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError::RequestFailed` if the HTTP client cannot be initialized,
+    /// or `SmsError::Tls` if the TLS options are unusable
+    pub fn build(self) -> Result<SmsClient, SmsError> {
+        let client = self.http_client()?;
+        Ok(SmsClient {
+            api_base_url: self
+                .api_base_url
+                .unwrap_or_else(|| DEFAULT_API_BASE_URL.to_string()),
+            client,
+            normalize_numbers: self.normalize_numbers,
+            default_country: self.default_country,
+            validate_numbers: self.validate_numbers,
+            validate_senders: self.validate_senders,
+            transliterator: self.transliterator,
+            concat_reference: AtomicU8::new(initial_concat_reference()),
+            bulk_concurrency: self.bulk_concurrency,
+            quiet_hours: self.quiet_hours,
+            gateway_timezone: self.gateway_timezone,
+            opt_out_registry: self.opt_out_registry,
+            opt_out_footer: self.opt_out_footer,
+            number_filter: self.number_filter,
+            idempotency: self.idempotency,
+            price_table: self.price_table,
+            middleware: self.middleware,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+            audit_sink: self.audit_sink,
+            event_listeners: self.event_listeners,
+            timeout: self.timeout,
+            #[cfg(not(target_arch = "wasm32"))]
+            rate_limiter: self.rate_limit.map(RateLimiter::new),
+            #[cfg(not(target_arch = "wasm32"))]
+            retry: self.retry,
+            auth: self.auth,
+            #[cfg(not(target_arch = "wasm32"))]
+            signer: self.signer,
+            credentials: self.credentials,
+        })
+    }
+}
+
+const DEFAULT_API_BASE_URL: &str = "https://api.esteria.eu";
+
+/// Start concatenation references at a time-derived value so that restarts
+/// don't reuse the references of recently sent messages
+fn initial_concat_reference() -> u8 {
+    Utc::now().timestamp_subsec_nanos().to_le_bytes()[1]
+}
+
+/// Reserved idempotency key of a send
+///
+/// A failed send, or one dropped before finishing, e.g. by a cancellation or
+/// an outer timeout, releases the key unless the request may have been
+/// accepted by the gateway, in which case the key is kept as uncertain.
+struct KeyReservation<'s> {
+    store: &'s dyn IdempotencyStore,
+    key: String,
+    window: Duration,
+    /// Set while an attempt may have been accepted by the gateway
+    maybe_sent: AtomicBool,
+    settled: bool,
+}
+
+impl KeyReservation<'_> {
+    /// Store the message ID of the successful send under the key
+    fn sent(mut self, message_id: &str) {
+        self.store.insert(&self.key, message_id, self.window);
+        self.settled = true;
+    }
+}
+
+impl Drop for KeyReservation<'_> {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        if self.maybe_sent.load(Ordering::Relaxed) {
+            self.store.mark_uncertain(&self.key);
+        } else {
+            self.store.release(&self.key);
+        }
+    }
+}
+
+impl Default for SmsClient {
+    fn default() -> Self {
+        Self::with_api_base_url(DEFAULT_API_BASE_URL)
+    }
+}
+
+impl SmsClient {
+    /// Create a new SMS client using the default API base URL
+    #[allow(dead_code)]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new SMS client with a custom API base URL
+    #[must_use]
+    pub fn with_api_base_url(api_base_url: impl Into<String>) -> Self {
+        SmsClientBuilder::new()
+            .with_api_base_url(api_base_url)
+            .build()
+            .expect("default client options are valid")
+    }
+
+    /// Create a builder for a client with non-default options
+    #[must_use]
+    pub fn builder() -> SmsClientBuilder {
+        SmsClientBuilder::new()
+    }
+
+    /// Apply number normalization and validation as configured
+    pub(crate) fn prepare_number<'n>(&self, number: &'n str) -> Result<Cow<'n, str>, SmsError> {
+        let invalid = |reason| SmsError::InvalidNumber {
+            number: number.to_string(),
+            reason,
+        };
+
+        let prepared = if self.normalize_numbers {
+            match phone::normalize(number, self.default_country.as_deref()) {
+                Ok(normalized) => Cow::Owned(normalized),
+                Err(err) if self.validate_numbers => return Err(invalid(err)),
+                Err(err) => {
+                    log::warn!(
+                        "Unable to normalize number {}: {err}",
+                        redact::number(number)
+                    );
+                    Cow::Borrowed(number)
+                }
+            }
+        } else {
+            Cow::Borrowed(number)
+        };
+
+        if self.validate_numbers {
+            phone::validate(&prepared).map_err(invalid)?;
+        }
+
+        Ok(prepared)
+    }
+
+    /// Apply the opt-out footer and transliteration if configured
+    pub(crate) fn prepare_text<'t>(&self, request: &'t SmsRequest<'_>) -> Cow<'t, str> {
+        if request.binary {
+            return Cow::Borrowed(&request.text);
+        }
+        let text: Cow<'t, str> = match &self.opt_out_footer {
+            Some(footer)
+                if request.category == MessageCategory::Marketing
+                    && request.udh.is_none()
+                    && !request.text.trim_end().ends_with(footer.as_str()) =>
+            {
+                Cow::Owned(format!("{}\n{footer}", request.text))
+            }
+            _ => Cow::Borrowed(&request.text),
+        };
+        match &self.transliterator {
+            Some(transliterator) => match text {
+                Cow::Borrowed(text) => transliterator.transliterate(text),
+                Cow::Owned(text) => Cow::Owned(transliterator.transliterate(&text).into_owned()),
+            },
+            None => text,
+        }
+    }
+
+    /// Segments a request takes once its text is prepared
+    pub(crate) fn segment_info(&self, request: &SmsRequest<'_>) -> sms::SegmentInfo {
+        let mut info = if request.binary {
+            sms::binary_segments(request.text.len() / 2)
+        } else {
+            sms::segments(&self.prepare_text(request), request.encoding)
+        };
+        // Messages with an explicit header are always sent as a single part
+        if request.udh.is_some() {
+            info.segments = 1;
+        }
+        info
+    }
+
+    /// Move the send time out of quiet hours if configured
+    fn schedule_time(&self, number: &str, time: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        let Some(quiet_hours) = &self.quiet_hours else {
+            return time;
+        };
+
+        let at = time.unwrap_or_else(Utc::now);
+        let allowed = quiet_hours.next_allowed(number, at);
+        if allowed == at {
+            time
+        } else {
+            log::info!(
+                "Deferring SMS to {} until {allowed} (quiet hours)",
+                redact::number(number)
+            );
+            Some(allowed)
+        }
+    }
+
+    /// Send an SMS message
+    ///
+    /// Returns the message ID on success (> 100)
+    ///
+    /// With the `tracing` feature, each call runs in an `sms.send` span
+    /// recording the sender, number, segment count, message ID and gateway
+    /// latency.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError::SendFailed` if the API returns a known error code (< 100),
+    /// `SmsError::UnknownResponse` for unknown codes and non-numeric responses,
+    /// `SmsError::Timeout` if the gateway doesn't answer within the timeout,
+    /// `SmsError::RequestFailed` if the HTTP request fails,
+    /// `SmsError::InvalidNumber` or `SmsError::InvalidSender` if the number or
+    /// sender fails client-side validation, `SmsError::Blocked` if the number filter rejects the number, or
+    /// `SmsError::OptedOut` if the recipient is in the opt-out registry
+    pub async fn send_sms(&self, request: SmsRequest<'_>) -> Result<String, SmsError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "sms.send",
+            sender = %request.sender,
+            number = %redact::number(&request.number),
+            segments = self.segment_info(&request).segments,
+            message_id = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+        );
+
+        let send = self.send_deduplicated(request);
+        #[cfg(feature = "tracing")]
+        let send = tracing::Instrument::instrument(send, span.clone());
+        let result = send.await;
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(message_id) => {
+                span.record("message_id", message_id.as_str());
+            }
+            Err(error) => tracing::warn!(parent: &span, %error, "SMS sending failed"),
+        }
+        result
+    }
+
+    /// Send an SMS message unless `cancel` completes first
+    ///
+    /// `cancel` is typically `token.cancelled()` of a shutdown token. A
+    /// request cancelled while in flight may still have reached the gateway.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError::Cancelled` if `cancel` completes first, otherwise
+    /// the errors of [`SmsClient::send_sms`]
+    pub async fn send_sms_with_cancel(
+        &self,
+        request: SmsRequest<'_>,
+        cancel: impl Future<Output = ()>,
+    ) -> Result<String, SmsError> {
+        let number = request.number.to_string();
+        let send = pin!(self.send_sms(request));
+        match future::select(send, pin!(cancel)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(((), _)) => Err(SmsError::Cancelled { number }),
+        }
+    }
+
+    async fn send_deduplicated(&self, request: SmsRequest<'_>) -> Result<String, SmsError> {
+        let Some((store, window)) = &self.idempotency else {
+            return self
+                .attempt_retrying(request, &AtomicBool::new(false))
+                .await;
+        };
+
+        let key = idempotency::request_key(&request).into_owned();
+        match store.reserve(&key, *window) {
+            Reservation::Reserved => {}
+            Reservation::Sent(message_id) => {
+                log::info!(
+                    "Skipping duplicate SMS to {} (key {key})",
+                    redact::number(&request.number)
+                );
+                return Ok(message_id);
+            }
+            Reservation::InProgress => {
+                return Err(SmsError::InProgress {
+                    number: request.number.to_string(),
+                });
+            }
+            Reservation::Uncertain => {
+                return Err(SmsError::Uncertain {
+                    number: request.number.to_string(),
+                });
+            }
+        }
+
+        let reservation = KeyReservation {
+            store: store.as_ref(),
+            key,
+            window: *window,
+            maybe_sent: AtomicBool::new(false),
+            settled: false,
+        };
+        let result = self
+            .attempt_retrying(request, &reservation.maybe_sent)
+            .await;
+        if let Ok(message_id) = &result {
+            reservation.sent(message_id);
+        }
+        result
+    }
+
+    /// Attempt a request, retrying retryable failures as the retry policy allows
+    ///
+    /// `maybe_sent` is set once an attempt may have been accepted by the gateway.
+    async fn attempt_retrying(
+        &self,
+        request: SmsRequest<'_>,
+        maybe_sent: &AtomicBool,
+    ) -> Result<String, SmsError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(policy) = &self.retry {
+            let mut retry = 0;
+            loop {
+                match self.attempt(request.as_borrowed(), maybe_sent).await {
+                    Err(err) if err.is_retryable() && retry + 1 < policy.max_attempts() => {
+                        retry += 1;
+                        #[cfg(feature = "metrics")]
+                        if let Some(metrics) = &self.metrics {
+                            metrics.record_retried();
+                        }
+                        let backoff = policy.backoff(retry);
+                        log::warn!(
+                            "Retrying SMS to {} in {backoff:?}: {err}",
+                            redact::number(&request.number)
+                        );
+                        tokio::time::sleep(backoff).await;
+                    }
+                    result => return result,
+                }
+            }
+        }
+        self.attempt(request, maybe_sent).await
+    }
+
+    /// Submit a request, reporting the attempt to the audit sink and event listeners
+    async fn attempt(
+        &self,
+        request: SmsRequest<'_>,
+        maybe_sent: &AtomicBool,
+    ) -> Result<String, SmsError> {
+        let mut call = GatewayCall::default();
+        let sent_before = maybe_sent.load(Ordering::Relaxed);
+        let result = self
+            .submit(request.as_borrowed(), &mut call, maybe_sent)
+            .await;
+        if let Err(err) = &result
+            && !err.may_have_been_sent()
+        {
+            maybe_sent.store(sent_before, Ordering::Relaxed);
+        }
+
+        if let Some(sink) = &self.audit_sink {
+            sink.record(&AuditRecord::new(&request, &result, &call));
+        }
+        for listener in &self.event_listeners {
+            match &result {
+                Ok(message_id) => listener.on_sent(&request, message_id),
+                Err(err) => listener.on_failed(&request, err),
+            }
+        }
+        result
+    }
+
+    async fn submit(
+        &self,
+        request: SmsRequest<'_>,
+        call: &mut GatewayCall,
+        maybe_sent: &AtomicBool,
+    ) -> Result<String, SmsError> {
+        if self.validate_senders
+            && let Err(reason) = sender::validate(&request.sender)
+        {
+            log::error!(
+                "SMS sending failed to: {}, {reason}",
+                redact::number(&request.number)
+            );
+            return Err(SmsError::InvalidSender {
+                sender: request.sender.to_string(),
+                reason,
+            });
+        }
+
+        let number = self.prepare_number(&request.number).inspect_err(|err| {
+            log::error!(
+                "SMS sending failed to: {}, {err}",
+                redact::number(&request.number)
+            );
+        })?;
+
+        if let Some(filter) = &self.number_filter
+            && let Err(reason) = filter.check(&number)
+        {
+            log::warn!("Not sending SMS to {}: {reason}", redact::number(&number));
+            return Err(SmsError::Blocked {
+                number: number.into_owned(),
+                reason,
+            });
+        }
+
+        if let Some(registry) = &self.opt_out_registry
+            && registry.contains(&number)
+        {
+            log::warn!(
+                "Not sending SMS to {}: recipient opted out",
+                redact::number(&number)
+            );
+            return Err(SmsError::OptedOut {
+                number: number.into_owned(),
+            });
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(&number, &request.sender).await;
+        }
+
+        let credentials = match &self.credentials {
+            Some(provider) => Some(provider.credentials()?),
+            None => None,
+        };
+
+        let mut params: HashMap<&str, String> = HashMap::new();
+
+        if !request.api_key.is_empty() {
+            params.insert("api-key", request.api_key.to_string());
+        } else if let Some(credentials) = &credentials {
+            params.insert("api-key", credentials.api_key.clone());
+        }
+        params.insert("sender", request.sender.to_string());
+        params.insert("number", number.trim_start_matches('+').to_string());
+        let text = self.prepare_text(&request);
+        params.insert("text", text.to_string());
+
+        if let Some(time) = self.schedule_time(&number, request.time) {
+            let time = time.with_timezone(&self.gateway_timezone);
+            params.insert("time", time.format("%Y-%m-%dT%H:%M:%S").to_string());
+        }
+
+        if let Some(dlr_url) = &request.dlr_url {
+            params.insert("dlr-url", dlr_url.to_string());
+        }
+
+        if let Some(expired) = request.expired {
+            params.insert("expired", expired.to_string());
+        }
+
+        if request.flags.contains(SmsFlags::DEBUG) {
+            params.insert("flag-debug", "1".to_string());
+        }
+
+        if request.flags.contains(SmsFlags::NOLOG) {
+            params.insert("flag-nolog", "3".to_string());
+        }
+
+        if request.flags.contains(SmsFlags::FLASH)
+            || request.message_class == Some(MessageClass::Class0)
+        {
+            params.insert("flag-flash", "1".to_string());
+        }
+
+        if request.flags.contains(SmsFlags::TEST) {
+            params.insert("flag-test", "1".to_string());
+        }
+
+        if request.flags.contains(SmsFlags::NOBL) {
+            params.insert("flag-nobl", "1".to_string());
+        }
+
+        if request.flags.contains(SmsFlags::CONVERT) {
+            params.insert("flag-convert", "1".to_string());
+        }
+
+        if let Some(user_key) = &request.user_key {
+            params.insert("user-key", user_key.to_string());
+        }
+
+        match request.encoding {
+            Encoding::Udh => {
+                params.insert("udh", "1".to_string());
+                params.insert("coding", "1".to_string());
+            }
+            Encoding::EightBit => {
+                params.insert("coding", "1".to_string());
+            }
+            Encoding::Ucs2 => {
+                params.insert("coding", "2".to_string());
+            }
+            Encoding::Auto if !sms::is_gsm7(&text) => {
+                params.insert("coding", "2".to_string());
+            }
+            Encoding::Default | Encoding::Auto => {}
+        }
+
+        if let Some(udh) = &request.udh {
+            params.insert("udh", sms::to_hex(udh));
+        }
+
+        let url = format!("{}/send", self.api_base_url);
+        let mut http_request = self
+            .auth
+            .apply(self.client.get(&url).query(&params))
+            .build()
+            .map_err(reqwest::Error::without_url)?;
+        if let Some(timeout) = request.timeout.or(self.timeout) {
+            *http_request.timeout_mut() = Some(timeout);
+        }
+        for middleware in &self.middleware {
+            middleware.on_request(&mut http_request);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(signer) = &self.signer {
+            let secret = credentials.as_ref().and_then(|c| c.secret.as_deref());
+            signer.attach(&mut http_request, secret.map(str::as_bytes));
+        }
+        let sent = if self.middleware.is_empty() {
+            None
+        } else {
+            http_request.try_clone()
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(url = %http_request.url().path(), "calling gateway");
+
+        let started = Utc::now();
+        maybe_sent.store(true, Ordering::Relaxed);
+        let outcome = self.call_gateway(http_request).await;
+        let latency = Utc::now() - started;
+        call.latency = Some(latency);
+
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("latency_ms", latency.num_milliseconds());
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_latency(latency);
+            if outcome.is_err() {
+                metrics.record_failed("transport");
+            }
+        }
+
+        let (status, resp_text) = match outcome {
+            Ok(response) => response,
+            Err(err) if err.is_timeout() => {
+                log::error!(
+                    "SMS sending to {} timed out",
+                    redact::number(&request.number)
+                );
+                return Err(SmsError::Timeout {
+                    number: request.number.to_string(),
+                });
+            }
+            Err(err) => return Err(err.into()),
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%status, body = resp_text.trim(), "gateway responded");
+
+        if let Some(sent) = &sent {
+            for middleware in &self.middleware {
+                middleware.on_response(sent, status, &resp_text);
+            }
+        }
+
+        // Overload and server errors carry no gateway code, whatever the body says
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            log::error!(
+                "SMS sending to {} failed with HTTP {status}",
+                redact::number(&request.number)
+            );
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &self.metrics {
+                metrics.record_failed("transport");
+            }
+            return Err(SmsError::HttpStatus {
+                number: request.number.to_string(),
+                status: status.as_u16(),
+            });
+        }
+
+        let result = resp_text.trim().parse::<i128>().ok();
+
+        #[cfg(feature = "metrics")]
+        if result.is_none()
+            && let Some(metrics) = &self.metrics
+        {
+            metrics.record_failed("unknown");
+        }
+
+        if let Some(code) = result {
+            if code > 100 {
+                #[cfg(feature = "metrics")]
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_sent(self.segment_info(&request).segments);
+                }
+                return Ok(resp_text);
+            }
+
+            call.code = Some(code);
+            if code == 3
+                && let Some(provider) = &self.credentials
+                && let Err(err) = provider.refresh()
+            {
+                log::warn!("Refreshing gateway credentials failed: {err}");
+            }
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &self.metrics {
+                metrics.record_failed(&code.to_string());
+            }
+
+            if let Some(known) = i32::try_from(code).ok().and_then(response::lookup) {
+                log::error!(
+                    "SMS sending failed to: {}, {}",
+                    redact::number(&request.number),
+                    known.message
+                );
+                return Err(SmsError::SendFailed {
+                    number: request.number.to_string(),
+                    code: Some(known.code),
+                    message: known.message,
+                });
+            }
+        }
+
+        let raw = resp_text.trim().to_string();
+        log::error!(
+            "SMS sending failed to: {}, unknown response: {raw}",
+            redact::number(&request.number)
+        );
+        Err(SmsError::UnknownResponse { raw })
+    }
+
+    /// Errors are stripped of the request URL, which holds the API key, number and text
+    async fn call_gateway(&self, request: Request) -> Result<(StatusCode, String), reqwest::Error> {
+        let response = self
+            .client
+            .execute(request)
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        let text = response.text().await.map_err(reqwest::Error::without_url)?;
+        Ok((status, text))
+    }
+
+    /// Send a message, splitting it into concatenated parts if it exceeds one segment
+    ///
+    /// Each part is sent as a separate request carrying a concatenation UDH, so
+    /// the recipient's phone reassembles them. Texts that fit a single segment
+    /// are sent unchanged. Sending stops at the first failing part; parts sent
+    /// before it are not recalled.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first part that fails (see [`SmsClient::send_sms`]),
+    /// or `SmsError::SendFailed` if the text needs more than 255 parts
+    pub async fn send_long_sms(
+        &self,
+        request: SmsRequest<'_>,
+    ) -> Result<MultipartResult, SmsError> {
+        let text = self.prepare_text(&request);
+        let parts = if request.binary {
+            binary_parts(&request)?
+        } else {
+            sms::split(&text, request.encoding)
+        };
+
+        if parts.len() == 1 {
+            let message_id = self.send_sms(request.as_borrowed()).await?;
+            return Ok(MultipartResult {
+                reference: None,
+                message_ids: vec![message_id],
+            });
+        }
+
+        let total = part_count(&request.number, parts.len())?;
+        let reference = self.concat_reference.fetch_add(1, Ordering::Relaxed);
+        let idempotency_key = self
+            .idempotency
+            .as_ref()
+            .map(|_| idempotency::request_key(&request));
+        let mut message_ids = Vec::with_capacity(parts.len());
+
+        for (sequence, part) in (1..=total).zip(parts) {
+            let part_request = SmsRequest {
+                text: Cow::Borrowed(part),
+                udh: Some(
+                    Udh::new()
+                        .with_concatenation(reference, total, sequence)
+                        .into(),
+                ),
+                idempotency_key: idempotency_key
+                    .as_ref()
+                    .map(|key| Cow::Owned(format!("{key}:{sequence}"))),
+                ..request.as_borrowed()
+            };
+            message_ids.push(self.send_sms(part_request).await?);
+        }
+
+        Ok(MultipartResult {
+            reference: Some(reference),
+            message_ids,
+        })
+    }
+
+    /// Send a WAP Push Service Indication
+    ///
+    /// The push is sent as a binary message to the WAP Push port. Payloads that
+    /// don't fit a single message are split into concatenated parts, with the
+    /// same caveats as [`SmsClient::send_long_sms`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first part that fails (see [`SmsClient::send_sms`])
+    pub async fn send_wap_push(
+        &self,
+        api_key: &str,
+        sender: &str,
+        number: &str,
+        push: &WapPush,
+    ) -> Result<MultipartResult, SmsError> {
+        let payload = push.payload();
+        let udh = push.udh();
+        let request = SmsRequest::new(api_key, sender, number, "");
+
+        if payload.len() <= udh.remaining_capacity() {
+            let message_id = self
+                .send_sms(request.with_binary(&payload).with_udh(udh))
+                .await?;
+            return Ok(MultipartResult {
+                reference: None,
+                message_ids: vec![message_id],
+            });
+        }
+
+        let chunk_len = udh.clone().with_concatenation(0, 0, 0).remaining_capacity();
+        let chunks: Vec<_> = payload.chunks(chunk_len).collect();
+        let total = part_count(number, chunks.len())?;
+        let reference = self.concat_reference.fetch_add(1, Ordering::Relaxed);
+        let mut message_ids = Vec::with_capacity(chunks.len());
+
+        for (sequence, chunk) in (1..=total).zip(chunks) {
+            let part_request = request
+                .clone()
+                .with_binary(chunk)
+                .with_udh(udh.clone().with_concatenation(reference, total, sequence));
+            message_ids.push(self.send_sms(part_request).await?);
+        }
+
+        Ok(MultipartResult {
+            reference: Some(reference),
+            message_ids,
+        })
+    }
+}
+
+/// Something that sends SMS messages
+///
+/// Application code can depend on this trait instead of [`SmsClient`], so
+/// tests can substitute a fake without an HTTP layer.
+pub trait SmsSender: Send + Sync {
+    /// Send an SMS message, see [`SmsClient::send_sms`]
+    fn send_sms(
+        &self,
+        request: SmsRequest<'_>,
+    ) -> impl Future<Output = Result<SendResult, SmsError>> + Send;
+}
+
+// The wasm HTTP backend's futures are not `Send`
+#[cfg(not(target_arch = "wasm32"))]
+impl SmsSender for SmsClient {
+    async fn send_sms(&self, request: SmsRequest<'_>) -> Result<SendResult, SmsError> {
+        let number = request.number.to_string();
+        let message_id = SmsClient::send_sms(self, request).await?;
+        Ok(SendResult { message_id, number })
+    }
+}
+
+impl<T: SmsSender + ?Sized> SmsSender for Arc<T> {
+    fn send_sms(
+        &self,
+        request: SmsRequest<'_>,
+    ) -> impl Future<Output = Result<SendResult, SmsError>> + Send {
+        (**self).send_sms(request)
+    }
+}
+
+/// Hex parts of a binary payload, split on byte boundaries
+fn binary_parts<'t>(request: &'t SmsRequest<'_>) -> Result<Vec<&'t str>, SmsError> {
+    let failed = |message: &str| SmsError::SendFailed {
+        number: request.number.to_string(),
+        code: None,
+        message: message.to_string(),
+    };
+    if !request.text.is_ascii() {
+        return Err(failed("binary payload is not hex-encoded"));
+    }
+    let udh_len = request.udh.as_ref().map_or(0, |udh| udh.len());
+    if request.text.len() / 2 <= sms::Charset::EightBit.capacity_with_udh(udh_len) {
+        return Ok(vec![&request.text]);
+    }
+    if request.udh.is_some() {
+        return Err(failed(
+            "binary payload and User Data Header exceed a single message",
+        ));
+    }
+    let chunk = 2 * sms::Charset::EightBit.multipart_capacity();
+    Ok((0..request.text.len())
+        .step_by(chunk)
+        .map(|start| &request.text[start..request.text.len().min(start + chunk)])
+        .collect())
+}
+
+/// Number of parts as sent in a concatenation header
+fn part_count(number: &str, parts: usize) -> Result<u8, SmsError> {
+    u8::try_from(parts).map_err(|_| SmsError::SendFailed {
+        number: number.to_string(),
+        code: None,
+        message: format!("message too long ({parts} parts, at most 255)"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::credentials::Credentials;
+    use crate::signing::SignaturePlacement;
+    use chrono::TimeZone;
+    use httpmock::prelude::*;
+    use reqwest::header::HeaderName;
+    use std::sync::atomic::AtomicUsize;
+
+    fn base_request<'a>() -> SmsRequest<'a> {
+        SmsRequest::new("k", "Alice", "+1234567890", "Hello")
+    }
+
+    #[tokio::test]
+    async fn send_sms_success_and_params() {
+        let server = MockServer::start();
+
+        // Build flags and encoding to verify query mapping
+        let mut flags = SmsFlags::empty();
+        flags |= SmsFlags::DEBUG
+            | SmsFlags::NOLOG
+            | SmsFlags::FLASH
+            | SmsFlags::TEST
+            | SmsFlags::NOBL
+            | SmsFlags::CONVERT;
+
+        let time = Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+
+        let req = base_request()
+            .with_time(time)
+            .with_dlr_url("https://example.com/dlr")
+            .with_expired(60)
+            .with_flags(flags)
+            .with_user_key("ukey")
+            .with_encoding(Encoding::Udh);
+
+        // Expect all query params and return success code (>100)
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("api-key", "k")
                 .query_param("sender", "Alice")
                 .query_param("number", "1234567890") // plus sign trimmed
                 .query_param("text", "Hello")
@@ -324,57 +2054,622 @@ mod tests {
         });
 
         let client = SmsClient::with_api_base_url(server.base_url());
-        let code = client.send_sms(req).await.unwrap();
-        assert_eq!(code, "1234");
+        let code = client.send_sms(req).await.unwrap();
+        assert_eq!(code, "1234");
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_api_error_mapped() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("3"); // 3 => unable to authenticate
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let err = client.send_sms(base_request()).await.unwrap_err();
+        match err {
+            SmsError::SendFailed {
+                number,
+                code,
+                message,
+            } => {
+                assert_eq!(number, "+1234567890");
+                assert_eq!(code, Some(3));
+                assert_eq!(message, "unable to authenticate");
+            }
+            other => panic!("Unexpected error type: {other}"),
+        }
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_unknown_text_maps_to_unknown_response() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("not-a-number");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let err = client.send_sms(base_request()).await.unwrap_err();
+        match err {
+            SmsError::UnknownResponse { raw } => assert_eq!(raw, "not-a-number"),
+            other => panic!("Unexpected error type: {other}"),
+        }
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_unregistered_code_maps_to_unknown_response() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("42\n");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let err = client.send_sms(base_request()).await.unwrap_err();
+        assert!(matches!(err, SmsError::UnknownResponse { raw } if raw == "42"));
+    }
+
+    #[tokio::test]
+    async fn send_sms_server_errors_are_retryable() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(503).body("8");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let err = client.send_sms(base_request()).await.unwrap_err();
+        assert!(matches!(err, SmsError::HttpStatus { status: 503, .. }));
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_sms_request_timeout_overrides_client() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200)
+                .body("1001")
+                .delay(Duration::from_millis(500));
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        let err = client
+            .send_sms(base_request().with_timeout(Duration::from_millis(50)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SmsError::Timeout { .. }));
+        assert!(err.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn send_sms_with_connection_options() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_pool_max_idle_per_host(4)
+            .with_pool_idle_timeout(None)
+            .with_tcp_keepalive(Some(Duration::from_secs(10)))
+            .with_http_version(HttpVersion::Http1Only)
+            .build()
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(client.send_sms(base_request()).await.unwrap(), "1001");
+        }
+        m.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn send_sms_connects_to_pinned_host() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(format!("http://gateway.internal:{}", server.port()))
+            .with_resolved_host("gateway.internal", &[*server.address()])
+            .build()
+            .unwrap();
+        assert_eq!(client.send_sms(base_request()).await.unwrap(), "1001");
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_through_authenticated_proxy() {
+        let proxy = MockServer::start();
+        let m = proxy.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .header("proxy-authorization", "Basic dXNlcjpzZWNyZXQ=");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url("http://gateway.invalid")
+            .with_proxy(ProxyConfig::new(proxy.base_url()).with_basic_auth("user", "secret"))
+            .build()
+            .unwrap();
+        assert_eq!(client.send_sms(base_request()).await.unwrap(), "1001");
+        m.assert();
+    }
+
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    #[test]
+    fn build_rejects_invalid_root_certificate() {
+        let result = SmsClient::builder()
+            .with_root_certificate(crate::tls::Certificate::from_der([0, 1, 2]))
+            .build();
+        assert!(matches!(result, Err(SmsError::Tls { .. })));
+    }
+
+    #[tokio::test]
+    async fn send_sms_applies_auth_header() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .header("authorization", "Bearer token")
+                .query_param_missing("api-key");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_auth(Auth::bearer("token"))
+            .build()
+            .unwrap();
+        let request = SmsRequest::new("", "Sender", "+37060000000", "Hi");
+        assert_eq!(client.send_sms(request).await.unwrap(), "1001");
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_signs_request() {
+        let signer = RequestSigner::new("secret");
+        let expected = signer.sign([
+            ("api-key", "k"),
+            ("sender", "Alice"),
+            ("number", "1234567890"),
+            ("text", "Hello"),
+        ]);
+        let server = MockServer::start();
+        let query = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("signature", &expected);
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_request_signer(signer.clone())
+            .build()
+            .unwrap();
+        assert_eq!(client.send_sms(base_request()).await.unwrap(), "1001");
+        query.assert();
+
+        let header = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .header("x-signature", &expected)
+                .query_param_missing("signature");
+            then.status(200).body("1002");
+        });
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_request_signer(signer.with_placement(SignaturePlacement::Header(
+                HeaderName::from_static("x-signature"),
+            )))
+            .build()
+            .unwrap();
+        assert_eq!(client.send_sms(base_request()).await.unwrap(), "1002");
+        header.assert();
+    }
+
+    #[derive(Debug, Default)]
+    struct RefreshCounter(AtomicUsize);
+
+    impl CredentialsProvider for RefreshCounter {
+        fn credentials(&self) -> Result<Credentials, CredentialsError> {
+            Ok(Credentials::new("rotated"))
+        }
+
+        fn refresh(&self) -> Result<(), CredentialsError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn send_sms_uses_credentials_provider() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("api-key", "rotated");
+            then.status(200).body("3");
+        });
+
+        let provider = Arc::new(RefreshCounter::default());
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_credentials_provider(provider.clone())
+            .build()
+            .unwrap();
+        let request = SmsRequest::new("", "Alice", "+1234567890", "Hello");
+        let err = client.send_sms(request).await.unwrap_err();
+        assert!(matches!(err, SmsError::SendFailed { code: Some(3), .. }));
+        m.assert();
+        assert_eq!(provider.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn send_sms_retries_retryable_errors() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_retry(RetryPolicy::new(3).with_backoff(Duration::ZERO, Duration::ZERO))
+            .build()
+            .unwrap();
+        let err = client.send_sms(base_request()).await.unwrap_err();
+        assert!(matches!(err, SmsError::SendFailed { code: Some(1), .. }));
+        m.assert_calls(3);
+    }
+
+    #[tokio::test]
+    async fn send_sms_http_failure_is_request_failed() {
+        // Use a non-routable private address to provoke connection error
+        let client = SmsClient::with_api_base_url("http://10.255.255.1".to_string());
+        let err = client.send_sms(base_request()).await.unwrap_err();
+        matches!(err, SmsError::RequestFailed(_));
+    }
+
+    #[tokio::test]
+    async fn errors_and_debug_hide_secrets() {
+        // Nothing listens on the discard port
+        let client = SmsClient::with_api_base_url("http://127.0.0.1:9");
+        let request = SmsRequest::new("secret-key", "Alice", "+37061234567", "Code 4821");
+        assert!(!format!("{request:?}").contains("secret-key"));
+        let err = client.send_sms(request).await.unwrap_err();
+        assert!(matches!(err, SmsError::RequestFailed(_)));
+        for shown in [err.to_string(), format!("{err:?}")] {
+            assert!(!shown.contains("secret-key"), "{shown}");
+            assert!(!shown.contains("37061234567"), "{shown}");
+            assert!(!shown.contains("4821"), "{shown}");
+        }
+    }
+
+    #[tokio::test]
+    async fn send_sms_auto_encoding_picks_coding() {
+        let server = MockServer::start();
+        let ucs2 = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Ačiū")
+                .query_param("coding", "2");
+            then.status(200).body("1001");
+        });
+        let gsm7 = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Thanks")
+                .query_param_missing("coding");
+            then.status(200).body("1002");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        for text in ["Ačiū", "Thanks"] {
+            let req =
+                SmsRequest::new("k", "Alice", "+1234567890", text).with_encoding(Encoding::Auto);
+            client.send_sms(req).await.unwrap();
+        }
+        ucs2.assert();
+        gsm7.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_ucs2_sets_coding() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Привет 🙂")
+                .query_param("coding", "2")
+                .query_param_missing("udh");
+            then.status(200).body("1234");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let req =
+            SmsRequest::new("k", "Alice", "+1234567890", "Привет 🙂").with_encoding(Encoding::Ucs2);
+        client.send_sms(req).await.unwrap();
         m.assert();
     }
 
     #[tokio::test]
-    async fn send_sms_api_error_mapped() {
+    async fn send_long_sms_sends_concatenated_parts() {
+        let server = MockServer::start();
+        let first = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "a".repeat(153))
+                .query_param_matches("udh", "^050003[0-9A-F]{2}0201$");
+            then.status(200).body("1001");
+        });
+        let second = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "b".repeat(152))
+                .query_param_matches("udh", "^050003[0-9A-F]{2}0202$");
+            then.status(200).body("1002");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let text = format!("{}{}", "a".repeat(153), "b".repeat(152));
+        let result = client
+            .send_long_sms(SmsRequest::new("k", "Alice", "+1234567890", text))
+            .await
+            .unwrap();
+
+        assert!(result.reference.is_some());
+        assert_eq!(result.message_ids, vec!["1001", "1002"]);
+        first.assert();
+        second.assert();
+    }
+
+    #[tokio::test]
+    async fn send_long_sms_single_part_has_no_udh() {
         let server = MockServer::start();
         let m = server.mock(|when, then| {
-            when.method(GET).path("/send");
-            then.status(200).body("3"); // 3 => unable to authenticate
+            when.method(GET).path("/send").query_param_missing("udh");
+            then.status(200).body("1234");
         });
 
         let client = SmsClient::with_api_base_url(server.base_url());
-        let err = client.send_sms(base_request()).await.unwrap_err();
-        match err {
-            SmsError::SendFailed { number, message } => {
-                assert_eq!(number, "+1234567890");
-                assert_eq!(message, "unable to authenticate");
-            }
-            SmsError::RequestFailed(err) => panic!("Unexpected error type: {err}"),
-        }
+        let result = client.send_long_sms(base_request()).await.unwrap();
+        assert_eq!(result.reference, None);
+        assert_eq!(result.message_ids, vec!["1234"]);
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_binary_payload_with_udh() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "0102FF")
+                .query_param("coding", "1")
+                .query_param("udh", "060504C34FC002");
+            then.status(200).body("1234");
+        });
+
+        let req = base_request()
+            .with_binary(&[0x01, 0x02, 0xFF])
+            .with_udh(Udh::new().with_ports(0xC34F, 0xC002));
+        assert!(matches!(req.encoding, Encoding::EightBit));
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        client.send_sms(req).await.unwrap();
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_long_sms_splits_binary_payloads_by_byte() {
+        let server = MockServer::start();
+        let first = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "AB".repeat(134))
+                .query_param("coding", "1");
+            then.status(200).body("1001");
+        });
+        let last = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "AB".repeat(7));
+            then.status(200).body("1002");
+        });
+
+        let payload = [0xAB; 141];
+        let client = SmsClient::with_api_base_url(server.base_url());
+        assert_eq!(
+            client
+                .segment_info(&base_request().with_binary(&payload))
+                .segments,
+            2
+        );
+        let result = client
+            .send_long_sms(base_request().with_binary(&payload))
+            .await
+            .unwrap();
+        assert_eq!(result.message_ids, vec!["1001", "1002"]);
+        first.assert();
+        last.assert();
+
+        let ported = base_request()
+            .with_binary(&payload)
+            .with_udh(Udh::new().with_ports(0xC34F, 0xC002));
+        assert!(matches!(
+            client.send_long_sms(ported).await,
+            Err(SmsError::SendFailed { code: None, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_wap_push_splits_large_payloads() {
+        let server = MockServer::start();
+        let single = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("udh", "0605040B8423F0")
+                .query_param("coding", "1");
+            then.status(200).body("1001");
+        });
+        let parts = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param_matches("udh", "^0B05040B8423F0000[0-9A-F]{3}020[12]$");
+            then.status(200).body("1002");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let short = WapPush::new("News", "https://example.com/n");
+        let result = client
+            .send_wap_push("k", "Alice", "+1234567890", &short)
+            .await
+            .unwrap();
+        assert_eq!(result.message_ids, vec!["1001"]);
+
+        let long = WapPush::new("N".repeat(150), "https://example.com/n");
+        let result = client
+            .send_wap_push("k", "Alice", "+1234567890", &long)
+            .await
+            .unwrap();
+        assert_eq!(result.message_ids, vec!["1002", "1002"]);
+        single.assert();
+        parts.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn send_sms_transliterates_when_configured() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Aciu - zr. \"Zalgiris\"")
+                .query_param_missing("coding");
+            then.status(200).body("1234");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_transliterator(Transliterator::new())
+            .build()
+            .unwrap();
+        let req = SmsRequest::new("k", "Alice", "+1234567890", "Ačiū – žr. „Žalgiris“")
+            .with_encoding(Encoding::Auto);
+        client.send_sms(req).await.unwrap();
         m.assert();
     }
 
     #[tokio::test]
-    async fn send_sms_unknown_text_maps_to_unknown_error() {
+    async fn send_sms_adds_opt_out_footer_to_marketing() {
+        let server = MockServer::start();
+        let marketing = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Sale today\nReply STOP to unsubscribe");
+            then.status(200).body("1234");
+        });
+        let transactional = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("text", "Sale today");
+            then.status(200).body("1235");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_opt_out_footer("Reply STOP to unsubscribe")
+            .with_price_table(PriceTable::new().with_default_price(0.03))
+            .build()
+            .unwrap();
+        let req = SmsRequest::new("k", "Alice", "+37061234567", "Sale today");
+        client.send_sms(req.as_borrowed()).await.unwrap();
+        let req = req.with_category(MessageCategory::Marketing);
+        client.send_sms(req.as_borrowed()).await.unwrap();
+        marketing.assert();
+        transactional.assert();
+
+        // 140 characters fit one segment, but not with the footer
+        let long = SmsRequest::new("k", "Alice", "+37061234567", "a".repeat(140));
+        assert_eq!(client.estimate_cost(&long).unwrap().unwrap().segments, 1);
+        let long = long.with_category(MessageCategory::Marketing);
+        assert_eq!(client.estimate_cost(&long).unwrap().unwrap().segments, 2);
+    }
+
+    #[tokio::test]
+    async fn send_sms_rejects_invalid_number_without_request() {
         let server = MockServer::start();
         let m = server.mock(|when, then| {
             when.method(GET).path("/send");
-            then.status(200).body("not-a-number");
+            then.status(200).body("1234");
         });
 
         let client = SmsClient::with_api_base_url(server.base_url());
-        let err = client.send_sms(base_request()).await.unwrap_err();
+        let req = SmsRequest::new("k", "Alice", "+370 CALL ME", "Hello");
+        let err = client.send_sms(req).await.unwrap_err();
         match err {
-            SmsError::SendFailed { number, message } => {
-                assert_eq!(number, "+1234567890");
-                assert_eq!(message, "unknown error");
+            SmsError::InvalidNumber { number, reason } => {
+                assert_eq!(number, "+370 CALL ME");
+                assert_eq!(reason, PhoneError::InvalidCharacter('C'));
             }
-            SmsError::RequestFailed(err) => panic!("Unexpected error type: {err}"),
+            other => panic!("Unexpected error type: {other}"),
         }
+        m.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn send_sms_skips_validation_when_disabled() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send").query_param("number", "123");
+            then.status(200).body("7");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_number_validation(false)
+            .build()
+            .unwrap();
+        let err = client
+            .send_sms(SmsRequest::new("k", "Alice", "123", "Hello"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SmsError::SendFailed { .. }));
         m.assert();
     }
 
     #[tokio::test]
-    async fn send_sms_http_failure_is_request_failed() {
-        // Use a non-routable private address to provoke connection error
-        let client = SmsClient::with_api_base_url("http://10.255.255.1".to_string());
-        let err = client.send_sms(base_request()).await.unwrap_err();
-        matches!(err, SmsError::RequestFailed(_));
+    async fn send_sms_normalizes_number_when_enabled() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200).body("1234");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_phone_normalization(Some("LT"))
+            .build()
+            .unwrap();
+        let req = SmsRequest::new("k", "Alice", "8 (612) 34-567", "Hello");
+        assert_eq!(client.send_sms(req).await.unwrap(), "1234");
+        m.assert();
     }
 
     #[test]
@@ -388,13 +2683,387 @@ mod tests {
         matches!(req.encoding, Encoding::EightBit);
     }
 
-    #[test]
-    fn get_response_code_message_works() {
-        assert_eq!(get_response_code_message(1), "system internal error");
+    #[tokio::test]
+    async fn send_sms_converts_time_to_gateway_timezone() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("time", "2024-01-10T09:00:00");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_gateway_timezone(chrono_tz::Europe::Riga)
+            .build()
+            .unwrap();
+        let at = DateTime::parse_from_rfc3339("2024-01-10T08:00:00+01:00").unwrap();
+        let req = SmsRequest::new("k", "Alice", "+37061234567", "Hi").with_time(at);
+        assert_eq!(req.time.unwrap().to_rfc3339(), "2024-01-10T07:00:00+00:00");
+        assert_eq!(client.send_sms(req).await.unwrap(), "1001");
+        m.assert();
+
+        let local = chrono::NaiveDate::from_ymd_opt(2024, 7, 1)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let req = SmsRequest::new("k", "Alice", "+37061234567", "Hi")
+            .with_local_time(local, chrono_tz::Europe::Vilnius);
+        assert_eq!(req.time.unwrap().to_rfc3339(), "2024-07-01T06:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn send_sms_returns_original_id_for_duplicates() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_idempotency(
+                Arc::new(crate::idempotency::MemoryIdempotencyStore::new()),
+                Duration::from_secs(600),
+            )
+            .build()
+            .unwrap();
+
+        let req = || SmsRequest::new("k", "Alice", "+37061234567", "Your order shipped");
+        assert_eq!(client.send_sms(req()).await.unwrap(), "1001");
+        assert_eq!(client.send_sms(req()).await.unwrap(), "1001");
+        m.assert_calls(1);
+
+        let keyed = req().with_idempotency_key("order-7");
+        assert_eq!(client.send_sms(keyed.clone()).await.unwrap(), "1001");
+        assert_eq!(client.send_sms(keyed).await.unwrap(), "1001");
+        m.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn send_sms_reserves_idempotency_keys() {
+        let server = MockServer::start();
+        let mut failing = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("2");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_idempotency(
+                Arc::new(crate::idempotency::MemoryIdempotencyStore::new()),
+                Duration::from_secs(600),
+            )
+            .build()
+            .unwrap();
+        let req =
+            || SmsRequest::new("k", "Alice", "+37061234567", "Hi").with_idempotency_key("order-8");
+
+        // A failed send releases its key
+        assert!(client.send_sms(req()).await.is_err());
+        failing.delete();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200)
+                .body("1001")
+                .delay(Duration::from_millis(200));
+        });
+
+        let (first, second) = futures_util::join!(client.send_sms(req()), async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.send_sms(req()).await
+        });
+        assert_eq!(first.unwrap(), "1001");
+        let err = second.unwrap_err();
+        assert!(matches!(err, SmsError::InProgress { .. }));
+        assert!(err.is_retryable());
+        assert_eq!(client.send_sms(req()).await.unwrap(), "1001");
+        m.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn send_sms_keeps_keys_of_sends_that_may_have_been_accepted() {
+        let server = MockServer::start();
+        let mut m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(503);
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_idempotency(
+                Arc::new(crate::idempotency::MemoryIdempotencyStore::new()),
+                Duration::from_secs(600),
+            )
+            .build()
+            .unwrap();
+        let req =
+            |key| SmsRequest::new("k", "Alice", "+37061234567", "Hi").with_idempotency_key(key);
+
+        let err = client.send_sms(req("order-9")).await.unwrap_err();
+        assert!(matches!(err, SmsError::HttpStatus { status: 503, .. }));
+        let err = client.send_sms(req("order-9")).await.unwrap_err();
+        assert!(matches!(err, SmsError::Uncertain { .. }));
+        assert!(!err.is_retryable());
+        m.assert_calls(1);
+
+        // A send dropped while in flight is just as uncertain
+        m.delete();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200)
+                .body("1001")
+                .delay(Duration::from_millis(200));
+        });
+        let cancel = tokio::time::sleep(Duration::from_millis(50));
+        let err = client
+            .send_sms_with_cancel(req("order-10"), cancel)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SmsError::Cancelled { .. }));
+        let err = client.send_sms(req("order-10")).await.unwrap_err();
+        assert!(matches!(err, SmsError::Uncertain { .. }));
+        m.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn sender_trait_is_usable_generically() {
+        async fn notify(sender: &impl SmsSender, number: &str) -> Result<SendResult, SmsError> {
+            sender
+                .send_sms(SmsRequest::new("k", "Alice", number, "Hi"))
+                .await
+        }
+
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let client = Arc::new(SmsClient::with_api_base_url(server.base_url()));
+        let result = notify(&client, "+37061234567").await.unwrap();
         assert_eq!(
-            get_response_code_message(19),
-            "invalid FLAG-CONVERT parameter"
+            result,
+            SendResult {
+                message_id: "1001".to_string(),
+                number: "+37061234567".to_string(),
+            }
+        );
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_rejects_invalid_sender() {
+        let client = SmsClient::with_api_base_url("http://127.0.0.1:9");
+        let req = SmsRequest::new("k", "TooLongSenderName", "+37061234567", "Hi");
+        match client.send_sms(req).await {
+            Err(SmsError::InvalidSender { sender, reason }) => {
+                assert_eq!(sender, "TooLongSenderName");
+                assert_eq!(reason, SenderError::AlphanumericTooLong(17));
+            }
+            other => panic!("expected InvalidSender, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_sms_applies_number_filter() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_number_filter(NumberFilter::new().with_blocked_prefix("+371"))
+            .build()
+            .unwrap();
+
+        let req = SmsRequest::new("k", "Alice", "+37120000000", "Hi");
+        match client.send_sms(req).await {
+            Err(SmsError::Blocked { number, reason }) => {
+                assert_eq!(number, "+37120000000");
+                assert_eq!(reason, BlockReason::BlockedPrefix("371".to_string()));
+            }
+            other => panic!("expected Blocked, got {other:?}"),
+        }
+        m.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn send_sms_refuses_opted_out_numbers() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let registry = Arc::new(crate::optout::MemoryOptOutRegistry::new());
+        registry.add("+37061234567").unwrap();
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_opt_out_registry(registry)
+            .build()
+            .unwrap();
+
+        let req = SmsRequest::new("k", "Alice", "+37061234567", "Sale!");
+        match client.send_sms(req).await {
+            Err(SmsError::OptedOut { number }) => assert_eq!(number, "+37061234567"),
+            other => panic!("expected OptedOut, got {other:?}"),
+        }
+        let req = SmsRequest::new("k", "Alice", "+37061234568", "Sale!");
+        assert_eq!(client.send_sms(req).await.unwrap(), "1001");
+        m.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn send_sms_defers_during_quiet_hours() {
+        let now = Utc::now();
+        let window_end = now + chrono::TimeDelta::hours(1);
+        let quiet = QuietHours::new(
+            (now - chrono::TimeDelta::hours(1)).time(),
+            window_end.time(),
+        );
+
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("time", window_end.format("%Y-%m-%dT%H:%M:%S").to_string());
+            then.status(200).body("1001");
+        });
+
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_quiet_hours(quiet)
+            .build()
+            .unwrap();
+        let req = SmsRequest::new("k", "Alice", "+37061234567", "Good night");
+        assert_eq!(client.send_sms(req).await.unwrap(), "1001");
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_sms_maps_message_class() {
+        let server = MockServer::start();
+        let flash = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("flag-flash", "1")
+                .query_param_missing("mclass");
+            then.status(200).body("1001");
+        });
+        let sim = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param_missing("mclass")
+                .query_param_missing("flag-flash");
+            then.status(200).body("1002");
+        });
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let req = SmsRequest::flash("k", "Alice", "+1234567890", "Hello");
+        assert_eq!(client.send_sms(req).await.unwrap(), "1001");
+        let req = base_request().with_message_class(MessageClass::Class2);
+        assert_eq!(client.send_sms(req).await.unwrap(), "1002");
+        flash.assert();
+        sim.assert();
+    }
+
+    #[test]
+    fn convenience_constructors_set_intent() {
+        let req = SmsRequest::test("k", "S", "N", "T");
+        assert_eq!(req.flags, SmsFlags::TEST);
+        assert_eq!(req.message_class, None);
+
+        let req = SmsRequest::flash("k", "S", "N", "T");
+        assert_eq!(req.message_class, Some(MessageClass::Class0));
+    }
+
+    #[test]
+    fn owned_request_round_trips() {
+        let api_key = String::from("key");
+        let dlr_url = String::from("https://example.com/dlr");
+        let owned: SmsRequestOwned = SmsRequest::new(&api_key, "S", "N", "T")
+            .with_dlr_url(&dlr_url)
+            .with_flags(SmsFlags::TEST)
+            .into_owned();
+        drop((api_key, dlr_url));
+
+        let handle = std::thread::spawn(move || owned);
+        let owned = handle.join().unwrap();
+
+        let borrowed = owned.as_borrowed();
+        assert!(matches!(borrowed.api_key, Cow::Borrowed("key")));
+        assert_eq!(borrowed.dlr_url.as_deref(), Some("https://example.com/dlr"));
+        assert_eq!(borrowed.flags, SmsFlags::TEST);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn request_serde_round_trip() {
+        let time = Utc.with_ymd_and_hms(2025, 1, 2, 3, 4, 5).unwrap();
+        let req = base_request()
+            .with_time(time)
+            .with_flags(SmsFlags::FLASH | SmsFlags::TEST)
+            .with_encoding(Encoding::EightBit);
+
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["flags"], "FLASH | TEST");
+        assert_eq!(json["encoding"], "eight_bit");
+        assert!(json.get("dlr_url").is_none());
+
+        let parsed: SmsRequestOwned = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.number, "+1234567890");
+        assert_eq!(parsed.time, Some(time));
+        assert_eq!(parsed.flags, SmsFlags::FLASH | SmsFlags::TEST);
+        assert!(matches!(parsed.encoding, Encoding::EightBit));
+
+        let minimal: SmsRequestOwned =
+            serde_json::from_str(r#"{"api_key": "k", "sender": "S", "number": "N", "text": "T"}"#)
+                .unwrap();
+        assert_eq!(minimal.flags, SmsFlags::empty());
+        assert!(matches!(minimal.encoding, Encoding::Default));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn error_serializes_with_kind() {
+        let err = SmsError::SendFailed {
+            number: "+1".to_string(),
+            code: Some(3),
+            message: "unable to authenticate".to_string(),
+        };
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "send_failed");
+        assert_eq!(json["message"], "unable to authenticate");
+    }
+
+    #[test]
+    fn classifies_retryable_errors() {
+        let failed = |code| SmsError::SendFailed {
+            number: "+1".to_string(),
+            code,
+            message: String::new(),
+        };
+        assert!(failed(Some(1)).is_retryable());
+        for code in [3, 5, 6, 7, 8] {
+            assert!(!failed(Some(code)).is_retryable());
+        }
+        assert!(!failed(None).is_retryable());
+        assert!(
+            SmsError::HttpStatus {
+                number: "+1".to_string(),
+                status: 503
+            }
+            .is_retryable()
+        );
+        assert!(
+            !SmsError::OptedOut {
+                number: "+1".to_string()
+            }
+            .is_retryable()
         );
-        assert_eq!(get_response_code_message(999), "unknown error");
     }
 }
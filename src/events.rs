@@ -0,0 +1,228 @@
+use crate::delivery::{DeliveryRecord, MessageDelivery};
+use crate::esteria::{SmsError, SmsRequest};
+
+/// Callbacks for message lifecycle events
+///
+/// Registered with [`crate::SmsClientBuilder::with_event_listener`]. Sent and
+/// failed events fire once per send attempt (once per part for long
+/// messages); delivered events fire from [`crate::SmsClient::record_delivery`].
+/// Callbacks run inline, so slow work should be handed off to a task.
+pub trait EventListener: Send + Sync + std::fmt::Debug {
+    /// The gateway accepted a message
+    fn on_sent(&self, _request: &SmsRequest<'_>, _message_id: &str) {}
+
+    /// A send attempt failed, client-side or at the gateway
+    fn on_failed(&self, _request: &SmsRequest<'_>, _error: &SmsError) {}
+
+    /// A delivery report updated a tracked message
+    fn on_delivered(&self, _record: &DeliveryRecord) {}
+
+    /// The aggregated status of a tracked message became final
+    ///
+    /// Fires once per message, after the `on_delivered` of the report that
+    /// delivered its last part or failed one of them.
+    fn on_message_delivered(&self, _delivery: &MessageDelivery) {}
+}
+
+/// Destination of serialized lifecycle events, e.g. a Kafka producer
+///
+/// Called inline from event callbacks, so it should only enqueue the
+/// payload (as `rdkafka`'s `FutureProducer::send_result` does).
+/// Implemented for Kafka by [`crate::kafka::KafkaPublisher`] (feature
+/// `kafka`).
+#[cfg(feature = "serde")]
+pub trait EventPublisher: Send + Sync + std::fmt::Debug {
+    /// Publish `payload` under `key`, the message ID for sent and delivered
+    /// events so that they land in the same partition
+    fn publish(&self, key: &str, payload: &[u8]);
+}
+
+/// Event listener publishing every event as a JSON object
+///
+/// Objects carry an `event` field (`sent`, `failed` or `delivered`) and
+/// a `timestamp`. Sent events have the `message_id`, `number` and `user_key`,
+/// failed events the `number` and serialized `error`, and delivered events
+/// the fields of the [`DeliveryRecord`]. Numbers follow the
+/// [`crate::redact`] policy.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct PublishingListener<P> {
+    publisher: P,
+}
+
+#[cfg(feature = "serde")]
+impl<P: EventPublisher> PublishingListener<P> {
+    /// Publish events to `publisher`
+    pub fn new(publisher: P) -> Self {
+        Self { publisher }
+    }
+
+    fn publish(&self, key: &str, mut event: serde_json::Value) {
+        event["timestamp"] = serde_json::json!(chrono::Utc::now());
+        match serde_json::to_vec(&event) {
+            Ok(payload) => self.publisher.publish(key, &payload),
+            Err(err) => log::error!("Failed to encode event: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<P: EventPublisher> EventListener for PublishingListener<P> {
+    fn on_sent(&self, request: &SmsRequest<'_>, message_id: &str) {
+        let message_id = message_id.trim();
+        let event = serde_json::json!({
+            "event": "sent",
+            "message_id": message_id,
+            "number": crate::redact::number(&request.number).to_string(),
+            "user_key": request.user_key,
+        });
+        self.publish(message_id, event);
+    }
+
+    fn on_failed(&self, request: &SmsRequest<'_>, error: &SmsError) {
+        let number = crate::redact::number(&request.number).to_string();
+        let event = serde_json::json!({
+            "event": "failed",
+            "number": number,
+            "user_key": request.user_key,
+            "error": error,
+        });
+        self.publish(&number, event);
+    }
+
+    fn on_delivered(&self, record: &DeliveryRecord) {
+        let mut event = serde_json::json!(record);
+        event["event"] = "delivered".into();
+        self.publish(&record.message_id, event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::{DeliveryStatus, DeliveryStore, MemoryDeliveryStore};
+    use crate::esteria::SmsClient;
+    use httpmock::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl EventListener for Recorder {
+        fn on_sent(&self, _request: &SmsRequest<'_>, message_id: &str) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("sent {message_id}"));
+        }
+
+        fn on_failed(&self, request: &SmsRequest<'_>, _error: &SmsError) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("failed {}", request.number));
+        }
+
+        fn on_delivered(&self, record: &DeliveryRecord) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("delivered {}", record.message_id));
+        }
+    }
+
+    #[tokio::test]
+    async fn listeners_receive_lifecycle_events() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let recorder = Arc::new(Recorder::default());
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_event_listener(recorder.clone())
+            .build()
+            .unwrap();
+        let store = MemoryDeliveryStore::new();
+
+        let request = SmsRequest::new("k", "Alice", "+37061234567", "Hi");
+        client.send_tracked(request, &store).await.unwrap();
+        let invalid = SmsRequest::new("k", "Alice", "12", "Hi");
+        client.send_sms(invalid).await.unwrap_err();
+        client
+            .record_delivery(&store, "1001", &DeliveryStatus::Delivered)
+            .await
+            .unwrap();
+        client
+            .record_delivery(&store, "9999", &DeliveryStatus::Delivered)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            ["sent 1001", "failed 12", "delivered 1001"]
+        );
+        assert_eq!(
+            store.get("1001").await.unwrap().unwrap().status,
+            Some(DeliveryStatus::Delivered)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, Default)]
+    struct Topic {
+        messages: Mutex<Vec<(String, serde_json::Value)>>,
+    }
+
+    #[cfg(feature = "serde")]
+    impl EventPublisher for Arc<Topic> {
+        fn publish(&self, key: &str, payload: &[u8]) {
+            let payload = serde_json::from_slice(payload).unwrap();
+            self.messages
+                .lock()
+                .unwrap()
+                .push((key.to_string(), payload));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn publishes_events_as_json() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let topic = Arc::new(Topic::default());
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_event_listener(Arc::new(PublishingListener::new(topic.clone())))
+            .build()
+            .unwrap();
+        let store = MemoryDeliveryStore::new();
+
+        let request = SmsRequest::new("k", "Alice", "+37061234567", "Hi").with_user_key("order-7");
+        client.send_tracked(request, &store).await.unwrap();
+        client
+            .record_delivery(&store, "1001", &DeliveryStatus::Delivered)
+            .await
+            .unwrap();
+
+        let messages = topic.messages.lock().unwrap();
+        assert_eq!(messages.len(), 2);
+        let (key, sent) = &messages[0];
+        assert_eq!(key, "1001");
+        assert_eq!(sent["event"], "sent");
+        assert_eq!(sent["user_key"], "order-7");
+        let (key, delivered) = &messages[1];
+        assert_eq!(key, "1001");
+        assert_eq!(delivered["event"], "delivered");
+        assert_eq!(delivered["status"], "delivered");
+        assert!(delivered["timestamp"].is_string());
+    }
+}
@@ -0,0 +1,235 @@
+use crate::delivery::{self, DeliveryStatus, DeliveryStore, DeliveryStoreError};
+use crate::esteria::{SmsClient, SmsError, SmsRequest};
+use crate::redact;
+use futures_util::StreamExt;
+use std::future::Future;
+use std::pin::pin;
+use std::time::Duration;
+
+/// How long to wait for a final delivery report by default
+pub const DEFAULT_DELIVERY_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// How often to check the delivery store by default
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why a message is handed to the fallback channel
+#[derive(Debug)]
+pub enum FallbackReason {
+    /// Sending failed, after the client's own retries
+    Failed(SmsError),
+    /// The delivery report gave a final status other than delivered
+    Undelivered(DeliveryStatus),
+    /// No final delivery report arrived within the delivery timeout
+    NoReport,
+}
+
+/// Message that couldn't be delivered by SMS
+pub struct Fallback {
+    pub number: String,
+    pub text: String,
+    pub user_key: Option<String>,
+    /// Gateway message ID, if the message was accepted
+    pub message_id: Option<String>,
+    pub reason: FallbackReason,
+}
+
+impl std::fmt::Debug for Fallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fallback")
+            .field("number", &redact::number(&self.number))
+            .field("user_key", &self.user_key)
+            .field("message_id", &self.message_id)
+            .field("reason", &self.reason)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Second channel for messages SMS couldn't deliver, e.g. email or a voice call
+pub trait FallbackHandler: Send + Sync {
+    /// Deliver `fallback` some other way
+    fn handle(&self, fallback: &Fallback) -> impl Future<Output = ()> + Send;
+}
+
+/// When to fall back from SMS to a [`FallbackHandler`]
+#[derive(Debug)]
+pub struct FallbackPolicy<H> {
+    handler: H,
+    delivery_timeout: Duration,
+    poll_interval: Duration,
+}
+
+impl<H: FallbackHandler> FallbackPolicy<H> {
+    /// Fall back to `handler`
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            delivery_timeout: DEFAULT_DELIVERY_TIMEOUT,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Fall back if no final delivery report arrives within `timeout`
+    #[must_use]
+    pub fn with_delivery_timeout(mut self, timeout: Duration) -> Self {
+        self.delivery_timeout = timeout;
+        self
+    }
+
+    /// Check the delivery store every `interval`
+    #[must_use]
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// The fallback handler
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+}
+
+impl SmsClient {
+    /// Send an SMS, falling back to `policy`'s handler if it isn't delivered
+    ///
+    /// The message is tracked in `store` (see [`SmsClient::send_tracked`]),
+    /// which must be fed by a delivery report receiver. The handler runs if
+    /// sending fails (except when cancelled), if the delivery report gives a
+    /// final status other than delivered, or if none arrives within the
+    /// delivery timeout. This resolves only then, so spawn it for messages
+    /// that shouldn't hold up the caller. Store errors while waiting are
+    /// logged and end the wait without falling back.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError` if the message could not be sent, after the handler ran
+    pub async fn send_with_fallback<S: DeliveryStore, H: FallbackHandler>(
+        &self,
+        request: SmsRequest<'_>,
+        store: &S,
+        policy: &FallbackPolicy<H>,
+    ) -> Result<String, SmsError> {
+        let mut fallback = Fallback {
+            number: request.number.to_string(),
+            text: request.text.to_string(),
+            user_key: request.user_key.as_deref().map(str::to_string),
+            message_id: None,
+            reason: FallbackReason::NoReport,
+        };
+        let message_id = match self.send_tracked(request, store).await {
+            Ok(message_id) => message_id,
+            Err(err @ SmsError::Cancelled { .. }) => return Err(err),
+            Err(err) => {
+                fallback.reason = FallbackReason::Failed(err);
+                policy.handler.handle(&fallback).await;
+                let FallbackReason::Failed(err) = fallback.reason else {
+                    unreachable!("reason was just set")
+                };
+                return Err(err);
+            }
+        };
+
+        let final_status = async {
+            let mut updates = pin!(delivery::watch_status(
+                store,
+                &message_id,
+                policy.poll_interval
+            ));
+            let mut status = None;
+            while let Some(update) = updates.next().await {
+                status = Some(update?);
+            }
+            Ok::<_, DeliveryStoreError>(status)
+        };
+        match tokio::time::timeout(policy.delivery_timeout, final_status).await {
+            Ok(Ok(Some(status))) if status.is_success() => return Ok(message_id),
+            Ok(Ok(Some(status))) if status.is_terminal() => {
+                fallback.reason = FallbackReason::Undelivered(status);
+            }
+            Ok(Err(err)) => {
+                log::error!("Failed to watch message {message_id}: {err}");
+                return Ok(message_id);
+            }
+            // Untracked before a final status, or timed out
+            Ok(Ok(_)) | Err(_) => {}
+        }
+        log::info!(
+            "Message {message_id} to {} not delivered, falling back",
+            redact::number(&fallback.number)
+        );
+        fallback.message_id = Some(message_id.clone());
+        policy.handler.handle(&fallback).await;
+        Ok(message_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::MemoryDeliveryStore;
+    use httpmock::prelude::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl FallbackHandler for Recorder {
+        async fn handle(&self, fallback: &Fallback) {
+            let reason = match &fallback.reason {
+                FallbackReason::Failed(_) => "failed".to_string(),
+                FallbackReason::Undelivered(status) => status.to_string(),
+                FallbackReason::NoReport => "no report".to_string(),
+            };
+            self.0.lock().unwrap().push(reason);
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_failure_or_missing_delivery() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200).body("1001");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234568");
+            then.status(200).body("2");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let store = MemoryDeliveryStore::new();
+        let policy = FallbackPolicy::new(Recorder::default())
+            .with_delivery_timeout(Duration::from_millis(200))
+            .with_poll_interval(Duration::from_millis(10));
+        let request = |number| SmsRequest::new("k", "Shop", number, "Your code is 1234");
+
+        let rejected = client
+            .send_with_fallback(request("+37061234568"), &store, &policy)
+            .await;
+        assert!(rejected.is_err());
+
+        let (undelivered, ()) = tokio::join!(
+            client.send_with_fallback(request("+37061234567"), &store, &policy),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                client
+                    .record_delivery(&store, "1001", &DeliveryStatus::parse("UNDELIV"))
+                    .await
+                    .unwrap();
+            }
+        );
+        assert_eq!(undelivered.unwrap(), "1001");
+
+        client
+            .send_with_fallback(request("+37061234567"), &store, &policy)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *policy.handler().0.lock().unwrap(),
+            ["failed", "undelivered", "no report"]
+        );
+    }
+}
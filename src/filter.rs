@@ -0,0 +1,133 @@
+use crate::phone;
+use std::collections::HashSet;
+use thiserror::Error;
+
+/// Why a number was rejected by a [`NumberFilter`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BlockReason {
+    #[error("number is on the block list")]
+    BlockedNumber,
+    #[error("prefix {0} is on the block list")]
+    BlockedPrefix(String),
+    #[error("number is not on the allow list")]
+    NotAllowed,
+}
+
+/// Client-side block list and allow list of recipient numbers
+///
+/// Rules are exact numbers or number prefixes, compared by digits (see
+/// [`phone::digits`]). Block rules always win. If any allow rule is
+/// configured, numbers matching no allow rule are rejected as well.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NumberFilter {
+    blocked_numbers: HashSet<String>,
+    blocked_prefixes: Vec<String>,
+    allowed_numbers: HashSet<String>,
+    allowed_prefixes: Vec<String>,
+}
+
+impl NumberFilter {
+    /// Create a filter that lets every number through
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Block a single number
+    #[must_use]
+    pub fn with_blocked_number(mut self, number: &str) -> Self {
+        self.blocked_numbers.insert(phone::digits(number));
+        self
+    }
+
+    /// Block all numbers starting with `prefix`, e.g. `+371` or `44`
+    #[must_use]
+    pub fn with_blocked_prefix(mut self, prefix: &str) -> Self {
+        self.blocked_prefixes.push(phone::digits(prefix));
+        self
+    }
+
+    /// Allow a single number, restricting sends to allow-listed numbers
+    #[must_use]
+    pub fn with_allowed_number(mut self, number: &str) -> Self {
+        self.allowed_numbers.insert(phone::digits(number));
+        self
+    }
+
+    /// Allow all numbers starting with `prefix`, restricting sends to allow-listed numbers
+    #[must_use]
+    pub fn with_allowed_prefix(mut self, prefix: &str) -> Self {
+        self.allowed_prefixes.push(phone::digits(prefix));
+        self
+    }
+
+    /// Check a number against the rules
+    ///
+    /// # Errors
+    ///
+    /// Returns the `BlockReason` if the number must not receive messages
+    pub fn check(&self, number: &str) -> Result<(), BlockReason> {
+        let digits = phone::digits(number);
+
+        if self.blocked_numbers.contains(&digits) {
+            return Err(BlockReason::BlockedNumber);
+        }
+        if let Some(prefix) = self
+            .blocked_prefixes
+            .iter()
+            .find(|prefix| digits.starts_with(prefix.as_str()))
+        {
+            return Err(BlockReason::BlockedPrefix(prefix.clone()));
+        }
+
+        let restricted = !self.allowed_numbers.is_empty() || !self.allowed_prefixes.is_empty();
+        let allowed = self.allowed_numbers.contains(&digits)
+            || self
+                .allowed_prefixes
+                .iter()
+                .any(|prefix| digits.starts_with(prefix.as_str()));
+        if restricted && !allowed {
+            return Err(BlockReason::NotAllowed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_rules_reject_matches() {
+        let filter = NumberFilter::new()
+            .with_blocked_number("+370 612 34567")
+            .with_blocked_prefix("+371");
+
+        assert_eq!(
+            filter.check("0037061234567"),
+            Err(BlockReason::BlockedNumber)
+        );
+        assert_eq!(
+            filter.check("+37120000000"),
+            Err(BlockReason::BlockedPrefix("371".to_string()))
+        );
+        assert_eq!(filter.check("+37061234568"), Ok(()));
+    }
+
+    #[test]
+    fn allow_rules_restrict_and_block_wins() {
+        let filter = NumberFilter::new()
+            .with_allowed_prefix("370")
+            .with_allowed_number("+442079460958")
+            .with_blocked_number("+37061234567");
+
+        assert_eq!(filter.check("+37061234568"), Ok(()));
+        assert_eq!(filter.check("+442079460958"), Ok(()));
+        assert_eq!(filter.check("+442079460959"), Err(BlockReason::NotAllowed));
+        assert_eq!(
+            filter.check("+37061234567"),
+            Err(BlockReason::BlockedNumber)
+        );
+    }
+}
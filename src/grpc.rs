@@ -0,0 +1,355 @@
+use crate::delivery::{DeliveryRecord, DeliveryStore};
+use crate::esteria::{Encoding, SmsClient, SmsError, SmsFlags, SmsRequest, SmsRequestOwned};
+use crate::events::EventListener;
+use proto::sms_server::{Sms, SmsServer};
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Request, Response, Status};
+
+/// Protobuf messages and service of `proto/esteria.proto`, shipped with the crate
+#[allow(clippy::all, clippy::pedantic)]
+pub mod proto {
+    tonic::include_proto!("esteria.v1");
+}
+
+/// Metadata key of failed calls carrying the error kind, as in the JSON
+/// errors of the REST server
+pub const ERROR_KIND_METADATA: &str = "esteria-error-kind";
+
+/// Event listener feeding delivery reports to [`SmsService`] streams
+///
+/// Register it on the client with
+/// [`crate::SmsClientBuilder::with_event_listener`] and pass it to
+/// [`SmsService::new`]. Reports are dropped while nobody is streaming.
+#[derive(Debug)]
+pub struct DeliveryFeed {
+    sender: broadcast::Sender<DeliveryRecord>,
+}
+
+impl DeliveryFeed {
+    /// Buffer up to `capacity` reports per stream; a stream that falls
+    /// further behind ends with `DATA_LOSS`
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sender: broadcast::channel(capacity).0,
+        }
+    }
+}
+
+impl EventListener for DeliveryFeed {
+    fn on_delivered(&self, record: &DeliveryRecord) {
+        // No receivers just means no open streams
+        let _ = self.sender.send(record.clone());
+    }
+}
+
+/// gRPC service in front of an [`SmsClient`], the counterpart of
+/// [`crate::server::Server`] for teams standardizing on gRPC
+///
+/// Implements the `esteria.v1.Sms` service of `proto/esteria.proto`:
+/// `SendSms` sends and tracks a message in the store, `SendBulk` sends one
+/// message to many numbers (untracked, like [`SmsClient::send_bulk`]) and
+/// `DeliveryReports` streams the reports recorded by the client from then
+/// on. Serve it with `tonic`'s transport, passing
+/// [`SmsService::into_server`] to `Server::builder().add_service`.
+///
+/// Failed sends answer a status mapped from the [`SmsError`], with its kind
+/// in the [`ERROR_KIND_METADATA`] metadata.
+pub struct SmsService<S> {
+    client: Arc<SmsClient>,
+    store: Arc<S>,
+    feed: Arc<DeliveryFeed>,
+}
+
+impl<S> std::fmt::Debug for SmsService<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmsService")
+            .field("feed", &self.feed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: DeliveryStore + 'static> SmsService<S> {
+    /// Serve `client`, tracking sent messages in `store` and streaming the
+    /// reports of `feed`, which must be registered on `client`
+    pub fn new(client: Arc<SmsClient>, store: Arc<S>, feed: Arc<DeliveryFeed>) -> Self {
+        Self {
+            client,
+            store,
+            feed,
+        }
+    }
+
+    /// Wrap the service for `tonic::transport::Server::add_service`
+    #[must_use]
+    pub fn into_server(self) -> SmsServer<Self> {
+        SmsServer::new(self)
+    }
+}
+
+/// Error kind of the JSON representation of `err`
+fn error_kind(err: &SmsError) -> String {
+    serde_json::to_value(err)
+        .ok()
+        .and_then(|value| value["kind"].as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// gRPC status reported for a failed send
+fn send_error_status(err: &SmsError) -> Status {
+    let code = match err {
+        SmsError::InvalidNumber { .. } | SmsError::InvalidSender { .. } | SmsError::Template(_) => {
+            Code::InvalidArgument
+        }
+        SmsError::OptedOut { .. } | SmsError::Blocked { .. } => Code::PermissionDenied,
+        SmsError::Timeout { .. } => Code::DeadlineExceeded,
+        SmsError::Credentials(_) | SmsError::Cancelled { .. } => Code::Unavailable,
+        SmsError::InProgress { .. } | SmsError::Uncertain { .. } => Code::Aborted,
+        SmsError::SendFailed { .. }
+        | SmsError::RequestFailed(_)
+        | SmsError::HttpStatus { .. }
+        | SmsError::UnknownResponse { .. }
+        | SmsError::Tls { .. } => Code::Unknown,
+    };
+    let mut status = Status::new(code, err.to_string());
+    if let Ok(kind) = error_kind(err).parse() {
+        status.metadata_mut().insert(ERROR_KIND_METADATA, kind);
+    }
+    status
+}
+
+fn sms_request(sms: proto::SmsRequest) -> Result<SmsRequestOwned, Status> {
+    let encoding = match proto::Encoding::try_from(sms.encoding) {
+        Ok(proto::Encoding::Default) => Encoding::Default,
+        Ok(proto::Encoding::EightBit) => Encoding::EightBit,
+        Ok(proto::Encoding::Udh) => Encoding::Udh,
+        Ok(proto::Encoding::Auto) => Encoding::Auto,
+        Ok(proto::Encoding::Ucs2) => Encoding::Ucs2,
+        Err(_) => return Err(Status::invalid_argument("unknown encoding")),
+    };
+    let flags = SmsFlags::from_bits(sms.flags)
+        .ok_or_else(|| Status::invalid_argument("unknown flag bits"))?;
+
+    let mut request = SmsRequest::new(sms.api_key, sms.sender, sms.number, sms.text)
+        .with_flags(flags)
+        .with_encoding(encoding);
+    if let Some(time) = sms.time {
+        let time = chrono::DateTime::parse_from_rfc3339(&time)
+            .map_err(|err| Status::invalid_argument(format!("invalid time: {err}")))?;
+        request = request.with_time(time);
+    }
+    request.dlr_url = sms.dlr_url.map(Into::into);
+    request.expired = sms.expired;
+    request.user_key = sms.user_key.map(Into::into);
+    request.idempotency_key = sms.idempotency_key.map(Into::into);
+    Ok(request)
+}
+
+fn delivery_report(record: DeliveryRecord) -> proto::DeliveryReport {
+    proto::DeliveryReport {
+        status: record
+            .status
+            .as_ref()
+            .map(|status| status.as_str().to_string())
+            .unwrap_or_default(),
+        reason: record
+            .status
+            .as_ref()
+            .and_then(|status| status.reason())
+            .map(str::to_string),
+        message_id: record.message_id,
+        user_key: record.user_key,
+        updated_at: record.updated_at.to_rfc3339(),
+        parts: record.parts,
+    }
+}
+
+type ReportStream = Pin<Box<dyn Stream<Item = Result<proto::DeliveryReport, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<S: DeliveryStore + 'static> Sms for SmsService<S> {
+    async fn send_sms(
+        &self,
+        request: Request<proto::SmsRequest>,
+    ) -> Result<Response<proto::SendResult>, Status> {
+        let sms = sms_request(request.into_inner())?;
+        let message_id = self
+            .client
+            .send_tracked(sms.as_borrowed(), &*self.store)
+            .await
+            .map_err(|err| send_error_status(&err))?;
+        Ok(Response::new(proto::SendResult {
+            message_id: message_id.trim().to_string(),
+            number: sms.number.into_owned(),
+        }))
+    }
+
+    async fn send_bulk(
+        &self,
+        request: Request<proto::SendBulkRequest>,
+    ) -> Result<Response<proto::SendBulkResponse>, Status> {
+        let request = request.into_inner();
+        let sms = sms_request(
+            request
+                .sms
+                .ok_or_else(|| Status::invalid_argument("sms is required"))?,
+        )?;
+        let report = self.client.send_bulk(sms, request.numbers).await;
+        let results = report
+            .results
+            .into_iter()
+            .map(|recipient| proto::RecipientResult {
+                number: recipient.number,
+                result: Some(match recipient.result {
+                    Ok(message_id) => {
+                        proto::recipient_result::Result::MessageId(message_id.trim().to_string())
+                    }
+                    Err(err) => proto::recipient_result::Result::Error(proto::Error {
+                        kind: error_kind(&err),
+                        message: err.to_string(),
+                    }),
+                }),
+            })
+            .collect();
+        Ok(Response::new(proto::SendBulkResponse { results }))
+    }
+
+    type DeliveryReportsStream = ReportStream;
+
+    async fn delivery_reports(
+        &self,
+        request: Request<proto::DeliveryReportsRequest>,
+    ) -> Result<Response<ReportStream>, Status> {
+        let message_ids: HashSet<String> = request.into_inner().message_ids.into_iter().collect();
+        let reports = BroadcastStream::new(self.feed.sender.subscribe())
+            .filter(move |record| match record {
+                Ok(record) => message_ids.is_empty() || message_ids.contains(&record.message_id),
+                Err(_) => true,
+            })
+            .map(|record| match record {
+                Ok(record) => Ok(delivery_report(record)),
+                Err(BroadcastStreamRecvError::Lagged(missed)) => Err(Status::data_loss(format!(
+                    "stream fell behind, {missed} reports dropped"
+                ))),
+            });
+        Ok(Response::new(Box::pin(reports)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::{DeliveryStatus, MemoryDeliveryStore};
+    use httpmock::prelude::*;
+
+    fn service(server: &MockServer) -> SmsService<MemoryDeliveryStore> {
+        let feed = Arc::new(DeliveryFeed::new(16));
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_event_listener(feed.clone())
+            .build()
+            .unwrap();
+        SmsService::new(Arc::new(client), Arc::new(MemoryDeliveryStore::new()), feed)
+    }
+
+    fn sms(number: &str) -> proto::SmsRequest {
+        proto::SmsRequest {
+            api_key: "k".to_string(),
+            sender: "Shop".to_string(),
+            number: number.to_string(),
+            text: "Hi".to_string(),
+            user_key: Some("order-7".to_string()),
+            ..proto::SmsRequest::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_and_streams_delivery_reports() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let service = service(&server);
+
+        let sent = service
+            .send_sms(Request::new(sms("+37061234567")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(sent.message_id, "1001");
+
+        let filter = proto::DeliveryReportsRequest {
+            message_ids: vec!["1001".to_string()],
+        };
+        let mut reports = service
+            .delivery_reports(Request::new(filter))
+            .await
+            .unwrap()
+            .into_inner();
+        for message_id in ["9999", "1001"] {
+            service
+                .client
+                .record_delivery(
+                    &*service.store,
+                    message_id,
+                    &DeliveryStatus::from_report("undelivered", Some("absent")),
+                )
+                .await
+                .unwrap();
+        }
+        let report = reports.next().await.unwrap().unwrap();
+        assert_eq!(report.message_id, "1001");
+        assert_eq!(report.user_key.as_deref(), Some("order-7"));
+        assert_eq!(report.status, "undelivered");
+        assert_eq!(report.reason.as_deref(), Some("absent"));
+    }
+
+    #[tokio::test]
+    async fn reports_errors_per_call_and_recipient() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let service = service(&server);
+
+        let status = service.send_sms(Request::new(sms("12"))).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.metadata().get(ERROR_KIND_METADATA).unwrap(),
+            "invalid_number"
+        );
+        let mut bad_time = sms("+37061234567");
+        bad_time.time = Some("tomorrow".to_string());
+        let status = service.send_sms(Request::new(bad_time)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let bulk = proto::SendBulkRequest {
+            sms: Some(sms("")),
+            numbers: vec!["+37061234567".to_string(), "12".to_string()],
+        };
+        let results = service
+            .send_bulk(Request::new(bulk))
+            .await
+            .unwrap()
+            .into_inner()
+            .results;
+        assert_eq!(
+            results[0].result,
+            Some(proto::recipient_result::Result::MessageId(
+                "1001".to_string()
+            ))
+        );
+        let Some(proto::recipient_result::Result::Error(error)) = &results[1].result else {
+            panic!("expected an error, got {:?}", results[1]);
+        };
+        assert_eq!(error.kind, "invalid_number");
+    }
+}
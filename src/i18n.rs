@@ -0,0 +1,98 @@
+//! Translated gateway error messages for user-facing applications
+
+use crate::esteria::SmsError;
+use crate::response;
+
+/// Language of error messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Locale {
+    /// English, as returned by [`response::lookup`]
+    #[default]
+    En,
+    /// Lithuanian, the gateway's native locale
+    Lt,
+}
+
+/// Lithuanian descriptions of the documented gateway error codes
+const LT: &[(i32, &str)] = &[
+    (1, "vidinė sistemos klaida"),
+    (2, "trūksta parametro PARAM_NAME"),
+    (3, "nepavyko autentifikuoti"),
+    (4, "IP adresas neleidžiamas"),
+    (5, "neteisingas parametras SENDER"),
+    (6, "siuntėjas SENDER neleidžiamas"),
+    (7, "neteisingas parametras NUMBER"),
+    (8, "neteisingas parametras CODING"),
+    (9, "nepavyko konvertuoti TEXT"),
+    (10, "UDH ir TEXT per ilgi"),
+    (11, "tuščias parametras TEXT"),
+    (12, "neteisingas parametras TIME"),
+    (13, "neteisingas parametras EXPIRED"),
+    (14, "neteisingas parametras DLR-URL"),
+    (15, "neteisingas parametras FLAG-FLASH"),
+    (16, "neteisingas parametras FLAG-NOLOG"),
+    (17, "neteisingas parametras FLAG-TEST"),
+    (18, "neteisingas parametras FLAG-NOBL"),
+    (19, "neteisingas parametras FLAG-CONVERT"),
+];
+
+/// Description of a gateway error code in `locale`
+///
+/// Codes without a translation, such as those added with
+/// [`response::register`], fall back to their English description.
+#[must_use]
+pub fn message(code: i32, locale: Locale) -> Option<String> {
+    let translated = match locale {
+        Locale::En => None,
+        Locale::Lt => LT.iter().find(|(known, _)| *known == code),
+    };
+    match translated {
+        Some((_, message)) => Some((*message).to_string()),
+        None => response::lookup(code).map(|code| code.message),
+    }
+}
+
+impl SmsError {
+    /// Error message with the gateway's reason translated to `locale`
+    ///
+    /// Errors other than gateway rejections keep their English message.
+    #[must_use]
+    pub fn localized_message(&self, locale: Locale) -> String {
+        match self {
+            Self::SendFailed {
+                code: Some(code), ..
+            } => message(*code, locale).unwrap_or_else(|| self.to_string()),
+            _ => self.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_gateway_codes() {
+        assert_eq!(
+            message(3, Locale::Lt).as_deref(),
+            Some("nepavyko autentifikuoti")
+        );
+        assert_eq!(
+            message(3, Locale::En).as_deref(),
+            Some("unable to authenticate")
+        );
+        assert_eq!(message(997, Locale::Lt), None);
+
+        let err = SmsError::SendFailed {
+            number: "+37061234567".to_string(),
+            code: Some(7),
+            message: "invalid NUMBER parameter".to_string(),
+        };
+        assert_eq!(
+            err.localized_message(Locale::Lt),
+            "neteisingas parametras NUMBER"
+        );
+    }
+}
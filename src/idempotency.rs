@@ -0,0 +1,209 @@
+use crate::esteria::SmsRequest;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// State of an idempotency key, as returned by [`IdempotencyStore::reserve`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reservation {
+    /// The key was free and is now held by the caller
+    Reserved,
+    /// Another send holding the key has not finished yet
+    InProgress,
+    /// A send with the key failed after it may have reached the gateway
+    Uncertain,
+    /// A send with the key succeeded with this message ID
+    Sent(String),
+}
+
+/// Message IDs of recent sends, keyed by idempotency key
+///
+/// When configured with [`crate::SmsClientBuilder::with_idempotency`], a send
+/// reserves its key first: a key found sent returns the stored message ID
+/// instead of sending again, a key still in progress fails the duplicate
+/// with [`crate::SmsError::InProgress`], and a key whose send may have been
+/// accepted fails it with [`crate::SmsError::Uncertain`].
+pub trait IdempotencyStore: Send + Sync + std::fmt::Debug {
+    /// Message ID stored under `key`, if it has not expired
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Atomically hold `key` for a send for at most `ttl`, unless it is
+    /// already held or sent
+    fn reserve(&self, key: &str, ttl: Duration) -> Reservation;
+
+    /// Store the message ID of a send for `ttl`, replacing its reservation
+    fn insert(&self, key: &str, message_id: &str, ttl: Duration);
+
+    /// Drop the reservation of a send that was not accepted, so the key can
+    /// be sent again
+    fn release(&self, key: &str);
+
+    /// Keep the reservation of a send that may have been accepted, until it
+    /// expires
+    fn mark_uncertain(&self, key: &str);
+}
+
+/// State of a stored key
+#[derive(Debug)]
+enum KeyState {
+    Reserved,
+    Uncertain,
+    Sent(String),
+}
+
+/// State and expiry of a key
+type Entry = (KeyState, DateTime<Utc>);
+
+/// In-memory idempotency store, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryIdempotencyStore {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl MemoryIdempotencyStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn expiry(now: DateTime<Utc>, ttl: Duration) -> DateTime<Utc> {
+    TimeDelta::from_std(ttl)
+        .ok()
+        .and_then(|ttl| now.checked_add_signed(ttl))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    fn get(&self, key: &str) -> Option<String> {
+        let entries = self.entries();
+        match entries.get(key)? {
+            (KeyState::Sent(message_id), expires_at) if *expires_at > Utc::now() => {
+                Some(message_id.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn reserve(&self, key: &str, ttl: Duration) -> Reservation {
+        let now = Utc::now();
+        let mut entries = self.entries();
+        entries.retain(|_, (_, expires_at)| *expires_at > now);
+        match entries.get(key) {
+            Some((KeyState::Sent(message_id), _)) => Reservation::Sent(message_id.clone()),
+            Some((KeyState::Reserved, _)) => Reservation::InProgress,
+            Some((KeyState::Uncertain, _)) => Reservation::Uncertain,
+            None => {
+                entries.insert(key.to_string(), (KeyState::Reserved, expiry(now, ttl)));
+                Reservation::Reserved
+            }
+        }
+    }
+
+    fn insert(&self, key: &str, message_id: &str, ttl: Duration) {
+        let now = Utc::now();
+        let mut entries = self.entries();
+        entries.retain(|_, (_, expires_at)| *expires_at > now);
+        let state = KeyState::Sent(message_id.to_string());
+        entries.insert(key.to_string(), (state, expiry(now, ttl)));
+    }
+
+    fn release(&self, key: &str) {
+        let mut entries = self.entries();
+        if matches!(entries.get(key), Some((KeyState::Reserved, _))) {
+            entries.remove(key);
+        }
+    }
+
+    fn mark_uncertain(&self, key: &str) {
+        if let Some((state @ KeyState::Reserved, _)) = self.entries().get_mut(key) {
+            *state = KeyState::Uncertain;
+        }
+    }
+}
+
+/// Idempotency key derived from the sender, number and text of a message
+///
+/// The key is stable across processes and versions, so it can be used with
+/// persistent stores.
+#[must_use]
+pub fn derive_key(sender: &str, number: &str, text: &str) -> String {
+    let hash = fnv1a([sender, number, text]);
+    format!("auto:{hash:016x}")
+}
+
+/// 64-bit FNV-1a of `fields`, with separators so that field boundaries matter
+pub(crate) fn fnv1a<'a>(fields: impl IntoIterator<Item = &'a str>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for field in fields {
+        for byte in field.bytes().chain([0xFF]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Key for a request: the explicit key if set, otherwise a derived one
+pub(crate) fn request_key<'r>(request: &'r SmsRequest<'_>) -> Cow<'r, str> {
+    match &request.idempotency_key {
+        Some(key) => Cow::Borrowed(key),
+        None => Cow::Owned(derive_key(&request.sender, &request.number, &request.text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_keys_are_stable_and_distinct() {
+        let key = derive_key("Alice", "+37061234567", "Hi");
+        assert_eq!(key, derive_key("Alice", "+37061234567", "Hi"));
+        assert_ne!(key, derive_key("Alice", "+37061234568", "Hi"));
+        assert_ne!(
+            derive_key("Alice", "1", "23"),
+            derive_key("Alice", "12", "3")
+        );
+    }
+
+    #[test]
+    fn memory_store_expires_entries() {
+        let store = MemoryIdempotencyStore::new();
+        store.insert("a", "1001", Duration::from_secs(60));
+        store.insert("b", "1002", Duration::ZERO);
+        assert_eq!(store.get("a").as_deref(), Some("1001"));
+        assert_eq!(store.get("b"), None);
+    }
+
+    #[test]
+    fn memory_store_reserves_keys() {
+        let store = MemoryIdempotencyStore::new();
+        let ttl = Duration::from_secs(60);
+        assert_eq!(store.reserve("a", ttl), Reservation::Reserved);
+        assert_eq!(store.reserve("a", ttl), Reservation::InProgress);
+        assert_eq!(store.get("a"), None);
+
+        store.release("a");
+        assert_eq!(store.reserve("a", ttl), Reservation::Reserved);
+        store.insert("a", "1001", ttl);
+        store.release("a");
+        assert_eq!(
+            store.reserve("a", ttl),
+            Reservation::Sent("1001".to_string())
+        );
+
+        assert_eq!(store.reserve("b", ttl), Reservation::Reserved);
+        store.mark_uncertain("b");
+        store.release("b");
+        assert_eq!(store.reserve("b", ttl), Reservation::Uncertain);
+        assert_eq!(store.get("b"), None);
+    }
+}
@@ -0,0 +1,257 @@
+use crate::delivery::DeliveryStatus;
+use crate::events::EventPublisher;
+use apache_avro::Schema;
+use apache_avro::writer::datum::GenericDatumWriter;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+
+/// Avro schema of the events published with [`EventEncoding::Avro`]
+///
+/// Fields absent from an event are null (`parts` is empty). The delivery
+/// status is flattened into its status word and `reason`.
+pub const EVENT_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "SmsEvent",
+    "namespace": "esteria",
+    "fields": [
+        {"name": "event", "type": "string"},
+        {"name": "timestamp", "type": "string"},
+        {"name": "message_id", "type": ["null", "string"], "default": null},
+        {"name": "number", "type": ["null", "string"], "default": null},
+        {"name": "user_key", "type": ["null", "string"], "default": null},
+        {"name": "error", "type": ["null", {
+            "type": "record",
+            "name": "SmsError",
+            "fields": [
+                {"name": "kind", "type": "string"},
+                {"name": "number", "type": ["null", "string"], "default": null},
+                {"name": "message", "type": "string"}
+            ]
+        }], "default": null},
+        {"name": "status", "type": ["null", "string"], "default": null},
+        {"name": "reason", "type": ["null", "string"], "default": null},
+        {"name": "updated_at", "type": ["null", "string"], "default": null},
+        {"name": "parts", "type": {"type": "array", "items": "string"}, "default": []}
+    ]
+}"#;
+
+/// Payload encoding of a [`KafkaPublisher`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EventEncoding {
+    /// The JSON objects of [`crate::events::PublishingListener`]
+    #[default]
+    Json,
+    /// Avro binary datums of [`EVENT_SCHEMA`]
+    Avro,
+    /// Avro datums in the Confluent wire format, prefixed with the ID the
+    /// schema is registered under in a schema registry
+    ConfluentAvro { schema_id: u32 },
+}
+
+#[derive(Debug, thiserror::Error)]
+enum EncodeError {
+    #[error("event is not JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("event doesn't match the Avro schema: {0}")]
+    Avro(#[from] apache_avro::Error),
+}
+
+/// [`EventPublisher`] producing lifecycle events to a Kafka topic through
+/// `rdkafka`
+///
+/// Use it with [`crate::events::PublishingListener`]: events are keyed by
+/// message ID, so those of a message land in the same partition. Publishing
+/// only enqueues the record; failures are logged, including delivery
+/// failures reported while a Tokio runtime is running.
+pub struct KafkaPublisher {
+    producer: FutureProducer,
+    topic: String,
+    encoding: EventEncoding,
+    schema: Schema,
+}
+
+impl KafkaPublisher {
+    /// Publish JSON events to `topic`
+    #[must_use]
+    pub fn new(producer: FutureProducer, topic: impl Into<String>) -> Self {
+        Self {
+            producer,
+            topic: topic.into(),
+            encoding: EventEncoding::Json,
+            schema: Schema::parse_str(EVENT_SCHEMA).expect("event schema is valid"),
+        }
+    }
+
+    /// Encode events as `encoding` instead of JSON
+    #[must_use]
+    pub fn with_encoding(mut self, encoding: EventEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Topic events are published to
+    #[must_use]
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    fn encode(&self, payload: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        let schema_id = match self.encoding {
+            EventEncoding::Json => return Ok(payload.to_vec()),
+            EventEncoding::Avro => None,
+            EventEncoding::ConfluentAvro { schema_id } => Some(schema_id),
+        };
+
+        let mut event: serde_json::Value = serde_json::from_slice(payload)?;
+        if let Some(status) = event.get("status").filter(|status| !status.is_null()) {
+            let status: DeliveryStatus = serde_json::from_value(status.clone())?;
+            event["reason"] = status.reason().into();
+            event["status"] = status.as_str().into();
+        }
+        let value = apache_avro::to_value(event)?.resolve(&self.schema)?;
+        let datum = GenericDatumWriter::builder(&self.schema)
+            .build()?
+            .write_value_to_vec(value)?;
+        Ok(match schema_id {
+            Some(schema_id) => [&[0][..], &schema_id.to_be_bytes(), &datum].concat(),
+            None => datum,
+        })
+    }
+}
+
+impl std::fmt::Debug for KafkaPublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaPublisher")
+            .field("topic", &self.topic)
+            .field("encoding", &self.encoding)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventPublisher for KafkaPublisher {
+    fn publish(&self, key: &str, payload: &[u8]) {
+        let payload = match self.encode(payload) {
+            Ok(payload) => payload,
+            Err(err) => {
+                log::error!("Failed to encode event for Kafka: {err}");
+                return;
+            }
+        };
+        let record = FutureRecord::to(&self.topic).key(key).payload(&payload);
+        let delivery = match self.producer.send_result(record) {
+            Ok(delivery) => delivery,
+            Err((err, _)) => {
+                log::error!("Failed to publish event to {}: {err}", self.topic);
+                return;
+            }
+        };
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let topic = self.topic.clone();
+            runtime.spawn(async move {
+                if let Ok(Err((err, _))) = delivery.await {
+                    log::error!("Failed to deliver event to {topic}: {err}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::ClientConfig;
+
+    fn publisher(encoding: EventEncoding) -> KafkaPublisher {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", "127.0.0.1:1")
+            .create()
+            .unwrap();
+        KafkaPublisher::new(producer, "sms-events").with_encoding(encoding)
+    }
+
+    fn decode(publisher: &KafkaPublisher, mut datum: &[u8]) -> apache_avro::types::Value {
+        apache_avro::reader::datum::GenericDatumReader::builder(&publisher.schema)
+            .build()
+            .unwrap()
+            .read_value(&mut datum)
+            .unwrap()
+    }
+
+    #[test]
+    fn encodes_events_as_avro() {
+        let publisher = publisher(EventEncoding::Avro);
+        let delivered = serde_json::json!({
+            "event": "delivered",
+            "timestamp": "2026-10-14T10:00:00Z",
+            "message_id": "1001",
+            "user_key": null,
+            "status": {"undelivered": {"reason": "absent subscriber"}},
+            "updated_at": "2026-10-14T10:00:00Z",
+        });
+        let datum = publisher
+            .encode(&serde_json::to_vec(&delivered).unwrap())
+            .unwrap();
+        let value: serde_json::Value =
+            apache_avro::from_value(&decode(&publisher, &datum)).unwrap();
+        assert_eq!(value["message_id"], "1001");
+        assert_eq!(value["status"], "undelivered");
+        assert_eq!(value["reason"], "absent subscriber");
+        assert_eq!(value["error"], serde_json::Value::Null);
+        assert_eq!(value["parts"], serde_json::json!([]));
+
+        let failed = br#"{"event": "failed", "timestamp": "2026-10-14T10:00:00Z",
+            "number": "+3706****567", "user_key": "order-7",
+            "error": {"kind": "timeout", "number": "+3706****567", "message": "timed out"}}"#;
+        let datum = publisher.encode(failed).unwrap();
+        let value: serde_json::Value =
+            apache_avro::from_value(&decode(&publisher, &datum)).unwrap();
+        assert_eq!(value["error"]["kind"], "timeout");
+        assert_eq!(value["status"], serde_json::Value::Null);
+
+        publisher.encode(br#"{"event": "sent"}"#).unwrap_err();
+    }
+
+    #[test]
+    fn frames_avro_for_a_schema_registry() {
+        let event =
+            br#"{"event": "sent", "timestamp": "2026-10-14T10:00:00Z", "message_id": "1001"}"#;
+        let framed = publisher(EventEncoding::ConfluentAvro { schema_id: 7 })
+            .encode(event)
+            .unwrap();
+        let plain = publisher(EventEncoding::Avro).encode(event).unwrap();
+        assert_eq!(framed[..5], [0, 0, 0, 0, 7]);
+        assert_eq!(framed[5..], plain);
+        assert_eq!(publisher(EventEncoding::Json).encode(event).unwrap(), event);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Kafka broker at ESTERIA_TEST_KAFKA_BROKERS"]
+    async fn publishes_to_a_topic() {
+        use rdkafka::Message;
+        use rdkafka::consumer::{Consumer, StreamConsumer};
+
+        let brokers = std::env::var("ESTERIA_TEST_KAFKA_BROKERS")
+            .unwrap_or_else(|_| "127.0.0.1:9092".to_string());
+        let topic = format!("esteria-test-{}", std::process::id());
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("group.id", &topic)
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .unwrap();
+        consumer.subscribe(&[topic.as_str()]).unwrap();
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .create()
+            .unwrap();
+        let publisher = KafkaPublisher::new(producer, &topic);
+
+        let event = br#"{"event": "sent", "message_id": "1001"}"#;
+        publisher.publish("1001", event);
+        let message = tokio::time::timeout(std::time::Duration::from_secs(30), consumer.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.key(), Some(b"1001".as_slice()));
+        assert_eq!(message.payload(), Some(event.as_slice()));
+    }
+}
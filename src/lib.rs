@@ -1,5 +1,96 @@
+#[cfg(all(feature = "amqp", not(target_arch = "wasm32")))]
+pub mod amqp;
+pub mod audit;
+pub mod auth;
+pub mod autoresponder;
+#[cfg(all(feature = "blocking", not(target_arch = "wasm32")))]
+pub mod blocking;
+pub mod bulk;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod campaign;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod connection;
+pub mod contacts;
+pub mod conversation;
+pub mod credentials;
+pub mod csv;
+pub mod delivery;
 pub mod esteria;
-pub use esteria::{Encoding, SmsClient, SmsError, SmsFlags, SmsRequest};
+pub mod events;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fallback;
+pub mod filter;
+#[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+pub mod grpc;
+#[cfg(feature = "i18n")]
+pub mod i18n;
+pub mod idempotency;
+#[cfg(all(feature = "kafka", not(target_arch = "wasm32")))]
+pub mod kafka;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod links;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod middleware;
+pub mod mock;
+#[cfg(all(feature = "nats", not(target_arch = "wasm32")))]
+pub mod nats;
+#[cfg(feature = "openapi")]
+pub mod openapi;
+pub mod optout;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod otp;
+pub mod phone;
+pub mod pricing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod provider;
+pub mod quiet_hours;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rate_limit;
+pub mod redact;
+pub mod response;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod retry;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod router;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scheduler;
+pub mod sender;
+#[cfg(all(feature = "server", not(target_arch = "wasm32")))]
+pub mod server;
+#[cfg(feature = "tower")]
+mod service;
+#[cfg(not(target_arch = "wasm32"))]
+mod shutdown;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod signing;
+pub mod sms;
+#[cfg(all(feature = "sqs", not(target_arch = "wasm32")))]
+pub mod sqs;
+pub mod template;
+#[cfg(all(
+    any(feature = "rustls", feature = "native-tls"),
+    not(target_arch = "wasm32")
+))]
+pub mod tls;
+pub mod trace_context;
+pub mod transliterate;
+pub mod udh;
+pub mod wap;
+#[cfg(all(feature = "worker", not(target_arch = "wasm32")))]
+pub mod worker;
+pub use auth::Auth;
+pub use bulk::{BulkReport, BulkSummary, Recipient, RecipientResult};
+pub use esteria::{
+    Encoding, MessageCategory, MessageClass, MultipartResult, Priority, SendResult, SmsClient,
+    SmsClientBuilder, SmsError, SmsFlags, SmsRequest, SmsRequestOwned, SmsSender,
+};
+
+// Persistent outbox
+#[cfg(all(feature = "outbox", not(target_arch = "wasm32")))]
+pub mod outbox;
 
 // Python bindings
 #[cfg(feature = "python")]
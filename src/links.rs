@@ -0,0 +1,221 @@
+use crate::esteria::SmsRequest;
+use reqwest::{Client, Url};
+use std::borrow::Cow;
+use std::future::Future;
+use std::ops::Range;
+use thiserror::Error;
+
+/// Query parameter carrying the user key in long URLs by default
+pub const DEFAULT_TAG_PARAM: &str = "user_key";
+
+/// Error types for link shortening
+#[derive(Error, Debug)]
+pub enum LinkError {
+    #[error("link shortener request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("link shortener returned an invalid short URL: {0}")]
+    InvalidResponse(String),
+}
+
+/// Service turning long URLs into short ones
+pub trait LinkShortener: Send + Sync {
+    /// Short URL redirecting to `url`
+    fn shorten(&self, url: &str) -> impl Future<Output = Result<String, LinkError>> + Send;
+}
+
+/// Shortener with a plain-text HTTP API, e.g. YOURLS or `is.gd`
+///
+/// Sends `GET {endpoint}?{param}={url}`, keeping any query parameters of the
+/// endpoint (such as an API key or `format=simple`), and expects the short
+/// URL as the response body.
+#[derive(Debug, Clone)]
+pub struct HttpShortener {
+    client: Client,
+    endpoint: Url,
+    param: String,
+}
+
+impl HttpShortener {
+    /// Shorten through `endpoint`, passing the long URL as `url`
+    #[must_use]
+    pub fn new(endpoint: Url) -> Self {
+        Self {
+            client: Client::new(),
+            endpoint,
+            param: "url".to_string(),
+        }
+    }
+
+    /// Pass the long URL as `param` instead of `url`
+    #[must_use]
+    pub fn with_param(mut self, param: impl Into<String>) -> Self {
+        self.param = param.into();
+        self
+    }
+
+    /// Use `client` for HTTP requests, e.g. to share its connection pool
+    #[must_use]
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+}
+
+impl LinkShortener for HttpShortener {
+    async fn shorten(&self, url: &str) -> Result<String, LinkError> {
+        let mut endpoint = self.endpoint.clone();
+        endpoint.query_pairs_mut().append_pair(&self.param, url);
+        let body = self
+            .client
+            .get(endpoint)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(reqwest::Error::without_url)?
+            .text()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let short = body.trim();
+        if Url::parse(short).is_err() {
+            return Err(LinkError::InvalidResponse(short.to_string()));
+        }
+        Ok(short.to_string())
+    }
+}
+
+/// Byte ranges of the `http://` and `https://` URLs in `text`
+///
+/// A URL runs to the next whitespace, without trailing punctuation such as
+/// the full stop ending a sentence.
+#[must_use]
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = Vec::new();
+    let mut rest = 0;
+    while let Some(offset) = text[rest..].find("http") {
+        let start = rest + offset;
+        let candidate = &text[start..];
+        let len = candidate
+            .find(char::is_whitespace)
+            .unwrap_or(candidate.len());
+        let url = candidate[..len].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'']);
+        let at_word_start = text[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        if at_word_start
+            && (url.starts_with("https://") || url.starts_with("http://"))
+            && url.len() > "https://".len()
+        {
+            urls.push(start..start + url.len());
+        }
+        rest = start + len.max(1);
+    }
+    urls
+}
+
+/// Shortens the URLs in message texts before sending
+///
+/// With a user key, each long URL gets the key as a query parameter before
+/// shortening, so every recipient gets a distinct short link and clicks can
+/// be tied to the user key of their delivery reports.
+#[derive(Debug, Clone)]
+pub struct Links<L> {
+    shortener: L,
+    tag_param: String,
+}
+
+impl<L: LinkShortener> Links<L> {
+    /// Shorten links with `shortener`
+    pub fn new(shortener: L) -> Self {
+        Self {
+            shortener,
+            tag_param: DEFAULT_TAG_PARAM.to_string(),
+        }
+    }
+
+    /// Add the user key to long URLs as `param` (default [`DEFAULT_TAG_PARAM`])
+    #[must_use]
+    pub fn with_tag_param(mut self, param: impl Into<String>) -> Self {
+        self.tag_param = param.into();
+        self
+    }
+
+    /// `text` with every URL replaced by a short one, tagged with `user_key` if given
+    ///
+    /// # Errors
+    ///
+    /// Returns `LinkError` if a URL can't be shortened
+    pub async fn rewrite(&self, text: &str, user_key: Option<&str>) -> Result<String, LinkError> {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for range in find_urls(text) {
+            let long = &text[range.clone()];
+            let tagged = match (user_key, Url::parse(long)) {
+                (Some(user_key), Ok(mut url)) => {
+                    url.query_pairs_mut().append_pair(&self.tag_param, user_key);
+                    Cow::Owned(url.to_string())
+                }
+                _ => Cow::Borrowed(long),
+            };
+            out.push_str(&text[last..range.start]);
+            out.push_str(&self.shortener.shorten(&tagged).await?);
+            last = range.end;
+        }
+        out.push_str(&text[last..]);
+        Ok(out)
+    }
+
+    /// `request` with the links in its text shortened and tagged with its user key
+    ///
+    /// # Errors
+    ///
+    /// Returns `LinkError` if a URL can't be shortened
+    pub async fn apply<'a>(&self, request: SmsRequest<'a>) -> Result<SmsRequest<'a>, LinkError> {
+        let text = self
+            .rewrite(&request.text, request.user_key.as_deref())
+            .await?;
+        Ok(SmsRequest {
+            text: Cow::Owned(text),
+            ..request
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[test]
+    fn finds_urls_without_trailing_punctuation() {
+        let text =
+            "See https://shop.example/a?b=1. Or (http://x.example/y), not xhttp://z or https://";
+        let urls: Vec<_> = find_urls(text).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(urls, ["https://shop.example/a?b=1", "http://x.example/y"]);
+    }
+
+    #[tokio::test]
+    async fn shortens_tagged_links() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/yourls-api.php")
+                .query_param("format", "simple")
+                .query_param("url", "https://shop.example/sale?utm=sms&user_key=order-42");
+            then.status(200).body("https://sho.rt/abc\n");
+        });
+        let endpoint = Url::parse(&server.url("/yourls-api.php?format=simple")).unwrap();
+        let links = Links::new(HttpShortener::new(endpoint));
+
+        let request = SmsRequest::new(
+            "k",
+            "Shop",
+            "+37061234567",
+            "Sale: https://shop.example/sale?utm=sms!",
+        )
+        .with_user_key("order-42");
+        let request = links.apply(request).await.unwrap();
+        assert_eq!(request.text, "Sale: https://sho.rt/abc!");
+        m.assert();
+    }
+}
@@ -1,7 +1,4 @@
-mod cli;
-mod esteria;
-
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    cli::run().await
+async fn main() -> std::process::ExitCode {
+    esteria_api_client::cli::run().await
 }
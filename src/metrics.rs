@@ -0,0 +1,281 @@
+use chrono::TimeDelta;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Upper bounds of the gateway latency buckets, in seconds
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+/// Upper bounds of the segments-per-message buckets
+const SEGMENT_BUCKETS: &[f64] = &[1.0, 2.0, 3.0, 4.0, 6.0, 8.0, 10.0];
+
+/// Send metrics, rendered in the Prometheus text exposition format
+///
+/// Register with [`crate::SmsClientBuilder::with_metrics`] and serve
+/// [`SmsMetrics::render`] from a `/metrics` endpoint. Only sends that reach
+/// the gateway are counted; client-side rejections are not. Retries of a
+/// send (see [`crate::SmsClientBuilder::with_retry`]) count the failed
+/// attempt and the retry.
+#[derive(Debug)]
+pub struct SmsMetrics {
+    sent: AtomicU64,
+    retried: AtomicU64,
+    failed: Mutex<BTreeMap<String, u64>>,
+    latency: Histogram,
+    segments: Histogram,
+    queue_depth: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    state: Mutex<HistogramState>,
+}
+
+#[derive(Debug)]
+struct HistogramState {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            state: Mutex::new(HistogramState {
+                counts: vec![0; bounds.len()],
+                sum: 0.0,
+                count: 0,
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, HistogramState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn observe(&self, value: f64) {
+        let mut state = self.state();
+        for (bound, count) in self.bounds.iter().zip(&mut state.counts) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        state.sum += value;
+        state.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let state = self.state();
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (bound, count) in self.bounds.iter().zip(&state.counts) {
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", state.count);
+        let _ = writeln!(out, "{name}_sum {}", state.sum);
+        let _ = writeln!(out, "{name}_count {}", state.count);
+    }
+}
+
+impl Default for SmsMetrics {
+    fn default() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+            failed: Mutex::new(BTreeMap::new()),
+            latency: Histogram::new(LATENCY_BUCKETS),
+            segments: Histogram::new(SEGMENT_BUCKETS),
+            queue_depth: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl SmsMetrics {
+    /// Create an empty set of metrics
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the queue depth gauge, e.g. from `Outbox::pending_count`
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.store(depth, Ordering::Relaxed);
+    }
+
+    /// Number of messages accepted by the gateway
+    #[must_use]
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+
+    /// Number of failed attempts that were retried
+    #[must_use]
+    pub fn retried(&self) -> u64 {
+        self.retried.load(Ordering::Relaxed)
+    }
+
+    /// Number of failed sends with the given reason: a gateway code, `transport` or `unknown`
+    #[must_use]
+    pub fn failed(&self, reason: &str) -> u64 {
+        self.failures().get(reason).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn record_sent(&self, segments: usize) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        #[allow(clippy::cast_precision_loss)]
+        self.segments.observe(segments as f64);
+    }
+
+    pub(crate) fn record_retried(&self) {
+        self.retried.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failed(&self, reason: &str) {
+        *self.failures().entry(reason.to_string()).or_insert(0) += 1;
+    }
+
+    pub(crate) fn record_latency(&self, latency: TimeDelta) {
+        #[allow(clippy::cast_precision_loss)]
+        self.latency
+            .observe(latency.num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6);
+    }
+
+    fn failures(&self) -> MutexGuard<'_, BTreeMap<String, u64>> {
+        self.failed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Render all metrics in the Prometheus text exposition format
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP esteria_sms_sent_total Messages accepted by the gateway\n");
+        out.push_str("# TYPE esteria_sms_sent_total counter\n");
+        let _ = writeln!(out, "esteria_sms_sent_total {}", self.sent());
+
+        out.push_str("# HELP esteria_sms_failed_total Failed sends by gateway code or reason\n");
+        out.push_str("# TYPE esteria_sms_failed_total counter\n");
+        for (reason, count) in self.failures().iter() {
+            let _ = writeln!(
+                out,
+                "esteria_sms_failed_total{{reason=\"{reason}\"}} {count}"
+            );
+        }
+
+        out.push_str("# HELP esteria_sms_retried_total Failed attempts that were retried\n");
+        out.push_str("# TYPE esteria_sms_retried_total counter\n");
+        let _ = writeln!(out, "esteria_sms_retried_total {}", self.retried());
+
+        self.latency.render(
+            &mut out,
+            "esteria_sms_gateway_latency_seconds",
+            "Gateway request latency",
+        );
+        self.segments.render(
+            &mut out,
+            "esteria_sms_segments",
+            "Segments per message accepted by the gateway",
+        );
+
+        out.push_str("# HELP esteria_sms_queue_depth Messages waiting to be sent\n");
+        out.push_str("# TYPE esteria_sms_queue_depth gauge\n");
+        let _ = writeln!(
+            out,
+            "esteria_sms_queue_depth {}",
+            self.queue_depth.load(Ordering::Relaxed)
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esteria::{SmsClient, SmsRequest};
+    use httpmock::prelude::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn client_records_sends() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200).body("1001");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234568");
+            then.status(200).body("2");
+        });
+
+        let metrics = Arc::new(SmsMetrics::new());
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_metrics(metrics.clone())
+            .build()
+            .unwrap();
+
+        let ok = SmsRequest::new("k", "Alice", "+37061234567", "a".repeat(200));
+        client.send_sms(ok).await.unwrap();
+        let failing = SmsRequest::new("k", "Alice", "+37061234568", "Hi");
+        client.send_sms(failing).await.unwrap_err();
+
+        assert_eq!(metrics.sent(), 1);
+        assert_eq!(metrics.failed("2"), 1);
+        assert!(
+            metrics
+                .render()
+                .contains("esteria_sms_segments_bucket{le=\"2\"} 1\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn client_counts_retries() {
+        use crate::retry::RetryPolicy;
+        use std::time::Duration;
+
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(503);
+        });
+
+        let metrics = Arc::new(SmsMetrics::new());
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_retry(RetryPolicy::new(3).with_backoff(Duration::ZERO, Duration::ZERO))
+            .with_metrics(metrics.clone())
+            .build()
+            .unwrap();
+        let request = SmsRequest::new("k", "Alice", "+37061234567", "Hi");
+        client.send_sms(request).await.unwrap_err();
+
+        m.assert_calls(3);
+        assert_eq!(metrics.retried(), 2);
+        assert_eq!(metrics.failed("transport"), 3);
+        assert!(metrics.render().contains("esteria_sms_retried_total 2\n"));
+    }
+
+    #[test]
+    fn renders_prometheus_text() {
+        let metrics = SmsMetrics::new();
+        metrics.record_sent(2);
+        metrics.record_failed("2");
+        metrics.record_latency(TimeDelta::milliseconds(80));
+        metrics.set_queue_depth(5);
+
+        let text = metrics.render();
+        assert!(text.contains("esteria_sms_sent_total 1\n"));
+        assert!(text.contains("esteria_sms_failed_total{reason=\"2\"} 1\n"));
+        assert!(text.contains("esteria_sms_gateway_latency_seconds_bucket{le=\"0.05\"} 0\n"));
+        assert!(text.contains("esteria_sms_gateway_latency_seconds_bucket{le=\"0.1\"} 1\n"));
+        assert!(text.contains("esteria_sms_segments_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("esteria_sms_segments_bucket{le=\"2\"} 1\n"));
+        assert!(text.contains("esteria_sms_queue_depth 5\n"));
+    }
+}
@@ -0,0 +1,75 @@
+use reqwest::{Request, StatusCode};
+
+/// Hooks around every HTTP request made to the gateway
+///
+/// Registered with [`crate::SmsClientBuilder::with_middleware`] and called in
+/// registration order. Use it to add correlation IDs or headers, rewrite the
+/// request, or record metrics, without changing the send methods. Requests
+/// refused client-side (validation, opt-outs, duplicates) never reach it.
+pub trait Middleware: Send + Sync + std::fmt::Debug {
+    /// Inspect or modify a request before it is sent
+    fn on_request(&self, _request: &mut Request) {}
+
+    /// Inspect the gateway response to `request`
+    ///
+    /// `request` is the request as sent, after all `on_request` hooks ran.
+    fn on_response(&self, _request: &Request, _status: StatusCode, _body: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esteria::{SmsClient, SmsRequest};
+    use httpmock::prelude::*;
+    use reqwest::header::HeaderValue;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct CorrelationId {
+        responses: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Middleware for CorrelationId {
+        fn on_request(&self, request: &mut Request) {
+            request
+                .headers_mut()
+                .insert("x-correlation-id", HeaderValue::from_static("abc"));
+        }
+
+        fn on_response(&self, request: &Request, _status: StatusCode, body: &str) {
+            let id = request.headers()["x-correlation-id"].to_str().unwrap();
+            self.responses
+                .lock()
+                .unwrap()
+                .push((id.to_string(), body.to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_see_request_and_response() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .header("x-correlation-id", "abc");
+            then.status(200).body("1001");
+        });
+
+        let middleware = Arc::new(CorrelationId::default());
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_middleware(middleware.clone())
+            .build()
+            .unwrap();
+
+        client
+            .send_sms(SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+            .await
+            .unwrap();
+        m.assert();
+        assert_eq!(
+            *middleware.responses.lock().unwrap(),
+            [("abc".to_string(), "1001".to_string())]
+        );
+    }
+}
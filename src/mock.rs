@@ -0,0 +1,191 @@
+use crate::esteria::{SendResult, SmsError, SmsRequest, SmsRequestOwned, SmsSender};
+use crate::phone;
+use crate::response;
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Scripted outcome of the next send
+#[derive(Debug)]
+enum Reply {
+    MessageId(String),
+    ErrorCode(i32),
+    Error(SmsError),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    requests: Vec<SmsRequestOwned>,
+    replies: VecDeque<Reply>,
+    next_id: u64,
+}
+
+/// In-memory [`SmsSender`] for tests
+///
+/// Every request is recorded. Scripted replies are used in order; once they
+/// run out, sends succeed with sequential message IDs starting at `1`.
+#[derive(Debug, Default)]
+pub struct MockSmsClient {
+    state: Mutex<State>,
+}
+
+impl MockSmsClient {
+    /// Create a mock whose sends all succeed
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Script the next unscripted send to succeed with `message_id`
+    #[must_use]
+    pub fn with_message_id(self, message_id: impl Into<String>) -> Self {
+        self.push(Reply::MessageId(message_id.into()));
+        self
+    }
+
+    /// Script the next unscripted send to fail with a gateway error code, e.g. `2`
+    #[must_use]
+    pub fn with_error_code(self, code: i32) -> Self {
+        self.push(Reply::ErrorCode(code));
+        self
+    }
+
+    /// Script the next unscripted send to fail with `error`
+    #[must_use]
+    pub fn with_error(self, error: SmsError) -> Self {
+        self.push(Reply::Error(error));
+        self
+    }
+
+    /// All requests received so far, in order
+    #[must_use]
+    pub fn requests(&self) -> Vec<SmsRequestOwned> {
+        self.state().requests.clone()
+    }
+
+    /// Requests sent to `number`, compared by digits
+    #[must_use]
+    pub fn requests_to(&self, number: &str) -> Vec<SmsRequestOwned> {
+        let digits = phone::digits(number);
+        self.state()
+            .requests
+            .iter()
+            .filter(|request| phone::digits(&request.number) == digits)
+            .cloned()
+            .collect()
+    }
+
+    /// Number of requests received so far
+    #[must_use]
+    pub fn sent_count(&self) -> usize {
+        self.state().requests.len()
+    }
+
+    /// Forget recorded requests and remaining scripted replies
+    pub fn reset(&self) {
+        *self.state() = State::default();
+    }
+
+    /// Assert that exactly one request was sent to `number`, returning it
+    ///
+    /// # Panics
+    ///
+    /// Panics if no request or more than one request was sent to `number`
+    #[must_use]
+    pub fn assert_sent_once_to(&self, number: &str) -> SmsRequestOwned {
+        let mut matching = self.requests_to(number);
+        assert!(
+            matching.len() == 1,
+            "expected exactly one SMS to {number}, got {}",
+            matching.len()
+        );
+        matching.remove(0)
+    }
+
+    /// Assert that no request was sent
+    ///
+    /// # Panics
+    ///
+    /// Panics if any request was received
+    pub fn assert_nothing_sent(&self) {
+        let count = self.sent_count();
+        assert!(count == 0, "expected no SMS, got {count}");
+    }
+
+    fn push(&self, reply: Reply) {
+        self.state().replies.push_back(reply);
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl SmsSender for MockSmsClient {
+    async fn send_sms(&self, request: SmsRequest<'_>) -> Result<SendResult, SmsError> {
+        let number = request.number.to_string();
+        let mut state = self.state();
+        state.requests.push(request.into_owned());
+
+        let message_id = match state.replies.pop_front() {
+            Some(Reply::MessageId(id)) => id,
+            Some(Reply::ErrorCode(code)) => {
+                return Err(match response::lookup(code) {
+                    Some(known) => SmsError::SendFailed {
+                        number,
+                        code: Some(code),
+                        message: known.message,
+                    },
+                    None => SmsError::UnknownResponse {
+                        raw: code.to_string(),
+                    },
+                });
+            }
+            Some(Reply::Error(error)) => return Err(error),
+            None => {
+                state.next_id += 1;
+                state.next_id.to_string()
+            }
+        };
+        Ok(SendResult { message_id, number })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_requests_and_scripts_replies() {
+        let mock = MockSmsClient::new()
+            .with_message_id("1001")
+            .with_error_code(2);
+
+        let first = mock
+            .send_sms(SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+            .await
+            .unwrap();
+        assert_eq!(first.message_id, "1001");
+
+        let err = mock
+            .send_sms(SmsRequest::new("k", "Alice", "+37061234568", "Hi"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SmsError::SendFailed { .. }));
+
+        let third = mock
+            .send_sms(SmsRequest::new("k", "Alice", "+37061234569", "Hi"))
+            .await
+            .unwrap();
+        assert_eq!(third.message_id, "1");
+
+        assert_eq!(mock.sent_count(), 3);
+        let request = mock.assert_sent_once_to("0037061234567");
+        assert_eq!(request.text, "Hi");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected exactly one SMS to +37061234567, got 0")]
+    fn assert_sent_once_to_panics_without_match() {
+        let _ = MockSmsClient::new().assert_sent_once_to("+37061234567");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::worker::{Delivery, JobQueue, JobResult, WorkerError};
+use async_nats::{Client, Subscriber};
+use futures_util::StreamExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::Mutex;
+
+/// [`JobQueue`] serving NATS requests through `async-nats`
+///
+/// Each request on the subject is a job, replied to on its reply subject.
+/// Core NATS doesn't redeliver, so [`JobQueue::ack`] and [`JobQueue::nack`]
+/// do nothing and retryable failures are replied like permanent ones. A
+/// job's ID is its `Nats-Msg-Id` header, or else its reply subject.
+///
+/// Jobs are handled one at a time; run several workers on a
+/// [queue group](NatsQueue::queue_subscribe) to serve requests concurrently.
+pub struct NatsQueue {
+    client: Client,
+    subscriber: Mutex<Subscriber>,
+    received: AtomicU64,
+}
+
+impl NatsQueue {
+    /// Serve requests published to `subject`
+    ///
+    /// # Errors
+    ///
+    /// Returns `WorkerError::Queue` if the subscription fails
+    pub async fn subscribe(client: Client, subject: String) -> Result<Self, WorkerError> {
+        let subscriber = client.subscribe(subject).await.map_err(queue_error)?;
+        Ok(Self::new(client, subscriber))
+    }
+
+    /// Serve requests published to `subject`, sharing them with the other
+    /// members of `queue_group`
+    ///
+    /// # Errors
+    ///
+    /// Returns `WorkerError::Queue` if the subscription fails
+    pub async fn queue_subscribe(
+        client: Client,
+        subject: String,
+        queue_group: String,
+    ) -> Result<Self, WorkerError> {
+        let subscriber = client
+            .queue_subscribe(subject, queue_group)
+            .await
+            .map_err(queue_error)?;
+        Ok(Self::new(client, subscriber))
+    }
+
+    fn new(client: Client, subscriber: Subscriber) -> Self {
+        Self {
+            client,
+            subscriber: Mutex::new(subscriber),
+            received: AtomicU64::new(0),
+        }
+    }
+}
+
+impl std::fmt::Debug for NatsQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NatsQueue")
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+fn queue_error(err: impl std::fmt::Display) -> WorkerError {
+    WorkerError::Queue(err.to_string())
+}
+
+impl JobQueue for NatsQueue {
+    async fn receive(&self) -> Result<Option<Delivery>, WorkerError> {
+        let Some(message) = self.subscriber.lock().await.next().await else {
+            return Ok(None);
+        };
+        let count = self.received.fetch_add(1, Ordering::Relaxed) + 1;
+        let id = message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get(async_nats::header::NATS_MESSAGE_ID))
+            .map(|id| id.as_str().to_string())
+            .or_else(|| message.reply.as_ref().map(ToString::to_string))
+            .unwrap_or_else(|| format!("{}-{count}", message.subject));
+
+        let mut delivery = Delivery::new(id, message.payload.to_vec());
+        delivery.reply_to = message.reply.map(|reply| reply.to_string());
+        Ok(Some(delivery))
+    }
+
+    async fn ack(&self, _delivery: &Delivery) -> Result<(), WorkerError> {
+        Ok(())
+    }
+
+    async fn nack(&self, _delivery: &Delivery) -> Result<(), WorkerError> {
+        Ok(())
+    }
+
+    /// Publish `result` to the request's reply subject; without one it is dropped
+    async fn reply(&self, delivery: &Delivery, result: &JobResult) -> Result<(), WorkerError> {
+        let Some(reply_to) = &delivery.reply_to else {
+            return Ok(());
+        };
+        self.client
+            .publish(reply_to.clone(), result.to_json().into())
+            .await
+            .map_err(queue_error)?;
+        self.client.flush().await.map_err(queue_error)
+    }
+
+    fn redelivers(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::contract;
+
+    #[tokio::test]
+    #[ignore = "needs a NATS server at ESTERIA_TEST_NATS_URL"]
+    async fn follows_the_job_queue_contract() {
+        let url = std::env::var("ESTERIA_TEST_NATS_URL")
+            .unwrap_or_else(|_| "nats://127.0.0.1:4222".to_string());
+        let client = async_nats::connect(url).await.unwrap();
+        let subject = client.new_inbox();
+        let queue = NatsQueue::subscribe(client.clone(), subject.clone())
+            .await
+            .unwrap();
+
+        let inbox = client.new_inbox();
+        let mut replies = client.subscribe(inbox.clone()).await.unwrap();
+        let payload = br#"{"sender": "Shop", "number": "+37060000000", "text": "Hi"}"#;
+        client
+            .publish_with_reply(subject, inbox.clone(), payload.as_slice().into())
+            .await
+            .unwrap();
+
+        let delivery = contract::check(&queue, payload).await;
+        assert_eq!(delivery.id, inbox);
+
+        let result = JobResult {
+            job_id: delivery.id.clone(),
+            number: None,
+            message_id: Some("1001".to_string()),
+            error: None,
+        };
+        queue.reply(&delivery, &result).await.unwrap();
+        let reply = replies.next().await.unwrap();
+        assert_eq!(reply.payload.to_vec(), result.to_json());
+    }
+}
@@ -0,0 +1,6 @@
+/// Serialized form of a [`std::time::Duration`], for `#[schema(value_type = ...)]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, utoipa::ToSchema)]
+pub struct DurationSchema {
+    pub secs: u64,
+    pub nanos: u32,
+}
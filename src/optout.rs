@@ -0,0 +1,255 @@
+use crate::phone;
+use crate::redact;
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use thiserror::Error;
+
+/// Keywords that opt a recipient out when sent as the whole message
+pub const OPT_OUT_KEYWORDS: &[&str] = &[
+    "STOP",
+    "STOPALL",
+    "UNSUB",
+    "UNSUBSCRIBE",
+    "CANCEL",
+    "END",
+    "QUIT",
+];
+
+/// Error types for opt-out registries
+#[derive(Error, Debug)]
+pub enum OptOutError {
+    #[error("opt-out registry I/O failed: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Numbers that asked not to receive messages
+///
+/// When configured with [`crate::SmsClientBuilder::with_opt_out_registry`],
+/// the registry is consulted before every send. Numbers are compared by
+/// their digits, so `+370 612 34567` and `0037061234567` are the same entry.
+pub trait OptOutRegistry: Send + Sync + std::fmt::Debug {
+    /// Whether `number` has opted out
+    fn contains(&self, number: &str) -> bool;
+
+    /// Record an opt-out
+    ///
+    /// # Errors
+    ///
+    /// Returns `OptOutError` if the registry can't be updated
+    fn add(&self, number: &str) -> Result<(), OptOutError>;
+
+    /// Remove an opt-out, returning whether the number was registered
+    ///
+    /// # Errors
+    ///
+    /// Returns `OptOutError` if the registry can't be updated
+    fn remove(&self, number: &str) -> Result<bool, OptOutError>;
+}
+
+/// Registry key for a number: its digits without an international `00` prefix
+#[must_use]
+pub fn registry_key(number: &str) -> String {
+    phone::digits(number)
+}
+
+/// Whether an inbound message text is an opt-out request, e.g. `Stop` or `UNSUBSCRIBE.`
+#[must_use]
+pub fn is_opt_out_keyword(text: &str) -> bool {
+    let word = text
+        .trim()
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_ascii_uppercase();
+    OPT_OUT_KEYWORDS.contains(&word.as_str())
+}
+
+/// Record an opt-out if the inbound message from `number` is an opt-out keyword
+///
+/// Returns whether the number was opted out.
+///
+/// # Errors
+///
+/// Returns `OptOutError` if the registry can't be updated
+pub fn process_inbound(
+    registry: &dyn OptOutRegistry,
+    number: &str,
+    text: &str,
+) -> Result<bool, OptOutError> {
+    if !is_opt_out_keyword(text) {
+        return Ok(false);
+    }
+    log::info!("Recording opt-out from {}", redact::number(number));
+    registry.add(number)?;
+    Ok(true)
+}
+
+/// In-memory opt-out registry, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryOptOutRegistry {
+    numbers: Mutex<HashSet<String>>,
+}
+
+impl MemoryOptOutRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn numbers(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.numbers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl OptOutRegistry for MemoryOptOutRegistry {
+    fn contains(&self, number: &str) -> bool {
+        self.numbers().contains(&registry_key(number))
+    }
+
+    fn add(&self, number: &str) -> Result<(), OptOutError> {
+        self.numbers().insert(registry_key(number));
+        Ok(())
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, OptOutError> {
+        Ok(self.numbers().remove(&registry_key(number)))
+    }
+}
+
+/// Opt-out registry stored as a text file with one number per line
+///
+/// Additions are appended; removals rewrite the file atomically.
+#[derive(Debug)]
+pub struct FileOptOutRegistry {
+    path: PathBuf,
+    numbers: Mutex<HashSet<String>>,
+}
+
+impl FileOptOutRegistry {
+    /// Load the registry from `path`, creating the file if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns `OptOutError::Io` if the file can't be read or created
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, OptOutError> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut numbers = HashSet::new();
+        for line in BufReader::new(file).lines() {
+            let key = registry_key(&line?);
+            if !key.is_empty() {
+                numbers.insert(key);
+            }
+        }
+
+        Ok(Self {
+            path,
+            numbers: Mutex::new(numbers),
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn numbers(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.numbers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn rewrite(&self, numbers: &HashSet<String>) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        for number in numbers {
+            writeln!(file, "{number}")?;
+        }
+        file.sync_all()?;
+        fs::rename(tmp, &self.path)
+    }
+}
+
+impl OptOutRegistry for FileOptOutRegistry {
+    fn contains(&self, number: &str) -> bool {
+        self.numbers().contains(&registry_key(number))
+    }
+
+    fn add(&self, number: &str) -> Result<(), OptOutError> {
+        let key = registry_key(number);
+        let mut numbers = self.numbers();
+        if numbers.contains(&key) {
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        writeln!(file, "{key}")?;
+        file.sync_data()?;
+        numbers.insert(key);
+        Ok(())
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, OptOutError> {
+        let key = registry_key(number);
+        let mut numbers = self.numbers();
+        if !numbers.contains(&key) {
+            return Ok(false);
+        }
+
+        let mut remaining = numbers.clone();
+        remaining.remove(&key);
+        self.rewrite(&remaining)?;
+        *numbers = remaining;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_keywords() {
+        assert!(is_opt_out_keyword("STOP"));
+        assert!(is_opt_out_keyword("  unsubscribe. "));
+        assert!(is_opt_out_keyword("Unsub!"));
+        assert!(!is_opt_out_keyword("Don't stop"));
+        assert!(!is_opt_out_keyword("STOPPED"));
+    }
+
+    #[test]
+    fn inbound_stop_is_recorded() {
+        let registry = MemoryOptOutRegistry::new();
+        assert!(process_inbound(&registry, "+370 612 34567", "Stop").unwrap());
+        assert!(!process_inbound(&registry, "+37061234568", "Thanks").unwrap());
+
+        assert!(registry.contains("0037061234567"));
+        assert!(!registry.contains("+37061234568"));
+        assert!(registry.remove("+37061234567").unwrap());
+        assert!(!registry.contains("+37061234567"));
+    }
+
+    #[test]
+    fn file_registry_persists() {
+        let path = std::env::temp_dir().join(format!("esteria-optout-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let registry = FileOptOutRegistry::open(&path).unwrap();
+        registry.add("+37061234567").unwrap();
+        registry.add("+37061234568").unwrap();
+        registry.add("+37061234567").unwrap();
+        assert!(registry.remove("+37061234568").unwrap());
+        drop(registry);
+
+        let registry = FileOptOutRegistry::open(&path).unwrap();
+        assert!(registry.contains("+37061234567"));
+        assert!(!registry.contains("+37061234568"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "37061234567\n");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,499 @@
+use crate::esteria::{Priority, SmsClient, SmsError, SmsRequest};
+use crate::optout::registry_key;
+use crate::signing::sha256;
+use crate::template::{SmsTemplate, TemplateError};
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
+
+/// How long a code can be verified by default
+pub const DEFAULT_TTL: TimeDelta = TimeDelta::minutes(5);
+
+/// Wrong guesses allowed per code by default
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Minimum time between codes sent to the same number by default
+pub const DEFAULT_RESEND_COOLDOWN: TimeDelta = TimeDelta::seconds(30);
+
+/// Error types for one-time passwords
+#[derive(Error, Debug)]
+pub enum OtpError {
+    #[error("OTP store failed: {0}")]
+    Store(String),
+    #[error("OTP template must use the {{code}} placeholder")]
+    MissingCode,
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    #[error("random number generator failed: {0}")]
+    Random(String),
+    /// A code was sent to the number too recently; retry after `retry_in`
+    #[error("a code was sent recently, retry in {} s", .retry_in.num_seconds())]
+    TooSoon { retry_in: TimeDelta },
+    /// Codes to the number were guessed wrong the maximum number of times;
+    /// no new code is sent until the pending one expires
+    #[error("too many wrong codes, try again later")]
+    TooManyAttempts,
+    /// The code expired, or no code was sent to the number
+    #[error("code expired, request a new one")]
+    Expired,
+    #[error(transparent)]
+    Send(#[from] SmsError),
+}
+
+/// Characters codes are made of
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CodeAlphabet {
+    /// Digits `0`–`9`
+    #[default]
+    Numeric,
+    /// Uppercase letters and digits, without the look-alikes `0`, `O`, `1` and `I`
+    Alphanumeric,
+}
+
+impl CodeAlphabet {
+    fn chars(self) -> &'static [u8] {
+        match self {
+            Self::Numeric => b"0123456789",
+            Self::Alphanumeric => b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ",
+        }
+    }
+}
+
+/// Generate a random code of `length` characters from the OS random number generator
+///
+/// # Errors
+///
+/// Returns `OtpError::Random` if the OS has no randomness available
+pub fn generate_code(length: usize, alphabet: CodeAlphabet) -> Result<String, OtpError> {
+    let chars = alphabet.chars();
+    // Bytes at or above the largest multiple of the alphabet size would bias the result
+    let limit = 256 - 256 % chars.len();
+    let mut code = String::with_capacity(length);
+    let mut buf = [0u8; 32];
+    while code.len() < length {
+        getrandom::fill(&mut buf).map_err(|err| OtpError::Random(err.to_string()))?;
+        code.extend(
+            buf.iter()
+                .filter(|&&b| usize::from(b) < limit)
+                .map(|&b| char::from(chars[usize::from(b) % chars.len()]))
+                .take(length - code.len()),
+        );
+    }
+    Ok(code)
+}
+
+/// Pending code of a number, stored as a salted hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OtpRecord {
+    pub salt: [u8; 16],
+    /// SHA-256 of the salt followed by the code
+    pub hash: [u8; 32],
+    pub sent_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Guesses so far, including wrong guesses of the codes this one replaced
+    pub attempts: u32,
+}
+
+impl OtpRecord {
+    fn new(code: &str, ttl: TimeDelta, attempts: u32) -> Result<Self, OtpError> {
+        let mut salt = [0u8; 16];
+        getrandom::fill(&mut salt).map_err(|err| OtpError::Random(err.to_string()))?;
+        let now = Utc::now();
+        Ok(Self {
+            salt,
+            hash: hash_code(&salt, code),
+            sent_at: now,
+            expires_at: now + ttl,
+            attempts,
+        })
+    }
+
+    /// Whether `code` is this record's code, compared in constant time
+    #[must_use]
+    pub fn matches(&self, code: &str) -> bool {
+        let hash = hash_code(&self.salt, code);
+        hash.iter()
+            .zip(self.hash)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+fn hash_code(salt: &[u8], code: &str) -> [u8; 32] {
+    let mut input = salt.to_vec();
+    input.extend_from_slice(code.as_bytes());
+    sha256(&input)
+}
+
+/// Storage for pending codes, keyed by number as digits (see [`registry_key`])
+pub trait OtpStore: Send + Sync {
+    /// The pending code of `number`, if any
+    fn get(&self, number: &str)
+    -> impl Future<Output = Result<Option<OtpRecord>, OtpError>> + Send;
+
+    /// Insert or replace the pending code of `number`
+    fn save(
+        &self,
+        number: &str,
+        record: &OtpRecord,
+    ) -> impl Future<Output = Result<(), OtpError>> + Send;
+
+    /// Remove the pending code of `number`
+    fn remove(&self, number: &str) -> impl Future<Output = Result<(), OtpError>> + Send;
+
+    /// Atomically count a guess of the pending code of `number`, returning
+    /// the updated record, if any
+    ///
+    /// Concurrent guesses must each see a distinct count, so the attempt
+    /// limit holds under parallel verifies.
+    fn record_attempt(
+        &self,
+        number: &str,
+    ) -> impl Future<Output = Result<Option<OtpRecord>, OtpError>> + Send;
+}
+
+/// In-memory OTP store, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryOtpStore {
+    records: Mutex<HashMap<String, OtpRecord>>,
+}
+
+impl MemoryOtpStore {
+    /// Create an empty store
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OtpStore for MemoryOtpStore {
+    async fn get(&self, number: &str) -> Result<Option<OtpRecord>, OtpError> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Utc::now();
+        records.retain(|_, record| record.expires_at > now);
+        Ok(records.get(number).cloned())
+    }
+
+    async fn save(&self, number: &str, record: &OtpRecord) -> Result<(), OtpError> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        records.insert(number.to_string(), record.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, number: &str) -> Result<(), OtpError> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        records.remove(number);
+        Ok(())
+    }
+
+    async fn record_attempt(&self, number: &str) -> Result<Option<OtpRecord>, OtpError> {
+        let mut records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(records.get_mut(number).map(|record| {
+            record.attempts += 1;
+            record.clone()
+        }))
+    }
+}
+
+/// One-time passwords sent by SMS, e.g. for two-factor authentication
+///
+/// Codes are rendered into a template with a `{code}` placeholder and sent
+/// with high priority. Only a salted hash is stored. A code can be verified
+/// until it expires or has been guessed wrong too often, and a new code
+/// replaces the pending one, but not within the resend cooldown. Wrong
+/// guesses carry over to the new code, so resending doesn't reset the
+/// attempt limit: once it is reached, the number is locked out until the
+/// pending code expires. Failures
+/// the user can act on are typed: [`OtpError::TooSoon`],
+/// [`OtpError::TooManyAttempts`] and [`OtpError::Expired`].
+#[derive(Debug)]
+pub struct Otp<S> {
+    store: S,
+    api_key: String,
+    sender: String,
+    template: SmsTemplate,
+    length: usize,
+    alphabet: CodeAlphabet,
+    ttl: TimeDelta,
+    max_attempts: u32,
+    resend_cooldown: TimeDelta,
+}
+
+impl<S: OtpStore> Otp<S> {
+    /// Send codes from `sender` with `template`; an empty `api_key` uses the client's credentials provider
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtpError::MissingCode` if `template` has no `{code}` placeholder
+    pub fn new(
+        store: S,
+        api_key: impl Into<String>,
+        sender: impl Into<String>,
+        template: SmsTemplate,
+    ) -> Result<Self, OtpError> {
+        if !template.placeholders().contains(&"code") {
+            return Err(OtpError::MissingCode);
+        }
+        Ok(Self {
+            store,
+            api_key: api_key.into(),
+            sender: sender.into(),
+            template,
+            length: 6,
+            alphabet: CodeAlphabet::Numeric,
+            ttl: DEFAULT_TTL,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            resend_cooldown: DEFAULT_RESEND_COOLDOWN,
+        })
+    }
+
+    /// Set the code length (default 6)
+    #[must_use]
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = length;
+        self
+    }
+
+    /// Set the code alphabet (default numeric)
+    #[must_use]
+    pub fn with_alphabet(mut self, alphabet: CodeAlphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    /// Set how long codes can be verified (default [`DEFAULT_TTL`])
+    #[must_use]
+    pub fn with_ttl(mut self, ttl: TimeDelta) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the wrong guesses allowed per code (default [`DEFAULT_MAX_ATTEMPTS`])
+    #[must_use]
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Set the minimum time between codes sent to one number (default [`DEFAULT_RESEND_COOLDOWN`])
+    #[must_use]
+    pub fn with_resend_cooldown(mut self, cooldown: TimeDelta) -> Self {
+        self.resend_cooldown = cooldown;
+        self
+    }
+
+    /// Send a new code to `number`, returning the message ID
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtpError::TooSoon` within the resend cooldown of the pending
+    /// code, `OtpError::TooManyAttempts` while the number is locked out, or
+    /// another `OtpError` if the code can't be stored or sent; a code that
+    /// wasn't sent is discarded and the pending one restored
+    pub async fn send(&self, client: &SmsClient, number: &str) -> Result<String, OtpError> {
+        let key = registry_key(number);
+        let pending = self
+            .store
+            .get(&key)
+            .await?
+            .filter(|pending| pending.expires_at > Utc::now());
+        if let Some(pending) = &pending {
+            if pending.attempts >= self.max_attempts {
+                return Err(OtpError::TooManyAttempts);
+            }
+            let retry_in = pending.sent_at + self.resend_cooldown - Utc::now();
+            if retry_in > TimeDelta::zero() {
+                return Err(OtpError::TooSoon { retry_in });
+            }
+        }
+
+        let code = generate_code(self.length, self.alphabet)?;
+        let text = self.template.render(&HashMap::from([("code", &code)]))?;
+        let attempts = pending.as_ref().map_or(0, |pending| pending.attempts);
+        self.store
+            .save(&key, &OtpRecord::new(&code, self.ttl, attempts)?)
+            .await?;
+
+        let request = SmsRequest::new(&*self.api_key, &*self.sender, number, text)
+            .with_priority(Priority::High);
+        match client.send_sms(request).await {
+            Ok(message_id) => Ok(message_id),
+            Err(err) => {
+                match &pending {
+                    Some(pending) => self.store.save(&key, pending).await?,
+                    None => self.store.remove(&key).await?,
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Check `code` against the pending code of `number`
+    ///
+    /// A correct code is consumed; a wrong one returns `false` while guesses
+    /// remain.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OtpError::Expired` if there is no pending code (it expired
+    /// or none was sent), `OtpError::TooManyAttempts` once the code has been
+    /// guessed wrong the maximum number of times, including by this guess,
+    /// or `OtpError::Store` if the store fails
+    pub async fn verify(&self, number: &str, code: &str) -> Result<bool, OtpError> {
+        let key = registry_key(number);
+        // Count the guess before comparing, so parallel guesses can't
+        // exceed the limit between reading and saving the count
+        let Some(record) = self.store.record_attempt(&key).await? else {
+            return Err(OtpError::Expired);
+        };
+        if record.expires_at <= Utc::now() {
+            self.store.remove(&key).await?;
+            return Err(OtpError::Expired);
+        }
+        // The record is kept, so the lockout lasts until it expires
+        if record.attempts > self.max_attempts {
+            return Err(OtpError::TooManyAttempts);
+        }
+        if record.matches(code.trim()) {
+            self.store.remove(&key).await?;
+            return Ok(true);
+        }
+        if record.attempts >= self.max_attempts {
+            return Err(OtpError::TooManyAttempts);
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+
+    #[test]
+    fn generates_codes_from_alphabet() {
+        let numeric = generate_code(6, CodeAlphabet::Numeric).unwrap();
+        assert_eq!(numeric.len(), 6);
+        assert!(numeric.bytes().all(|b| b.is_ascii_digit()));
+
+        let alphanumeric = generate_code(40, CodeAlphabet::Alphanumeric).unwrap();
+        assert!(
+            alphanumeric
+                .bytes()
+                .all(|b| CodeAlphabet::Alphanumeric.chars().contains(&b))
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_and_verifies_codes() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let otp = Otp::new(
+            MemoryOtpStore::new(),
+            "k",
+            "Bank",
+            SmsTemplate::new("Your code is {code}").unwrap(),
+        )
+        .unwrap()
+        .with_max_attempts(2);
+
+        assert!(matches!(
+            otp.verify("+37061234567", "123456").await,
+            Err(OtpError::Expired)
+        ));
+        otp.send(&client, "+37061234567").await.unwrap();
+        assert!(!otp.verify("+37061234567", "wrong").await.unwrap());
+        assert!(matches!(
+            otp.send(&client, "370 612 34567").await,
+            Err(OtpError::TooSoon { retry_in }) if retry_in > TimeDelta::seconds(25)
+        ));
+        m.assert_calls(1);
+
+        // Pretend the code was "123456" by storing a record for it
+        let known = OtpRecord {
+            attempts: 1,
+            ..OtpRecord::new("123456", DEFAULT_TTL, 0).unwrap()
+        };
+        otp.store.save("37061234567", &known).await.unwrap();
+        assert!(otp.verify("370 612 34567", "123456").await.unwrap());
+        assert!(matches!(
+            otp.verify("+37061234567", "123456").await,
+            Err(OtpError::Expired)
+        ));
+
+        otp.store.save("37061234567", &known).await.unwrap();
+        assert!(matches!(
+            otp.verify("+37061234567", "000000").await,
+            Err(OtpError::TooManyAttempts)
+        ));
+        assert!(matches!(
+            otp.verify("+37061234567", "123456").await,
+            Err(OtpError::TooManyAttempts)
+        ));
+
+        assert!(matches!(
+            Otp::new(
+                MemoryOtpStore::new(),
+                "k",
+                "Bank",
+                SmsTemplate::new("Hi").unwrap()
+            ),
+            Err(OtpError::MissingCode)
+        ));
+    }
+
+    #[tokio::test]
+    async fn resends_keep_wrong_guesses() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let otp = Otp::new(
+            MemoryOtpStore::new(),
+            "k",
+            "Bank",
+            SmsTemplate::new("Your code is {code}").unwrap(),
+        )
+        .unwrap()
+        .with_max_attempts(3)
+        .with_resend_cooldown(TimeDelta::zero());
+
+        otp.send(&client, "+37061234567").await.unwrap();
+        assert!(!otp.verify("+37061234567", "wrong").await.unwrap());
+        otp.send(&client, "+37061234567").await.unwrap();
+        assert_eq!(
+            otp.store
+                .get("37061234567")
+                .await
+                .unwrap()
+                .unwrap()
+                .attempts,
+            1
+        );
+
+        let guesses =
+            futures_util::future::join_all((0..5).map(|_| otp.verify("+37061234567", "wrong")))
+                .await;
+        assert_eq!(
+            guesses
+                .iter()
+                .filter(|guess| matches!(guess, Ok(false)))
+                .count(),
+            1
+        );
+        assert!(matches!(
+            otp.send(&client, "+37061234567").await,
+            Err(OtpError::TooManyAttempts)
+        ));
+        m.assert_calls(2);
+    }
+}
@@ -0,0 +1,555 @@
+use crate::esteria::{Priority, SmsClient, SmsRequest, SmsRequestOwned};
+use crate::shutdown::Shutdown;
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    request TEXT NOT NULL,
+    state TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    message_id TEXT,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 1
+);
+CREATE INDEX IF NOT EXISTS outbox_state ON outbox (state, id);
+CREATE INDEX IF NOT EXISTS outbox_priority ON outbox (state, priority DESC, id);
+";
+
+/// Stored sort key of a priority, higher first
+fn rank(priority: Priority) -> i64 {
+    match priority {
+        Priority::Low => 0,
+        Priority::Normal => 1,
+        Priority::High => 2,
+    }
+}
+
+/// Error types for the outbox store
+#[derive(Error, Debug)]
+pub enum OutboxError {
+    #[error("outbox storage failed: {0}")]
+    Storage(#[from] rusqlite::Error),
+    #[error("outbox entry encoding failed: {0}")]
+    Encoding(#[from] serde_json::Error),
+    #[error("outbox is shut down")]
+    ShutDown,
+    #[error("outbox is full ({0} unsent entries)")]
+    QueueFull(usize),
+}
+
+/// Delivery state of an outbox entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxState {
+    /// Waiting to be sent
+    Pending,
+    /// Claimed by a worker; reset to pending when the outbox is reopened
+    Sending,
+    /// Accepted by the gateway
+    Sent,
+    /// Rejected by the gateway or the client; not retried
+    Failed,
+}
+
+impl OutboxState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Sending => "sending",
+            Self::Sent => "sent",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn parse(state: &str) -> Self {
+        match state {
+            "sending" => Self::Sending,
+            "sent" => Self::Sent,
+            "failed" => Self::Failed,
+            _ => Self::Pending,
+        }
+    }
+}
+
+/// Message stored in the outbox
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub request: SmsRequestOwned,
+    pub state: OutboxState,
+    /// Number of send attempts so far
+    pub attempts: u32,
+    /// Gateway message ID once sent
+    pub message_id: Option<String>,
+    /// Error of the last failed attempt
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Persistent queue of outgoing messages backed by SQLite
+///
+/// Enqueued messages survive process restarts. An entry that was being sent
+/// when the process stopped is retried when the outbox is reopened, so
+/// delivery is at-least-once.
+///
+/// An outbox is unbounded unless a capacity is set with
+/// [`Outbox::with_capacity`].
+pub struct Outbox {
+    conn: Mutex<Connection>,
+    shutdown: Shutdown,
+    capacity: Option<usize>,
+    space: Notify,
+}
+
+impl Outbox {
+    /// Open or create an outbox database file
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError::Storage` if the database can't be opened
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, OutboxError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open a transient in-memory outbox, mainly for tests
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError::Storage` if the database can't be created
+    pub fn open_in_memory() -> Result<Self, OutboxError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, OutboxError> {
+        conn.execute_batch(SCHEMA)?;
+        conn.execute(
+            "UPDATE outbox SET state = ?1, updated_at = ?2 WHERE state = ?3",
+            params![
+                OutboxState::Pending.as_str(),
+                Utc::now().timestamp(),
+                OutboxState::Sending.as_str()
+            ],
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            shutdown: Shutdown::default(),
+            capacity: None,
+            space: Notify::new(),
+        })
+    }
+
+    /// Limit the number of unsent (pending or sending) entries
+    ///
+    /// Once full, [`Outbox::enqueue`] fails with `OutboxError::QueueFull` and
+    /// [`Outbox::enqueue_wait`] waits until the worker has made room.
+    #[must_use]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Store a message for sending and return its outbox ID
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError::ShutDown` after [`Outbox::shutdown`],
+    /// `OutboxError::QueueFull` if the outbox is at capacity, or another
+    /// `OutboxError` if the request can't be encoded or stored
+    pub fn enqueue(&self, request: &SmsRequest<'_>) -> Result<i64, OutboxError> {
+        if self.shutdown.is_closed() {
+            return Err(OutboxError::ShutDown);
+        }
+        let priority = rank(request.priority);
+        let request = serde_json::to_string(request)?;
+        let now = Utc::now().timestamp();
+        let conn = self.conn();
+        if let Some(capacity) = self.capacity {
+            let unsent: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM outbox WHERE state IN (?1, ?2)",
+                params![OutboxState::Pending.as_str(), OutboxState::Sending.as_str()],
+                |row| row.get(0),
+            )?;
+            if usize::try_from(unsent).unwrap_or_default() >= capacity {
+                return Err(OutboxError::QueueFull(capacity));
+            }
+        }
+        conn.execute(
+            "INSERT INTO outbox (request, state, created_at, updated_at, priority)
+             VALUES (?1, ?2, ?3, ?3, ?4)",
+            params![request, OutboxState::Pending.as_str(), now, priority],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Store a message for sending, waiting for room if the outbox is full
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError::ShutDown` if the outbox is shut down while
+    /// waiting, or another `OutboxError` if the request can't be stored
+    pub async fn enqueue_wait(&self, request: &SmsRequest<'_>) -> Result<i64, OutboxError> {
+        loop {
+            let space = self.space.notified();
+            match self.enqueue(request) {
+                Err(OutboxError::QueueFull(_)) => tokio::select! {
+                    () = space => {}
+                    () = self.shutdown.closed() => {}
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Look up an entry by outbox ID
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError` if the entry can't be read or decoded
+    pub fn entry(&self, id: i64) -> Result<Option<OutboxEntry>, OutboxError> {
+        let row = self
+            .conn()
+            .query_row(
+                "SELECT request, state, attempts, message_id, last_error, created_at
+                 FROM outbox WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                        row.get(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+
+        let Some((request, state, attempts, message_id, last_error, created_at)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(OutboxEntry {
+            id,
+            request: serde_json::from_str(&request)?,
+            state: OutboxState::parse(&state),
+            attempts,
+            message_id,
+            last_error,
+            created_at: DateTime::from_timestamp(created_at, 0).unwrap_or_default(),
+        }))
+    }
+
+    /// Number of entries waiting to be sent
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError::Storage` if the database can't be queried
+    pub fn pending_count(&self) -> Result<usize, OutboxError> {
+        let count: i64 = self.conn().query_row(
+            "SELECT COUNT(*) FROM outbox WHERE state = ?1",
+            params![OutboxState::Pending.as_str()],
+            |row| row.get(0),
+        )?;
+        Ok(usize::try_from(count).unwrap_or_default())
+    }
+
+    fn claim(&self) -> Result<Option<(i64, SmsRequestOwned)>, OutboxError> {
+        let claimed = self
+            .conn()
+            .query_row(
+                "UPDATE outbox SET state = ?1, attempts = attempts + 1, updated_at = ?2
+                 WHERE id = (SELECT id FROM outbox WHERE state = ?3
+                             ORDER BY priority DESC, id LIMIT 1)
+                 RETURNING id, request",
+                params![
+                    OutboxState::Sending.as_str(),
+                    Utc::now().timestamp(),
+                    OutboxState::Pending.as_str()
+                ],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+
+        match claimed {
+            Some((id, request)) => Ok(Some((id, serde_json::from_str(&request)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn finish(
+        &self,
+        id: i64,
+        state: OutboxState,
+        message_id: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<(), OutboxError> {
+        self.conn().execute(
+            "UPDATE outbox SET state = ?1, message_id = ?2, last_error = ?3, updated_at = ?4
+             WHERE id = ?5",
+            params![
+                state.as_str(),
+                message_id,
+                error,
+                Utc::now().timestamp(),
+                id
+            ],
+        )?;
+        if matches!(state, OutboxState::Sent | OutboxState::Failed) {
+            self.space.notify_waiters();
+        }
+        Ok(())
+    }
+
+    /// Send pending entries until the outbox is empty, returning how many were sent
+    ///
+    /// Entries are sent highest [`Priority`] first, then in enqueue order.
+    /// Entries failing permanently are marked failed. A retryable error (see
+    /// [`crate::SmsError::is_retryable`]) returns the entry to the queue and stops
+    /// draining, so the next drain retries it. After [`Outbox::shutdown`] no further entries are claimed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError` if the store can't be read or updated
+    pub async fn drain(&self, client: &SmsClient) -> Result<usize, OutboxError> {
+        let _worker = self.shutdown.enter();
+        let mut sent = 0;
+        while !self.shutdown.is_closed()
+            && let Some((id, request)) = self.claim()?
+        {
+            match client.send_sms(request).await {
+                Ok(message_id) => {
+                    self.finish(id, OutboxState::Sent, Some(&message_id), None)?;
+                    sent += 1;
+                }
+                Err(err) if err.is_retryable() => {
+                    log::warn!("Outbox entry {id} will be retried: {err}");
+                    self.finish(id, OutboxState::Pending, None, Some(&err.to_string()))?;
+                    break;
+                }
+                Err(err) => {
+                    log::error!("Outbox entry {id} failed: {err}");
+                    self.finish(id, OutboxState::Failed, None, Some(&err.to_string()))?;
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Drain the outbox every `poll_interval`, until [`Outbox::shutdown`]
+    ///
+    /// # Errors
+    ///
+    /// Returns `OutboxError` if the store can't be read or updated
+    pub async fn run(
+        &self,
+        client: &SmsClient,
+        poll_interval: Duration,
+    ) -> Result<(), OutboxError> {
+        let _worker = self.shutdown.enter();
+        while !self.shutdown.is_closed() {
+            self.drain(client).await?;
+            tokio::select! {
+                () = tokio::time::sleep(poll_interval) => {}
+                () = self.shutdown.closed() => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop accepting messages and wait for entries being sent to finish
+    ///
+    /// Once this resolves, running drains and workers have returned. Entries
+    /// not sent yet stay pending in the database for the next start.
+    pub async fn shutdown(&self) {
+        self.shutdown.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn drains_pending_entries() {
+        let server = MockServer::start();
+        let ok = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234567");
+            then.status(200).body("1001");
+        });
+        let rejected = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37061234568");
+            then.status(200).body("3");
+        });
+
+        let outbox = Outbox::open_in_memory().unwrap();
+        let first = outbox
+            .enqueue(&SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+            .unwrap();
+        let second = outbox
+            .enqueue(&SmsRequest::new("k", "Alice", "+37061234568", "Hi"))
+            .unwrap();
+        assert_eq!(outbox.pending_count().unwrap(), 2);
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        assert_eq!(outbox.drain(&client).await.unwrap(), 1);
+        assert_eq!(outbox.pending_count().unwrap(), 0);
+
+        let first = outbox.entry(first).unwrap().unwrap();
+        assert_eq!(first.state, OutboxState::Sent);
+        assert_eq!(first.message_id.as_deref(), Some("1001"));
+        assert_eq!(first.attempts, 1);
+
+        let second = outbox.entry(second).unwrap().unwrap();
+        assert_eq!(second.state, OutboxState::Failed);
+        assert!(second.last_error.is_some());
+        ok.assert();
+        rejected.assert();
+    }
+
+    #[tokio::test]
+    async fn keeps_timed_out_entries_pending() {
+        let server = MockServer::start();
+        let slow = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200)
+                .body("1001")
+                .delay(Duration::from_millis(500));
+        });
+
+        let outbox = Outbox::open_in_memory().unwrap();
+        let id = outbox
+            .enqueue(&SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+            .unwrap();
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        assert_eq!(outbox.drain(&client).await.unwrap(), 0);
+
+        let entry = outbox.entry(id).unwrap().unwrap();
+        assert_eq!(entry.state, OutboxState::Pending);
+        assert!(entry.last_error.unwrap().contains("timed out"));
+        slow.assert();
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_worker_and_rejects_new_entries() {
+        let outbox = Arc::new(Outbox::open_in_memory().unwrap());
+        let worker = tokio::spawn({
+            let outbox = outbox.clone();
+            async move {
+                let client = SmsClient::new();
+                outbox.run(&client, Duration::from_secs(60)).await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        tokio::time::timeout(Duration::from_secs(1), outbox.shutdown())
+            .await
+            .unwrap();
+        worker.await.unwrap().unwrap();
+
+        let err = outbox
+            .enqueue(&SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+            .unwrap_err();
+        assert!(matches!(err, OutboxError::ShutDown));
+    }
+
+    #[tokio::test]
+    async fn full_outbox_applies_backpressure() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let outbox = Arc::new(Outbox::open_in_memory().unwrap().with_capacity(1));
+        let request = SmsRequest::new("k", "Alice", "+37061234567", "Hi");
+        outbox.enqueue(&request).unwrap();
+        assert!(matches!(
+            outbox.enqueue(&request),
+            Err(OutboxError::QueueFull(1))
+        ));
+
+        let waiting = tokio::spawn({
+            let outbox = outbox.clone();
+            async move {
+                let request = SmsRequest::new("k", "Alice", "+37061234567", "Later");
+                outbox.enqueue_wait(&request).await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        assert_eq!(outbox.drain(&client).await.unwrap(), 1);
+        let id = tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(outbox.entry(id).unwrap().unwrap().request.text, "Later");
+    }
+
+    #[test]
+    fn claims_higher_priority_first() {
+        let outbox = Outbox::open_in_memory().unwrap();
+        let marketing =
+            SmsRequest::new("k", "Alice", "+37061234567", "Sale").with_priority(Priority::Low);
+        let reminder = SmsRequest::new("k", "Alice", "+37061234567", "Reminder");
+        let otp =
+            SmsRequest::new("k", "Alice", "+37061234567", "Code").with_priority(Priority::High);
+        for request in [&marketing, &reminder, &otp] {
+            outbox.enqueue(request).unwrap();
+        }
+
+        let order: Vec<_> = std::iter::from_fn(|| outbox.claim().unwrap())
+            .map(|(_, request)| request.text.into_owned())
+            .collect();
+        assert_eq!(order, ["Code", "Reminder", "Sale"]);
+    }
+
+    #[tokio::test]
+    async fn survives_restart() {
+        let path = std::env::temp_dir().join(format!("esteria-outbox-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let id = {
+            let outbox = Outbox::open(&path).unwrap();
+            let id = outbox
+                .enqueue(&SmsRequest::new("k", "Alice", "+37061234567", "Hi"))
+                .unwrap();
+            // Simulate a crash after the entry was claimed
+            outbox.claim().unwrap().unwrap();
+            id
+        };
+
+        let outbox = Outbox::open(&path).unwrap();
+        let entry = outbox.entry(id).unwrap().unwrap();
+        assert_eq!(entry.state, OutboxState::Pending);
+        assert_eq!(entry.request.text, "Hi");
+        assert_eq!(outbox.pending_count().unwrap(), 1);
+
+        drop(outbox);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
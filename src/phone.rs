@@ -0,0 +1,277 @@
+use thiserror::Error;
+
+/// Error types for phone number handling
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PhoneError {
+    #[error("empty number")]
+    Empty,
+    #[error("invalid character '{0}'")]
+    InvalidCharacter(char),
+    #[error("unknown country: {0}")]
+    UnknownCountry(String),
+    #[error("too short ({0} digits, at least {MIN_DIGITS} expected)")]
+    TooShort(usize),
+    #[error("too long ({0} digits, at most {MAX_DIGITS} allowed)")]
+    TooLong(usize),
+    #[error("invalid country prefix")]
+    InvalidCountryPrefix,
+}
+
+/// Minimum number of digits in an international number, including the calling code
+pub const MIN_DIGITS: usize = 7;
+
+/// Maximum number of digits in an international number (ITU-T E.164)
+pub const MAX_DIGITS: usize = 15;
+
+/// Calling code prefixes that are unassigned (ITU-T spare codes)
+const SPARE_PREFIXES: &[&str] = &["28", "83", "89"];
+
+/// Dialing rules of a country
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Country {
+    /// ISO 3166-1 alpha-2 code
+    pub iso: &'static str,
+    /// International calling code without the leading `+`
+    pub calling_code: &'static str,
+    /// Prefixes dialed before national numbers (empty if not used)
+    pub trunk_prefixes: &'static [&'static str],
+    /// Digits of the longest national number, without a trunk prefix
+    pub max_national_digits: usize,
+}
+
+impl Country {
+    const fn new(
+        iso: &'static str,
+        calling_code: &'static str,
+        trunk_prefixes: &'static [&'static str],
+        max_national_digits: usize,
+    ) -> Self {
+        Self {
+            iso,
+            calling_code,
+            trunk_prefixes,
+            max_national_digits,
+        }
+    }
+}
+
+const COUNTRIES: &[Country] = &[
+    Country::new("AT", "43", &["0"], 13),
+    Country::new("BE", "32", &["0"], 9),
+    Country::new("BY", "375", &["8"], 10),
+    Country::new("CA", "1", &[], 10),
+    Country::new("CH", "41", &["0"], 9),
+    Country::new("CZ", "420", &[], 9),
+    Country::new("DE", "49", &["0"], 11),
+    Country::new("DK", "45", &[], 8),
+    Country::new("EE", "372", &[], 8),
+    Country::new("ES", "34", &[], 9),
+    Country::new("FI", "358", &["0"], 10),
+    Country::new("FR", "33", &["0"], 9),
+    Country::new("GB", "44", &["0"], 10),
+    Country::new("IE", "353", &["0"], 9),
+    Country::new("IT", "39", &[], 11),
+    Country::new("LT", "370", &["8", "0"], 8),
+    Country::new("LV", "371", &[], 8),
+    Country::new("NL", "31", &["0"], 9),
+    Country::new("NO", "47", &[], 8),
+    Country::new("PL", "48", &[], 9),
+    Country::new("PT", "351", &[], 9),
+    Country::new("SE", "46", &["0"], 9),
+    Country::new("UA", "380", &["0"], 9),
+    Country::new("US", "1", &[], 10),
+];
+
+/// Look up dialing rules by ISO 3166-1 alpha-2 code (case-insensitive)
+#[must_use]
+pub fn country(iso: &str) -> Option<&'static Country> {
+    COUNTRIES.iter().find(|c| c.iso.eq_ignore_ascii_case(iso))
+}
+
+/// Normalize a phone number to E.164 format (`+` followed by digits)
+///
+/// Spaces, dashes, dots, slashes and parentheses are removed. Numbers starting
+/// with `+` or `00` are international. Other numbers are treated as national
+/// numbers of `default_country` if given: a trunk prefix is replaced with the
+/// calling code, and otherwise the calling code is prepended unless the number
+/// starts with it and is too long to be national. Without a default country,
+/// such numbers are assumed to already include the calling code.
+///
+/// # Errors
+///
+/// Returns `PhoneError::Empty` if no digits remain, `PhoneError::InvalidCharacter`
+/// for letters or other unexpected characters, and `PhoneError::UnknownCountry`
+/// if `default_country` is not a known ISO code.
+pub fn normalize(number: &str, default_country: Option<&str>) -> Result<String, PhoneError> {
+    let mut digits = String::with_capacity(number.len());
+    let mut international = false;
+
+    for c in number.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            '+' if digits.is_empty() && !international => international = true,
+            ' ' | '-' | '.' | '/' | '(' | ')' => {}
+            _ => return Err(PhoneError::InvalidCharacter(c)),
+        }
+    }
+
+    if !international && let Some(rest) = digits.strip_prefix("00") {
+        digits = rest.to_string();
+        international = true;
+    }
+
+    if digits.is_empty() {
+        return Err(PhoneError::Empty);
+    }
+
+    if !international && let Some(iso) = default_country {
+        let country = country(iso).ok_or_else(|| PhoneError::UnknownCountry(iso.to_string()))?;
+
+        let trunk = country
+            .trunk_prefixes
+            .iter()
+            .find(|prefix| digits.starts_with(*prefix));
+
+        if let Some(trunk) = trunk {
+            digits = format!("{}{}", country.calling_code, &digits[trunk.len()..]);
+        } else if !digits.starts_with(country.calling_code)
+            || digits.len() <= country.max_national_digits
+        {
+            digits = format!("{}{digits}", country.calling_code);
+        }
+    }
+
+    Ok(format!("+{digits}"))
+}
+
+/// Digits of a number without formatting and without an international `00` prefix
+///
+/// Unlike [`normalize`] this never fails, which makes it suitable as a lookup
+/// key for number lists.
+#[must_use]
+pub fn digits(number: &str) -> String {
+    let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+    match digits.strip_prefix("00") {
+        Some(rest) => rest.to_string(),
+        None => digits,
+    }
+}
+
+/// Check that a number looks like a dialable international number
+///
+/// Formatting characters are accepted as in [`normalize`], but the number must
+/// already be international (leading `+`, `00`, or the calling code). Only
+/// obvious mistakes are rejected; passing validation does not mean the
+/// number exists.
+///
+/// # Errors
+///
+/// Returns the `PhoneError` describing the first problem found.
+pub fn validate(number: &str) -> Result<(), PhoneError> {
+    let normalized = normalize(number, None)?;
+    let digits = &normalized[1..];
+
+    if digits.starts_with('0') || SPARE_PREFIXES.iter().any(|p| digits.starts_with(p)) {
+        return Err(PhoneError::InvalidCountryPrefix);
+    }
+
+    match digits.len() {
+        len if len < MIN_DIGITS => Err(PhoneError::TooShort(len)),
+        len if len > MAX_DIGITS => Err(PhoneError::TooLong(len)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn international_formats() {
+        assert_eq!(normalize("+370 612 34567", None).unwrap(), "+37061234567");
+        assert_eq!(normalize("00370-612-34567", None).unwrap(), "+37061234567");
+        assert_eq!(
+            normalize("(+44) 20.7946/0958", None).unwrap(),
+            "+442079460958"
+        );
+        assert_eq!(normalize("37061234567", None).unwrap(), "+37061234567");
+    }
+
+    #[test]
+    fn national_formats_with_default_country() {
+        assert_eq!(
+            normalize("8 612 34567", Some("LT")).unwrap(),
+            "+37061234567"
+        );
+        assert_eq!(normalize("0612 34567", Some("lt")).unwrap(), "+37061234567");
+        assert_eq!(
+            normalize("020 7946 0958", Some("GB")).unwrap(),
+            "+442079460958"
+        );
+        assert_eq!(normalize("2000 0000", Some("LV")).unwrap(), "+37120000000");
+        assert_eq!(
+            normalize("(212) 555-1234", Some("US")).unwrap(),
+            "+12125551234"
+        );
+        assert_eq!(
+            normalize("+49 30 123456", Some("LT")).unwrap(),
+            "+4930123456"
+        );
+    }
+
+    #[test]
+    fn national_numbers_starting_with_calling_code() {
+        assert_eq!(normalize("47123456", Some("NO")).unwrap(), "+4747123456");
+        assert_eq!(normalize("4747123456", Some("NO")).unwrap(), "+4747123456");
+        assert_eq!(
+            normalize("391 234 5678", Some("IT")).unwrap(),
+            "+393912345678"
+        );
+        assert_eq!(
+            normalize("39 391 234 5678", Some("IT")).unwrap(),
+            "+393912345678"
+        );
+        assert_eq!(
+            normalize("37061234567", Some("LT")).unwrap(),
+            "+37061234567"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        assert_eq!(normalize(" - ", None), Err(PhoneError::Empty));
+        assert_eq!(normalize("+", None), Err(PhoneError::Empty));
+        assert_eq!(
+            normalize("12ab", None),
+            Err(PhoneError::InvalidCharacter('a'))
+        );
+        assert_eq!(
+            normalize("1+2", None),
+            Err(PhoneError::InvalidCharacter('+'))
+        );
+        assert_eq!(
+            normalize("0612", Some("XX")),
+            Err(PhoneError::UnknownCountry("XX".to_string()))
+        );
+    }
+
+    #[test]
+    fn validate_accepts_plausible_numbers() {
+        assert_eq!(validate("+37061234567"), Ok(()));
+        assert_eq!(validate("+1 (212) 555-1234"), Ok(()));
+        assert_eq!(validate("0037061234567"), Ok(()));
+        assert_eq!(validate("1234567"), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_obvious_mistakes() {
+        assert_eq!(validate("+370abc"), Err(PhoneError::InvalidCharacter('a')));
+        assert_eq!(validate("+370612"), Err(PhoneError::TooShort(6)));
+        assert_eq!(validate("+3706123456789012"), Err(PhoneError::TooLong(16)));
+        assert_eq!(validate("061234567"), Err(PhoneError::InvalidCountryPrefix));
+        assert_eq!(
+            validate("+2891234567"),
+            Err(PhoneError::InvalidCountryPrefix)
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::esteria::{Encoding, SmsClient, SmsError, SmsRequest};
+use crate::phone;
+use crate::sms;
+
+/// Per-segment prices by destination number prefix
+///
+/// Prices are in whatever unit the account is billed in (credits or
+/// currency). The longest matching prefix wins; numbers matching no prefix
+/// use the default price, if any.
+///
+/// The gateway API used by this client only provides `/send` and has no
+/// pricing or coverage endpoint, so prices are configured locally, e.g.
+/// collected from a price list export of `(prefix, price)` rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PriceTable {
+    #[cfg_attr(feature = "serde", serde(default))]
+    prices: Vec<(String, f64)>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    default_price: Option<f64>,
+}
+
+/// Estimated cost of a message before sending
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CostEstimate {
+    /// Number of billed segments
+    pub segments: usize,
+    pub price_per_segment: f64,
+    pub total: f64,
+}
+
+impl PriceTable {
+    /// Create an empty price table
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the per-segment price for numbers starting with `prefix`, e.g. `+370`
+    #[must_use]
+    pub fn with_price(mut self, prefix: &str, per_segment: f64) -> Self {
+        let prefix = phone::digits(prefix);
+        self.prices.retain(|(existing, _)| *existing != prefix);
+        self.prices.push((prefix, per_segment));
+        self.prices
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Set the per-segment price for numbers matching no prefix
+    #[must_use]
+    pub fn with_default_price(mut self, per_segment: f64) -> Self {
+        self.default_price = Some(per_segment);
+        self
+    }
+
+    /// Per-segment price for a destination number
+    #[must_use]
+    pub fn price_for(&self, number: &str) -> Option<f64> {
+        let digits = phone::digits(number);
+        self.prices
+            .iter()
+            .find(|(prefix, _)| digits.starts_with(prefix.as_str()))
+            .map(|(_, price)| *price)
+            .or(self.default_price)
+    }
+
+    /// Estimate the cost of sending `text` to `number`
+    ///
+    /// Returns `None` if there is no price for the destination.
+    #[must_use]
+    pub fn estimate(&self, number: &str, text: &str, encoding: Encoding) -> Option<CostEstimate> {
+        let price_per_segment = self.price_for(number)?;
+        let segments = sms::segments(text, encoding).segments;
+        Some(CostEstimate {
+            segments,
+            price_per_segment,
+            #[allow(clippy::cast_precision_loss)]
+            total: segments as f64 * price_per_segment,
+        })
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<(S, f64)> for PriceTable {
+    fn from_iter<I: IntoIterator<Item = (S, f64)>>(rows: I) -> Self {
+        rows.into_iter()
+            .fold(Self::new(), |table, (prefix, price)| {
+                table.with_price(prefix.as_ref(), price)
+            })
+    }
+}
+
+impl SmsClient {
+    /// Estimate the cost of a request with the configured price table
+    ///
+    /// The number and text are prepared as they would be for sending
+    /// (normalization, transliteration), and long texts are counted as the
+    /// concatenated parts [`SmsClient::send_long_sms`] would send. Returns
+    /// `None` if no price table is configured or it has no price for the
+    /// destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SmsError::InvalidNumber` if the number fails client-side validation
+    pub fn estimate_cost(
+        &self,
+        request: &SmsRequest<'_>,
+    ) -> Result<Option<CostEstimate>, SmsError> {
+        let Some(prices) = &self.price_table else {
+            return Ok(None);
+        };
+
+        let number = self.prepare_number(&request.number)?;
+        let segments = self.segment_info(request).segments;
+        Ok(prices.price_for(&number).map(|price| CostEstimate {
+            segments,
+            price_per_segment: price,
+            #[allow(clippy::cast_precision_loss)]
+            total: segments as f64 * price,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let prices = PriceTable::new()
+            .with_price("+370", 0.03)
+            .with_price("37069", 0.05)
+            .with_default_price(0.10);
+
+        assert_eq!(prices.price_for("+37061234567"), Some(0.03));
+        assert_eq!(prices.price_for("+37069234567"), Some(0.05));
+        assert_eq!(prices.price_for("+442079460958"), Some(0.10));
+        assert_eq!(PriceTable::new().price_for("+37061234567"), None);
+
+        let collected: PriceTable = [("370", 0.03), ("37069", 0.05)].into_iter().collect();
+        assert_eq!(collected.price_for("+37069234567"), Some(0.05));
+    }
+
+    #[test]
+    fn client_estimate_counts_segments() {
+        let client = SmsClient::builder()
+            .with_price_table(PriceTable::new().with_price("370", 0.03))
+            .build()
+            .unwrap();
+
+        let long = SmsRequest::new("k", "Alice", "+37061234567", "a".repeat(161));
+        let estimate = client.estimate_cost(&long).unwrap().unwrap();
+        assert_eq!(estimate.segments, 2);
+        assert!((estimate.total - 0.06).abs() < 1e-9);
+
+        let unicode = SmsRequest::new("k", "Alice", "+37061234567", "ą".repeat(71))
+            .with_encoding(Encoding::Auto);
+        assert_eq!(client.estimate_cost(&unicode).unwrap().unwrap().segments, 2);
+
+        let abroad = SmsRequest::new("k", "Alice", "+442079460958", "Hi");
+        assert_eq!(client.estimate_cost(&abroad).unwrap(), None);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::delivery::{DLR_USER_KEY_PARAM, DeliveryStatus, percent_encode};
+use crate::esteria::{MessageClass, SmsClient, SmsError, SmsRequest};
+use crate::redact;
+use crate::sms::{self, Charset};
+use chrono::Utc;
+use reqwest::{Client, Url};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+/// Future returned by [`Provider`] methods
+pub type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Error types for provider operations other than sending
+#[derive(Error, Debug)]
+pub enum ProviderError {
+    #[error("{provider} does not support {operation}")]
+    Unsupported {
+        provider: String,
+        operation: &'static str,
+    },
+    #[error(transparent)]
+    Sms(#[from] SmsError),
+}
+
+/// Account balance reported by a provider
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Balance {
+    pub amount: f64,
+    pub currency: Option<String>,
+}
+
+/// SMS gateway backend, so applications aren't tied to one gateway
+///
+/// Methods return boxed futures so providers can be mixed behind
+/// `Arc<dyn Provider>`. Gateways without a status or balance endpoint keep
+/// the default implementations, which return `ProviderError::Unsupported`.
+pub trait Provider: Send + Sync {
+    /// Short name, e.g. `esteria`, for logs and routing decisions
+    fn name(&self) -> &str;
+
+    /// Send a message, returning its message ID
+    fn send<'a>(&'a self, request: SmsRequest<'a>) -> ProviderFuture<'a, Result<String, SmsError>>;
+
+    /// Delivery status of a sent message, `None` if not known yet
+    fn status<'a>(
+        &'a self,
+        _message_id: &'a str,
+    ) -> ProviderFuture<'a, Result<Option<DeliveryStatus>, ProviderError>> {
+        Box::pin(async move { Err(unsupported(self.name(), "status")) })
+    }
+
+    /// Current account balance
+    fn balance(&self) -> ProviderFuture<'_, Result<Balance, ProviderError>> {
+        Box::pin(async move { Err(unsupported(self.name(), "balance")) })
+    }
+}
+
+fn unsupported(provider: &str, operation: &'static str) -> ProviderError {
+    ProviderError::Unsupported {
+        provider: provider.to_string(),
+        operation,
+    }
+}
+
+/// The Esteria gateway; delivery statuses arrive as reports (see [`crate::delivery`])
+impl Provider for SmsClient {
+    fn name(&self) -> &str {
+        "esteria"
+    }
+
+    fn send<'a>(&'a self, request: SmsRequest<'a>) -> ProviderFuture<'a, Result<String, SmsError>> {
+        Box::pin(self.send_sms(request))
+    }
+}
+
+/// Gateway speaking the Kannel `sendsms` HTTP interface
+///
+/// Sends the sender, number, text, encoding, message class and delivery
+/// report URL; other request options are ignored. Kannel doesn't return
+/// message IDs, so every send gets a locally generated, unique ID. It is
+/// appended to the delivery report URL as `id`, followed by the request's
+/// user key as `user_key` unless the URL already has one (see
+/// [`crate::delivery::DlrUrl::with_user_key`]). A signed report URL must
+/// list `id` as [unsigned](crate::delivery::DlrUrlSigner::with_unsigned_params).
+pub struct KannelProvider {
+    client: Client,
+    url: Url,
+    username: String,
+    password: String,
+    next_id: AtomicU64,
+}
+
+impl std::fmt::Debug for KannelProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KannelProvider")
+            .field("url", &self.url.as_str())
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+impl KannelProvider {
+    /// Send through the `sendsms` endpoint at `url`, e.g. `http://kannel:13013/cgi-bin/sendsms`
+    #[must_use]
+    pub fn new(url: Url, username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            url,
+            username: username.into(),
+            password: password.into(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Use `client` for HTTP requests, e.g. to share its connection pool
+    #[must_use]
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    async fn send_kannel(&self, request: SmsRequest<'_>) -> Result<String, SmsError> {
+        let coding = match sms::charset_for(&request.text, request.encoding) {
+            Charset::Gsm7 => "0",
+            Charset::EightBit => "1",
+            Charset::Ucs2 => "2",
+        };
+        let mut params = vec![
+            ("username", self.username.as_str()),
+            ("password", self.password.as_str()),
+            ("from", &request.sender),
+            ("to", &request.number),
+            ("text", &request.text),
+            ("charset", "UTF-8"),
+            ("coding", coding),
+        ];
+        if let Some(class) = request.message_class {
+            params.push((
+                "mclass",
+                match class {
+                    MessageClass::Class0 => "0",
+                    MessageClass::Class1 => "1",
+                    MessageClass::Class2 => "2",
+                    MessageClass::Class3 => "3",
+                },
+            ));
+        }
+        let message_id = format!(
+            "kannel-{}-{}",
+            Utc::now().timestamp_millis(),
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let dlr_url = request.dlr_url.as_deref().map(|dlr_url| {
+            let mut dlr_url = dlr_url.to_string();
+            dlr_url.push(if dlr_url.contains('?') { '&' } else { '?' });
+            dlr_url.push_str(&format!("id={}", percent_encode(&message_id)));
+            if let Some(user_key) = &request.user_key
+                && !dlr_url.contains(&format!("{DLR_USER_KEY_PARAM}="))
+            {
+                dlr_url.push_str(&format!(
+                    "&{DLR_USER_KEY_PARAM}={}",
+                    percent_encode(user_key)
+                ));
+            }
+            dlr_url
+        });
+        if let Some(dlr_url) = &dlr_url {
+            params.extend([("dlr-mask", "31"), ("dlr-url", dlr_url)]);
+        }
+
+        let response = self
+            .client
+            .get(self.url.clone())
+            .query(&params)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let status = response.status();
+        let body = response.text().await.map_err(reqwest::Error::without_url)?;
+        // "0: Accepted for delivery" or "3: Queued for later delivery"
+        let code = body
+            .split(':')
+            .next()
+            .and_then(|code| code.trim().parse::<i32>().ok());
+        if !status.is_success() || !matches!(code, Some(0 | 3)) {
+            log::warn!(
+                "Kannel rejected SMS to {}: {}",
+                redact::number(&request.number),
+                body.trim()
+            );
+            return Err(SmsError::SendFailed {
+                number: request.number.to_string(),
+                code,
+                message: body.trim().to_string(),
+            });
+        }
+
+        Ok(message_id)
+    }
+}
+
+impl Provider for KannelProvider {
+    fn name(&self) -> &str {
+        "kannel"
+    }
+
+    fn send<'a>(&'a self, request: SmsRequest<'a>) -> ProviderFuture<'a, Result<String, SmsError>> {
+        Box::pin(self.send_kannel(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esteria::Encoding;
+    use httpmock::prelude::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn sends_through_any_provider() {
+        let server = MockServer::start();
+        let esteria = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+        let kannel = server.mock(|when, then| {
+            when.method(GET)
+                .path("/cgi-bin/sendsms")
+                .query_param("username", "u")
+                .query_param("to", "+37061234567")
+                .query_param("coding", "2");
+            then.status(202).body("0: Accepted for delivery");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/cgi-bin/sendsms")
+                .query_param("to", "+37061234568");
+            then.status(403).body("Authorization failed for sendsms");
+        });
+
+        let url = Url::parse(&server.url("/cgi-bin/sendsms")).unwrap();
+        let providers: Vec<Arc<dyn Provider>> = vec![
+            Arc::new(SmsClient::with_api_base_url(server.base_url())),
+            Arc::new(KannelProvider::new(url, "u", "p")),
+        ];
+        let request = || {
+            SmsRequest::new("k", "Shop", "+37061234567", "Labas")
+                .with_encoding(Encoding::Ucs2)
+                .with_user_key("order-42")
+        };
+
+        assert_eq!(providers[0].send(request()).await.unwrap(), "1001");
+        let first = providers[1].send(request()).await.unwrap();
+        let second = providers[1].send(request()).await.unwrap();
+        assert!(first.starts_with("kannel-"));
+        assert_ne!(first, second);
+        esteria.assert();
+        kannel.assert_calls(2);
+
+        let reported = server.mock(|when, then| {
+            when.method(GET)
+                .path("/cgi-bin/sendsms")
+                .query_param("to", "+37061234569")
+                .query_param_matches(
+                    "dlr-url",
+                    "^https://example.com/dlr\\?status=%d&id=kannel-[0-9-]+&user_key=order-42$",
+                );
+            then.status(202).body("0: Accepted for delivery");
+        });
+        providers[1]
+            .send(
+                SmsRequest::new("k", "Shop", "+37061234569", "Hi")
+                    .with_user_key("order-42")
+                    .with_dlr_url("https://example.com/dlr?status=%d"),
+            )
+            .await
+            .unwrap();
+        reported.assert();
+
+        let rejected = providers[1]
+            .send(SmsRequest::new("k", "Shop", "+37061234568", "Hi"))
+            .await;
+        assert!(matches!(
+            rejected,
+            Err(SmsError::SendFailed { code: None, .. })
+        ));
+        assert!(matches!(
+            providers[1].balance().await,
+            Err(ProviderError::Unsupported {
+                operation: "balance",
+                ..
+            })
+        ));
+    }
+}
@@ -1,31 +1,57 @@
-use crate::esteria::{Encoding, SmsClient, SmsError, SmsFlags, SmsRequest};
-use chrono::DateTime;
-use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use crate::esteria::{Encoding, SmsClient, SmsError, SmsFlags, SmsRequest, SmsRequestOwned};
+use crate::sms::Charset;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use pyo3_async_runtimes::tokio::future_into_py;
-use std::sync::Arc;
+use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex, PoisonError};
 
-#[pyclass(from_py_object)]
-#[derive(Clone)]
+#[pyclass(name = "SmsClient")]
 pub struct PySmsClient {
-    inner: Arc<SmsClient>,
+    /// `None` once closed
+    inner: Mutex<Option<Arc<SmsClient>>>,
+    api_key: Option<String>,
+    default_sender: Option<String>,
 }
 
 #[pymethods]
 impl PySmsClient {
+    /// Client sending through `api_base_url`
+    ///
+    /// `api_key` and `default_sender` are used by sends that don't pass their own.
     #[new]
-    #[pyo3(signature = (api_base_url = "https://api.esteria.eu"))]
-    fn new(api_base_url: &str) -> Self {
-        Self {
-            inner: Arc::new(SmsClient::with_api_base_url(api_base_url)),
-        }
+    #[pyo3(signature = (
+        api_base_url = "https://api.esteria.eu",
+        api_key = None,
+        default_sender = None,
+        bulk_concurrency = 1
+    ))]
+    fn new(
+        api_base_url: &str,
+        api_key: Option<String>,
+        default_sender: Option<String>,
+        bulk_concurrency: usize,
+    ) -> PyResult<Self> {
+        let client = SmsClient::builder()
+            .with_api_base_url(api_base_url)
+            .with_bulk_concurrency(bulk_concurrency)
+            .build()
+            .map_err(to_py_err)?;
+        Ok(Self {
+            inner: Mutex::new(Some(Arc::new(client))),
+            api_key,
+            default_sender,
+        })
     }
 
     #[pyo3(signature = (
-        api_key,
-        sender,
         number,
         text,
+        *,
+        api_key=None,
+        sender=None,
         time=None,
         dlr_url=None,
         expired=None,
@@ -38,15 +64,15 @@ impl PySmsClient {
         user_key=None,
         use_8bit=true,
         udh=false
-    ))]
+    ) -> "typing.Awaitable[SmsResult]")]
     #[allow(clippy::fn_params_excessive_bools, clippy::too_many_arguments)]
     fn send_sms<'py>(
         &self,
         py: Python<'py>,
-        api_key: String,
-        sender: String,
         number: String,
         text: String,
+        api_key: Option<String>,
+        sender: Option<String>,
         time: Option<i64>,
         dlr_url: Option<String>,
         expired: Option<i32>,
@@ -60,79 +86,443 @@ impl PySmsClient {
         use_8bit: bool,
         udh: bool,
     ) -> PyResult<Bound<'py, PyAny>> {
-        let client = self.inner.clone();
+        let client = self.client()?;
+        let request = self.sms_request(
+            number,
+            text,
+            api_key,
+            sender,
+            time,
+            dlr_url,
+            expired,
+            flag_debug,
+            flag_nolog,
+            flag_flash,
+            flag_test,
+            flag_nobl,
+            flag_convert,
+            user_key,
+            use_8bit,
+            udh,
+        )?;
+        future_into_py(py, send(client, request))
+    }
 
-        future_into_py(py, async move {
-            let mut flags = SmsFlags::empty();
-            if flag_debug {
-                flags |= SmsFlags::DEBUG;
-            }
-            if flag_nolog {
-                flags |= SmsFlags::NOLOG;
-            }
-            if flag_flash {
-                flags |= SmsFlags::FLASH;
-            }
-            if flag_test {
-                flags |= SmsFlags::TEST;
-            }
-            if flag_nobl {
-                flags |= SmsFlags::NOBL;
-            }
-            if flag_convert {
-                flags |= SmsFlags::CONVERT;
-            }
+    /// Send an SMS and wait for the result, for code outside asyncio
+    ///
+    /// Runs on the client's internal runtime and releases the GIL while waiting.
+    #[pyo3(signature = (
+        number,
+        text,
+        *,
+        api_key=None,
+        sender=None,
+        time=None,
+        dlr_url=None,
+        expired=None,
+        flag_debug=false,
+        flag_nolog=false,
+        flag_flash=false,
+        flag_test=false,
+        flag_nobl=false,
+        flag_convert=false,
+        user_key=None,
+        use_8bit=true,
+        udh=false
+    ))]
+    #[allow(clippy::fn_params_excessive_bools, clippy::too_many_arguments)]
+    fn send_sms_sync(
+        &self,
+        py: Python<'_>,
+        number: String,
+        text: String,
+        api_key: Option<String>,
+        sender: Option<String>,
+        time: Option<i64>,
+        dlr_url: Option<String>,
+        expired: Option<i32>,
+        flag_debug: bool,
+        flag_nolog: bool,
+        flag_flash: bool,
+        flag_test: bool,
+        flag_nobl: bool,
+        flag_convert: bool,
+        user_key: Option<String>,
+        use_8bit: bool,
+        udh: bool,
+    ) -> PyResult<PySmsResult> {
+        let client = self.client()?;
+        let request = self.sms_request(
+            number,
+            text,
+            api_key,
+            sender,
+            time,
+            dlr_url,
+            expired,
+            flag_debug,
+            flag_nolog,
+            flag_flash,
+            flag_test,
+            flag_nobl,
+            flag_convert,
+            user_key,
+            use_8bit,
+            udh,
+        )?;
+        py.detach(|| get_runtime().block_on(send(client, request)))
+    }
 
-            let encoding = if udh {
-                Encoding::Udh
-            } else if use_8bit {
-                Encoding::EightBit
-            } else {
-                Encoding::Default
-            };
+    /// Send the same message to many numbers, up to `bulk_concurrency` at once
+    ///
+    /// Resolves to a list in input order holding the `SmsResult` for each
+    /// number sent to, or the exception it failed with; failures don't stop
+    /// the batch.
+    #[pyo3(signature = (
+        numbers,
+        text,
+        *,
+        api_key=None,
+        sender=None,
+        time=None,
+        dlr_url=None,
+        expired=None,
+        flags=None,
+        user_key=None,
+        encoding=None
+    ) -> "typing.Awaitable[list[SmsResult | Exception]]")]
+    #[allow(clippy::too_many_arguments)]
+    fn send_bulk<'py>(
+        &self,
+        py: Python<'py>,
+        numbers: Vec<String>,
+        text: String,
+        api_key: Option<String>,
+        sender: Option<String>,
+        time: Option<i64>,
+        dlr_url: Option<String>,
+        expired: Option<i32>,
+        flags: Option<PySmsFlags>,
+        user_key: Option<String>,
+        encoding: Option<PyEncoding>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.client()?;
 
-            let datetime = time
-                .map(|timestamp| {
-                    DateTime::from_timestamp(timestamp, 0)
-                        .ok_or_else(|| PyValueError::new_err("Invalid timestamp"))
-                })
-                .transpose()?;
+        let (api_key, sender) = self.credentials(api_key, sender)?;
+        let mut base: SmsRequestOwned = SmsRequest::new(api_key, sender, "", text)
+            .with_flags(flags.map_or(SmsFlags::empty(), |flags| flags.0))
+            .with_encoding(encoding.map_or(Encoding::Default, |encoding| encoding.0));
 
-            let mut request = SmsRequest::new(&api_key, &sender, &number, &text)
-                .with_flags(flags)
-                .with_encoding(encoding);
+        if let Some(dt) = parse_time(time)? {
+            base = base.with_time(dt);
+        }
 
-            if let Some(dt) = datetime {
-                request = request.with_time(dt);
-            }
+        if let Some(url) = dlr_url {
+            base = base.with_dlr_url(url);
+        }
 
-            if let Some(url) = dlr_url.as_deref() {
-                request = request.with_dlr_url(url);
-            }
+        if let Some(exp) = expired {
+            base = base.with_expired(exp);
+        }
 
-            if let Some(exp) = expired {
-                request = request.with_expired(exp);
-            }
+        if let Some(key) = user_key {
+            base = base.with_user_key(key);
+        }
 
-            if let Some(key) = user_key.as_deref() {
-                request = request.with_user_key(key);
+        // Each send owns its request, so the batch future stays `Send`
+        let concurrency = client.bulk_concurrency;
+        let sends = stream::iter(numbers).map(move |number| {
+            let client = client.clone();
+            let request = SmsRequest {
+                number: Cow::Owned(number),
+                ..base.clone()
+            };
+            async move {
+                let raw_response = client.send_sms(request.as_borrowed()).await?;
+                Ok(PySmsResult::new(&client, &request, raw_response))
             }
+        });
 
-            client.send_sms(request).await.map_err(|e| match e {
-                SmsError::SendFailed { number, message } => {
-                    PyRuntimeError::new_err(format!("SMS sending failed to: {number}, {message}"))
-                }
-                SmsError::RequestFailed(e) => {
-                    PyRuntimeError::new_err(format!("HTTP request failed: {e}"))
-                }
-            })
+        future_into_py(py, async move {
+            let results: Vec<Result<_, SmsError>> = sends.buffered(concurrency).collect().await;
+            let results: Vec<Py<PyAny>> = Python::attach(|py| {
+                results
+                    .into_iter()
+                    .map(|result| match result {
+                        Ok(result) => Py::new(py, result)
+                            .map_or_else(|e| e.into_value(py).into_any(), Py::into_any),
+                        Err(e) => to_py_err(e).into_value(py).into_any(),
+                    })
+                    .collect()
+            });
+            Ok(results)
         })
     }
+
+    /// Release the connection pool; sends afterwards raise `SmsError`
+    ///
+    /// Sends in flight finish first. Closing again does nothing.
+    fn close(&self) {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+    }
+
+    #[pyo3(signature = () -> "typing.Awaitable[SmsClient]")]
+    fn __aenter__(slf: Bound<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let py = slf.py();
+        let slf = slf.unbind();
+        future_into_py(py, async move { Ok(slf) })
+    }
+
+    #[pyo3(signature = (_exc_type, _exc_value, _traceback) -> "typing.Awaitable[None]")]
+    fn __aexit__<'py>(
+        &self,
+        py: Python<'py>,
+        _exc_type: Bound<'py, PyAny>,
+        _exc_value: Bound<'py, PyAny>,
+        _traceback: Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.close();
+        future_into_py(py, async move { Ok(()) })
+    }
+}
+
+impl PySmsClient {
+    /// The client, unless closed
+    fn client(&self) -> PyResult<Arc<SmsClient>> {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or_else(|| exceptions::SmsError::new_err("client is closed"))
+    }
+
+    /// Request from the arguments of `send_sms`
+    #[allow(clippy::fn_params_excessive_bools, clippy::too_many_arguments)]
+    fn sms_request(
+        &self,
+        number: String,
+        text: String,
+        api_key: Option<String>,
+        sender: Option<String>,
+        time: Option<i64>,
+        dlr_url: Option<String>,
+        expired: Option<i32>,
+        flag_debug: bool,
+        flag_nolog: bool,
+        flag_flash: bool,
+        flag_test: bool,
+        flag_nobl: bool,
+        flag_convert: bool,
+        user_key: Option<String>,
+        use_8bit: bool,
+        udh: bool,
+    ) -> PyResult<SmsRequestOwned> {
+        let mut flags = SmsFlags::empty();
+        if flag_debug {
+            flags |= SmsFlags::DEBUG;
+        }
+        if flag_nolog {
+            flags |= SmsFlags::NOLOG;
+        }
+        if flag_flash {
+            flags |= SmsFlags::FLASH;
+        }
+        if flag_test {
+            flags |= SmsFlags::TEST;
+        }
+        if flag_nobl {
+            flags |= SmsFlags::NOBL;
+        }
+        if flag_convert {
+            flags |= SmsFlags::CONVERT;
+        }
+
+        let encoding = if udh {
+            Encoding::Udh
+        } else if use_8bit {
+            Encoding::EightBit
+        } else {
+            Encoding::Default
+        };
+
+        let datetime = parse_time(time)?;
+
+        let (api_key, sender) = self.credentials(api_key, sender)?;
+        let mut request: SmsRequestOwned = SmsRequest::new(api_key, sender, number, text)
+            .with_flags(flags)
+            .with_encoding(encoding);
+
+        if let Some(dt) = datetime {
+            request = request.with_time(dt);
+        }
+
+        if let Some(url) = dlr_url {
+            request = request.with_dlr_url(url);
+        }
+
+        if let Some(exp) = expired {
+            request = request.with_expired(exp);
+        }
+
+        if let Some(key) = user_key {
+            request = request.with_user_key(key);
+        }
+
+        Ok(request)
+    }
+
+    /// API key and sender of a send, falling back to the client's
+    fn credentials(
+        &self,
+        api_key: Option<String>,
+        sender: Option<String>,
+    ) -> PyResult<(String, String)> {
+        let api_key = api_key.or_else(|| self.api_key.clone()).ok_or_else(|| {
+            PyValueError::new_err("no API key; pass api_key or set it on the client")
+        })?;
+        let sender = sender
+            .or_else(|| self.default_sender.clone())
+            .ok_or_else(|| {
+                PyValueError::new_err("no sender; pass sender or set default_sender on the client")
+            })?;
+        Ok((api_key, sender))
+    }
+}
+
+/// Send `request`, converting the outcome for Python
+async fn send(client: Arc<SmsClient>, request: SmsRequestOwned) -> PyResult<PySmsResult> {
+    match client.send_sms(request.as_borrowed()).await {
+        Ok(raw_response) => Ok(PySmsResult::new(&client, &request, raw_response)),
+        Err(e) => Err(to_py_err(e)),
+    }
+}
+
+/// Outcome of a successful send
+#[pyclass(name = "SmsResult", frozen, get_all)]
+pub struct PySmsResult {
+    message_id: String,
+    segments: usize,
+    /// Character set the text was sent in: `gsm7`, `8bit` or `ucs2`
+    encoding_used: &'static str,
+    raw_response: String,
+}
+
+impl PySmsResult {
+    fn new(client: &SmsClient, request: &SmsRequest<'_>, raw_response: String) -> Self {
+        let info = client.segment_info(request);
+        Self {
+            message_id: raw_response.trim().to_string(),
+            segments: info.segments,
+            encoding_used: match info.charset {
+                Charset::Gsm7 => "gsm7",
+                Charset::EightBit => "8bit",
+                Charset::Ucs2 => "ucs2",
+            },
+            raw_response,
+        }
+    }
+}
+
+#[pymethods]
+impl PySmsResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "SmsResult(message_id='{}', segments={}, encoding_used='{}')",
+            self.message_id, self.segments, self.encoding_used
+        )
+    }
 }
 
-#[pyclass(from_py_object)]
+/// Convert a Unix timestamp argument
+fn parse_time(time: Option<i64>) -> PyResult<Option<DateTime<Utc>>> {
+    time.map(|timestamp| {
+        DateTime::from_timestamp(timestamp, 0)
+            .ok_or_else(|| PyValueError::new_err("Invalid timestamp"))
+    })
+    .transpose()
+}
+
+/// Python exception raised for a failed send
+fn to_py_err(e: SmsError) -> PyErr {
+    match e {
+        SmsError::SendFailed {
+            number,
+            code,
+            message,
+        } => {
+            let message = format!("SMS sending failed to: {number}, {message}");
+            send_failed(message, number, code)
+        }
+        SmsError::OptedOut { ref number }
+        | SmsError::Blocked { ref number, .. }
+        | SmsError::Cancelled { ref number }
+        | SmsError::InProgress { ref number }
+        | SmsError::Uncertain { ref number } => send_failed(e.to_string(), number.clone(), None),
+        SmsError::RequestFailed(e) => {
+            exceptions::HttpError::new_err(format!("HTTP request failed: {e}"))
+        }
+        SmsError::Timeout { .. } | SmsError::HttpStatus { .. } | SmsError::Tls { .. } => {
+            exceptions::HttpError::new_err(e.to_string())
+        }
+        SmsError::UnknownResponse { .. } => exceptions::InvalidResponse::new_err(e.to_string()),
+        SmsError::Credentials(_) => exceptions::SmsError::new_err(e.to_string()),
+        SmsError::InvalidNumber { .. } | SmsError::InvalidSender { .. } | SmsError::Template(_) => {
+            PyValueError::new_err(e.to_string())
+        }
+    }
+}
+
+/// `SendFailed` carrying the number and gateway error code
+fn send_failed(message: String, number: String, code: Option<i32>) -> PyErr {
+    let err = exceptions::SendFailed::new_err(message);
+    Python::attach(|py| {
+        let value = err.value(py);
+        match value
+            .setattr("number", number)
+            .and_then(|()| value.setattr("code", code))
+        {
+            Ok(()) => err,
+            Err(setattr_err) => setattr_err,
+        }
+    })
+}
+
+/// Exceptions raised by the client
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+
+    create_exception!(
+        _esteria_api_client,
+        SmsError,
+        PyException,
+        "Base class of SMS sending errors"
+    );
+    create_exception!(
+        _esteria_api_client,
+        SendFailed,
+        SmsError,
+        "The message was not sent to `number`; `code` is the gateway error code, if any"
+    );
+    create_exception!(
+        _esteria_api_client,
+        HttpError,
+        SmsError,
+        "The gateway could not be reached or timed out"
+    );
+    create_exception!(
+        _esteria_api_client,
+        InvalidResponse,
+        SmsError,
+        "The gateway answered with an unknown code or a non-numeric body"
+    );
+}
+
+#[pyclass(name = "Encoding", from_py_object)]
 #[derive(Clone, Copy)]
-#[allow(dead_code)]
 pub struct PyEncoding(Encoding);
 
 #[pymethods]
@@ -145,9 +535,15 @@ impl PyEncoding {
 
     #[classattr]
     const UDH: Self = Self(Encoding::Udh);
+
+    #[classattr]
+    const AUTO: Self = Self(Encoding::Auto);
+
+    #[classattr]
+    const UCS2: Self = Self(Encoding::Ucs2);
 }
 
-#[pyclass(from_py_object)]
+#[pyclass(name = "SmsFlags", from_py_object)]
 #[derive(Clone, Copy)]
 pub struct PySmsFlags(SmsFlags);
 
@@ -204,10 +600,11 @@ impl PySmsFlags {
     }
 }
 
+/// Declared inline so its contents are introspectable for type stubs
 #[pymodule]
-fn _esteria_api_client(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_class::<PySmsClient>()?;
-    m.add_class::<PyEncoding>()?;
-    m.add_class::<PySmsFlags>()?;
-    Ok(())
+mod _esteria_api_client {
+    #[pymodule_export]
+    use super::exceptions::{HttpError, InvalidResponse, SendFailed, SmsError};
+    #[pymodule_export]
+    use super::{PyEncoding, PySmsClient, PySmsFlags, PySmsResult};
 }
@@ -0,0 +1,169 @@
+use chrono::{DateTime, Days, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Daily window in which messages must not reach recipients
+///
+/// The window is evaluated in the recipient's local time, resolved from the
+/// number prefix (see [`QuietHours::with_prefix_timezone`]) or the default
+/// timezone. A window may span midnight, e.g. 22:00–08:00.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    timezone: Tz,
+    prefix_timezones: Vec<(String, Tz)>,
+}
+
+impl QuietHours {
+    /// Disallow sending from `start` (inclusive) until `end` (exclusive)
+    ///
+    /// Recipient times default to UTC.
+    #[must_use]
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        Self {
+            start,
+            end,
+            timezone: Tz::UTC,
+            prefix_timezones: Vec::new(),
+        }
+    }
+
+    /// Set the timezone for recipients not matched by a number prefix
+    #[must_use]
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Use `timezone` for numbers starting with `prefix`, e.g. `370` or `+370`
+    ///
+    /// The longest matching prefix wins.
+    #[must_use]
+    pub fn with_prefix_timezone(mut self, prefix: &str, timezone: Tz) -> Self {
+        self.prefix_timezones
+            .push((digits(prefix).to_string(), timezone));
+        self.prefix_timezones
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Local timezone of the recipient
+    #[must_use]
+    pub fn timezone_for(&self, number: &str) -> Tz {
+        let number = digits(number);
+        self.prefix_timezones
+            .iter()
+            .find(|(prefix, _)| number.starts_with(prefix.as_str()))
+            .map_or(self.timezone, |(_, timezone)| *timezone)
+    }
+
+    /// Whether a message to `number` at `at` falls into quiet hours
+    #[must_use]
+    pub fn is_quiet(&self, number: &str, at: DateTime<Utc>) -> bool {
+        let time = at.with_timezone(&self.timezone_for(number)).time();
+        self.contains(time)
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Earliest time from `at` on when a message to `number` may be sent
+    #[must_use]
+    pub fn next_allowed(&self, number: &str, at: DateTime<Utc>) -> DateTime<Utc> {
+        if !self.is_quiet(number, at) {
+            return at;
+        }
+
+        let timezone = self.timezone_for(number);
+        let local = at.with_timezone(&timezone).naive_local();
+        let mut end = local.date().and_time(self.end);
+        if end <= local {
+            end = end + Days::new(1);
+        }
+        resolve_local(timezone, end)
+    }
+}
+
+/// Convert a local time to UTC, moving past DST gaps
+pub(crate) fn resolve_local(timezone: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(local + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .map_or_else(|| local.and_utc(), |at| at.with_timezone(&Utc))
+}
+
+fn digits(number: &str) -> &str {
+    let number = number.trim_start_matches('+');
+    number.strip_prefix("00").unwrap_or(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hm(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn defers_to_end_of_overnight_window() {
+        let quiet = QuietHours::new(hm(22, 0), hm(8, 0))
+            .with_prefix_timezone("+370", chrono_tz::Europe::Vilnius);
+        let number = "+37061234567";
+
+        // 21:30 UTC is 23:30 in Vilnius (EET, winter)
+        let late = Utc.with_ymd_and_hms(2024, 1, 10, 21, 30, 0).unwrap();
+        assert!(quiet.is_quiet(number, late));
+        assert_eq!(
+            quiet.next_allowed(number, late),
+            Utc.with_ymd_and_hms(2024, 1, 11, 6, 0, 0).unwrap()
+        );
+
+        // 03:00 local, same day
+        let early = Utc.with_ymd_and_hms(2024, 1, 11, 1, 0, 0).unwrap();
+        assert_eq!(
+            quiet.next_allowed(number, early),
+            Utc.with_ymd_and_hms(2024, 1, 11, 6, 0, 0).unwrap()
+        );
+
+        let daytime = Utc.with_ymd_and_hms(2024, 1, 11, 10, 0, 0).unwrap();
+        assert!(!quiet.is_quiet(number, daytime));
+        assert_eq!(quiet.next_allowed(number, daytime), daytime);
+    }
+
+    #[test]
+    fn resolves_timezone_by_longest_prefix() {
+        let quiet = QuietHours::new(hm(13, 0), hm(14, 0))
+            .with_timezone(chrono_tz::Europe::London)
+            .with_prefix_timezone("1", chrono_tz::America::New_York)
+            .with_prefix_timezone("1808", chrono_tz::Pacific::Honolulu);
+
+        assert_eq!(
+            quiet.timezone_for("+18085550100"),
+            chrono_tz::Pacific::Honolulu
+        );
+        assert_eq!(
+            quiet.timezone_for("0012125550100"),
+            chrono_tz::America::New_York
+        );
+        assert_eq!(
+            quiet.timezone_for("+37061234567"),
+            chrono_tz::Europe::London
+        );
+
+        // 13:30 in London, 08:30 in New York
+        let at = Utc.with_ymd_and_hms(2024, 1, 10, 13, 30, 0).unwrap();
+        assert!(quiet.is_quiet("+447700900123", at));
+        assert!(!quiet.is_quiet("+12125550100", at));
+    }
+}
@@ -0,0 +1,285 @@
+use crate::phone;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Allowed throughput of one rate limit bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    per_second: f64,
+    burst: f64,
+}
+
+impl Rate {
+    /// Allow `per_second` messages per second, with bursts of up to one second's worth
+    ///
+    /// # Panics
+    ///
+    /// Panics if `per_second` is not positive
+    #[must_use]
+    pub fn per_second(per_second: f64) -> Self {
+        assert!(per_second > 0.0, "rate must be positive");
+        Self {
+            per_second,
+            burst: per_second.ceil(),
+        }
+    }
+
+    /// Allow bursts of up to `burst` messages before throttling kicks in
+    #[must_use]
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = f64::from(burst.max(1));
+        self
+    }
+}
+
+/// Client-side send rate limits by destination country and sender ID
+///
+/// Each prefix, e.g. `+370`, gets its own bucket and the longest matching
+/// prefix wins. Numbers matching no prefix share the default bucket, or are
+/// not limited if there is none. Sender limits work the same way, keyed by
+/// the exact `sender` value, except that every sender without its own limit
+/// gets a separate bucket at the default sender rate. A send must fit both
+/// its country and its sender bucket; sends over the limit wait for their turn.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimit {
+    countries: Vec<(String, Rate)>,
+    default_rate: Option<Rate>,
+    senders: HashMap<String, Rate>,
+    default_sender_rate: Option<Rate>,
+}
+
+impl RateLimit {
+    /// Create rate limits that don't limit anything yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit sends to numbers starting with `prefix`, e.g. `+370`
+    #[must_use]
+    pub fn with_country(mut self, prefix: &str, rate: Rate) -> Self {
+        let prefix = phone::digits(prefix);
+        self.countries.retain(|(existing, _)| *existing != prefix);
+        self.countries.push((prefix, rate));
+        self.countries
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Limit sends to numbers matching no country prefix
+    #[must_use]
+    pub fn with_default(mut self, rate: Rate) -> Self {
+        self.default_rate = Some(rate);
+        self
+    }
+
+    /// Limit sends from `sender`, e.g. a registered alphanumeric sender name
+    #[must_use]
+    pub fn with_sender(mut self, sender: impl Into<String>, rate: Rate) -> Self {
+        self.senders.insert(sender.into(), rate);
+        self
+    }
+
+    /// Limit sends from each sender without its own limit
+    #[must_use]
+    pub fn with_default_sender(mut self, rate: Rate) -> Self {
+        self.default_sender_rate = Some(rate);
+        self
+    }
+
+    fn sender_rate(&self, sender: &str) -> Option<Rate> {
+        self.senders
+            .get(sender)
+            .copied()
+            .or(self.default_sender_rate)
+    }
+
+    /// Bucket key and rate for a destination number
+    fn country_bucket(&self, number: &str) -> Option<(&str, Rate)> {
+        let digits = phone::digits(number);
+        self.countries
+            .iter()
+            .find(|(prefix, _)| digits.starts_with(prefix.as_str()))
+            .map(|(prefix, rate)| (prefix.as_str(), *rate))
+            .or_else(|| self.default_rate.map(|rate| ("", rate)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum BucketKey {
+    Country(String),
+    Sender(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Shared state of the buckets of a [`RateLimit`]
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limits: RateLimit,
+    buckets: Mutex<HashMap<BucketKey, Bucket>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: RateLimit) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn buckets(&self) -> MutexGuard<'_, HashMap<BucketKey, Bucket>> {
+        self.buckets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reserve a send from `sender` to `number`, returning how long to wait before sending
+    fn reserve(&self, number: &str, sender: &str, now: Instant) -> Duration {
+        let country = self
+            .limits
+            .country_bucket(number)
+            .map(|(prefix, rate)| self.take(BucketKey::Country(prefix.to_string()), rate, now));
+        let sender = self
+            .limits
+            .sender_rate(sender)
+            .map(|rate| self.take(BucketKey::Sender(sender.to_string()), rate, now));
+        country.max(sender).unwrap_or_default()
+    }
+
+    fn take(&self, key: BucketKey, rate: Rate, now: Instant) -> Duration {
+        let mut buckets = self.buckets();
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: rate.burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate.per_second).min(rate.burst);
+        bucket.updated = now;
+
+        // Going negative reserves a slot, so waiting sends are served in order
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate.per_second)
+        }
+    }
+
+    /// Wait until a send from `sender` to `number` is within the limits
+    pub(crate) async fn acquire(&self, number: &str, sender: &str) {
+        let delay = self.reserve(number, sender, Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_are_kept_per_country() {
+        let limiter = RateLimiter::new(
+            RateLimit::new()
+                .with_country("+370", Rate::per_second(2.0))
+                .with_country("+49", Rate::per_second(1.0))
+                .with_default(Rate::per_second(1.0)),
+        );
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.reserve("+37061234567", "Alice", now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.reserve("+37061234568", "Alice", now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.reserve("+37061234569", "Alice", now),
+            Duration::from_millis(500)
+        );
+
+        assert_eq!(
+            limiter.reserve("+4915112345678", "Alice", now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.reserve("+4915112345679", "Alice", now),
+            Duration::from_secs(1)
+        );
+
+        // Countries without their own limit share the default bucket
+        assert_eq!(
+            limiter.reserve("+442079460958", "Alice", now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            limiter.reserve("+33612345678", "Alice", now),
+            Duration::from_secs(1)
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            limiter.reserve("+37061234567", "Alice", later),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn senders_have_separate_buckets() {
+        let limiter = RateLimiter::new(
+            RateLimit::new()
+                .with_sender("Bank", Rate::per_second(2.0))
+                .with_default_sender(Rate::per_second(1.0)),
+        );
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("+37061234567", "Bank", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("+37061234567", "Bank", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("+37061234567", "Shop", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("+37061234567", "News", now), Duration::ZERO);
+        assert_eq!(
+            limiter.reserve("+37061234567", "Shop", now),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            limiter.reserve("+37061234567", "Bank", now),
+            Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn send_waits_for_the_fuller_bucket() {
+        let limiter = RateLimiter::new(
+            RateLimit::new()
+                .with_country("+370", Rate::per_second(1.0))
+                .with_sender("Bank", Rate::per_second(4.0)),
+        );
+        let now = Instant::now();
+
+        assert_eq!(limiter.reserve("+37061234567", "Bank", now), Duration::ZERO);
+        assert_eq!(
+            limiter.reserve("+37061234568", "Bank", now),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn unmatched_numbers_are_unlimited_without_default() {
+        let limiter =
+            RateLimiter::new(RateLimit::new().with_country("+370", Rate::per_second(1.0)));
+        let now = Instant::now();
+        for _ in 0..5 {
+            assert_eq!(
+                limiter.reserve("+442079460958", "Alice", now),
+                Duration::ZERO
+            );
+        }
+    }
+}
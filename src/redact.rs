@@ -0,0 +1,116 @@
+use crate::idempotency;
+use crate::phone;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How phone numbers appear in logs, tracing output, error messages and `Debug` output
+///
+/// The policy is process-wide, since `Debug` and `Display` implementations
+/// have no access to client configuration. Serialized requests and stored
+/// data are never redacted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Redaction {
+    /// Show numbers in full
+    #[default]
+    None,
+    /// Keep the first four and last two digits, e.g. `+3706*****67`
+    Mask,
+    /// Replace numbers with a stable hash, so log lines can still be correlated
+    Hash,
+    /// Replace numbers with `<redacted>`
+    Omit,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+/// Set the redaction policy for the whole process
+pub fn set_policy(policy: Redaction) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Current redaction policy
+#[must_use]
+pub fn policy() -> Redaction {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => Redaction::Mask,
+        2 => Redaction::Hash,
+        3 => Redaction::Omit,
+        _ => Redaction::None,
+    }
+}
+
+impl Redaction {
+    /// Format `number` according to this policy
+    #[must_use]
+    pub fn apply(self, number: &str) -> String {
+        match self {
+            Self::None => number.to_string(),
+            Self::Mask => {
+                let digits = number.chars().filter(char::is_ascii_digit).count();
+                let mut seen = 0;
+                number
+                    .chars()
+                    .map(|c| {
+                        if !c.is_ascii_digit() {
+                            return c;
+                        }
+                        seen += 1;
+                        let keep = digits > 6 && (seen <= 4 || seen > digits - 2);
+                        if keep { c } else { '*' }
+                    })
+                    .collect()
+            }
+            Self::Hash => {
+                let hash = idempotency::fnv1a([phone::digits(number).as_str()]);
+                format!("#{:08x}", hash >> 32)
+            }
+            Self::Omit => "<redacted>".to_string(),
+        }
+    }
+}
+
+/// Number formatted according to the current policy
+#[must_use]
+pub fn number(number: &str) -> Redacted<'_> {
+    Redacted(number)
+}
+
+/// Phone number displayed according to the redaction policy
+#[derive(Clone, Copy)]
+pub struct Redacted<'a>(&'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match policy() {
+            Redaction::None => f.write_str(self.0),
+            policy => f.write_str(&policy.apply(self.0)),
+        }
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match policy() {
+            Redaction::None => fmt::Debug::fmt(self.0, f),
+            policy => fmt::Debug::fmt(&policy.apply(self.0), f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_policies() {
+        assert_eq!(Redaction::None.apply("+37061234567"), "+37061234567");
+        assert_eq!(Redaction::Mask.apply("+370 612 34567"), "+370 6** ***67");
+        assert_eq!(Redaction::Mask.apply("12345"), "*****");
+        assert_eq!(Redaction::Omit.apply("+37061234567"), "<redacted>");
+
+        let hash = Redaction::Hash.apply("+37061234567");
+        assert_eq!(hash, Redaction::Hash.apply("0037061234567"));
+        assert_ne!(hash, Redaction::Hash.apply("+37061234568"));
+        assert!(hash.starts_with('#'));
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::sync::{PoisonError, RwLock};
+
+/// Gateway error code with its description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseCode {
+    pub code: i32,
+    pub message: String,
+    /// Whether the failure is transient, see [`crate::SmsError::is_retryable`]
+    pub retryable: bool,
+}
+
+/// Documented gateway error codes
+const BUILTIN: &[(i32, &str, bool)] = &[
+    (1, "system internal error", true),
+    (2, "missing PARAM_NAME parameter", false),
+    (3, "unable to authenticate", false),
+    (4, "IP ADDRESS is not allowed", false),
+    (5, "invalid SENDER parameter", false),
+    (6, "SENDER is not allowed", false),
+    (7, "invalid NUMBER parameter", false),
+    (8, "invalid CODING parameter", false),
+    (9, "unable to convert TEXT", false),
+    (10, "length of UDH and TEXT too long", false),
+    (11, "empty TEXT parameter", false),
+    (12, "invalid TIME parameter", false),
+    (13, "invalid EXPIRED parameter", false),
+    (14, "invalid DLR-URL parameter", false),
+    (15, "Invalid FLAG-FLASH parameter", false),
+    (16, "invalid FLAG-NOLOG parameter", false),
+    (17, "invalid FLAG-TEST parameter", false),
+    (18, "invalid FLAG-NOBL parameter", false),
+    (19, "invalid FLAG-CONVERT parameter", false),
+];
+
+static REGISTERED: RwLock<BTreeMap<i32, (String, bool)>> = RwLock::new(BTreeMap::new());
+
+/// Register or override a gateway error code for the whole process
+///
+/// Codes the gateway returns that are neither documented nor registered are
+/// reported as `SmsError::UnknownResponse`.
+pub fn register(code: i32, message: impl Into<String>, retryable: bool) {
+    REGISTERED
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(code, (message.into(), retryable));
+}
+
+/// Description of a gateway error code, if it is documented or registered
+#[must_use]
+pub fn lookup(code: i32) -> Option<ResponseCode> {
+    let registered = REGISTERED.read().unwrap_or_else(PoisonError::into_inner);
+    if let Some((message, retryable)) = registered.get(&code) {
+        return Some(ResponseCode {
+            code,
+            message: message.clone(),
+            retryable: *retryable,
+        });
+    }
+
+    BUILTIN
+        .iter()
+        .find(|(builtin, _, _)| *builtin == code)
+        .map(|&(code, message, retryable)| ResponseCode {
+            code,
+            message: message.to_string(),
+            retryable,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_builtin_and_registered_codes() {
+        assert_eq!(lookup(1).unwrap().message, "system internal error");
+        assert!(lookup(1).unwrap().retryable);
+        assert_eq!(
+            lookup(19).unwrap().message,
+            "invalid FLAG-CONVERT parameter"
+        );
+        assert_eq!(lookup(998), None);
+
+        register(998, "daily quota exceeded", true);
+        assert_eq!(
+            lookup(998),
+            Some(ResponseCode {
+                code: 998,
+                message: "daily quota exceeded".to_string(),
+                retryable: true,
+            })
+        );
+    }
+}
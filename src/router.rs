@@ -0,0 +1,328 @@
+use crate::esteria::{SmsError, SmsRequest};
+use crate::phone;
+use crate::pricing::PriceTable;
+use crate::provider::{Provider, ProviderFuture};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long a least-cost candidate is skipped after a transient failure
+pub const DEFAULT_HEALTH_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Condition of an override rule, given the request to route
+pub type RoutePredicate = dyn Fn(&SmsRequest<'_>) -> bool + Send + Sync;
+
+/// Provider considered by least-cost routing
+struct Candidate {
+    provider: Arc<dyn Provider>,
+    prices: PriceTable,
+    priority: u32,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Candidate {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none_or(|until| until <= now)
+    }
+
+    fn set_unhealthy_until(&self, until: Option<Instant>) {
+        *self
+            .unhealthy_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = until;
+    }
+}
+
+/// Picks a provider per message by destination prefix or price
+///
+/// Override rules are checked first, in the order added; then the longest
+/// matching number prefix wins, e.g. `+370` over `+3`. Messages matching
+/// neither go to the least-cost candidates if there are any (see
+/// [`Router::with_least_cost`]), otherwise to the default provider. The router is a [`Provider`] itself,
+/// so routers can be nested. Status and balance queries aren't routed,
+/// since a message ID doesn't tell which provider sent it.
+#[derive(Clone)]
+pub struct Router {
+    overrides: Vec<(Arc<RoutePredicate>, Arc<dyn Provider>)>,
+    routes: Vec<(String, Arc<dyn Provider>)>,
+    candidates: Vec<Arc<Candidate>>,
+    cooldown: Duration,
+    default: Arc<dyn Provider>,
+}
+
+impl std::fmt::Debug for Router {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let routes: Vec<_> = self
+            .routes
+            .iter()
+            .map(|(prefix, provider)| (prefix.as_str(), provider.name()))
+            .collect();
+        let candidates: Vec<_> = self
+            .candidates
+            .iter()
+            .map(|candidate| candidate.provider.name())
+            .collect();
+        f.debug_struct("Router")
+            .field("overrides", &self.overrides.len())
+            .field("routes", &routes)
+            .field("candidates", &candidates)
+            .field("cooldown", &self.cooldown)
+            .field("default", &self.default.name())
+            .finish()
+    }
+}
+
+impl Router {
+    /// Route messages matching no rule to `default`
+    pub fn new(default: Arc<dyn Provider>) -> Self {
+        Self {
+            overrides: Vec::new(),
+            routes: Vec::new(),
+            candidates: Vec::new(),
+            cooldown: DEFAULT_HEALTH_COOLDOWN,
+            default,
+        }
+    }
+
+    /// Route numbers starting with `prefix`, e.g. `+370`, to `provider`
+    ///
+    /// A prefix added again replaces its earlier route.
+    #[must_use]
+    pub fn with_route(mut self, prefix: &str, provider: Arc<dyn Provider>) -> Self {
+        let prefix = phone::digits(prefix);
+        self.routes.retain(|(existing, _)| *existing != prefix);
+        self.routes.push((prefix, provider));
+        self.routes
+            .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        self
+    }
+
+    /// Route requests matching `predicate` to `provider`, before any prefix route
+    ///
+    /// For example, send everything from a `Bank` sender through a dedicated account.
+    #[must_use]
+    pub fn with_override(
+        mut self,
+        predicate: impl Fn(&SmsRequest<'_>) -> bool + Send + Sync + 'static,
+        provider: Arc<dyn Provider>,
+    ) -> Self {
+        self.overrides.push((Arc::new(predicate), provider));
+        self
+    }
+
+    /// Consider `provider` for least-cost routing, priced by `prices`
+    ///
+    /// Among the healthy candidates, the one with the lowest per-segment
+    /// price for the destination wins; ties and candidates without a price
+    /// are ordered by `priority`, lowest first, with priced candidates ahead
+    /// of unpriced ones. A candidate failing with a retryable error is
+    /// skipped for the health cooldown and the message is sent through the
+    /// next one; candidates in cooldown are only tried if all are. Since a
+    /// timed out request may have been accepted, use idempotency keys.
+    #[must_use]
+    pub fn with_least_cost(
+        mut self,
+        provider: Arc<dyn Provider>,
+        prices: PriceTable,
+        priority: u32,
+    ) -> Self {
+        self.candidates.push(Arc::new(Candidate {
+            provider,
+            prices,
+            priority,
+            unhealthy_until: Mutex::new(None),
+        }));
+        self
+    }
+
+    /// Skip failed least-cost candidates for `cooldown` (default [`DEFAULT_HEALTH_COOLDOWN`])
+    #[must_use]
+    pub fn with_health_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Provider that `request` would be sent through first
+    #[must_use]
+    pub fn route(&self, request: &SmsRequest<'_>) -> &Arc<dyn Provider> {
+        match self.resolve(request) {
+            Resolved::Fixed(provider) => provider,
+            Resolved::LeastCost(candidates) => &candidates[0].provider,
+        }
+    }
+
+    fn resolve(&self, request: &SmsRequest<'_>) -> Resolved<'_> {
+        if let Some((_, provider)) = self
+            .overrides
+            .iter()
+            .find(|(predicate, _)| predicate(request))
+        {
+            return Resolved::Fixed(provider);
+        }
+        let digits = phone::digits(&request.number);
+        if let Some((_, provider)) = self
+            .routes
+            .iter()
+            .find(|(prefix, _)| digits.starts_with(prefix.as_str()))
+        {
+            return Resolved::Fixed(provider);
+        }
+        if self.candidates.is_empty() {
+            return Resolved::Fixed(&self.default);
+        }
+
+        let now = Instant::now();
+        let mut ranked: Vec<_> = self
+            .candidates
+            .iter()
+            .map(|candidate| {
+                let price = candidate.prices.price_for(&digits);
+                (candidate, !candidate.is_healthy(now), price)
+            })
+            .collect();
+        ranked.sort_by(|(a, a_unhealthy, a_price), (b, b_unhealthy, b_price)| {
+            a_unhealthy
+                .cmp(b_unhealthy)
+                .then_with(|| match (a_price, b_price) {
+                    (Some(a), Some(b)) => a.total_cmp(b),
+                    (a, b) => b.is_some().cmp(&a.is_some()),
+                })
+                .then_with(|| a.priority.cmp(&b.priority))
+        });
+        Resolved::LeastCost(ranked.into_iter().map(|(c, _, _)| c).collect())
+    }
+}
+
+enum Resolved<'r> {
+    Fixed(&'r Arc<dyn Provider>),
+    /// Candidates in the order to try them
+    LeastCost(Vec<&'r Arc<Candidate>>),
+}
+
+impl Provider for Router {
+    fn name(&self) -> &str {
+        "router"
+    }
+
+    fn send<'a>(&'a self, request: SmsRequest<'a>) -> ProviderFuture<'a, Result<String, SmsError>> {
+        let candidates = match self.resolve(&request) {
+            Resolved::Fixed(provider) => {
+                log::debug!("Routing SMS through {}", provider.name());
+                return provider.send(request);
+            }
+            Resolved::LeastCost(candidates) => candidates,
+        };
+        Box::pin(async move {
+            let (last, rest) = candidates.split_last().expect("candidates are not empty");
+            for candidate in rest {
+                log::debug!("Routing SMS through {}", candidate.provider.name());
+                match candidate.provider.send(request.as_borrowed()).await {
+                    Err(err) if err.is_retryable() => {
+                        log::warn!(
+                            "{} failed, trying the next provider: {err}",
+                            candidate.provider.name()
+                        );
+                        candidate.set_unhealthy_until(Some(Instant::now() + self.cooldown));
+                    }
+                    result => {
+                        candidate.set_unhealthy_until(None);
+                        return result;
+                    }
+                }
+            }
+            log::debug!("Routing SMS through {}", last.provider.name());
+            let result = last.provider.send(request).await;
+            match &result {
+                Err(err) if err.is_retryable() => {
+                    last.set_unhealthy_until(Some(Instant::now() + self.cooldown));
+                }
+                _ => last.set_unhealthy_until(None),
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named(&'static str);
+
+    impl Provider for Named {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn send<'a>(&'a self, _: SmsRequest<'a>) -> ProviderFuture<'a, Result<String, SmsError>> {
+            Box::pin(async move { Ok(self.0.to_string()) })
+        }
+    }
+
+    struct Down;
+
+    impl Provider for Down {
+        fn name(&self) -> &str {
+            "down"
+        }
+
+        fn send<'a>(
+            &'a self,
+            request: SmsRequest<'a>,
+        ) -> ProviderFuture<'a, Result<String, SmsError>> {
+            let number = request.number.to_string();
+            Box::pin(async move { Err(SmsError::Timeout { number }) })
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_by_prefix_and_overrides() {
+        let baltic: Arc<dyn Provider> = Arc::new(Named("baltic"));
+        let router = Router::new(Arc::new(Named("default")))
+            .with_route("+370", baltic.clone())
+            .with_route("+371", baltic.clone())
+            .with_route("+3", Arc::new(Named("europe")))
+            .with_override(|request| request.sender == "Bank", Arc::new(Named("bank")));
+
+        let send = |sender, number| router.send(SmsRequest::new("k", sender, number, "Hi"));
+        assert_eq!(send("Shop", "+370 612 34567").await.unwrap(), "baltic");
+        assert_eq!(send("Shop", "+37121234567").await.unwrap(), "baltic");
+        assert_eq!(send("Shop", "+33612345678").await.unwrap(), "europe");
+        assert_eq!(send("Shop", "+4915112345678").await.unwrap(), "default");
+        assert_eq!(send("Bank", "+37061234567").await.unwrap(), "bank");
+    }
+
+    #[tokio::test]
+    async fn picks_cheapest_healthy_provider() {
+        let router = Router::new(Arc::new(Named("default")))
+            .with_least_cost(
+                Arc::new(Down),
+                PriceTable::new().with_price("+370", 0.01),
+                0,
+            )
+            .with_least_cost(
+                Arc::new(Named("a")),
+                PriceTable::new().with_price("+370", 0.03),
+                2,
+            )
+            .with_least_cost(
+                Arc::new(Named("b")),
+                PriceTable::new()
+                    .with_price("+370", 0.03)
+                    .with_price("+49", 0.05),
+                1,
+            );
+
+        let send = |number| router.send(SmsRequest::new("k", "Shop", number, "Hi"));
+        let lithuania = SmsRequest::new("k", "Shop", "+37061234567", "Hi");
+        assert_eq!(router.route(&lithuania).name(), "down");
+        // Down times out, a and b tie on price and b has priority
+        assert_eq!(send("+37061234567").await.unwrap(), "b");
+        assert_eq!(router.route(&lithuania).name(), "b");
+        // Only b has a price for +49; without prices, priority decides
+        assert_eq!(send("+4915112345678").await.unwrap(), "b");
+        assert_eq!(send("+33612345678").await.unwrap(), "b");
+    }
+}
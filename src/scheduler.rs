@@ -0,0 +1,601 @@
+use crate::esteria::{SmsClient, SmsError, SmsRequest, SmsRequestOwned};
+use crate::shutdown::Shutdown;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// How late a run may start before it counts as missed
+const MISSED_RUN_GRACE: TimeDelta = TimeDelta::seconds(60);
+
+/// Error types for the scheduler
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    #[error("invalid cron expression '{expression}': {reason}")]
+    InvalidCron { expression: String, reason: String },
+    #[error("cron expression '{0}' has no upcoming runs")]
+    NoUpcomingRun(String),
+}
+
+/// What to do with recurring runs that were missed, e.g. while the process was down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MissedRunPolicy {
+    /// Don't send missed runs, wait for the next one
+    Skip,
+    /// Send once for any number of missed runs
+    #[default]
+    RunOnce,
+    /// Send once for every missed run
+    RunAll,
+}
+
+/// Cron schedule of a recurring message, evaluated in UTC
+///
+/// Accepts standard five-field expressions (`minute hour day month weekday`),
+/// or six and seven fields with leading seconds and trailing year.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Recurrence {
+    expression: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    missed: MissedRunPolicy,
+}
+
+impl Recurrence {
+    /// Parse a cron expression, e.g. `0 9 * * Mon` for every Monday at 09:00
+    ///
+    /// # Errors
+    ///
+    /// Returns `ScheduleError::InvalidCron` if the expression can't be parsed
+    pub fn cron(expression: impl Into<String>) -> Result<Self, ScheduleError> {
+        let expression = expression.into();
+        parse_cron(&expression)?;
+        Ok(Self {
+            expression,
+            missed: MissedRunPolicy::default(),
+        })
+    }
+
+    /// Set the missed-run policy (default [`MissedRunPolicy::RunOnce`])
+    #[must_use]
+    pub fn with_missed_run_policy(mut self, policy: MissedRunPolicy) -> Self {
+        self.missed = policy;
+        self
+    }
+
+    /// Cron expression as given to [`Recurrence::cron`]
+    #[must_use]
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    #[must_use]
+    pub fn missed_run_policy(&self) -> MissedRunPolicy {
+        self.missed
+    }
+
+    /// First run strictly after `after`
+    #[must_use]
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        parse_cron(&self.expression).ok()?.after(&after).next()
+    }
+}
+
+fn parse_cron(expression: &str) -> Result<cron::Schedule, ScheduleError> {
+    let parsed = if expression.split_whitespace().count() == 5 {
+        cron::Schedule::from_str(&format!("0 {expression}"))
+    } else {
+        cron::Schedule::from_str(expression)
+    };
+    parsed.map_err(|err| ScheduleError::InvalidCron {
+        expression: expression.to_string(),
+        reason: err.to_string(),
+    })
+}
+
+/// Message held by the [`Scheduler`] until it is due
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScheduledMessage {
+    pub id: u64,
+    /// Time of the next run
+    pub at: DateTime<Utc>,
+    pub request: SmsRequestOwned,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub recurrence: Option<Recurrence>,
+}
+
+/// Callbacks for persisting the schedule, e.g. to a database
+///
+/// Store messages in [`ScheduleHook::on_scheduled`], remove them in the other
+/// callbacks, and pass the stored ones to [`Scheduler::restore`] on startup.
+pub trait ScheduleHook: Send + Sync {
+    /// A message was added to the schedule, or a recurring message was
+    /// rescheduled to its next run
+    fn on_scheduled(&self, _message: &ScheduledMessage) {}
+    /// A message was removed with [`Scheduler::cancel`]
+    fn on_cancelled(&self, _id: u64) {}
+    /// A message was sent, or failed to send
+    ///
+    /// Recurring messages have already been rescheduled at this point.
+    fn on_dispatched(&self, _id: u64, _result: &Result<String, SmsError>) {}
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    request: SmsRequestOwned,
+    recurrence: Option<Recurrence>,
+}
+
+type Queue = BTreeMap<(DateTime<Utc>, u64), Entry>;
+
+struct Inner {
+    client: Arc<SmsClient>,
+    queue: Mutex<Queue>,
+    next_id: AtomicU64,
+    notify: Notify,
+    hook: Option<Arc<dyn ScheduleHook>>,
+    shutdown: Shutdown,
+}
+
+/// In-process scheduler that sends messages at a given time
+///
+/// Unlike the gateway `time` parameter, messages are held locally, so there
+/// is no limit on how far ahead they can be scheduled, but they are only sent
+/// while [`Scheduler::run`] is being polled. Clones share the same schedule.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+/// Handle to a scheduled message
+#[derive(Clone)]
+pub struct ScheduleHandle {
+    id: u64,
+    scheduler: Scheduler,
+}
+
+impl ScheduleHandle {
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Remove the message from the schedule, stopping a recurring message
+    ///
+    /// Returns whether the message was still scheduled.
+    pub fn cancel(&self) -> bool {
+        self.scheduler.cancel(self.id)
+    }
+}
+
+impl Scheduler {
+    /// Create a scheduler sending through `client`
+    #[must_use]
+    pub fn new(client: Arc<SmsClient>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                client,
+                queue: Mutex::new(BTreeMap::new()),
+                next_id: AtomicU64::new(1),
+                notify: Notify::new(),
+                hook: None,
+                shutdown: Shutdown::default(),
+            }),
+        }
+    }
+
+    /// Register persistence callbacks
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler has already been cloned
+    #[must_use]
+    pub fn with_hook(mut self, hook: Arc<dyn ScheduleHook>) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("hook must be set before the scheduler is shared")
+            .hook = Some(hook);
+        self
+    }
+
+    fn queue(&self) -> MutexGuard<'_, Queue> {
+        self.inner
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn handle(&self, id: u64) -> ScheduleHandle {
+        ScheduleHandle {
+            id,
+            scheduler: self.clone(),
+        }
+    }
+
+    /// Schedule a message to be sent at `at`
+    ///
+    /// Messages scheduled in the past are sent immediately.
+    pub fn schedule(&self, request: SmsRequest<'_>, at: DateTime<Utc>) -> ScheduleHandle {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.insert(ScheduledMessage {
+            id,
+            at,
+            request: request.into_owned(),
+            recurrence: None,
+        });
+        self.handle(id)
+    }
+
+    /// Schedule a message to be sent on every run of `recurrence`
+    ///
+    /// # Errors
+    ///
+    /// Returns `ScheduleError::NoUpcomingRun` if the schedule never runs again
+    pub fn schedule_recurring(
+        &self,
+        request: SmsRequest<'_>,
+        recurrence: Recurrence,
+    ) -> Result<ScheduleHandle, ScheduleError> {
+        let at = recurrence
+            .next_after(Utc::now())
+            .ok_or_else(|| ScheduleError::NoUpcomingRun(recurrence.expression.clone()))?;
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        self.insert(ScheduledMessage {
+            id,
+            at,
+            request: request.into_owned(),
+            recurrence: Some(recurrence),
+        });
+        Ok(self.handle(id))
+    }
+
+    /// Re-add a persisted message, keeping its ID
+    ///
+    /// Runs of a recurring message that passed while it was not scheduled are
+    /// handled according to its [`MissedRunPolicy`].
+    pub fn restore(&self, message: ScheduledMessage) -> ScheduleHandle {
+        self.inner
+            .next_id
+            .fetch_max(message.id + 1, Ordering::Relaxed);
+        self.queue().insert(
+            (message.at, message.id),
+            Entry {
+                request: message.request,
+                recurrence: message.recurrence,
+            },
+        );
+        self.inner.notify.notify_one();
+        self.handle(message.id)
+    }
+
+    fn insert(&self, message: ScheduledMessage) {
+        if let Some(hook) = &self.inner.hook {
+            hook.on_scheduled(&message);
+        }
+        self.queue().insert(
+            (message.at, message.id),
+            Entry {
+                request: message.request,
+                recurrence: message.recurrence,
+            },
+        );
+        self.inner.notify.notify_one();
+    }
+
+    /// Remove a message from the schedule, returning whether it was pending
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut queue = self.queue();
+        let Some(key) = queue.keys().find(|(_, key_id)| *key_id == id).copied() else {
+            return false;
+        };
+        queue.remove(&key);
+        drop(queue);
+
+        if let Some(hook) = &self.inner.hook {
+            hook.on_cancelled(id);
+        }
+        true
+    }
+
+    /// Messages waiting to be sent, earliest first
+    #[must_use]
+    pub fn pending(&self) -> Vec<ScheduledMessage> {
+        self.queue()
+            .iter()
+            .map(|((at, id), entry)| ScheduledMessage {
+                id: *id,
+                at: *at,
+                request: entry.request.clone(),
+                recurrence: entry.recurrence.clone(),
+            })
+            .collect()
+    }
+
+    fn pop_due(&self, now: DateTime<Utc>) -> Option<ScheduledMessage> {
+        let mut queue = self.queue();
+        let entry = queue.first_entry().filter(|entry| entry.key().0 <= now)?;
+        let (at, id) = *entry.key();
+        let entry = entry.remove();
+        Some(ScheduledMessage {
+            id,
+            at,
+            request: entry.request,
+            recurrence: entry.recurrence,
+        })
+    }
+
+    fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.queue().keys().next().map(|(at, _)| *at)
+    }
+
+    /// Send messages as they become due, until [`Scheduler::shutdown`]
+    pub async fn run(&self) {
+        let shutdown = &self.inner.shutdown;
+        let _worker = shutdown.enter();
+        loop {
+            let notified = self.inner.notify.notified();
+
+            while !shutdown.is_closed()
+                && let Some(message) = self.pop_due(Utc::now())
+            {
+                self.run_due(message).await;
+            }
+            if shutdown.is_closed() {
+                return;
+            }
+
+            match self.next_due() {
+                Some(at) => {
+                    let delay = (at - Utc::now()).to_std().unwrap_or_default();
+                    tokio::select! {
+                        () = tokio::time::sleep(delay) => {}
+                        () = notified => {}
+                        () = shutdown.closed() => {}
+                    }
+                }
+                None => tokio::select! {
+                    () = notified => {}
+                    () = shutdown.closed() => {}
+                },
+            }
+        }
+    }
+
+    /// Stop sending and wait for messages being sent to finish
+    ///
+    /// Once this resolves, [`Scheduler::run`] has returned on every clone.
+    /// Messages that were not due yet stay in [`Scheduler::pending`] and were
+    /// passed to the hook, so they can be restored after a restart. Messages
+    /// scheduled after shutdown are kept the same way but never sent.
+    pub async fn shutdown(&self) {
+        self.inner.shutdown.close().await;
+    }
+
+    async fn run_due(&self, message: ScheduledMessage) {
+        let Some(recurrence) = &message.recurrence else {
+            self.dispatch(message.id, message.request).await;
+            return;
+        };
+
+        // Reschedule before sending, so the message can be cancelled meanwhile
+        let now = Utc::now();
+        let missed = now - message.at > MISSED_RUN_GRACE;
+        let from = match recurrence.missed {
+            MissedRunPolicy::RunAll => message.at,
+            MissedRunPolicy::Skip | MissedRunPolicy::RunOnce => now,
+        };
+        match recurrence.next_after(from) {
+            Some(at) => self.insert(ScheduledMessage {
+                at,
+                ..message.clone()
+            }),
+            None => log::info!("Recurring message {} has no more runs", message.id),
+        }
+
+        if missed && recurrence.missed == MissedRunPolicy::Skip {
+            log::warn!(
+                "Skipped missed run of message {} at {}",
+                message.id,
+                message.at
+            );
+        } else {
+            self.dispatch(message.id, message.request).await;
+        }
+    }
+
+    async fn dispatch(&self, id: u64, request: SmsRequestOwned) {
+        let result = self.inner.client.send_sms(request).await;
+        match &result {
+            Ok(message_id) => log::info!("Scheduled message {id} sent as {message_id}"),
+            Err(err) => log::error!("Scheduled message {id} failed: {err}"),
+        }
+        if let Some(hook) = &self.inner.hook {
+            hook.on_dispatched(id, &result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use httpmock::prelude::*;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Recorder {
+        fn events(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl ScheduleHook for Recorder {
+        fn on_scheduled(&self, message: &ScheduledMessage) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("scheduled {}", message.id));
+        }
+        fn on_cancelled(&self, id: u64) {
+            self.0.lock().unwrap().push(format!("cancelled {id}"));
+        }
+        fn on_dispatched(&self, id: u64, result: &Result<String, SmsError>) {
+            let message_id = result.as_deref().unwrap_or("error");
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("dispatched {id} {message_id}"));
+        }
+    }
+
+    async fn run_until(scheduler: &Scheduler, done: impl Fn() -> bool) {
+        let worker = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.run().await }
+        });
+        for _ in 0..100 {
+            if done() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        worker.abort();
+    }
+
+    #[tokio::test]
+    async fn sends_due_messages_and_skips_cancelled() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send").query_param("text", "Later");
+            then.status(200).body("1001");
+        });
+
+        let recorder = Arc::new(Recorder::default());
+        let client = Arc::new(SmsClient::with_api_base_url(server.base_url()));
+        let scheduler = Scheduler::new(client).with_hook(recorder.clone());
+
+        let soon = Utc::now() + TimeDelta::milliseconds(50);
+        let first = scheduler.schedule(SmsRequest::new("k", "A", "+37061234567", "Later"), soon);
+        let second = scheduler.schedule(SmsRequest::new("k", "A", "+37061234567", "Never"), soon);
+        assert!(second.cancel());
+        assert!(!second.cancel());
+        assert_eq!(scheduler.pending().len(), 1);
+
+        run_until(&scheduler, || recorder.events().len() == 4).await;
+
+        assert_eq!(
+            recorder.events(),
+            vec![
+                format!("scheduled {}", first.id()),
+                format!("scheduled {}", second.id()),
+                format!("cancelled {}", second.id()),
+                format!("dispatched {} 1001", first.id()),
+            ]
+        );
+        assert!(scheduler.pending().is_empty());
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_worker_and_keeps_pending() {
+        let scheduler = Scheduler::new(Arc::new(SmsClient::new()));
+        let later = Utc::now() + TimeDelta::hours(1);
+        scheduler.schedule(SmsRequest::new("k", "A", "+37061234567", "Later"), later);
+
+        let worker = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.run().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        tokio::time::timeout(Duration::from_secs(1), scheduler.shutdown())
+            .await
+            .unwrap();
+        worker.await.unwrap();
+        assert_eq!(scheduler.pending().len(), 1);
+    }
+
+    #[test]
+    fn restore_keeps_ids_unique() {
+        let scheduler = Scheduler::new(Arc::new(SmsClient::new()));
+        scheduler.restore(ScheduledMessage {
+            id: 41,
+            at: Utc::now(),
+            request: SmsRequest::new("k", "A", "+37061234567", "Hi").into_owned(),
+            recurrence: None,
+        });
+        let handle =
+            scheduler.schedule(SmsRequest::new("k", "A", "+37061234567", "Hi"), Utc::now());
+        assert_eq!(handle.id(), 42);
+        assert_eq!(scheduler.pending().len(), 2);
+    }
+
+    #[test]
+    fn parses_cron_expressions() {
+        let weekly = Recurrence::cron("0 9 * * Mon").unwrap();
+        let wednesday = Utc.with_ymd_and_hms(2024, 1, 3, 12, 0, 0).unwrap();
+        assert_eq!(
+            weekly.next_after(wednesday),
+            Some(Utc.with_ymd_and_hms(2024, 1, 8, 9, 0, 0).unwrap())
+        );
+
+        let with_seconds = Recurrence::cron("30 0 9 * * *").unwrap();
+        assert_eq!(
+            with_seconds.next_after(wednesday),
+            Some(Utc.with_ymd_and_hms(2024, 1, 4, 9, 0, 30).unwrap())
+        );
+
+        assert!(matches!(
+            Recurrence::cron("every monday"),
+            Err(ScheduleError::InvalidCron { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn applies_missed_run_policy() {
+        let server = MockServer::start();
+        let once = server.mock(|when, then| {
+            when.method(GET).path("/send").query_param("text", "Once");
+            then.status(200).body("1001");
+        });
+        let skipped = server.mock(|when, then| {
+            when.method(GET).path("/send").query_param("text", "Skip");
+            then.status(200).body("1002");
+        });
+
+        let client = Arc::new(SmsClient::with_api_base_url(server.base_url()));
+        let scheduler = Scheduler::new(client);
+        let hourly = Recurrence::cron("0 * * * *").unwrap();
+        let restore = |id, text: &str, policy| {
+            scheduler.restore(ScheduledMessage {
+                id,
+                at: Utc::now() - TimeDelta::hours(3),
+                request: SmsRequest::new("k", "A", "+37061234567", text).into_owned(),
+                recurrence: Some(hourly.clone().with_missed_run_policy(policy)),
+            })
+        };
+        let once_handle = restore(1, "Once", MissedRunPolicy::RunOnce);
+        restore(2, "Skip", MissedRunPolicy::Skip);
+
+        run_until(&scheduler, || {
+            scheduler.pending().iter().all(|m| m.at > Utc::now()) && once.calls() == 1
+        })
+        .await;
+
+        once.assert_calls(1);
+        skipped.assert_calls(0);
+        assert_eq!(scheduler.pending().len(), 2);
+        assert!(once_handle.cancel());
+        assert_eq!(scheduler.pending().len(), 1);
+    }
+}
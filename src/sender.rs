@@ -0,0 +1,92 @@
+use thiserror::Error;
+
+/// Maximum length of an alphanumeric sender ID
+pub const MAX_ALPHANUMERIC_LEN: usize = 11;
+/// Maximum number of digits of a numeric sender ID
+pub const MAX_NUMERIC_LEN: usize = 15;
+
+/// Punctuation accepted in alphanumeric sender IDs besides letters and digits
+const ALLOWED_PUNCTUATION: &str = " .-_&+'!?#@/:()";
+
+/// Error types for sender ID validation
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SenderError {
+    #[error("sender is empty")]
+    Empty,
+    #[error("invalid character '{0}' in sender")]
+    InvalidCharacter(char),
+    #[error("alphanumeric sender too long ({0} characters, at most {MAX_ALPHANUMERIC_LEN})")]
+    AlphanumericTooLong(usize),
+    #[error("numeric sender too long ({0} digits, at most {MAX_NUMERIC_LEN})")]
+    NumericTooLong(usize),
+}
+
+/// Check that a sender ID is accepted by operators
+///
+/// Senders without letters are numeric (optionally with a leading `+`) and may
+/// have up to 15 digits. Other senders are alphanumeric: up to 11 ASCII
+/// letters, digits, spaces or common punctuation.
+///
+/// # Errors
+///
+/// Returns the `SenderError` describing the first problem found.
+pub fn validate(sender: &str) -> Result<(), SenderError> {
+    if sender.is_empty() {
+        return Err(SenderError::Empty);
+    }
+
+    if !sender.chars().any(|c| c.is_ascii_alphabetic()) {
+        let digits = sender.strip_prefix('+').unwrap_or(sender);
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(SenderError::InvalidCharacter(c));
+        }
+        if digits.is_empty() {
+            return Err(SenderError::Empty);
+        }
+        if digits.len() > MAX_NUMERIC_LEN {
+            return Err(SenderError::NumericTooLong(digits.len()));
+        }
+        return Ok(());
+    }
+
+    if let Some(c) = sender
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !ALLOWED_PUNCTUATION.contains(*c))
+    {
+        return Err(SenderError::InvalidCharacter(c));
+    }
+
+    let len = sender.chars().count();
+    if len > MAX_ALPHANUMERIC_LEN {
+        return Err(SenderError::AlphanumericTooLong(len));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_senders() {
+        assert_eq!(validate("Alice"), Ok(()));
+        assert_eq!(validate("Shop & Co."), Ok(()));
+        assert_eq!(validate("+37061234567"), Ok(()));
+        assert_eq!(validate("123456789012345"), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_senders() {
+        assert_eq!(validate(""), Err(SenderError::Empty));
+        assert_eq!(
+            validate("VeryLongSender"),
+            Err(SenderError::AlphanumericTooLong(14))
+        );
+        assert_eq!(
+            validate("1234567890123456"),
+            Err(SenderError::NumericTooLong(16))
+        );
+        assert_eq!(validate("Ąžuolas"), Err(SenderError::InvalidCharacter('Ą')));
+        assert_eq!(validate("123 456"), Err(SenderError::InvalidCharacter(' ')));
+    }
+}
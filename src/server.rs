@@ -0,0 +1,576 @@
+use crate::delivery::{DLR_USER_KEY_PARAM, DeliveryRecord, DeliveryStatus, DeliveryStore};
+use crate::esteria::{SendResult, SmsClient, SmsError, SmsRequestOwned};
+use crate::signing::DlrUrlSigner;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{CONTENT_TYPE, HeaderValue};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use utoipa::{OpenApi, ToResponse, ToSchema};
+
+/// Largest accepted request body
+pub const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Delay before accepting again after the first accept error
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Longest delay between attempts while accepting keeps failing
+pub const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// OpenAPI 3 document of the REST API, served at `GET /openapi.json`
+///
+/// Schemas are derived from the request and result types, so they follow
+/// their serde representation, for generating client SDKs against the proxy.
+#[must_use]
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+/// REST proxy in front of an [`SmsClient`], for services that can't embed the client
+///
+/// - `POST /messages` sends an [`SmsRequestOwned`] given as JSON, answering
+///   `201` with a [`SendResult`]. The API key may be left out when the
+///   client has a credentials provider.
+/// - `GET /messages/{id}` returns the tracked [`crate::delivery::DeliveryRecord`].
+/// - `GET /dlr?id=...&status=...` records a delivery report, so it can be
+///   used as the `dlr_url` of sent messages. An optional `reason` (or
+///   `error`) parameter is kept for undelivered messages. With
+///   [`Server::with_dlr_signer`], reports need a valid token, and the
+///   signed `user_key` must be the one the message was tracked with, so a
+///   token can't be reused to report another message.
+/// - `GET /balance` answers `501`, as the gateway has no balance endpoint.
+/// - `GET /openapi.json` returns the [`openapi`] document.
+///
+/// Errors are returned as `{"error": {"kind": ..., "message": ...}}`.
+pub struct Server<S> {
+    client: Arc<SmsClient>,
+    store: Arc<S>,
+    dlr_signer: Option<Arc<DlrUrlSigner>>,
+}
+
+impl<S> Clone for Server<S> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            store: self.store.clone(),
+            dlr_signer: self.dlr_signer.clone(),
+        }
+    }
+}
+
+type HttpResponse = Response<Full<Bytes>>;
+
+fn respond(status: StatusCode, body: &serde_json::Value) -> HttpResponse {
+    let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+/// Body of every error answer
+#[derive(serde::Serialize, ToSchema, ToResponse)]
+#[response(description = "Request failed")]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(serde::Serialize, ToSchema)]
+struct ErrorBody {
+    /// E.g. `send_failed`, `invalid_number`, `opted_out`, `timeout`,
+    /// `invalid_body` or `not_found`
+    kind: String,
+    /// Recipient, redacted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<String>,
+    message: String,
+}
+
+fn error(status: StatusCode, kind: &str, message: impl std::fmt::Display) -> HttpResponse {
+    let body = ErrorResponse {
+        error: ErrorBody {
+            kind: kind.to_string(),
+            number: None,
+            message: message.to_string(),
+        },
+    };
+    respond(status, &json!(body))
+}
+
+/// HTTP status reported for a failed send
+fn send_error_status(err: &SmsError) -> StatusCode {
+    match err {
+        SmsError::InvalidNumber { .. } | SmsError::InvalidSender { .. } | SmsError::Template(_) => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        SmsError::OptedOut { .. } | SmsError::Blocked { .. } => StatusCode::FORBIDDEN,
+        SmsError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+        SmsError::Credentials(_) | SmsError::Cancelled { .. } => StatusCode::SERVICE_UNAVAILABLE,
+        SmsError::InProgress { .. } | SmsError::Uncertain { .. } => StatusCode::CONFLICT,
+        SmsError::SendFailed { .. }
+        | SmsError::RequestFailed(_)
+        | SmsError::HttpStatus { .. }
+        | SmsError::UnknownResponse { .. }
+        | SmsError::Tls { .. } => StatusCode::BAD_GATEWAY,
+    }
+}
+
+fn query(request: &Request<Incoming>) -> HashMap<String, String> {
+    request
+        .uri()
+        .query()
+        .map(|query| {
+            form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl<S: DeliveryStore + 'static> Server<S> {
+    /// Serve `client`, tracking sent messages in `store`
+    pub fn new(client: Arc<SmsClient>, store: Arc<S>) -> Self {
+        Self {
+            client,
+            store,
+            dlr_signer: None,
+        }
+    }
+
+    /// Reject delivery reports whose URL lacks a valid `signer` token
+    #[must_use]
+    pub fn with_dlr_signer(mut self, signer: DlrUrlSigner) -> Self {
+        self.dlr_signer = Some(Arc::new(signer));
+        self
+    }
+
+    async fn send(&self, request: Request<Incoming>) -> HttpResponse {
+        let body = match Limited::new(request.into_body(), MAX_BODY_BYTES)
+            .collect()
+            .await
+        {
+            Ok(body) => body.to_bytes(),
+            Err(err) => return error(StatusCode::PAYLOAD_TOO_LARGE, "invalid_body", err),
+        };
+        let sms: SmsRequestOwned = match serde_json::from_slice(&body) {
+            Ok(sms) => sms,
+            Err(err) => return error(StatusCode::BAD_REQUEST, "invalid_body", err),
+        };
+
+        match self
+            .client
+            .send_tracked(sms.as_borrowed(), &*self.store)
+            .await
+        {
+            Ok(message_id) => respond(
+                StatusCode::CREATED,
+                &json!(SendResult {
+                    message_id: message_id.trim().to_string(),
+                    number: sms.number.into_owned(),
+                }),
+            ),
+            Err(err) => respond(send_error_status(&err), &json!({ "error": err })),
+        }
+    }
+
+    async fn status(&self, message_id: &str) -> HttpResponse {
+        match self.store.get(message_id).await {
+            Ok(Some(record)) => respond(StatusCode::OK, &json!(record)),
+            Ok(None) => error(StatusCode::NOT_FOUND, "not_found", "message is not tracked"),
+            Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, "store", err),
+        }
+    }
+
+    async fn delivery_report(&self, request: &Request<Incoming>) -> HttpResponse {
+        if let Some(signer) = &self.dlr_signer {
+            let url = reqwest::Url::parse("http://localhost")
+                .and_then(|base| base.join(&request.uri().to_string()));
+            if !url.is_ok_and(|url| signer.verify(&url)) {
+                return error(StatusCode::FORBIDDEN, "invalid_token", "invalid DLR token");
+            }
+        }
+        let params = query(request);
+        let (Some(message_id), Some(status)) = (params.get("id"), params.get("status")) else {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "invalid_query",
+                "id and status are required",
+            );
+        };
+        if self.dlr_signer.is_some() {
+            match self.store.get(message_id).await {
+                Ok(Some(record)) if record.user_key.as_ref() != params.get(DLR_USER_KEY_PARAM) => {
+                    return error(
+                        StatusCode::FORBIDDEN,
+                        "invalid_token",
+                        "DLR token was issued for another message",
+                    );
+                }
+                Ok(_) => {}
+                Err(err) => return error(StatusCode::INTERNAL_SERVER_ERROR, "store", err),
+            }
+        }
+        let reason = params.get("reason").or_else(|| params.get("error"));
+        let status = DeliveryStatus::from_report(status, reason.map(String::as_str));
+        match self
+            .client
+            .record_delivery(&*self.store, message_id, &status)
+            .await
+        {
+            Ok(Some(record)) => respond(StatusCode::OK, &json!(record)),
+            Ok(None) => error(StatusCode::NOT_FOUND, "not_found", "message is not tracked"),
+            Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, "store", err),
+        }
+    }
+
+    async fn handle(self, request: Request<Incoming>) -> HttpResponse {
+        let path = request.uri().path().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (request.method(), segments.as_slice()) {
+            (&Method::POST, ["messages"]) => self.send(request).await,
+            (&Method::GET, ["messages", message_id]) => self.status(message_id).await,
+            (&Method::GET, ["dlr"]) => self.delivery_report(&request).await,
+            (&Method::GET, ["openapi.json"]) => respond(StatusCode::OK, &json!(openapi())),
+            (&Method::GET, ["balance"]) => error(
+                StatusCode::NOT_IMPLEMENTED,
+                "unsupported",
+                "the gateway has no balance endpoint",
+            ),
+            (_, ["messages" | "dlr" | "balance" | "openapi.json"] | ["messages", _]) => error(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "method_not_allowed",
+                "method not allowed",
+            ),
+            _ => error(StatusCode::NOT_FOUND, "not_found", "no such endpoint"),
+        }
+    }
+
+    /// Accept connections on `listener` until `shutdown` completes
+    ///
+    /// Connections already accepted are served to completion in the background.
+    /// Accept errors, such as running out of file descriptors, are logged and
+    /// retried with a backoff of up to [`MAX_ACCEPT_BACKOFF`].
+    pub async fn serve(self, listener: TcpListener, shutdown: impl Future<Output = ()>) {
+        let mut shutdown = pin!(shutdown);
+        let mut backoff = MIN_ACCEPT_BACKOFF;
+        loop {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                () = &mut shutdown => return,
+            };
+            let (stream, peer) = match accepted {
+                Ok(accepted) => {
+                    backoff = MIN_ACCEPT_BACKOFF;
+                    accepted
+                }
+                Err(err) => {
+                    log::warn!("Accepting a connection failed, retrying in {backoff:?}: {err}");
+                    tokio::select! {
+                        () = tokio::time::sleep(backoff) => {}
+                        () = &mut shutdown => return,
+                    }
+                    backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+                    continue;
+                }
+            };
+            let server = self.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, std::convert::Infallible>(server.handle(request).await) }
+                });
+                if let Err(err) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    log::warn!("Connection from {peer} failed: {err}");
+                }
+            });
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Esteria SMS proxy",
+        description = "REST API of `esteria serve`, sending SMS through the Esteria gateway."
+    ),
+    paths(send_sms, get_message, record_delivery, get_balance),
+    components(schemas(ErrorBody), responses(ErrorResponse))
+)]
+struct ApiDoc;
+
+// Operations of `Server::handle`, described for `ApiDoc`
+
+/// Send one SMS
+#[utoipa::path(
+    post,
+    path = "/messages",
+    operation_id = "sendSms",
+    request_body = SmsRequestOwned,
+    responses(
+        (status = 201, description = "Accepted by the gateway", body = SendResult),
+        (status = 400, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 409, response = ErrorResponse),
+        (status = 413, response = ErrorResponse),
+        (status = 422, response = ErrorResponse),
+        (status = 502, response = ErrorResponse),
+        (status = 503, response = ErrorResponse),
+        (status = 504, response = ErrorResponse),
+    )
+)]
+#[allow(dead_code)]
+fn send_sms() {}
+
+/// Delivery status of a sent message
+#[utoipa::path(
+    get,
+    path = "/messages/{id}",
+    operation_id = "getMessage",
+    params(("id" = String, Path, description = "Gateway message ID")),
+    responses(
+        (status = 200, description = "Tracked message", body = DeliveryRecord),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+#[allow(dead_code)]
+fn get_message() {}
+
+/// Delivery report callback, for use as the `dlr_url`
+#[utoipa::path(
+    get,
+    path = "/dlr",
+    operation_id = "recordDelivery",
+    params(
+        ("id" = String, Query, description = "Gateway message ID"),
+        ("status" = String, Query, description = "Reported status"),
+        ("reason" = Option<String>, Query, description = "Why the message was undelivered; `error` is accepted too"),
+    ),
+    responses(
+        (status = 200, description = "Updated message", body = DeliveryRecord),
+        (status = 400, response = ErrorResponse),
+        (status = 403, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+#[allow(dead_code)]
+fn record_delivery() {}
+
+/// Not supported: the gateway has no balance endpoint
+#[utoipa::path(
+    get,
+    path = "/balance",
+    operation_id = "getBalance",
+    responses((status = 501, response = ErrorResponse))
+)]
+#[allow(dead_code)]
+fn get_balance() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::MemoryDeliveryStore;
+    use httpmock::prelude::*;
+
+    async fn start(
+        gateway: &MockServer,
+        configure: impl FnOnce(Server<MemoryDeliveryStore>) -> Server<MemoryDeliveryStore>,
+    ) -> (String, Arc<MemoryDeliveryStore>) {
+        let client = Arc::new(SmsClient::with_api_base_url(gateway.base_url()));
+        let store = Arc::new(MemoryDeliveryStore::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = configure(Server::new(client, store.clone()));
+        tokio::spawn(server.serve(listener, std::future::pending()));
+        (url, store)
+    }
+
+    #[tokio::test]
+    async fn sends_and_reports_status() {
+        let gateway = MockServer::start();
+        gateway.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37060000000");
+            then.status(200).body("1001");
+        });
+        let (url, _store) = start(&gateway, |server| server).await;
+        let http = reqwest::Client::new();
+
+        let sent = http
+            .post(format!("{url}/messages"))
+            .body(r#"{"sender": "Shop", "number": "+37060000000", "text": "Hi"}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(sent.status(), 201);
+        let body: serde_json::Value = sent.json().await.unwrap();
+        assert_eq!(body["message_id"], "1001");
+
+        let report = http
+            .get(format!("{url}/dlr?id=1001&status=delivered"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(report.status(), 200);
+
+        let status: serde_json::Value = http
+            .get(format!("{url}/messages/1001"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(status["status"], "delivered");
+
+        let report: serde_json::Value = http
+            .get(format!("{url}/dlr?id=1001&status=UNDELIV&reason=013"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(report["status"]["undelivered"]["reason"], "013");
+
+        let missing = http.get(format!("{url}/messages/9")).send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn maps_errors_to_statuses() {
+        let gateway = MockServer::start();
+        gateway.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("7");
+        });
+        let (url, _store) = start(&gateway, |server| server).await;
+        let http = reqwest::Client::new();
+
+        let rejected = http
+            .post(format!("{url}/messages"))
+            .body(r#"{"sender": "Shop", "number": "+37060000000", "text": "Hi"}"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(rejected.status(), 502);
+        let body: serde_json::Value = rejected.json().await.unwrap();
+        assert_eq!(body["error"]["kind"], "send_failed");
+
+        let invalid = http
+            .post(format!("{url}/messages"))
+            .body("not json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(invalid.status(), 400);
+
+        let balance = http.get(format!("{url}/balance")).send().await.unwrap();
+        assert_eq!(balance.status(), 501);
+
+        let openapi = http
+            .get(format!("{url}/openapi.json"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(openapi.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsigned_delivery_reports() {
+        let gateway = MockServer::start();
+        let signer = DlrUrlSigner::new("secret").with_unsigned_params(["status"]);
+        let (url, store) = start(&gateway, |server| server.with_dlr_signer(signer.clone())).await;
+        store.save("1001", None).await.unwrap();
+        let http = reqwest::Client::new();
+
+        let forged = http
+            .get(format!("{url}/dlr?id=1001&status=delivered"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(forged.status(), 403);
+
+        let signed = signer.sign(&reqwest::Url::parse(&format!("{url}/dlr?id=1001")).unwrap());
+        let report = http
+            .get(format!("{signed}&status=delivered"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(report.status(), 200);
+
+        // A token signed for order-7 can't report the message of order-8
+        let signer = signer.with_unsigned_params(["id", "status"]);
+        let (url, store) = start(&gateway, |server| server.with_dlr_signer(signer.clone())).await;
+        store.save("1002", Some("order-8")).await.unwrap();
+        let signed =
+            signer.sign(&reqwest::Url::parse(&format!("{url}/dlr?user_key=order-7")).unwrap());
+        let reused = http
+            .get(format!("{signed}&id=1002&status=delivered"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(reused.status(), 403);
+        let signed =
+            signer.sign(&reqwest::Url::parse(&format!("{url}/dlr?user_key=order-8")).unwrap());
+        let report = http
+            .get(format!("{signed}&id=1002&status=delivered"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(report.status(), 200);
+    }
+
+    /// `$ref` targets anywhere in `value`
+    fn refs<'a>(value: &'a serde_json::Value, out: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                if let Some(target) = map.get("$ref").and_then(|target| target.as_str()) {
+                    out.push(target);
+                }
+                map.values().for_each(|value| refs(value, out));
+            }
+            serde_json::Value::Array(values) => values.iter().for_each(|value| refs(value, out)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn openapi_describes_types() {
+        let document = json!(openapi());
+        assert_eq!(document["info"]["version"], env!("CARGO_PKG_VERSION"));
+
+        let mut targets = Vec::new();
+        refs(&document, &mut targets);
+        for target in targets {
+            let path = target.strip_prefix("#/").unwrap().split('/');
+            let resolved = path.fold(&document, |value, segment| &value[segment]);
+            assert!(resolved.is_object(), "dangling reference {target}");
+        }
+
+        let schemas = &document["components"]["schemas"];
+        let request = &schemas["SmsRequest"];
+        assert_eq!(request["required"], json!(["sender", "number", "text"]));
+        // Flags serialize by name, e.g. "FLASH | TEST"
+        assert_eq!(request["properties"]["flags"]["type"], "string");
+        assert_eq!(
+            document["paths"]["/messages"]["post"]["responses"]["201"]["content"]["application/json"]
+                ["schema"]["$ref"],
+            "#/components/schemas/SendResult"
+        );
+        assert!(schemas["DeliveryStatus"]["oneOf"].is_array());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::esteria::{SmsClient, SmsError, SmsRequestOwned};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Sends requests with [`SmsClient::send_sms`], responding with the message ID
+///
+/// Implemented for `Arc<SmsClient>` so the service is cheap to clone, as
+/// tower middleware such as retry and buffering requires. The client has no
+/// back-pressure of its own, so the service is always ready.
+impl Service<SmsRequestOwned> for Arc<SmsClient> {
+    type Response = String;
+    type Error = SmsError;
+    type Future = Pin<Box<dyn Future<Output = Result<String, SmsError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), SmsError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: SmsRequestOwned) -> Self::Future {
+        let client = Arc::clone(self);
+        Box::pin(async move { client.send_sms(request).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esteria::SmsRequest;
+    use httpmock::prelude::*;
+
+    #[tokio::test]
+    async fn service_sends_requests() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1001");
+        });
+
+        let mut service = Arc::new(SmsClient::with_api_base_url(server.base_url()));
+        std::future::poll_fn(|cx| service.poll_ready(cx))
+            .await
+            .unwrap();
+        let id = service
+            .call(SmsRequest::new("k", "Alice", "+37061234567", "Hi").into_owned())
+            .await
+            .unwrap();
+        assert_eq!(id, "1001");
+        m.assert();
+    }
+}
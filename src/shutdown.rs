@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+/// Shutdown state shared by a background worker and its handles
+///
+/// Workers hold a [`WorkerGuard`] while running; [`Shutdown::close`] resolves
+/// once every guard has been dropped.
+#[derive(Debug, Default)]
+pub(crate) struct Shutdown {
+    closed: AtomicBool,
+    workers: AtomicUsize,
+    wake: Notify,
+    stopped: Notify,
+}
+
+/// Marks a worker as running until dropped
+pub(crate) struct WorkerGuard<'a>(&'a Shutdown);
+
+impl Shutdown {
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once shutdown has started
+    pub(crate) async fn closed(&self) {
+        let wake = self.wake.notified();
+        if self.is_closed() {
+            return;
+        }
+        wake.await;
+    }
+
+    pub(crate) fn enter(&self) -> WorkerGuard<'_> {
+        self.workers.fetch_add(1, Ordering::SeqCst);
+        WorkerGuard(self)
+    }
+
+    /// Start shutting down and wait until no worker is running
+    pub(crate) async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.wake.notify_waiters();
+        loop {
+            let stopped = self.stopped.notified();
+            if self.workers.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            stopped.await;
+        }
+    }
+}
+
+impl Drop for WorkerGuard<'_> {
+    fn drop(&mut self) {
+        self.0.workers.fetch_sub(1, Ordering::SeqCst);
+        self.0.stopped.notify_waiters();
+    }
+}
@@ -0,0 +1,293 @@
+use crate::delivery::percent_encode;
+use crate::sms;
+use crate::trace_context::TRACEPARENT;
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Request, Url};
+use sha2::{Digest, Sha256};
+
+/// Query parameter the signature is sent in by default
+pub const DEFAULT_SIGNATURE_PARAM: &str = "signature";
+
+/// Query parameter carrying the token of a signed DLR URL by default
+pub const DEFAULT_DLR_TOKEN_PARAM: &str = "token";
+
+/// Where a request signature is attached
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignaturePlacement {
+    /// A query parameter with this name
+    Query(String),
+    /// A request header with this name
+    Header(HeaderName),
+}
+
+/// HMAC-SHA256 signer for gateway requests
+///
+/// The signature covers the canonical form of the query parameters: sorted
+/// by name, percent-encoded as `name=value` and joined with `&`. It is sent
+/// as lowercase hex, by default in the `signature` query parameter.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RequestSigner {
+    secret: Vec<u8>,
+    placement: SignaturePlacement,
+}
+
+impl RequestSigner {
+    /// Sign with a shared secret
+    #[must_use]
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            placement: SignaturePlacement::Query(DEFAULT_SIGNATURE_PARAM.to_string()),
+        }
+    }
+
+    /// Attach the signature somewhere other than the `signature` query parameter
+    #[must_use]
+    pub fn with_placement(mut self, placement: SignaturePlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Where the signature is attached
+    #[must_use]
+    pub fn placement(&self) -> &SignaturePlacement {
+        &self.placement
+    }
+
+    /// Signature of a set of parameters, as lowercase hex
+    ///
+    /// A parameter named like the signature query parameter is left out.
+    #[must_use]
+    pub fn sign<'a, I>(&self, params: I) -> String
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        hex_mac(&self.secret, &self.canonicalize(params))
+    }
+
+    /// Check a signature over `params`, e.g. of an incoming callback
+    #[must_use]
+    pub fn verify<'a, I>(&self, params: I, signature: &str) -> bool
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        constant_time_eq(&self.sign(params), signature)
+    }
+
+    /// Sign the query parameters of `request` and attach the signature
+    ///
+    /// `secret` overrides the signer's own, e.g. with a rotated one.
+    pub(crate) fn attach(&self, request: &mut Request, secret: Option<&[u8]>) {
+        let params: Vec<(String, String)> = request.url().query_pairs().into_owned().collect();
+        let canonical = self.canonicalize(params.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        let secret = secret.unwrap_or(&self.secret);
+        let signature = hex_mac(secret, &canonical);
+        match &self.placement {
+            SignaturePlacement::Query(name) => {
+                request
+                    .url_mut()
+                    .query_pairs_mut()
+                    .append_pair(name, &signature);
+            }
+            SignaturePlacement::Header(name) => {
+                let value = HeaderValue::from_str(&signature).expect("hex is a valid header value");
+                request.headers_mut().insert(name.clone(), value);
+            }
+        }
+    }
+
+    fn canonicalize<'a, I>(&self, params: I) -> String
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        let skip = match &self.placement {
+            SignaturePlacement::Query(name) => Some(name.as_str()),
+            SignaturePlacement::Header(_) => None,
+        };
+        canonicalize(params, |name| Some(name) != skip)
+    }
+}
+
+/// `Debug` output omits the secret
+impl std::fmt::Debug for RequestSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestSigner")
+            .field("placement", &self.placement)
+            .finish_non_exhaustive()
+    }
+}
+
+/// HMAC tokens for DLR callback URLs, rejecting spoofed delivery reports
+///
+/// [`DlrUrlSigner::sign`] appends a `token` parameter to a `dlr_url` at send
+/// time, covering its path and query; [`DlrUrlSigner::verify`] checks the
+/// URL of the incoming callback. Parameters the gateway fills in or adds
+/// (placeholders like the status) must be declared
+/// [unsigned](DlrUrlSigner::with_unsigned_params); `traceparent` always is.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DlrUrlSigner {
+    secret: Vec<u8>,
+    param: String,
+    unsigned: Vec<String>,
+}
+
+impl DlrUrlSigner {
+    /// Sign with a secret known only to us
+    #[must_use]
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        Self {
+            secret: secret.into(),
+            param: DEFAULT_DLR_TOKEN_PARAM.to_string(),
+            unsigned: vec![TRACEPARENT.to_string()],
+        }
+    }
+
+    /// Carry the token in a parameter other than `token`
+    #[must_use]
+    pub fn with_param(mut self, param: impl Into<String>) -> Self {
+        self.param = param.into();
+        self
+    }
+
+    /// Leave parameters filled in or added by the gateway out of the token
+    #[must_use]
+    pub fn with_unsigned_params<I, S>(mut self, params: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.unsigned.extend(params.into_iter().map(Into::into));
+        self
+    }
+
+    /// `url` with the token parameter appended
+    #[must_use]
+    pub fn sign(&self, url: &Url) -> Url {
+        let mut signed = url.clone();
+        signed
+            .query_pairs_mut()
+            .append_pair(&self.param, &self.token(url));
+        signed
+    }
+
+    /// Check the token of an incoming callback URL
+    ///
+    /// Only the path and query are covered, so the host may differ, e.g.
+    /// behind a reverse proxy.
+    #[must_use]
+    pub fn verify(&self, url: &Url) -> bool {
+        url.query_pairs()
+            .find(|(name, _)| *name == self.param.as_str())
+            .is_some_and(|(_, token)| constant_time_eq(&self.token(url), &token))
+    }
+
+    fn token(&self, url: &Url) -> String {
+        let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        let query = canonicalize(
+            params.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+            |name| name != self.param && !self.unsigned.iter().any(|unsigned| unsigned == name),
+        );
+        hex_mac(&self.secret, &format!("{}?{query}", url.path()))
+    }
+}
+
+/// `Debug` output omits the secret
+impl std::fmt::Debug for DlrUrlSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DlrUrlSigner")
+            .field("param", &self.param)
+            .field("unsigned", &self.unsigned)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sorted `name=value` pairs joined with `&`, keeping the names `include` accepts
+fn canonicalize<'a, I>(params: I, include: impl Fn(&str) -> bool) -> String
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut pairs: Vec<_> = params
+        .into_iter()
+        .filter(|(name, _)| include(name))
+        .map(|(name, value)| (percent_encode(name), percent_encode(value)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Compare a lowercase hex signature without leaking where it differs
+fn constant_time_eq(expected: &str, signature: &str) -> bool {
+    expected.len() == signature.len()
+        && expected
+            .bytes()
+            .zip(signature.to_ascii_lowercase().bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn hex_mac(secret: &[u8], canonical: &str) -> String {
+    sms::to_hex(&hmac_sha256(secret, canonical.as_bytes())).to_ascii_lowercase()
+}
+
+/// SHA-256 digest
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// HMAC-SHA256 (RFC 2104)
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_canonical_parameters() {
+        let signer = RequestSigner::new("secret");
+        let signature = signer.sign([("text", "Hi there"), ("sender", "Alice")]);
+
+        // Order doesn't matter and the signature parameter itself is skipped
+        assert!(signer.verify(
+            [
+                ("sender", "Alice"),
+                ("signature", "ignored"),
+                ("text", "Hi there")
+            ],
+            &signature.to_ascii_uppercase()
+        ));
+        assert!(!signer.verify([("sender", "Alice"), ("text", "Hi")], &signature));
+        assert_eq!(
+            signer.canonicalize([("text", "Hi there"), ("sender", "A&B")]),
+            "sender=A%26B&text=Hi%20there"
+        );
+    }
+
+    #[test]
+    fn verifies_signed_dlr_urls() {
+        let signer = DlrUrlSigner::new("secret").with_unsigned_params(["status"]);
+        let url = Url::parse("https://example.com/dlr?id=42&status=%d").unwrap();
+        let signed = signer.sign(&url);
+        assert!(signed.as_str().contains("&token="));
+
+        // The gateway fills in the status and the callback arrives via a proxy
+        let callback = signed
+            .as_str()
+            .replace("status=%25d", "status=1")
+            .replace("example.com", "internal:8080");
+        assert!(signer.verify(&Url::parse(&callback).unwrap()));
+
+        let forged = callback.replace("id=42", "id=43");
+        assert!(!signer.verify(&Url::parse(&forged).unwrap()));
+        assert!(!signer.verify(&url));
+        assert!(!DlrUrlSigner::new("other").verify(&signed));
+    }
+}
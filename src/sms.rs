@@ -0,0 +1,319 @@
+use crate::esteria::Encoding;
+
+/// GSM 03.38 basic character set
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+/// GSM 03.38 extension table characters, sent as escape + character
+const GSM7_EXTENSION: &str = "\u{000C}^{}\\[~]|€";
+
+/// Size of the user data of a single SMS in bytes
+const USER_DATA_LEN: usize = 140;
+
+/// Size of a concatenation User Data Header with an 8-bit reference in bytes
+const CONCAT_UDH_LEN: usize = 6;
+
+/// Character set a message is transmitted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// GSM 03.38 7-bit default alphabet (160 septets, 153 when concatenated)
+    Gsm7,
+    /// 8-bit data (140 bytes, 134 when concatenated)
+    EightBit,
+    /// UCS-2 / UTF-16 (70 code units, 67 when concatenated)
+    Ucs2,
+}
+
+impl Charset {
+    /// Capacity of a single, non-concatenated message in units of this charset
+    #[must_use]
+    pub fn single_capacity(self) -> usize {
+        self.capacity_with_udh(0)
+    }
+
+    /// Capacity of each part of a concatenated message in units of this charset
+    #[must_use]
+    pub fn multipart_capacity(self) -> usize {
+        self.capacity_with_udh(CONCAT_UDH_LEN)
+    }
+
+    /// Capacity of a message carrying a User Data Header of `udh_len` bytes
+    #[must_use]
+    pub fn capacity_with_udh(self, udh_len: usize) -> usize {
+        let bytes = USER_DATA_LEN.saturating_sub(udh_len);
+        match self {
+            Self::Gsm7 => bytes * 8 / 7,
+            Self::EightBit => bytes,
+            Self::Ucs2 => bytes / 2,
+        }
+    }
+
+    /// Number of units a character takes in this charset
+    #[must_use]
+    pub fn char_units(self, c: char) -> usize {
+        match self {
+            Self::Gsm7 if GSM7_EXTENSION.contains(c) => 2,
+            Self::Gsm7 => 1,
+            Self::EightBit => c.len_utf8(),
+            Self::Ucs2 => c.len_utf16(),
+        }
+    }
+}
+
+/// Segment calculation result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// Character set the text will be sent in
+    pub charset: Charset,
+    /// Whether the whole text can be represented in GSM-7
+    pub fits_gsm7: bool,
+    /// Message length in units of `charset` (septets, bytes or UTF-16 code units)
+    pub units: usize,
+    /// Number of SMS segments the message takes
+    pub segments: usize,
+    /// Capacity of each segment in units of `charset`
+    pub per_segment: usize,
+    /// Units still available in the last segment
+    pub remaining: usize,
+    /// Distinct characters outside GSM-7, in order of first appearance
+    pub non_gsm7: Vec<char>,
+}
+
+/// Check whether a character is part of the GSM-7 alphabet (including extensions)
+#[must_use]
+pub fn is_gsm7_char(c: char) -> bool {
+    GSM7_BASIC.contains(c) || GSM7_EXTENSION.contains(c)
+}
+
+/// Check whether a text can be sent using GSM-7
+#[must_use]
+pub fn is_gsm7(text: &str) -> bool {
+    text.chars().all(is_gsm7_char)
+}
+
+/// Charset a text is sent in for the given encoding
+///
+/// With the default and automatic encodings, texts containing characters
+/// outside GSM-7 require UCS-2.
+#[must_use]
+pub fn charset_for(text: &str, encoding: Encoding) -> Charset {
+    match encoding {
+        Encoding::Default | Encoding::Auto if is_gsm7(text) => Charset::Gsm7,
+        Encoding::Default | Encoding::Auto | Encoding::Ucs2 => Charset::Ucs2,
+        Encoding::EightBit | Encoding::Udh => Charset::EightBit,
+    }
+}
+
+/// Calculate how many segments a text takes with the given encoding
+#[must_use]
+pub fn segments(text: &str, encoding: Encoding) -> SegmentInfo {
+    let charset = charset_for(text, encoding);
+
+    let mut non_gsm7 = Vec::new();
+    for c in text.chars() {
+        if !is_gsm7_char(c) && !non_gsm7.contains(&c) {
+            non_gsm7.push(c);
+        }
+    }
+
+    let units: usize = text.chars().map(|c| charset.char_units(c)).sum();
+
+    let (segments, per_segment, used_in_last) = if units <= charset.single_capacity() {
+        (1, charset.single_capacity(), units)
+    } else {
+        let parts = pack(text, charset, charset.multipart_capacity());
+        let (_, last) = parts.last().copied().unwrap_or_default();
+        (parts.len(), charset.multipart_capacity(), last)
+    };
+
+    SegmentInfo {
+        charset,
+        fits_gsm7: non_gsm7.is_empty(),
+        units,
+        segments,
+        per_segment,
+        remaining: per_segment - used_in_last,
+        non_gsm7,
+    }
+}
+
+/// Calculate how many segments a binary payload of `bytes` bytes takes
+#[must_use]
+pub fn binary_segments(bytes: usize) -> SegmentInfo {
+    let charset = Charset::EightBit;
+    let (segments, per_segment) = if bytes <= charset.single_capacity() {
+        (1, charset.single_capacity())
+    } else {
+        let per_segment = charset.multipart_capacity();
+        (bytes.div_ceil(per_segment), per_segment)
+    };
+    SegmentInfo {
+        charset,
+        fits_gsm7: false,
+        units: bytes,
+        segments,
+        per_segment,
+        remaining: segments * per_segment - bytes,
+        non_gsm7: Vec::new(),
+    }
+}
+
+/// Split a text into the parts of a concatenated message
+///
+/// Texts that fit a single segment are returned as one part. Characters, GSM-7
+/// escape sequences and UTF-16 surrogate pairs are never split across parts.
+#[must_use]
+pub fn split(text: &str, encoding: Encoding) -> Vec<&str> {
+    let charset = charset_for(text, encoding);
+    let units: usize = text.chars().map(|c| charset.char_units(c)).sum();
+
+    if units <= charset.single_capacity() {
+        return vec![text];
+    }
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (end, _) in pack(text, charset, charset.multipart_capacity()) {
+        parts.push(&text[start..end]);
+        start = end;
+    }
+    parts
+}
+
+/// Encode bytes as an uppercase hex string
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut out, b| {
+            let _ = write!(out, "{b:02X}");
+            out
+        })
+}
+
+/// Pack characters into parts of at most `capacity` units without splitting a
+/// character, returning the end byte offset and units used of each part
+fn pack(text: &str, charset: Charset, capacity: usize) -> Vec<(usize, usize)> {
+    let mut parts = vec![(0, 0)];
+
+    for (offset, c) in text.char_indices() {
+        let units = charset.char_units(c);
+        let end = offset + c.len_utf8();
+        let current = parts.last_mut().expect("parts is never empty");
+        if current.1 + units > capacity {
+            parts.push((end, units));
+        } else {
+            *current = (end, current.1 + units);
+        }
+    }
+
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_binary_payload_bytes() {
+        assert_eq!(binary_segments(140).segments, 1);
+        let info = binary_segments(141);
+        assert_eq!(
+            (info.segments, info.per_segment, info.remaining),
+            (2, 134, 127)
+        );
+    }
+
+    #[test]
+    fn gsm7_detection() {
+        assert!(is_gsm7("Hello @ 10€ [ok]"));
+        assert!(!is_gsm7("Labas, ąžuolas"));
+        assert!(!is_gsm7("🙂"));
+    }
+
+    #[test]
+    fn gsm7_segment_boundaries() {
+        let info = segments(&"a".repeat(160), Encoding::Default);
+        assert_eq!(
+            (info.charset, info.segments, info.remaining),
+            (Charset::Gsm7, 1, 0)
+        );
+
+        let info = segments(&"a".repeat(161), Encoding::Default);
+        assert_eq!(
+            (info.segments, info.per_segment, info.remaining),
+            (2, 153, 145)
+        );
+
+        // Extension characters take two septets
+        let info = segments(&"€".repeat(80), Encoding::Default);
+        assert_eq!((info.units, info.segments), (160, 1));
+    }
+
+    #[test]
+    fn escape_sequences_are_not_split_across_segments() {
+        // 152 septets followed by an extension character: it moves to the next part
+        let text = format!("{}€{}", "a".repeat(152), "a".repeat(10));
+        let info = segments(&text, Encoding::Default);
+        assert_eq!(info.units, 164);
+        assert_eq!(info.segments, 2);
+        assert_eq!(info.remaining, 153 - 12);
+    }
+
+    #[test]
+    fn non_gsm7_text_uses_ucs2() {
+        let info = segments("Ačiū 🙂 ačiū", Encoding::Default);
+        assert_eq!(info.charset, Charset::Ucs2);
+        assert!(!info.fits_gsm7);
+        assert_eq!(info.non_gsm7, vec!['č', 'ū', '🙂']);
+        assert_eq!(info.units, 12);
+
+        let info = segments(&"ž".repeat(71), Encoding::Default);
+        assert_eq!((info.segments, info.per_segment), (2, 67));
+    }
+
+    #[test]
+    fn ucs2_applies_to_gsm7_text_too() {
+        let info = segments(&"a".repeat(71), Encoding::Ucs2);
+        assert_eq!(info.charset, Charset::Ucs2);
+        assert!(info.fits_gsm7);
+        assert_eq!(info.segments, 2);
+    }
+
+    #[test]
+    fn eight_bit_counts_bytes() {
+        let info = segments(&"a".repeat(141), Encoding::EightBit);
+        assert_eq!(info.charset, Charset::EightBit);
+        assert_eq!((info.units, info.segments), (141, 2));
+    }
+
+    #[test]
+    fn split_respects_segment_capacity() {
+        assert_eq!(split("short", Encoding::Default), vec!["short"]);
+
+        let text = format!("{}€{}", "a".repeat(152), "b".repeat(200));
+        let parts = split(&text, Encoding::Default);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], "a".repeat(152));
+        assert_eq!(parts.concat(), text);
+        for part in &parts {
+            assert!(segments(part, Encoding::Default).units <= 153);
+        }
+
+        let emoji = "🙂".repeat(40);
+        let parts = split(&emoji, Encoding::Default);
+        assert_eq!(
+            parts.iter().map(|p| p.chars().count()).collect::<Vec<_>>(),
+            vec![33, 7]
+        );
+    }
+
+    #[test]
+    fn capacity_shrinks_with_udh() {
+        assert_eq!(Charset::Gsm7.capacity_with_udh(7), 152);
+        assert_eq!(Charset::EightBit.capacity_with_udh(7), 133);
+        assert_eq!(Charset::Ucs2.capacity_with_udh(12), 64);
+        assert_eq!(to_hex(&[0x0A, 0xFF]), "0AFF");
+    }
+}
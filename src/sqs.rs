@@ -0,0 +1,268 @@
+use crate::worker::{Delivery, JobQueue, JobResult, WorkerError};
+use aws_sdk_sqs::Client;
+use aws_sdk_sqs::error::DisplayErrorContext;
+use aws_sdk_sqs::types::MessageAttributeValue;
+use std::time::Duration;
+
+/// Message attribute naming the queue URL a job's result is sent to
+pub const REPLY_TO_ATTRIBUTE: &str = "ReplyTo";
+
+/// Longest long-poll wait SQS allows, in seconds
+const MAX_WAIT_SECS: i32 = 20;
+
+/// [`JobQueue`] consuming an Amazon SQS queue through `aws-sdk-sqs`
+///
+/// Jobs are received with a fixed visibility timeout, which
+/// [`SmsClient::run_worker`](crate::SmsClient::run_worker) extends while a
+/// slow send is in progress. Handled jobs are deleted; nacked ones are made
+/// visible again at once. A job's ID is its SQS message ID and its
+/// `receipt` the receipt handle. Results are sent to the queue URL in the
+/// job's `ReplyTo` string attribute, if any.
+///
+/// SQS delivers at least once, so enable idempotency on the client
+/// ([`crate::SmsClientBuilder::with_idempotency`]) to not send a redelivered
+/// job twice.
+#[derive(Debug, Clone)]
+pub struct SqsQueue {
+    client: Client,
+    queue_url: String,
+    visibility_timeout: Duration,
+}
+
+impl SqsQueue {
+    /// Consume the queue at `queue_url`, hiding each received job for
+    /// `visibility_timeout` (whole seconds, at most 12 hours)
+    #[must_use]
+    pub fn new(client: Client, queue_url: impl Into<String>, visibility_timeout: Duration) -> Self {
+        Self {
+            client,
+            queue_url: queue_url.into(),
+            visibility_timeout,
+        }
+    }
+
+    fn visibility_secs(&self) -> i32 {
+        i32::try_from(self.visibility_timeout.as_secs()).unwrap_or(i32::MAX)
+    }
+
+    async fn change_visibility(&self, delivery: &Delivery, secs: i32) -> Result<(), WorkerError> {
+        self.client
+            .change_message_visibility()
+            .queue_url(&self.queue_url)
+            .receipt_handle(receipt_handle(delivery)?)
+            .visibility_timeout(secs)
+            .send()
+            .await
+            .map_err(queue_error)?;
+        Ok(())
+    }
+}
+
+fn queue_error(err: impl std::error::Error) -> WorkerError {
+    WorkerError::Queue(DisplayErrorContext(err).to_string())
+}
+
+fn receipt_handle(delivery: &Delivery) -> Result<&str, WorkerError> {
+    delivery
+        .receipt
+        .as_deref()
+        .ok_or_else(|| WorkerError::Queue(format!("job {} has no receipt handle", delivery.id)))
+}
+
+impl JobQueue for SqsQueue {
+    /// Long-poll until a job arrives; SQS queues don't close
+    async fn receive(&self) -> Result<Option<Delivery>, WorkerError> {
+        loop {
+            let output = self
+                .client
+                .receive_message()
+                .queue_url(&self.queue_url)
+                .max_number_of_messages(1)
+                .wait_time_seconds(MAX_WAIT_SECS)
+                .visibility_timeout(self.visibility_secs())
+                .message_attribute_names(REPLY_TO_ATTRIBUTE)
+                .send()
+                .await
+                .map_err(queue_error)?;
+            let Some(message) = output
+                .messages
+                .and_then(|messages| messages.into_iter().next())
+            else {
+                continue;
+            };
+
+            let (Some(id), Some(receipt)) = (message.message_id(), message.receipt_handle()) else {
+                return Err(WorkerError::Queue(
+                    "SQS returned a message without an ID or receipt handle".to_string(),
+                ));
+            };
+            let mut delivery =
+                Delivery::new(id, message.body().unwrap_or_default()).with_receipt(receipt);
+            delivery.reply_to = message
+                .message_attributes()
+                .and_then(|attributes| attributes.get(REPLY_TO_ATTRIBUTE))
+                .and_then(MessageAttributeValue::string_value)
+                .map(str::to_string);
+            return Ok(Some(delivery));
+        }
+    }
+
+    async fn ack(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+        self.client
+            .delete_message()
+            .queue_url(&self.queue_url)
+            .receipt_handle(receipt_handle(delivery)?)
+            .send()
+            .await
+            .map_err(queue_error)?;
+        Ok(())
+    }
+
+    async fn nack(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+        self.change_visibility(delivery, 0).await
+    }
+
+    /// Send `result` to the job's `ReplyTo` queue; without one it is dropped
+    async fn reply(&self, delivery: &Delivery, result: &JobResult) -> Result<(), WorkerError> {
+        let Some(reply_to) = &delivery.reply_to else {
+            return Ok(());
+        };
+        let body = String::from_utf8(result.to_json()).expect("JSON is UTF-8");
+        self.client
+            .send_message()
+            .queue_url(reply_to)
+            .message_body(body)
+            .send()
+            .await
+            .map_err(queue_error)?;
+        Ok(())
+    }
+
+    fn visibility_timeout(&self) -> Option<Duration> {
+        Some(self.visibility_timeout)
+    }
+
+    async fn extend(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+        self.change_visibility(delivery, self.visibility_secs())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worker::contract;
+    use aws_sdk_sqs::config::{BehaviorVersion, Credentials, Region};
+    use httpmock::prelude::*;
+
+    fn client(endpoint: &str) -> Client {
+        let config = aws_sdk_sqs::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("eu-north-1"))
+            .credentials_provider(Credentials::new("key", "secret", None, None, "test"))
+            .endpoint_url(endpoint)
+            .build();
+        Client::from_conf(config)
+    }
+
+    fn action(name: &str) -> String {
+        format!("AmazonSQS.{name}")
+    }
+
+    #[tokio::test]
+    async fn maps_jobs_to_sqs_calls() {
+        let server = MockServer::start();
+        let queue_url = format!("{}/000000000000/jobs", server.base_url());
+        let receive = server.mock(|when, then| {
+            when.method(POST)
+                .header("x-amz-target", action("ReceiveMessage"))
+                .json_body_includes(r#"{"VisibilityTimeout": 30, "MaxNumberOfMessages": 1}"#);
+            then.status(200)
+                .header("content-type", "application/x-amz-json-1.0")
+                .json_body(serde_json::json!({"Messages": [{
+                    "MessageId": "job-1",
+                    "ReceiptHandle": "handle-1",
+                    "Body": "{}",
+                    "MessageAttributes": {
+                        "ReplyTo": {"DataType": "String", "StringValue": "https://sqs/replies"}
+                    }
+                }]}));
+        });
+        let delete = server.mock(|when, then| {
+            when.method(POST)
+                .header("x-amz-target", action("DeleteMessage"))
+                .json_body_includes(r#"{"ReceiptHandle": "handle-1"}"#);
+            then.status(200)
+                .header("content-type", "application/x-amz-json-1.0")
+                .body("{}");
+        });
+        let hide = |secs: i32| {
+            server.mock(move |when, then| {
+                when.method(POST)
+                    .header("x-amz-target", action("ChangeMessageVisibility"))
+                    .json_body_includes(format!(
+                        r#"{{"ReceiptHandle": "handle-1", "VisibilityTimeout": {secs}}}"#
+                    ));
+                then.status(200)
+                    .header("content-type", "application/x-amz-json-1.0")
+                    .body("{}");
+            })
+        };
+        let (release, extend) = (hide(0), hide(30));
+
+        let queue = SqsQueue::new(
+            client(&server.base_url()),
+            queue_url,
+            Duration::from_secs(30),
+        );
+        assert_eq!(queue.visibility_timeout(), Some(Duration::from_secs(30)));
+        let delivery = queue.receive().await.unwrap().unwrap();
+        assert_eq!(delivery.id, "job-1");
+        assert_eq!(delivery.payload, b"{}");
+        assert_eq!(delivery.receipt.as_deref(), Some("handle-1"));
+        assert_eq!(delivery.reply_to.as_deref(), Some("https://sqs/replies"));
+        receive.assert();
+
+        queue.extend(&delivery).await.unwrap();
+        extend.assert();
+        queue.nack(&delivery).await.unwrap();
+        release.assert();
+        queue.ack(&delivery).await.unwrap();
+        delete.assert();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an SQS endpoint, e.g. ElasticMQ, at ESTERIA_TEST_SQS_ENDPOINT"]
+    async fn follows_the_job_queue_contract() {
+        let endpoint = std::env::var("ESTERIA_TEST_SQS_ENDPOINT")
+            .unwrap_or_else(|_| "http://127.0.0.1:9324".to_string());
+        let client = client(&endpoint);
+        let queue_url = client
+            .create_queue()
+            .queue_name(format!("esteria-test-{}", std::process::id()))
+            .send()
+            .await
+            .unwrap()
+            .queue_url
+            .unwrap();
+
+        let payload = r#"{"sender": "Shop", "number": "+37060000000", "text": "Hi"}"#;
+        client
+            .send_message()
+            .queue_url(&queue_url)
+            .message_body(payload)
+            .send()
+            .await
+            .unwrap();
+        let queue = SqsQueue::new(client.clone(), &queue_url, Duration::from_secs(30));
+        let delivery = contract::check(&queue, payload.as_bytes()).await;
+        assert!(delivery.receipt.is_some());
+
+        client
+            .delete_queue()
+            .queue_url(queue_url)
+            .send()
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use thiserror::Error;
+
+/// Error types for message templates
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unclosed placeholder at position {0}")]
+    Unclosed(usize),
+    #[error("unmatched '}}' at position {0}")]
+    UnmatchedBrace(usize),
+    #[error("invalid placeholder name '{name}' at position {position}")]
+    InvalidName { name: String, position: usize },
+    #[error("unbound placeholders: {}", .0.join(", "))]
+    Unbound(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// Message template with `{name}` placeholders
+///
+/// Literal braces are written as `{{` and `}}`. Placeholder names may contain
+/// ASCII letters, digits, `_`, `-` and `.`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmsTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl SmsTemplate {
+    /// Parse a template
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError` if a placeholder is not closed, a `}` is not
+    /// escaped, or a placeholder name is empty or contains invalid characters
+    pub fn new(source: impl Into<String>) -> Result<Self, TemplateError> {
+        let source = source.into();
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.char_indices().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|(_, c)| *c == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|(_, c)| *c == '}').is_some() => literal.push('}'),
+                '}' => return Err(TemplateError::UnmatchedBrace(position)),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(TemplateError::Unclosed(position)),
+                        }
+                    }
+
+                    if name.is_empty() || !name.chars().all(is_name_char) {
+                        return Err(TemplateError::InvalidName { name, position });
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(name));
+                }
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { source, segments })
+    }
+
+    /// Template source as given to [`SmsTemplate::new`]
+    #[must_use]
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Distinct placeholder names, in order of first appearance
+    #[must_use]
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for segment in &self.segments {
+            if let Segment::Placeholder(name) = segment
+                && !names.contains(&name.as_str())
+            {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Check that every placeholder is bound in `context`
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError::Unbound` listing all missing placeholder names
+    pub fn validate<K, V, S>(&self, context: &HashMap<K, V, S>) -> Result<(), TemplateError>
+    where
+        K: Borrow<str> + Hash + Eq,
+        S: BuildHasher,
+    {
+        let missing: Vec<String> = self
+            .placeholders()
+            .into_iter()
+            .filter(|name| !context.contains_key(*name))
+            .map(str::to_string)
+            .collect();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(TemplateError::Unbound(missing))
+        }
+    }
+
+    /// Render the template with values from `context`
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError::Unbound` listing all missing placeholder names
+    pub fn render<K, V, S>(&self, context: &HashMap<K, V, S>) -> Result<String, TemplateError>
+    where
+        K: Borrow<str> + Hash + Eq,
+        V: AsRef<str>,
+        S: BuildHasher,
+    {
+        self.validate(context)?;
+
+        let mut out = String::with_capacity(self.source.len());
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => out.push_str(text),
+                Segment::Placeholder(name) => {
+                    if let Some(value) = context.get(name.as_str()) {
+                        out.push_str(value.as_ref());
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_placeholders() {
+        let template = SmsTemplate::new("Hi {name}, your code is {code}. Bye {name}!").unwrap();
+        assert_eq!(template.placeholders(), vec!["name", "code"]);
+
+        let context = HashMap::from([("name", "Ona"), ("code", "1234")]);
+        assert_eq!(
+            template.render(&context).unwrap(),
+            "Hi Ona, your code is 1234. Bye Ona!"
+        );
+    }
+
+    #[test]
+    fn escaped_braces_are_literal() {
+        let template = SmsTemplate::new("{{literal}} {x}}}").unwrap();
+        let context = HashMap::from([("x".to_string(), "1".to_string())]);
+        assert_eq!(template.render(&context).unwrap(), "{literal} 1}");
+    }
+
+    #[test]
+    fn reports_all_unbound_placeholders() {
+        let template = SmsTemplate::new("{a} {b} {c}").unwrap();
+        let context = HashMap::from([("b", "2")]);
+        assert_eq!(
+            template.render(&context),
+            Err(TemplateError::Unbound(vec![
+                "a".to_string(),
+                "c".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert_eq!(
+            SmsTemplate::new("Hi {name"),
+            Err(TemplateError::Unclosed(3))
+        );
+        assert_eq!(
+            SmsTemplate::new("a } b"),
+            Err(TemplateError::UnmatchedBrace(2))
+        );
+        assert!(matches!(
+            SmsTemplate::new("{}"),
+            Err(TemplateError::InvalidName { .. })
+        ));
+        assert!(matches!(
+            SmsTemplate::new("{first name}"),
+            Err(TemplateError::InvalidName { .. })
+        ));
+    }
+}
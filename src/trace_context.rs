@@ -0,0 +1,188 @@
+use crate::middleware::Middleware;
+use reqwest::header::HeaderValue;
+use reqwest::{Request, Url};
+use std::fmt;
+use std::sync::Arc;
+
+/// Name of the W3C trace context header and DLR URL parameter
+pub const TRACEPARENT: &str = "traceparent";
+
+/// W3C `traceparent` value identifying the caller's span
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: u128,
+    pub parent_id: u64,
+    pub sampled: bool,
+}
+
+impl TraceParent {
+    /// Parse a version `00` header value, e.g. from an incoming request or a DLR callback
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.trim().split('-');
+        let (Some("00"), Some(trace_id), Some(parent_id), Some(flags), None) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            return None;
+        };
+        if trace_id.len() != 32 || parent_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let parent_id = u64::from_str_radix(parent_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        (trace_id != 0 && parent_id != 0).then_some(Self {
+            trace_id,
+            parent_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+}
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.parent_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+/// Middleware propagating the current trace context to the gateway
+///
+/// `current` returns the caller's active span context, typically taken from
+/// the OpenTelemetry context of the surrounding request. Gateway requests get
+/// a `traceparent` header; with [`TraceContext::with_dlr_propagation`], the
+/// delivery report URL also gets a `traceparent` parameter, so the DLR
+/// callback can be joined to the same trace.
+#[derive(Clone)]
+pub struct TraceContext {
+    current: Arc<dyn Fn() -> Option<TraceParent> + Send + Sync>,
+    propagate_to_dlr: bool,
+}
+
+impl fmt::Debug for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceContext")
+            .field("propagate_to_dlr", &self.propagate_to_dlr)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TraceContext {
+    /// Propagate the context returned by `current`
+    pub fn new(current: impl Fn() -> Option<TraceParent> + Send + Sync + 'static) -> Self {
+        Self {
+            current: Arc::new(current),
+            propagate_to_dlr: false,
+        }
+    }
+
+    /// Also add the context to delivery report URLs
+    #[must_use]
+    pub fn with_dlr_propagation(mut self) -> Self {
+        self.propagate_to_dlr = true;
+        self
+    }
+}
+
+/// Append a `traceparent` parameter to the `dlr-url` query parameter of `url`
+fn propagate_to_dlr_url(url: &mut Url, parent: TraceParent) {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    let mut query = url.query_pairs_mut();
+    query.clear();
+    for (key, value) in pairs {
+        if key == "dlr-url"
+            && let Ok(mut dlr_url) = Url::parse(&value)
+        {
+            dlr_url
+                .query_pairs_mut()
+                .append_pair(TRACEPARENT, &parent.to_string());
+            query.append_pair(&key, dlr_url.as_str());
+        } else {
+            query.append_pair(&key, &value);
+        }
+    }
+}
+
+impl Middleware for TraceContext {
+    fn on_request(&self, request: &mut Request) {
+        let Some(parent) = (self.current)() else {
+            return;
+        };
+        if let Ok(value) = HeaderValue::from_str(&parent.to_string()) {
+            request.headers_mut().insert(TRACEPARENT, value);
+        }
+        if self.propagate_to_dlr {
+            propagate_to_dlr_url(request.url_mut(), parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::esteria::{SmsClient, SmsRequest};
+    use httpmock::prelude::*;
+
+    const HEADER: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parses_and_formats_traceparent() {
+        let parent = TraceParent::parse(HEADER).unwrap();
+        assert_eq!(parent.trace_id, 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736);
+        assert!(parent.sampled);
+        assert_eq!(parent.to_string(), HEADER);
+
+        assert_eq!(
+            TraceParent::parse("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            None
+        );
+        assert_eq!(
+            TraceParent::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01"),
+            None
+        );
+        assert_eq!(TraceParent::parse("00-4bf9-00f067aa0ba902b7-01"), None);
+    }
+
+    #[tokio::test]
+    async fn propagates_to_gateway_and_dlr_url() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .header(TRACEPARENT, HEADER)
+                .query_param(
+                    "dlr-url",
+                    format!("https://example.com/dlr?id=7&{TRACEPARENT}={HEADER}"),
+                );
+            then.status(200).body("1001");
+        });
+
+        let context = TraceContext::new(|| TraceParent::parse(HEADER)).with_dlr_propagation();
+        let client = SmsClient::builder()
+            .with_api_base_url(server.base_url())
+            .with_middleware(Arc::new(context))
+            .build()
+            .unwrap();
+        client
+            .send_sms(
+                SmsRequest::new("k", "Alice", "+37061234567", "Hi")
+                    .with_dlr_url("https://example.com/dlr?id=7"),
+            )
+            .await
+            .unwrap();
+        m.assert();
+    }
+}
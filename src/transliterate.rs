@@ -0,0 +1,188 @@
+use crate::sms;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Built-in single-character replacements, grouped by replacement
+///
+/// Characters that are already part of GSM-7 (e.g. `é`, `ä`, `ß`) are not listed.
+const SIMPLE: &[(&str, char)] = &[
+    ("áâãāăą", 'a'),
+    ("ÀÁÂÃĀĂĄ", 'A'),
+    ("çćĉċč", 'c'),
+    ("ĆĈĊČ", 'C'),
+    ("ďđð", 'd'),
+    ("ĎĐ", 'D'),
+    ("êëēĕėęě", 'e'),
+    ("ÈÊËĒĔĖĘĚ", 'E'),
+    ("ĝğġģ", 'g'),
+    ("ĜĞĠĢ", 'G'),
+    ("ĥħ", 'h'),
+    ("ĤĦ", 'H'),
+    ("íîïĩīĭįı", 'i'),
+    ("ÌÍÎÏĨĪĬĮİ", 'I'),
+    ("ĵ", 'j'),
+    ("Ĵ", 'J'),
+    ("ķ", 'k'),
+    ("Ķ", 'K'),
+    ("ĺļľŀł", 'l'),
+    ("ĹĻĽĿŁ", 'L'),
+    ("ńņňŉ", 'n'),
+    ("ŃŅŇ", 'N'),
+    ("óôõōŏő", 'o'),
+    ("ÒÓÔÕŌŎŐ", 'O'),
+    ("ŕŗř", 'r'),
+    ("ŔŖŘ", 'R'),
+    ("śŝşšș", 's'),
+    ("ŚŜŞŠȘ", 'S'),
+    ("ţťŧț", 't'),
+    ("ŢŤŦȚ", 'T'),
+    ("úûũūŭůűų", 'u'),
+    ("ÙÚÛŨŪŬŮŰŲ", 'U'),
+    ("ŵ", 'w'),
+    ("Ŵ", 'W'),
+    ("ýÿŷ", 'y'),
+    ("ÝŸŶ", 'Y'),
+    ("źżž", 'z'),
+    ("ŹŻŽ", 'Z'),
+    ("‘’‚‛′`´", '\''),
+    ("“”„‟″«»", '"'),
+    ("‐‑‒–—―−", '-'),
+    (
+        "\u{00A0}\u{2002}\u{2003}\u{2007}\u{2009}\u{200A}\u{202F}",
+        ' ',
+    ),
+    ("•", '*'),
+];
+
+/// Built-in multi-character replacements
+const EXPANDED: &[(char, &str)] = &[
+    ('…', "..."),
+    ('œ', "oe"),
+    ('Œ', "OE"),
+    ('ĳ', "ij"),
+    ('Ĳ', "IJ"),
+    ('þ', "th"),
+    ('Þ', "Th"),
+    ('™', "TM"),
+    ('©', "(c)"),
+    ('®', "(R)"),
+];
+
+/// Local replacement of characters outside GSM-7 with GSM-7 lookalikes
+///
+/// Unlike the gateway `CONVERT` flag, the result can be previewed, and the
+/// mapping table can be adjusted with [`Transliterator::with_mapping`].
+#[derive(Debug, Clone)]
+pub struct Transliterator {
+    map: HashMap<char, String>,
+    fallback: Option<String>,
+}
+
+impl Default for Transliterator {
+    fn default() -> Self {
+        let simple = SIMPLE
+            .iter()
+            .flat_map(|(from, to)| from.chars().map(|c| (c, to.to_string())));
+        let expanded = EXPANDED.iter().map(|(from, to)| (*from, (*to).to_string()));
+
+        Self {
+            map: simple.chain(expanded).collect(),
+            fallback: None,
+        }
+    }
+}
+
+impl Transliterator {
+    /// Create a transliterator with the built-in mapping table
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a transliterator without any mappings
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            map: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Add or override the replacement for a character
+    #[must_use]
+    pub fn with_mapping(mut self, from: char, to: impl Into<String>) -> Self {
+        self.map.insert(from, to.into());
+        self
+    }
+
+    /// Remove the replacement for a character, keeping it as is
+    #[must_use]
+    pub fn without_mapping(mut self, from: char) -> Self {
+        self.map.remove(&from);
+        self
+    }
+
+    /// Replace characters that are neither mapped nor GSM-7 with `fallback`
+    ///
+    /// By default such characters are kept, so the message falls back to UCS-2.
+    #[must_use]
+    pub fn with_fallback(mut self, fallback: impl Into<String>) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+
+    /// Transliterate a text, borrowing it if nothing needs to change
+    #[must_use]
+    pub fn transliterate<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        let needs_change = |c: char| {
+            self.map.contains_key(&c) || (self.fallback.is_some() && !sms::is_gsm7_char(c))
+        };
+
+        if !text.chars().any(needs_change) {
+            return Cow::Borrowed(text);
+        }
+
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match (self.map.get(&c), &self.fallback) {
+                (Some(replacement), _) => out.push_str(replacement),
+                (None, Some(fallback)) if !sms::is_gsm7_char(c) => out.push_str(fallback),
+                _ => out.push(c),
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_diacritics_and_punctuation() {
+        let t = Transliterator::new();
+        assert_eq!(t.transliterate("Ačiū, ąžuolas!"), "Aciu, azuolas!");
+        assert_eq!(t.transliterate("“Grüße” – Łódź…"), "\"Grüße\" - Lodz...");
+        assert!(sms::is_gsm7(&t.transliterate("Rīga, Šiauliai, Kraków")));
+    }
+
+    #[test]
+    fn keeps_gsm7_text_borrowed() {
+        let t = Transliterator::new();
+        assert!(matches!(t.transliterate("Café, 10€"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn mapping_table_is_configurable() {
+        let t = Transliterator::new()
+            .with_mapping('ß', "ss")
+            .without_mapping('ą');
+        assert_eq!(t.transliterate("Straße ą 🙂"), "Strasse ą 🙂");
+
+        let t = Transliterator::new().with_fallback("?");
+        assert_eq!(t.transliterate("ž🙂"), "z?");
+
+        let t = Transliterator::empty();
+        assert_eq!(t.transliterate("ž🙂"), "ž🙂");
+    }
+}
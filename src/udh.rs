@@ -0,0 +1,185 @@
+use crate::sms;
+use std::borrow::Cow;
+
+/// Concatenated short message, 8-bit reference
+pub const IEI_CONCAT_8BIT: u8 = 0x00;
+/// Application port addressing, 8-bit ports
+pub const IEI_PORTS_8BIT: u8 = 0x04;
+/// Application port addressing, 16-bit ports
+pub const IEI_PORTS_16BIT: u8 = 0x05;
+/// Concatenated short message, 16-bit reference
+pub const IEI_CONCAT_16BIT: u8 = 0x08;
+
+/// Maximum size of the user data in a single SMS, in bytes
+const MAX_USER_DATA: usize = 140;
+
+/// A single information element of a User Data Header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InformationElement {
+    /// Information element identifier (IEI)
+    pub id: u8,
+    /// Element data, without the identifier and length bytes
+    pub data: Vec<u8>,
+}
+
+/// User Data Header builder
+///
+/// Serializes to the UDH length byte followed by the information elements, as
+/// defined in 3GPP TS 23.040.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Udh {
+    elements: Vec<InformationElement>,
+}
+
+impl Udh {
+    /// Create an empty header
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a concatenation element with an 8-bit reference (`sequence` is 1-based)
+    #[must_use]
+    pub fn with_concatenation(self, reference: u8, total: u8, sequence: u8) -> Self {
+        self.with_element(IEI_CONCAT_8BIT, [reference, total, sequence])
+    }
+
+    /// Add a concatenation element with a 16-bit reference (`sequence` is 1-based)
+    #[must_use]
+    pub fn with_concatenation_16bit(self, reference: u16, total: u8, sequence: u8) -> Self {
+        let [hi, lo] = reference.to_be_bytes();
+        self.with_element(IEI_CONCAT_16BIT, [hi, lo, total, sequence])
+    }
+
+    /// Add application port addressing with 16-bit ports (e.g. 2948 for WAP Push)
+    #[must_use]
+    pub fn with_ports(self, destination: u16, source: u16) -> Self {
+        let [dh, dl] = destination.to_be_bytes();
+        let [sh, sl] = source.to_be_bytes();
+        self.with_element(IEI_PORTS_16BIT, [dh, dl, sh, sl])
+    }
+
+    /// Add application port addressing with 8-bit ports
+    #[must_use]
+    pub fn with_ports_8bit(self, destination: u8, source: u8) -> Self {
+        self.with_element(IEI_PORTS_8BIT, [destination, source])
+    }
+
+    /// Add an arbitrary information element
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is longer than 255 bytes, which cannot be encoded
+    #[must_use]
+    pub fn with_element(mut self, id: u8, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        assert!(
+            u8::try_from(data.len()).is_ok(),
+            "information element data too long"
+        );
+        self.elements.push(InformationElement { id, data });
+        self
+    }
+
+    /// Information elements in order of insertion
+    #[must_use]
+    pub fn elements(&self) -> &[InformationElement] {
+        &self.elements
+    }
+
+    /// Whether the header has no information elements
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Serialized size in bytes, including the length byte (0 if empty)
+    #[must_use]
+    pub fn len(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        1 + self
+            .elements
+            .iter()
+            .map(|e| 2 + e.data.len())
+            .sum::<usize>()
+    }
+
+    /// Bytes left for the message body in a single SMS carrying this header
+    #[must_use]
+    pub fn remaining_capacity(&self) -> usize {
+        MAX_USER_DATA.saturating_sub(self.len())
+    }
+
+    /// Serialize the header (empty if there are no elements)
+    ///
+    /// # Panics
+    ///
+    /// Panics if the elements exceed the 255 bytes a header length can express
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut bytes = Vec::with_capacity(self.len());
+        bytes.push(u8::try_from(self.len() - 1).expect("user data header too long"));
+        for element in &self.elements {
+            bytes.push(element.id);
+            bytes.push(u8::try_from(element.data.len()).expect("checked in with_element"));
+            bytes.extend_from_slice(&element.data);
+        }
+        bytes
+    }
+
+    /// Serialize the header as an uppercase hex string
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        sms::to_hex(&self.to_bytes())
+    }
+}
+
+impl From<Udh> for Cow<'_, [u8]> {
+    fn from(udh: Udh) -> Self {
+        Cow::Owned(udh.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_standard_elements() {
+        assert_eq!(
+            Udh::new().with_concatenation(0x2A, 3, 2).to_hex(),
+            "0500032A0302"
+        );
+        assert_eq!(
+            Udh::new().with_concatenation_16bit(0x1234, 2, 1).to_hex(),
+            "06080412340201"
+        );
+        assert_eq!(Udh::new().with_ports(2948, 9200).to_hex(), "0605040B8423F0");
+    }
+
+    #[test]
+    fn combines_elements_and_reports_size() {
+        let udh = Udh::new()
+            .with_ports(2948, 9200)
+            .with_concatenation(7, 2, 1)
+            .with_element(0x24, vec![0x01]);
+        assert_eq!(udh.to_hex(), "0E05040B8423F00003070201240101");
+        assert_eq!(udh.len(), 15);
+        assert_eq!(udh.remaining_capacity(), 125);
+        assert_eq!(udh.elements()[2].id, 0x24);
+    }
+
+    #[test]
+    fn empty_header_serializes_to_nothing() {
+        let udh = Udh::new();
+        assert!(udh.is_empty());
+        assert_eq!(udh.len(), 0);
+        assert!(udh.to_bytes().is_empty());
+    }
+}
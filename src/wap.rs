@@ -0,0 +1,146 @@
+use crate::udh::Udh;
+
+/// WAP Push destination port (WSP connectionless push)
+pub const WAP_PUSH_PORT: u16 = 2948;
+/// WAP Push source port
+pub const WAP_PUSH_SOURCE_PORT: u16 = 9200;
+
+/// WSP header: transaction id, Push PDU, headers length, content type
+/// `application/vnd.wap.sic` with `charset=utf-8`
+const WSP_HEADER: [u8; 7] = [0x01, 0x06, 0x04, 0x03, 0xAE, 0x81, 0xEA];
+
+/// WBXML 1.2, SI 1.0 public identifier, UTF-8 charset, empty string table
+const WBXML_HEADER: [u8; 4] = [0x02, 0x05, 0x6A, 0x00];
+
+const TOKEN_SI: u8 = 0x45;
+const TOKEN_INDICATION: u8 = 0xC6;
+const TOKEN_STR_I: u8 = 0x03;
+const TOKEN_END: u8 = 0x01;
+
+/// Attribute start tokens for `href`, longest prefix first
+const HREF_PREFIXES: [(&str, u8); 4] = [
+    ("https://www.", 0x0F),
+    ("https://", 0x0E),
+    ("http://www.", 0x0D),
+    ("http://", 0x0C),
+];
+const TOKEN_HREF: u8 = 0x0B;
+
+/// Service Indication action, controlling how the phone alerts the user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SiAction {
+    /// Store silently
+    SignalNone,
+    /// Low priority alert
+    SignalLow,
+    /// Medium priority alert
+    #[default]
+    SignalMedium,
+    /// High priority alert
+    SignalHigh,
+    /// Delete a previously pushed indication
+    Delete,
+}
+
+impl SiAction {
+    fn token(self) -> u8 {
+        match self {
+            Self::SignalNone => 0x05,
+            Self::SignalLow => 0x06,
+            Self::SignalMedium => 0x07,
+            Self::SignalHigh => 0x08,
+            Self::Delete => 0x09,
+        }
+    }
+}
+
+/// WAP Push Service Indication (a title and a link)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WapPush {
+    title: String,
+    url: String,
+    action: SiAction,
+}
+
+impl WapPush {
+    /// Create a Service Indication for `url`, shown to the user as `title`
+    #[must_use]
+    pub fn new(title: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            url: url.into(),
+            action: SiAction::default(),
+        }
+    }
+
+    /// Set the alert action
+    #[must_use]
+    pub fn with_action(mut self, action: SiAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Encode the WSP push PDU carrying the WBXML Service Indication
+    #[must_use]
+    pub fn payload(&self) -> Vec<u8> {
+        let (href_token, href_rest) = HREF_PREFIXES
+            .iter()
+            .find_map(|(prefix, token)| Some((*token, self.url.strip_prefix(prefix)?)))
+            .unwrap_or((TOKEN_HREF, &self.url));
+
+        let mut payload = Vec::with_capacity(
+            WSP_HEADER.len() + WBXML_HEADER.len() + self.url.len() + self.title.len() + 12,
+        );
+        payload.extend_from_slice(&WSP_HEADER);
+        payload.extend_from_slice(&WBXML_HEADER);
+        payload.extend_from_slice(&[TOKEN_SI, TOKEN_INDICATION, href_token, TOKEN_STR_I]);
+        payload.extend_from_slice(href_rest.as_bytes());
+        payload.extend_from_slice(&[0x00, self.action.token(), TOKEN_END, TOKEN_STR_I]);
+        payload.extend_from_slice(self.title.as_bytes());
+        payload.extend_from_slice(&[0x00, TOKEN_END, TOKEN_END]);
+        payload
+    }
+
+    /// User Data Header addressing the WAP Push port
+    #[must_use]
+    pub fn udh(&self) -> Udh {
+        Udh::new().with_ports(WAP_PUSH_PORT, WAP_PUSH_SOURCE_PORT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sms::to_hex;
+
+    #[test]
+    fn encodes_service_indication() {
+        let push = WapPush::new("Hi", "http://www.example.com/a").with_action(SiAction::SignalHigh);
+        let expected = [
+            "01060403AE81EA",             // WSP
+            "02056A00",                   // WBXML header
+            "45C60D03",                   // si, indication, href="http://www."
+            "6578616D706C652E636F6D2F61", // example.com/a
+            "00080103",                   // action=signal-high, END, STR_I
+            "4869",                       // Hi
+            "000101",                     // END indication, END si
+        ]
+        .concat();
+        assert_eq!(to_hex(&push.payload()), expected);
+    }
+
+    #[test]
+    fn uses_plain_href_for_other_schemes() {
+        let payload = WapPush::new("T", "ftp://x").payload();
+        assert_eq!(&payload[13..17], &[0x0B, 0x03, b'f', b't']);
+        assert_eq!(payload[payload.len() - 7], SiAction::SignalMedium.token());
+    }
+
+    #[test]
+    fn addresses_wap_push_port() {
+        assert_eq!(
+            WapPush::new("T", "http://x").udh().to_hex(),
+            "0605040B8423F0"
+        );
+    }
+}
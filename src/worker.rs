@@ -0,0 +1,456 @@
+use crate::esteria::{SmsClient, SmsRequestOwned};
+use serde_json::json;
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::pin;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+
+/// Error types for queue workers
+#[derive(Error, Debug)]
+pub enum WorkerError {
+    #[error("job queue failed: {0}")]
+    Queue(String),
+}
+
+/// Message received from a job queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    /// Broker message ID, stable across redeliveries
+    pub id: String,
+    /// JSON [`SmsRequestOwned`]
+    pub payload: Vec<u8>,
+    /// Where the result should be published, e.g. an AMQP reply queue
+    pub reply_to: Option<String>,
+    /// Handle of this particular delivery, e.g. an SQS receipt handle
+    pub receipt: Option<String>,
+    /// Identifier the requester matches the reply with, e.g. an AMQP correlation ID
+    pub correlation_id: Option<String>,
+}
+
+impl Delivery {
+    /// Message `id` carrying `payload`, without a reply address
+    pub fn new(id: impl Into<String>, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            id: id.into(),
+            payload: payload.into(),
+            reply_to: None,
+            receipt: None,
+            correlation_id: None,
+        }
+    }
+
+    /// Publish the result to `reply_to`
+    #[must_use]
+    pub fn with_reply_to(mut self, reply_to: impl Into<String>) -> Self {
+        self.reply_to = Some(reply_to.into());
+        self
+    }
+
+    /// Acknowledge through `receipt` rather than the message ID
+    #[must_use]
+    pub fn with_receipt(mut self, receipt: impl Into<String>) -> Self {
+        self.receipt = Some(receipt.into());
+        self
+    }
+
+    /// Reply with `correlation_id`, so the requester can match the result
+    #[must_use]
+    pub fn with_correlation_id(mut self, correlation_id: impl Into<String>) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+}
+
+/// Result of a send job, published as JSON
+///
+/// Holds the message ID on success, or the serialized [`crate::SmsError`]
+/// (kind `invalid_payload` for messages that aren't a valid request).
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct JobResult {
+    pub job_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<serde_json::Value>,
+}
+
+impl JobResult {
+    /// The job was sent
+    pub fn is_success(&self) -> bool {
+        self.message_id.is_some()
+    }
+
+    /// JSON encoding of the result
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("job results serialize to JSON")
+    }
+}
+
+/// Broker holding send jobs, e.g. a RabbitMQ queue
+///
+/// Implemented for RabbitMQ by [`crate::amqp::AmqpQueue`] (feature `amqp`),
+/// NATS by [`crate::nats::NatsQueue`] (feature `nats`) and Amazon SQS by
+/// [`crate::sqs::SqsQueue`] (feature `sqs`); for other brokers, implement
+/// it over their client and run [`SmsClient::run_worker`].
+pub trait JobQueue: Send + Sync {
+    /// Wait for the next message, or `None` once the queue is closed
+    fn receive(&self) -> impl Future<Output = Result<Option<Delivery>, WorkerError>> + Send;
+
+    /// Remove a handled message, whether it was sent or failed permanently
+    fn ack(&self, delivery: &Delivery) -> impl Future<Output = Result<(), WorkerError>> + Send;
+
+    /// Return a message for redelivery after a retryable failure
+    fn nack(&self, delivery: &Delivery) -> impl Future<Output = Result<(), WorkerError>> + Send;
+
+    /// Publish the result of a handled message, typically to its `reply_to`
+    fn reply(
+        &self,
+        delivery: &Delivery,
+        result: &JobResult,
+    ) -> impl Future<Output = Result<(), WorkerError>> + Send;
+
+    /// Whether nacked messages are delivered again (default true)
+    ///
+    /// Without redelivery, as with NATS request/reply, retryable failures
+    /// are replied like permanent ones so the requester isn't left waiting.
+    fn redelivers(&self) -> bool {
+        true
+    }
+
+    /// Time after which an unacknowledged message is delivered again, as
+    /// with SQS visibility timeouts (default none)
+    ///
+    /// While a job is being sent, [`JobQueue::extend`] is called every half
+    /// of this time so that slow sends (e.g. waiting for a rate limit) are
+    /// not redelivered meanwhile.
+    fn visibility_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Postpone the redelivery of a message being handled by another
+    /// [visibility timeout](JobQueue::visibility_timeout)
+    fn extend(&self, _delivery: &Delivery) -> impl Future<Output = Result<(), WorkerError>> + Send {
+        async { Ok(()) }
+    }
+}
+
+impl SmsClient {
+    /// Send the jobs of `queue` until it closes or `shutdown` completes
+    ///
+    /// Jobs are sent one at a time with the client's retries and rate limits.
+    /// A job that still fails with a retryable error is returned to the
+    /// queue if it [redelivers](JobQueue::redelivers); any other outcome is
+    /// replied and acknowledged. The delivery ID is the job's default
+    /// idempotency key, so with [`crate::SmsClientBuilder::with_idempotency`]
+    /// a redelivered job that was already sent is not sent twice. Returns the
+    /// number of jobs sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WorkerError` if the queue fails
+    pub async fn run_worker<Q: JobQueue>(
+        &self,
+        queue: &Q,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<usize, WorkerError> {
+        let mut shutdown = pin!(shutdown);
+        let mut sent = 0;
+        loop {
+            let delivery = tokio::select! {
+                delivery = queue.receive() => delivery?,
+                () = &mut shutdown => return Ok(sent),
+            };
+            let Some(delivery) = delivery else {
+                return Ok(sent);
+            };
+
+            let mut request: SmsRequestOwned = match serde_json::from_slice(&delivery.payload) {
+                Ok(request) => request,
+                Err(err) => {
+                    log::error!("Job {} has an invalid payload: {err}", delivery.id);
+                    let result = JobResult {
+                        job_id: delivery.id.clone(),
+                        number: None,
+                        message_id: None,
+                        error: Some(json!({"kind": "invalid_payload", "message": err.to_string()})),
+                    };
+                    queue.reply(&delivery, &result).await?;
+                    queue.ack(&delivery).await?;
+                    continue;
+                }
+            };
+            if request.idempotency_key.is_none() {
+                request.idempotency_key = Some(Cow::Owned(delivery.id.clone()));
+            }
+
+            let mut result = JobResult {
+                job_id: delivery.id.clone(),
+                number: Some(request.number.to_string()),
+                message_id: None,
+                error: None,
+            };
+            let outcome = match queue.visibility_timeout() {
+                Some(timeout) => {
+                    let mut send = pin!(self.send_sms(request.as_borrowed()));
+                    let period = (timeout / 2).max(Duration::from_millis(1));
+                    let mut extend = tokio::time::interval_at(Instant::now() + period, period);
+                    loop {
+                        tokio::select! {
+                            outcome = &mut send => break outcome,
+                            _ = extend.tick() => {
+                                // Redelivery is harmless thanks to the idempotency key
+                                if let Err(err) = queue.extend(&delivery).await {
+                                    log::warn!("Failed to extend job {}: {err}", delivery.id);
+                                }
+                            }
+                        }
+                    }
+                }
+                None => self.send_sms(request.as_borrowed()).await,
+            };
+            match outcome {
+                Ok(message_id) => {
+                    result.message_id = Some(message_id.trim().to_string());
+                    sent += 1;
+                }
+                Err(err) if err.is_retryable() && queue.redelivers() => {
+                    log::warn!("Job {} will be redelivered: {err}", delivery.id);
+                    queue.nack(&delivery).await?;
+                    continue;
+                }
+                Err(err) => {
+                    log::error!("Job {} failed: {err}", delivery.id);
+                    result.error = Some(json!(err));
+                }
+            }
+            queue.reply(&delivery, &result).await?;
+            queue.ack(&delivery).await?;
+        }
+    }
+}
+
+/// Checks shared by the broker-backed queues' tests
+#[cfg(all(test, any(feature = "amqp", feature = "nats", feature = "sqs")))]
+pub(crate) mod contract {
+    use super::*;
+
+    /// Check a queue holding the single job `payload`: it is received,
+    /// redelivered after a nack if the queue [redelivers](JobQueue::redelivers),
+    /// and gone once acked. Returns the delivery, e.g. to check its reply.
+    pub(crate) async fn check<Q: JobQueue>(queue: &Q, payload: &[u8]) -> Delivery {
+        let wait = Duration::from_secs(10);
+        let receive = async || {
+            tokio::time::timeout(wait, queue.receive())
+                .await
+                .expect("a job within the timeout")
+                .unwrap()
+                .expect("an open queue")
+        };
+
+        let mut delivery = receive().await;
+        assert_eq!(delivery.payload, payload);
+        if queue.redelivers() {
+            queue.nack(&delivery).await.unwrap();
+            let again = receive().await;
+            assert_eq!(again.id, delivery.id);
+            assert_eq!(again.payload, payload);
+            delivery = again;
+        }
+        queue.ack(&delivery).await.unwrap();
+
+        let next = tokio::time::timeout(Duration::from_secs(1), queue.receive()).await;
+        assert!(
+            !matches!(next, Ok(Ok(Some(_)))),
+            "acked job was delivered again"
+        );
+        delivery
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::prelude::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct TestQueue {
+        pending: Mutex<VecDeque<Delivery>>,
+        log: Mutex<Vec<String>>,
+        no_redelivery: bool,
+        visibility_timeout: Option<Duration>,
+    }
+
+    impl JobQueue for TestQueue {
+        async fn receive(&self) -> Result<Option<Delivery>, WorkerError> {
+            Ok(self.pending.lock().unwrap().pop_front())
+        }
+
+        async fn ack(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("ack {}", delivery.id));
+            Ok(())
+        }
+
+        async fn nack(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("nack {}", delivery.id));
+            Ok(())
+        }
+
+        async fn reply(&self, delivery: &Delivery, result: &JobResult) -> Result<(), WorkerError> {
+            let reply_to = delivery.reply_to.as_deref().unwrap_or("-");
+            let result = String::from_utf8(result.to_json()).unwrap();
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("reply {reply_to} {result}"));
+            Ok(())
+        }
+
+        fn redelivers(&self) -> bool {
+            !self.no_redelivery
+        }
+
+        fn visibility_timeout(&self) -> Option<Duration> {
+            self.visibility_timeout
+        }
+
+        async fn extend(&self, delivery: &Delivery) -> Result<(), WorkerError> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("extend {}", delivery.id));
+            Ok(())
+        }
+    }
+
+    fn request(number: &str) -> String {
+        format!(r#"{{"api_key": "k", "sender": "Shop", "number": "{number}", "text": "Hi"}}"#)
+    }
+
+    #[tokio::test]
+    async fn sends_jobs_and_replies() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37060000001");
+            then.status(200).body("1001");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37060000002");
+            then.status(200).body("7");
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/send")
+                .query_param("number", "37060000003");
+            then.status(200).body("1");
+        });
+
+        let queue = TestQueue::default();
+        queue.pending.lock().unwrap().extend([
+            Delivery::new("a", request("+37060000001")).with_reply_to("results"),
+            Delivery::new("b", request("+37060000002")),
+            Delivery::new("c", request("+37060000003")),
+            Delivery::new("d", "{"),
+        ]);
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let sent = client
+            .run_worker(&queue, std::future::pending())
+            .await
+            .unwrap();
+        assert_eq!(sent, 1);
+
+        let log = queue.log.into_inner().unwrap();
+        assert_eq!(log.len(), 7);
+        assert_eq!(
+            log[0],
+            r#"reply results {"job_id":"a","number":"+37060000001","message_id":"1001"}"#
+        );
+        assert_eq!(log[1], "ack a");
+        assert!(log[2].starts_with(
+            r#"reply - {"job_id":"b","number":"+37060000002","error":{"kind":"send_failed""#
+        ));
+        assert_eq!(log[4], "nack c");
+        assert!(log[5].contains(r#""kind":"invalid_payload""#));
+        assert_eq!(log[6], "ack d");
+    }
+
+    #[tokio::test]
+    async fn replies_retryable_errors_without_redelivery() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200).body("1");
+        });
+
+        let queue = TestQueue {
+            no_redelivery: true,
+            ..TestQueue::default()
+        };
+        let inbox = "_INBOX.1";
+        queue
+            .pending
+            .lock()
+            .unwrap()
+            .push_back(Delivery::new("a", request("+37060000001")).with_reply_to(inbox));
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        let sent = client
+            .run_worker(&queue, std::future::pending())
+            .await
+            .unwrap();
+        assert_eq!(sent, 0);
+
+        let log = queue.log.into_inner().unwrap();
+        assert!(log[0].starts_with(&format!("reply {inbox} ")));
+        assert!(log[0].contains(r#""kind":"send_failed""#));
+        assert_eq!(log[1], "ack a");
+    }
+
+    #[tokio::test]
+    async fn extends_visibility_of_slow_sends() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/send");
+            then.status(200)
+                .body("1001")
+                .delay(Duration::from_millis(250));
+        });
+
+        let queue = TestQueue {
+            visibility_timeout: Some(Duration::from_millis(100)),
+            ..TestQueue::default()
+        };
+        queue
+            .pending
+            .lock()
+            .unwrap()
+            .push_back(Delivery::new("a", request("+37060000001")).with_receipt("r-1"));
+
+        let client = SmsClient::with_api_base_url(server.base_url());
+        client
+            .run_worker(&queue, std::future::pending())
+            .await
+            .unwrap();
+
+        let log = queue.log.into_inner().unwrap();
+        let extended = log.iter().filter(|entry| *entry == "extend a").count();
+        assert!(extended >= 2, "{log:?}");
+        assert_eq!(log.last().unwrap(), "ack a");
+    }
+}